@@ -0,0 +1,9 @@
+//! A simulated Kobo e-reader for end-to-end testing of the proxy.
+//!
+//! [`Simulator`] walks through the requests a device makes when it syncs, checking
+//! each response the way a device relies on it, so a deployment can be validated
+//! without pointing a real device at it.
+
+mod simulator;
+
+pub use simulator::{Simulator, StepResult, StepStatus};
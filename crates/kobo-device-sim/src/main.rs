@@ -0,0 +1,49 @@
+//! Runs a simulated Kobo device against a proxy and reports each step.
+
+use clap::Parser;
+use kobo_device_sim::{Simulator, StepStatus};
+
+/// Simulate a Kobo e-reader syncing through a kobo-server proxy: authenticate,
+/// initialize, sync the library, download a book, and update its reading progress.
+/// Exits with an error if any step fails.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Arguments {
+    /// The URL devices use to reach the proxy, i.e. its `--frontend-url`.
+    #[arg(env = "KOBO_PROXY_URL")]
+    proxy_url: String,
+    /// The device ID the simulator authenticates as.
+    #[arg(long, default_value = "kobo-device-sim", env = "KOBO_DEVICE_ID")]
+    device_id: String,
+    /// The user key of a device linked to a Kobo account. Without it, the proxy
+    /// must be started with `--synthetic-device-auth`.
+    #[arg(long, env = "KOBO_USER_KEY")]
+    user_key: Option<String>,
+}
+
+#[tokio::main]
+#[expect(
+    clippy::print_stdout,
+    reason = "The step report is the output of the simulator."
+)]
+async fn main() -> anyhow::Result<()> {
+    let arguments = Arguments::parse();
+    let results = Simulator::new(arguments.proxy_url)
+        .device_id(arguments.device_id)
+        .user_key(arguments.user_key)
+        .run()
+        .await;
+    for result in &results {
+        println!("{result}");
+    }
+
+    let failures = results
+        .iter()
+        .filter(|result| result.status == StepStatus::Fail)
+        .count();
+    if failures > 0 {
+        anyhow::bail!("{failures} step(s) failed");
+    }
+
+    Ok(())
+}
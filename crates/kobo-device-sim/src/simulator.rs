@@ -0,0 +1,505 @@
+//! The device flow run by the simulator.
+//!
+//! The simulator authenticates like a device, fetches the initialization resources,
+//! syncs the library, downloads the first book, and updates its reading progress.
+//! The progress update sends the book's current reading state back unchanged, so
+//! running the simulator against a real account does not move anyone's bookmark.
+
+pub use implementation::{Simulator, StepResult, StepStatus};
+
+mod implementation {
+    use std::{fmt, time::Duration};
+
+    use http_body_util::{BodyExt as _, Full};
+    use hyper::{
+        Method, Request, StatusCode, Uri,
+        body::Bytes,
+        header::{self, HeaderValue},
+    };
+    use hyper_util::{
+        client::legacy::{Client, connect::HttpConnector},
+        rt::TokioExecutor,
+    };
+    use serde_json::{Value, json};
+
+    /// How long each request may take before its step is reported as failed.
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// The `User-Agent` of a Kobo Libra 2, so the proxy identifies the model and
+    /// firmware like it would for a real device.
+    const USER_AGENT: &str = "Mozilla/5.0 (Linux; U; Android 2.0; en-us;) AppleWebKit/538.1 \
+                              (KHTML, like Gecko) Version/4.0 Mobile Safari/538.1 (Kobo Touch \
+                              0388/4.38.21908)";
+
+    /// The firmware version sent during authentication.
+    const APP_VERSION: &str = "4.38.21908";
+
+    /// The platform ID of a Kobo Libra 2.
+    const PLATFORM_ID: &str = "00000000-0000-0000-0000-000000000388";
+
+    /// Header the proxy identifies devices by.
+    const DEVICE_ID_HEADER: &str = "x-kobo-deviceid";
+
+    /// The steps of the device flow, in order.
+    const STEPS: [&str; 5] = [
+        "Device authentication",
+        "Initialization",
+        "Library sync",
+        "Book download",
+        "Reading progress",
+    ];
+
+    type HttpClient = Client<hyper_rustls::HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+    /// The outcome of a single step.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum StepStatus {
+        /// The step succeeded.
+        Pass,
+        /// The step was not run, because an earlier step failed or there was nothing
+        /// to do.
+        Skip,
+        /// The step failed.
+        Fail,
+    }
+
+    impl fmt::Display for StepStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::Pass => write!(f, "PASS"),
+                Self::Skip => write!(f, "SKIP"),
+                Self::Fail => write!(f, "FAIL"),
+            }
+        }
+    }
+
+    /// The result of a single step, with an actionable message.
+    #[derive(Clone, Debug)]
+    pub struct StepResult {
+        /// The name of the step.
+        pub name: String,
+        /// The outcome of the step.
+        pub status: StepStatus,
+        /// Details about the outcome, including how to fix failures.
+        pub message: String,
+    }
+
+    impl StepResult {
+        fn new(name: &str, status: StepStatus, message: impl Into<String>) -> Self {
+            Self {
+                name: name.to_owned(),
+                status,
+                message: message.into(),
+            }
+        }
+    }
+
+    impl fmt::Display for StepResult {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "[{}] {}: {}", self.status, self.name, self.message)
+        }
+    }
+
+    /// The book the download and progress steps use, taken from the library sync.
+    #[derive(Debug)]
+    struct Book {
+        entitlement_id: String,
+        download_url: Option<String>,
+    }
+
+    /// Runs the device flow against a proxy.
+    pub struct Simulator {
+        proxy_url: String,
+        device_id: String,
+        user_key: Option<String>,
+        client: HttpClient,
+    }
+
+    impl Simulator {
+        /// Creates a simulator for the proxy at `proxy_url`, the frontend URL the
+        /// proxy is configured with.
+        #[must_use]
+        pub fn new<T: Into<String>>(proxy_url: T) -> Self {
+            let connector = hyper_rustls::HttpsConnectorBuilder::new()
+                .with_webpki_roots()
+                .https_or_http()
+                .enable_http1()
+                .build();
+
+            Self {
+                proxy_url: proxy_url.into().trim_end_matches('/').to_owned(),
+                device_id: "kobo-device-sim".to_owned(),
+                user_key: None,
+                client: Client::builder(TokioExecutor::new()).build(connector),
+            }
+        }
+
+        /// Sets the device ID the simulator authenticates as.
+        #[must_use]
+        pub fn device_id<T: Into<String>>(mut self, device_id: T) -> Self {
+            self.device_id = device_id.into();
+            self
+        }
+
+        /// Sets the user key of a device linked to a Kobo account, to authenticate
+        /// with that account rather than with synthetic tokens.
+        #[must_use]
+        pub fn user_key(mut self, user_key: Option<String>) -> Self {
+            self.user_key = user_key;
+            self
+        }
+
+        /// Runs every step and returns the results in order. Steps after a failed
+        /// step are skipped.
+        pub async fn run(&self) -> Vec<StepResult> {
+            let mut results = Vec::new();
+            // The steps stop at the first failure; the remaining ones are skipped.
+            let _completed = self.run_steps(&mut results).await;
+
+            if let Some(failed) = results
+                .last()
+                .filter(|result| result.status == StepStatus::Fail)
+                .map(|result| result.name.clone())
+            {
+                for name in &STEPS[results.len()..] {
+                    results.push(StepResult::new(
+                        name,
+                        StepStatus::Skip,
+                        format!("Skipped because {failed} failed"),
+                    ));
+                }
+            }
+
+            results
+        }
+
+        async fn run_steps(&self, results: &mut Vec<StepResult>) -> Option<()> {
+            let [auth, initialization, sync, download, progress] = STEPS;
+            let token = record(results, auth, self.authenticate().await)?;
+            let sync_url = record(results, initialization, self.initialize(&token).await)?;
+            let book = record(results, sync, self.sync(&token, &sync_url).await)?;
+
+            let Some(book) = book else {
+                for name in [download, progress] {
+                    results.push(StepResult::new(
+                        name,
+                        StepStatus::Skip,
+                        "The library has no books",
+                    ));
+                }
+                return Some(());
+            };
+            match &book.download_url {
+                Some(url) => {
+                    record(results, download, self.download(&token, url).await);
+                }
+                None => results.push(StepResult::new(
+                    download,
+                    StepStatus::Skip,
+                    "The book has no download URL",
+                )),
+            }
+            record(
+                results,
+                progress,
+                self.update_progress(&token, &book.entitlement_id).await,
+            );
+
+            Some(())
+        }
+
+        /// Authenticates the device, returning its access token.
+        async fn authenticate(&self) -> anyhow::Result<(String, String)> {
+            let body = json!({
+                "AffiliateName": "Kobo",
+                "AppVersion": APP_VERSION,
+                "DeviceId": self.device_id,
+                "PlatformId": PLATFORM_ID,
+                "UserKey": self.user_key.clone().unwrap_or_default(),
+            });
+            let url = format!("{}/v1/auth/device", self.proxy_url);
+            let (status, response) = self.send(Method::POST, &url, None, Some(&body)).await?;
+            if !status.is_success() {
+                anyhow::bail!(
+                    "The proxy responded with {status}. Start the proxy with \
+                     --synthetic-device-auth, or pass the --user-key of a device linked to the \
+                     Kobo account"
+                );
+            }
+
+            let token = parse_json(&response)?["AccessToken"]
+                .as_str()
+                .map(ToOwned::to_owned)
+                .ok_or_else(|| anyhow::anyhow!("The response has no access token"))?;
+            Ok((token, "Received an access token".to_owned()))
+        }
+
+        /// Fetches the initialization resources, returning the library sync URL.
+        async fn initialize(&self, token: &str) -> anyhow::Result<(String, String)> {
+            let url = format!("{}/v1/initialization", self.proxy_url);
+            let response = self.get(&url, token).await?;
+            let sync_url = parse_json(&response)?["Resources"]["library_sync"]
+                .as_str()
+                .map(ToOwned::to_owned)
+                .ok_or_else(|| anyhow::anyhow!("The resources have no library_sync URL"))?;
+            if !sync_url.starts_with(&self.proxy_url) {
+                anyhow::bail!(
+                    "The library_sync resource is {sync_url}, so devices would bypass the proxy. \
+                     Start the proxy with --frontend-url {}",
+                    self.proxy_url
+                );
+            }
+
+            Ok((sync_url, "Resources point at the proxy".to_owned()))
+        }
+
+        /// Syncs the library, returning the first book in it.
+        async fn sync(
+            &self,
+            token: &str,
+            sync_url: &str,
+        ) -> anyhow::Result<(Option<Book>, String)> {
+            let response = self.get(sync_url, token).await?;
+            let json = parse_json(&response)?;
+            let changes = json
+                .as_array()
+                .ok_or_else(|| anyhow::anyhow!("The response is not a list of changes"))?;
+
+            let book = changes.iter().find_map(|change| {
+                let entitlement = change
+                    .get("NewEntitlement")
+                    .or_else(|| change.get("ChangedEntitlement"))?;
+                Some(Book {
+                    entitlement_id: entitlement["BookEntitlement"]["Id"].as_str()?.to_owned(),
+                    download_url: entitlement["BookMetadata"]["DownloadUrls"][0]["Url"]
+                        .as_str()
+                        .map(ToOwned::to_owned),
+                })
+            });
+            Ok((book, format!("Received {} changes", changes.len())))
+        }
+
+        /// Downloads a book.
+        async fn download(&self, token: &str, url: &str) -> anyhow::Result<((), String)> {
+            let response = self.get(url, token).await?;
+            if response.is_empty() {
+                anyhow::bail!("The download is empty");
+            }
+
+            Ok(((), format!("Downloaded {} bytes", response.len())))
+        }
+
+        /// Sends the book's current reading state back, as a device does after
+        /// reading.
+        async fn update_progress(
+            &self,
+            token: &str,
+            entitlement_id: &str,
+        ) -> anyhow::Result<((), String)> {
+            let url = format!("{}/v1/library/{entitlement_id}/state", self.proxy_url);
+            let response = self.get(&url, token).await?;
+            let state = parse_json(&response)?
+                .get(0)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("The proxy returned no reading state"))?;
+
+            let body = json!({ "ReadingStates": [state] });
+            let (status, _) = self
+                .send(Method::PUT, &url, Some(token), Some(&body))
+                .await?;
+            if !status.is_success() {
+                anyhow::bail!("The proxy responded with {status} to the progress update");
+            }
+
+            Ok(((), "Sent the current reading state back".to_owned()))
+        }
+
+        /// Sends an authenticated GET request, failing unless it succeeds.
+        async fn get(&self, url: &str, token: &str) -> anyhow::Result<Bytes> {
+            let (status, body) = self.send(Method::GET, url, Some(token), None).await?;
+            if !status.is_success() {
+                anyhow::bail!("The proxy responded with {status}");
+            }
+
+            Ok(body)
+        }
+
+        /// Sends a request with the headers of a device and returns the status and
+        /// body.
+        async fn send(
+            &self,
+            method: Method,
+            url: &str,
+            token: Option<&str>,
+            body: Option<&Value>,
+        ) -> anyhow::Result<(StatusCode, Bytes)> {
+            let uri: Uri = url.parse()?;
+            let mut request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::USER_AGENT, USER_AGENT)
+                .header(DEVICE_ID_HEADER, &self.device_id);
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+            }
+            let request = match body {
+                Some(body) => request
+                    .header(
+                        header::CONTENT_TYPE,
+                        HeaderValue::from_static("application/json"),
+                    )
+                    .body(Full::new(Bytes::from(serde_json::to_vec(body)?)))?,
+                None => request.body(Full::default())?,
+            };
+
+            tokio::time::timeout(REQUEST_TIMEOUT, async {
+                let response = self.client.request(request).await?;
+                let status = response.status();
+                let body = response.into_body().collect().await?.to_bytes();
+                anyhow::Ok((status, body))
+            })
+            .await
+            .map_err(|_| anyhow::anyhow!("Timed out after {REQUEST_TIMEOUT:?}"))?
+        }
+    }
+
+    /// Records the outcome of a step, returning its value if it passed.
+    fn record<T>(
+        results: &mut Vec<StepResult>,
+        name: &str,
+        outcome: anyhow::Result<(T, String)>,
+    ) -> Option<T> {
+        match outcome {
+            Ok((value, message)) => {
+                results.push(StepResult::new(name, StepStatus::Pass, message));
+                Some(value)
+            }
+            Err(e) => {
+                results.push(StepResult::new(name, StepStatus::Fail, e.to_string()));
+                None
+            }
+        }
+    }
+
+    fn parse_json(body: &[u8]) -> anyhow::Result<Value> {
+        serde_json::from_slice(body)
+            .map_err(|e| anyhow::anyhow!("The response is not valid JSON: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Json, Router,
+        routing::{get, post},
+    };
+    use kobo_proxy_core::{KOBO_API_URL, fixtures::Fixture};
+    use serde_json::json;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Kobo's download host, which the fake proxy serves downloads for.
+    const DOWNLOAD_HOST: &str = "https://storedownloads.kobo.com";
+
+    /// Serves the fixtures the way the proxy does, with the Kobo URLs rewritten to
+    /// the fake proxy when `rewrite_urls` is set, and returns the proxy URL.
+    async fn spawn_fake_proxy(rewrite_urls: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let rewrite = {
+            let url = url.clone();
+            move |fixture: Fixture| {
+                let body = fixture.body().replace(DOWNLOAD_HOST, &url);
+                if rewrite_urls {
+                    body.replace(KOBO_API_URL, &url)
+                } else {
+                    body
+                }
+            }
+        };
+        let router = Router::new()
+            .route(
+                "/v1/auth/device",
+                post(|| async { Json(json!({ "AccessToken": "token", "TokenType": "Bearer" })) }),
+            )
+            .route(
+                "/v1/initialization",
+                get({
+                    let rewrite = rewrite.clone();
+                    move || async move { rewrite(Fixture::Initialization) }
+                }),
+            )
+            .route(
+                "/v1/library/sync",
+                get(move || async move { rewrite(Fixture::LibrarySync) }),
+            )
+            .route("/download", get(|| async { "kepub" }))
+            .route(
+                "/v1/library/{id}/state",
+                get(|| async { Fixture::ReadingState.body() })
+                    .put(|| async { Json(json!({ "RequestResult": "Success" })) }),
+            );
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        url
+    }
+
+    #[test]
+    fn step_result_display_includes_status() {
+        let result = StepResult {
+            name: "Library sync".to_owned(),
+            status: StepStatus::Fail,
+            message: "The proxy responded with 502 Bad Gateway".to_owned(),
+        };
+
+        assert_eq!(
+            result.to_string(),
+            "[FAIL] Library sync: The proxy responded with 502 Bad Gateway"
+        );
+    }
+
+    #[tokio::test]
+    async fn run_passes_against_a_working_proxy() {
+        let proxy_url = spawn_fake_proxy(true).await;
+
+        let results = Simulator::new(proxy_url).run().await;
+
+        assert_eq!(results.len(), 5);
+        for result in &results {
+            assert_eq!(result.status, StepStatus::Pass, "{result}");
+        }
+        assert_eq!(results[3].message, "Downloaded 5 bytes");
+    }
+
+    #[tokio::test]
+    async fn run_fails_when_resources_bypass_the_proxy() {
+        let proxy_url = spawn_fake_proxy(false).await;
+
+        let results = Simulator::new(proxy_url).run().await;
+
+        assert_eq!(results[1].status, StepStatus::Fail);
+        assert!(results[1].message.contains("--frontend-url"));
+        assert!(
+            results[2..]
+                .iter()
+                .all(|result| result.status == StepStatus::Skip)
+        );
+    }
+
+    #[tokio::test]
+    async fn run_fails_when_the_proxy_is_unreachable() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let results = Simulator::new(format!("http://127.0.0.1:{port}"))
+            .run()
+            .await;
+
+        assert_eq!(results[0].status, StepStatus::Fail);
+        assert_eq!(results.len(), 5);
+    }
+}
@@ -0,0 +1,106 @@
+//! The error envelope returned by the local API.
+
+pub use implementation::ApiError;
+
+mod implementation {
+    use std::time::Duration;
+
+    use axum::{
+        Json,
+        response::{IntoResponse, Response},
+    };
+    use hyper::{StatusCode, header};
+    use serde_json::json;
+
+    /// An error returned by the local API, serialized as
+    /// `{"error": {"code": "...", "message": "..."}}`.
+    #[derive(Debug)]
+    pub struct ApiError {
+        status: StatusCode,
+        code: &'static str,
+        message: String,
+        retry_after: Option<Duration>,
+    }
+
+    impl ApiError {
+        /// The request was malformed, e.g. an invalid query parameter.
+        pub fn bad_request<M: Into<String>>(message: M) -> Self {
+            Self {
+                status: StatusCode::BAD_REQUEST,
+                code: "bad_request",
+                message: message.into(),
+                retry_after: None,
+            }
+        }
+
+        /// The request is not allowed on this listener.
+        pub fn forbidden<M: Into<String>>(message: M) -> Self {
+            Self {
+                status: StatusCode::FORBIDDEN,
+                code: "forbidden",
+                message: message.into(),
+                retry_after: None,
+            }
+        }
+
+        /// The caller has used up its request budget and may retry after `retry_after`.
+        pub fn rate_limited(retry_after: Duration) -> Self {
+            Self {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                code: "rate_limited",
+                message: "Too many requests, slow down".to_owned(),
+                retry_after: Some(retry_after),
+            }
+        }
+    }
+
+    impl IntoResponse for ApiError {
+        fn into_response(self) -> Response {
+            let body = Json(json!({
+                "error": {
+                    "code": self.code,
+                    "message": self.message,
+                }
+            }));
+            let mut response = (self.status, body).into_response();
+            if let Some(retry_after) = self.retry_after {
+                // Round up so clients never retry before the budget has refilled.
+                let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, header::HeaderValue::from(seconds));
+            }
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::response::IntoResponse as _;
+    use http_body_util::BodyExt as _;
+    use hyper::{StatusCode, header};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn bad_request_uses_error_envelope() {
+        let response = ApiError::bad_request("Invalid cursor").into_response();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "bad_request");
+        assert_eq!(body["error"]["message"], "Invalid cursor");
+    }
+
+    #[test]
+    fn rate_limited_rounds_retry_after_up() {
+        let response = ApiError::rate_limited(Duration::from_millis(1500)).into_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    }
+}
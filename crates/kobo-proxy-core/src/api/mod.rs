@@ -0,0 +1,6 @@
+//! Shared extractors and response types for the local `/api/*` endpoints.
+
+pub mod error;
+pub mod pagination;
+pub mod query;
+pub mod rate_limit;
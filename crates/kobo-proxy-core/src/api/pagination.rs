@@ -0,0 +1,221 @@
+//! Cursor pagination for list endpoints.
+//!
+//! List endpoints accept `cursor` and `limit` query parameters and return a [`Page`]
+//! of items with the cursor of the next page, if any. Cursors are opaque to clients:
+//! they should only ever pass back a `next_cursor` they were given.
+//!
+//! A cursor holds the stable key of the last item on its page, rather than a
+//! position, so the next page starts after that item even if items were added or
+//! removed in between.
+
+pub use implementation::{Page, Pagination, SortOrder};
+
+mod implementation {
+    use axum::extract::FromRequestParts;
+    use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+    use hyper::http::request::Parts;
+    use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+    use crate::api::{error::ApiError, query::ApiQuery};
+
+    /// Number of items returned when no limit is given.
+    const DEFAULT_LIMIT: usize = 100;
+
+    /// Largest number of items returned in one page.
+    const MAX_LIMIT: usize = 1000;
+
+    /// The pagination query parameters.
+    #[derive(Debug, Deserialize)]
+    struct PageParameters {
+        cursor: Option<String>,
+        limit: Option<usize>,
+    }
+
+    /// A page of a list endpoint's items.
+    #[derive(Debug, Serialize)]
+    pub struct Page<T> {
+        /// The items on this page.
+        pub items: Vec<T>,
+        /// The cursor to request the next page with, or `None` on the last page.
+        pub next_cursor: Option<String>,
+    }
+
+    /// The order of a list endpoint's items by their keys.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum SortOrder {
+        /// Smallest key first.
+        Ascending,
+        /// Largest key first, e.g. newest first.
+        Descending,
+    }
+
+    impl SortOrder {
+        /// Checks if `key` is listed after `cursor`.
+        fn is_after<K: Ord>(self, key: &K, cursor: &K) -> bool {
+            match self {
+                Self::Ascending => key > cursor,
+                Self::Descending => key < cursor,
+            }
+        }
+    }
+
+    /// The page of items requested by the `cursor` and `limit` query parameters.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct Pagination {
+        cursor: Option<String>,
+        limit: usize,
+    }
+
+    impl Pagination {
+        /// Returns the requested page of `items`, which are sorted in `order` of the
+        /// unique keys returned by `key`.
+        ///
+        /// # Errors
+        ///
+        /// Fails if the cursor is not a key of these items.
+        pub fn page<T, K, F>(
+            self,
+            items: Vec<T>,
+            order: SortOrder,
+            key: F,
+        ) -> Result<Page<T>, ApiError>
+        where
+            K: Ord + Serialize + DeserializeOwned,
+            F: Fn(&T) -> K,
+        {
+            let cursor = self.cursor.as_deref().map(decode_cursor::<K>).transpose()?;
+            let mut remaining = items.into_iter().filter(|item| {
+                cursor
+                    .as_ref()
+                    .is_none_or(|cursor| order.is_after(&key(item), cursor))
+            });
+            let items: Vec<T> = remaining.by_ref().take(self.limit).collect();
+            let next_cursor = match (remaining.next(), items.last()) {
+                (Some(_), Some(last)) => encode_cursor(&key(last)),
+                _ => None,
+            };
+            Ok(Page { items, next_cursor })
+        }
+    }
+
+    /// Encodes `key` as a cursor.
+    fn encode_cursor<K: Serialize>(key: &K) -> Option<String> {
+        serde_json::to_vec(key)
+            .ok()
+            .map(|json| URL_SAFE_NO_PAD.encode(json))
+    }
+
+    /// Decodes a cursor created by [`encode_cursor`].
+    fn decode_cursor<K: DeserializeOwned>(cursor: &str) -> Result<K, ApiError> {
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| ApiError::bad_request(format!("Invalid cursor '{cursor}'")))
+    }
+
+    impl<S> FromRequestParts<S> for Pagination
+    where
+        S: Send + Sync,
+    {
+        type Rejection = ApiError;
+
+        async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+            let ApiQuery(parameters) =
+                ApiQuery::<PageParameters>::from_request_parts(parts, state).await?;
+            let limit = parameters.limit.unwrap_or(DEFAULT_LIMIT);
+            if !(1..=MAX_LIMIT).contains(&limit) {
+                return Err(ApiError::bad_request(format!(
+                    "limit must be between 1 and {MAX_LIMIT}"
+                )));
+            }
+
+            Ok(Self {
+                cursor: parameters.cursor,
+                limit,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, extract::FromRequestParts as _, http::Request};
+
+    use super::*;
+
+    async fn pagination(uri: &str) -> Option<Pagination> {
+        let (mut parts, _body) = Request::builder()
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+            .into_parts();
+        Pagination::from_request_parts(&mut parts, &()).await.ok()
+    }
+
+    async fn page(uri: &str, items: &[u32], order: SortOrder) -> Page<u32> {
+        pagination(uri)
+            .await
+            .unwrap()
+            .page(items.to_vec(), order, |item| *item)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn pages_follow_next_cursor() {
+        let items: Vec<u32> = (0..5).collect();
+
+        let first = page("/api/items?limit=2", &items, SortOrder::Ascending).await;
+        assert_eq!(first.items, [0, 1]);
+        let cursor = first.next_cursor.unwrap();
+
+        let second = page(
+            &format!("/api/items?limit=2&cursor={cursor}"),
+            &items,
+            SortOrder::Ascending,
+        )
+        .await;
+        assert_eq!(second.items, [2, 3]);
+        let cursor = second.next_cursor.unwrap();
+
+        let last = page(
+            &format!("/api/items?limit=2&cursor={cursor}"),
+            &items,
+            SortOrder::Ascending,
+        )
+        .await;
+        assert_eq!(last.items, [4]);
+        assert!(last.next_cursor.is_none());
+    }
+
+    #[tokio::test]
+    async fn pages_are_stable_when_items_change() {
+        let first = page("/api/items?limit=2", &[4, 3, 2, 1], SortOrder::Descending).await;
+        assert_eq!(first.items, [4, 3]);
+        let cursor = first.next_cursor.unwrap();
+
+        // A newer item is added and the last item of the first page is removed.
+        let second = page(
+            &format!("/api/items?limit=2&cursor={cursor}"),
+            &[5, 4, 2, 1],
+            SortOrder::Descending,
+        )
+        .await;
+
+        assert_eq!(second.items, [2, 1]);
+        assert!(second.next_cursor.is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_parameters() {
+        assert!(pagination("/api/items?limit=0").await.is_none());
+        assert!(pagination("/api/items?limit=1001").await.is_none());
+        assert!(
+            pagination("/api/items?cursor=abc")
+                .await
+                .unwrap()
+                .page(vec![1_u32], SortOrder::Ascending, |item| *item)
+                .is_err()
+        );
+    }
+}
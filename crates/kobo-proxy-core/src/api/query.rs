@@ -0,0 +1,31 @@
+//! Query string extraction for the local API.
+
+pub use implementation::ApiQuery;
+
+mod implementation {
+    use axum::extract::{FromRequestParts, Query};
+    use hyper::http::request::Parts;
+    use serde::de::DeserializeOwned;
+
+    use crate::api::error::ApiError;
+
+    /// Deserializes the query string like [`Query`], rejecting malformed parameters
+    /// with an [`ApiError`] instead of a plain text response.
+    #[derive(Debug)]
+    pub struct ApiQuery<T>(pub T);
+
+    impl<T, S> FromRequestParts<S> for ApiQuery<T>
+    where
+        T: DeserializeOwned,
+        S: Send + Sync,
+    {
+        type Rejection = ApiError;
+
+        async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+            Query::<T>::from_request_parts(parts, state)
+                .await
+                .map(|Query(query)| Self(query))
+                .map_err(|rejection| ApiError::bad_request(rejection.body_text()))
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Per-caller rate limits for the local API.
+//!
+//! Each caller gets a token bucket that holds a minute's worth of requests and
+//! refills continuously. Callers are keyed by their client IP address, as resolved
+//! behind trusted proxies. Headers a caller sends are not authenticated, so keying
+//! on one would let a caller pick a fresh budget for every request.
+
+pub use implementation::{RateLimiter, enforce_rate_limits};
+
+mod implementation {
+    use std::{
+        collections::HashMap,
+        sync::{Mutex, MutexGuard, PoisonError},
+        time::{Duration, Instant},
+    };
+
+    use axum::{
+        extract::{ConnectInfo, Request, State},
+        middleware::Next,
+        response::{IntoResponse as _, Response},
+    };
+
+    use crate::{api::error::ApiError, listener::ClientAddress, state::server_state::ServerState};
+
+    /// Number of callers tracked before buckets that have refilled are forgotten.
+    const MAX_TRACKED_KEYS: usize = 1024;
+
+    /// A caller's remaining request budget.
+    #[derive(Debug)]
+    struct Bucket {
+        tokens: f64,
+        updated: Instant,
+    }
+
+    /// Request budgets keyed by caller.
+    #[derive(Debug, Default)]
+    pub struct RateLimiter {
+        requests_per_minute: Option<u32>,
+        buckets: Mutex<HashMap<String, Bucket>>,
+    }
+
+    impl RateLimiter {
+        /// Creates a rate limiter allowing each caller `requests_per_minute`, or
+        /// unlimited requests when `None`.
+        pub fn new(requests_per_minute: Option<u32>) -> Self {
+            Self {
+                requests_per_minute: requests_per_minute.filter(|&limit| limit > 0),
+                buckets: Mutex::default(),
+            }
+        }
+
+        fn get_buckets_lock(&self) -> MutexGuard<'_, HashMap<String, Bucket>> {
+            self.buckets.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Takes a request from the budget of `key`.
+        ///
+        /// # Errors
+        ///
+        /// Returns how long to wait before retrying when the budget is spent.
+        pub fn check(&self, key: &str) -> Result<(), Duration> {
+            self.check_at(key, Instant::now())
+        }
+
+        pub(super) fn check_at(&self, key: &str, now: Instant) -> Result<(), Duration> {
+            let Some(requests_per_minute) = self.requests_per_minute else {
+                return Ok(());
+            };
+            let capacity = f64::from(requests_per_minute);
+            let refill_per_second = capacity / 60.0;
+
+            let mut buckets = self.get_buckets_lock();
+            if buckets.len() >= MAX_TRACKED_KEYS && !buckets.contains_key(key) {
+                buckets.retain(|_, bucket| {
+                    let elapsed = now.saturating_duration_since(bucket.updated);
+                    bucket.tokens + elapsed.as_secs_f64() * refill_per_second < capacity
+                });
+            }
+            let bucket = buckets.entry(key.to_owned()).or_insert(Bucket {
+                tokens: capacity,
+                updated: now,
+            });
+            let elapsed = now.saturating_duration_since(bucket.updated);
+            bucket.tokens =
+                (bucket.tokens + elapsed.as_secs_f64() * refill_per_second).min(capacity);
+            bucket.updated = now;
+
+            if bucket.tokens >= 1.0 {
+                bucket.tokens -= 1.0;
+                Ok(())
+            } else {
+                Err(Duration::from_secs_f64(
+                    (1.0 - bucket.tokens) / refill_per_second,
+                ))
+            }
+        }
+    }
+
+    /// Identifies the caller of a local API request by its client IP address.
+    fn rate_limit_key(request: &Request) -> String {
+        request
+            .extensions()
+            .get::<ConnectInfo<ClientAddress>>()
+            .map_or_else(
+                || "anonymous".to_owned(),
+                |ConnectInfo(ClientAddress(address))| address.ip().to_string(),
+            )
+    }
+
+    /// Rejects local API requests from callers that have used up their budget with
+    /// `429 Too Many Requests`.
+    pub async fn enforce_rate_limits(
+        State(server_state): State<ServerState>,
+        request: Request,
+        next: Next,
+    ) -> Response {
+        if let Err(retry_after) = server_state
+            .api_rate_limiter
+            .check(&rate_limit_key(&request))
+        {
+            tracing::warn!(
+                path = request.uri().path(),
+                "API rate limit exceeded, retry after {retry_after:?}"
+            );
+            return ApiError::rate_limited(retry_after).into_response();
+        }
+        next.run(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        time::{Duration, Instant},
+    };
+
+    use axum::{body::Body, extract::ConnectInfo, http::Request};
+    use hyper::StatusCode;
+    use tower::ServiceExt as _;
+
+    use super::*;
+    use crate::{listener::ClientAddress, router::create_router, state::server_state::ServerState};
+
+    #[test]
+    fn budget_refills_over_time() {
+        let limiter = RateLimiter::new(Some(60));
+        let start = Instant::now();
+        for _ in 0..60 {
+            assert!(limiter.check_at("key", start).is_ok());
+        }
+
+        assert_eq!(limiter.check_at("key", start), Err(Duration::from_secs(1)));
+        assert!(limiter.check_at("other", start).is_ok());
+        assert!(
+            limiter
+                .check_at("key", start + Duration::from_secs(1))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn unlimited_without_a_limit() {
+        let limiter = RateLimiter::new(None);
+        let start = Instant::now();
+
+        assert!((0..1000).all(|_| limiter.check_at("key", start).is_ok()));
+    }
+
+    #[tokio::test]
+    async fn rate_limits_api_requests_per_client_ip() {
+        let state = ServerState::builder("http://frontend.test")
+            .api_rate_limit(Some(1))
+            .build();
+        let router = create_router(false, false, state);
+        let build_request = |address: &str, api_key: &'static str| {
+            let mut request = Request::builder()
+                .uri("/api/devices")
+                .header("x-api-key", api_key)
+                .body(Body::empty())
+                .expect("failed to build request");
+            request.extensions_mut().insert(ConnectInfo(ClientAddress(
+                address.parse::<SocketAddr>().unwrap(),
+            )));
+            request
+        };
+
+        let first = router
+            .clone()
+            .oneshot(build_request("192.0.2.1:4000", "a"))
+            .await
+            .unwrap();
+        let second = router
+            .clone()
+            .oneshot(build_request("192.0.2.1:4001", "b"))
+            .await
+            .unwrap();
+        let other = router
+            .oneshot(build_request("192.0.2.2:4000", "a"))
+            .await
+            .unwrap();
+
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(other.status(), StatusCode::OK);
+    }
+}
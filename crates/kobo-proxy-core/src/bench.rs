@@ -0,0 +1,182 @@
+//! Benchmark of the response rewrite pipeline, run by the `bench` subcommand.
+//!
+//! Representative initialization and library sync payloads are gzip-decoded, have
+//! their Kobo API URLs rewritten, and are re-encoded, as the proxy does for device
+//! responses, so throughput can be compared across hardware and releases.
+
+pub use implementation::{Bench, BenchResult};
+
+mod implementation {
+    use std::{
+        fmt,
+        hint::black_box,
+        time::{Duration, Instant},
+    };
+
+    use anyhow::{Result, anyhow};
+    use axum::body::Bytes;
+    use flate2::Compression;
+    use serde_json::{Map, Value, json};
+
+    use crate::{
+        kobo_protocol::{EntitlementBuilder, KoboId},
+        routes::{constants::KOBO_API_URL, initialization::rewrite_urls},
+        utils::http_body::{compress_gzip, decode_response_body, encode_response_body},
+    };
+
+    /// Frontend URL the payloads are rewritten to.
+    const FRONTEND_URL: &str = "http://192.168.1.10:8080";
+
+    /// Number of resource URLs in the initialization payload.
+    const INITIALIZATION_RESOURCES: usize = 150;
+
+    /// Number of books in the library sync payload, a full page for a large library.
+    const SYNC_ENTITLEMENTS: usize = 100;
+
+    /// An initialization response with a resource URL per entry, like the real one.
+    fn initialization_payload() -> String {
+        let resources: Map<String, Value> = (0..INITIALIZATION_RESOURCES)
+            .map(|index| {
+                (
+                    format!("resource_{index}"),
+                    json!(format!("{KOBO_API_URL}/v1/resources/{index}")),
+                )
+            })
+            .collect();
+        json!({ "Resources": resources }).to_string()
+    }
+
+    /// A page of library sync entitlements with download URLs.
+    fn library_sync_payload() -> String {
+        let entitlements: Vec<_> = (0..SYNC_ENTITLEMENTS)
+            .map(|index| {
+                let id = KoboId::from_u128(index as u128);
+                let url = format!("{KOBO_API_URL}/v1/download/{id}");
+                EntitlementBuilder::new(id, format!("Benchmark Book {index}"))
+                    .description("A representative description of a book. ".repeat(8))
+                    .download_url("EPUB3", &url, 1_048_576)
+                    .download_url("KEPUB", format!("{url}/kepub"), 1_048_576)
+                    .build_new()
+            })
+            .collect();
+        serde_json::to_string(&entitlements).unwrap_or_default()
+    }
+
+    /// The timing of one payload through the pipeline.
+    #[derive(Clone, Debug)]
+    pub struct BenchResult {
+        /// The payload that was processed.
+        pub name: &'static str,
+        /// Size of the decoded payload in bytes.
+        pub decoded_bytes: usize,
+        /// Size of the gzip-encoded payload in bytes.
+        pub encoded_bytes: usize,
+        /// Number of times the payload was processed.
+        pub iterations: u32,
+        /// Total time spent processing the payload.
+        pub elapsed: Duration,
+    }
+
+    impl BenchResult {
+        /// Decoded megabytes processed per second.
+        #[must_use]
+        pub fn throughput(&self) -> f64 {
+            #[expect(
+                clippy::cast_precision_loss,
+                reason = "Only used for a human readable approximation."
+            )]
+            let megabytes = self.decoded_bytes as f64 * f64::from(self.iterations) / 1_000_000.0;
+            megabytes / self.elapsed.as_secs_f64().max(f64::EPSILON)
+        }
+    }
+
+    impl fmt::Display for BenchResult {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "{}: {} B ({} B gzip), {} µs per response, {:.1} MB/s",
+                self.name,
+                self.decoded_bytes,
+                self.encoded_bytes,
+                (self.elapsed / self.iterations.max(1)).as_micros(),
+                self.throughput()
+            )
+        }
+    }
+
+    /// Runs the payloads through the rewrite pipeline.
+    pub struct Bench {
+        iterations: u32,
+        compression: Compression,
+    }
+
+    impl Bench {
+        /// Creates a benchmark processing each payload `iterations` times, encoding at
+        /// `gzip_level` like the server does.
+        #[must_use]
+        pub fn new(iterations: u32, gzip_level: u32) -> Self {
+            Self {
+                iterations,
+                compression: Compression::new(gzip_level),
+            }
+        }
+
+        /// Runs the benchmark, returning a result per payload.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if a payload cannot be encoded or decoded.
+        pub async fn run(&self) -> Result<Vec<BenchResult>> {
+            let mut results = Vec::new();
+            for (name, payload) in [
+                ("initialization", initialization_payload()),
+                ("library sync", library_sync_payload()),
+            ] {
+                results.push(self.run_payload(name, &payload).await?);
+            }
+
+            Ok(results)
+        }
+
+        async fn run_payload(&self, name: &'static str, payload: &str) -> Result<BenchResult> {
+            let encoded = Bytes::from(compress_gzip(payload, self.compression)?);
+            let start = Instant::now();
+            for _ in 0..self.iterations {
+                let decoded = decode_response_body(&encoded, true)
+                    .await
+                    .map_err(|status| anyhow!("Failed to decode the {name} payload: {status}"))?;
+                let rewritten = rewrite_urls(&decoded, FRONTEND_URL);
+                let body = encode_response_body(&rewritten, Some(self.compression))
+                    .await
+                    .map_err(|status| anyhow!("Failed to encode the {name} payload: {status}"))?;
+                drop(black_box(body));
+            }
+
+            Ok(BenchResult {
+                name,
+                decoded_bytes: payload.len(),
+                encoded_bytes: encoded.len(),
+                iterations: self.iterations,
+                elapsed: start.elapsed(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn run_reports_every_payload() {
+        let results = Bench::new(2, 6).run().await.unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| result.iterations == 2));
+        assert!(
+            results
+                .iter()
+                .all(|result| result.encoded_bytes < result.decoded_bytes)
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Sanitized Kobo store API payloads for tests.
+//!
+//! The payloads in the crate's `fixtures` directory were recorded from real Kobo
+//! store API responses, with identifiers, tokens, and personal details replaced by
+//! placeholders. They keep the field names, nesting, and value formats devices rely
+//! on, so handlers can be developed against realistic data rather than hand-written
+//! JSON.
+
+pub use implementation::Fixture;
+
+mod implementation {
+    use axum::body::{Body, Bytes};
+    use hyper::{Response, header};
+    use serde_json::Value;
+
+    #[cfg(test)]
+    use crate::state::capture_log::{CapturedExchange, CapturedRequest, CapturedResponse};
+
+    /// Content type of every Kobo store API JSON response.
+    const JSON_CONTENT_TYPE: &str = "application/json; charset=utf-8";
+
+    /// A recorded Kobo store API response.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Fixture {
+        /// `GET /v1/initialization`: the resource URLs a device uses for every other
+        /// request.
+        Initialization,
+        /// `GET /v1/library/sync`: a new purchased entitlement and a changed reading
+        /// state.
+        LibrarySync,
+        /// `GET /v1/library/{Ids}/state`: the reading state of a book in progress.
+        ReadingState,
+        /// `GET /v1/user/profile`: the signed-in user's account details.
+        UserProfile,
+    }
+
+    impl Fixture {
+        /// Every fixture in the corpus.
+        pub const ALL: [Self; 4] = [
+            Self::Initialization,
+            Self::LibrarySync,
+            Self::ReadingState,
+            Self::UserProfile,
+        ];
+
+        /// The request path the payload was recorded for.
+        #[must_use]
+        pub fn path(self) -> &'static str {
+            match self {
+                Self::Initialization => "/v1/initialization",
+                Self::LibrarySync => "/v1/library/sync",
+                Self::ReadingState => "/v1/library/00000000-0000-4000-8000-000000000002/state",
+                Self::UserProfile => "/v1/user/profile",
+            }
+        }
+
+        /// The recorded response body.
+        #[must_use]
+        pub fn body(self) -> &'static str {
+            match self {
+                Self::Initialization => include_str!("../fixtures/initialization.json"),
+                Self::LibrarySync => include_str!("../fixtures/library_sync.json"),
+                Self::ReadingState => include_str!("../fixtures/reading_state.json"),
+                Self::UserProfile => include_str!("../fixtures/user_profile.json"),
+            }
+        }
+
+        /// The recorded response body, parsed.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the fixture file is not valid JSON.
+        pub fn json(self) -> serde_json::Result<Value> {
+            serde_json::from_str(self.body())
+        }
+
+        /// The recorded response, as the Kobo store API sends it.
+        #[must_use]
+        pub fn response(self) -> Response<Body> {
+            let mut response =
+                Response::new(Body::from(Bytes::from_static(self.body().as_bytes())));
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static(JSON_CONTENT_TYPE),
+            );
+            response
+        }
+
+        /// An exchange in which a device requested the fixture and received it as the
+        /// Kobo store API sent it, for replaying through the router.
+        #[cfg(test)]
+        pub(crate) fn exchange(self) -> CapturedExchange {
+            let response = CapturedResponse {
+                status: 200,
+                headers: vec![(
+                    header::CONTENT_TYPE.to_string(),
+                    JSON_CONTENT_TYPE.to_owned(),
+                )],
+                body: Bytes::from_static(self.body().as_bytes()),
+            };
+            CapturedExchange {
+                request: CapturedRequest {
+                    method: "GET".to_owned(),
+                    uri: self.path().to_owned(),
+                    headers: vec![("x-kobo-deviceid".to_owned(), "fixture-device".to_owned())],
+                    body: Bytes::new(),
+                },
+                upstream: Some(response.clone()),
+                response,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_fixture_is_valid_json() {
+        for fixture in Fixture::ALL {
+            assert!(fixture.json().is_ok(), "{fixture:?}");
+        }
+    }
+
+    #[test]
+    fn initialization_points_at_the_kobo_store_api() {
+        let json = Fixture::Initialization.json().unwrap();
+
+        assert_eq!(
+            json["Resources"]["library_sync"],
+            "https://storeapi.kobo.com/v1/library/sync"
+        );
+    }
+
+    #[test]
+    fn response_is_json() {
+        let response = Fixture::LibrarySync.response();
+
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/json; charset=utf-8"
+        );
+    }
+}
@@ -0,0 +1,734 @@
+//! Typed Kobo store API payloads for library sync.
+//!
+//! The library sync response is an array of items such as `NewEntitlement` or
+//! `ChangedReadingState`, each holding `PascalCase` objects whose timestamps are UTC
+//! with second precision and whose IDs are UUIDs. The builders here fill in the
+//! fields devices expect, so tools and tests can construct valid payloads without
+//! reverse-engineering field names. The types also deserialize, ignoring fields they
+//! do not model, so upstream responses can be checked against them.
+
+pub use implementation::{
+    BookEntitlement, BookMetadata, Bookmark, BookmarkLocation, DownloadUrl, Entitlement,
+    EntitlementBuilder, KoboId, ReadingState, ReadingStateBuilder, ReadingStatistics,
+    ReadingStatus, ReadingStatusInfo, SyncItem, kobo_timestamp,
+};
+
+mod implementation {
+    use std::{fmt, str::FromStr};
+
+    use anyhow::bail;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize, Serializer};
+
+    /// Timestamp format of the Kobo store API.
+    const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+    /// Formats a timestamp the way the Kobo store API does, e.g.
+    /// `2024-05-01T12:30:00Z`.
+    #[must_use]
+    pub fn kobo_timestamp(timestamp: DateTime<Utc>) -> String {
+        timestamp.format(TIMESTAMP_FORMAT).to_string()
+    }
+
+    fn serialize_timestamp<S: Serializer>(
+        timestamp: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&kobo_timestamp(*timestamp))
+    }
+
+    #[expect(clippy::ref_option, reason = "serde passes fields by reference")]
+    fn serialize_optional_timestamp<S: Serializer>(
+        timestamp: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match timestamp {
+            Some(timestamp) => serialize_timestamp(timestamp, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    /// A UUID in the lowercase hyphenated form used for Kobo IDs.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    #[serde(try_from = "String", into = "String")]
+    pub struct KoboId(String);
+
+    impl KoboId {
+        /// Creates an ID from the 128 bits of a UUID, e.g. to number books in tests.
+        #[must_use]
+        pub fn from_u128(value: u128) -> Self {
+            let hex = format!("{value:032x}");
+            Self(format!(
+                "{}-{}-{}-{}-{}",
+                &hex[0..8],
+                &hex[8..12],
+                &hex[12..16],
+                &hex[16..20],
+                &hex[20..32]
+            ))
+        }
+
+        /// Generates a random (version 4) ID.
+        #[must_use]
+        pub fn random() -> Self {
+            let value = rand::random::<u128>();
+            // Set the version nibble to 4 and the variant bits to 10.
+            Self::from_u128((value & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62))
+        }
+
+        /// Returns the ID as a string.
+        #[must_use]
+        pub fn as_str(&self) -> &str {
+            &self.0
+        }
+    }
+
+    impl FromStr for KoboId {
+        type Err = anyhow::Error;
+
+        fn from_str(value: &str) -> anyhow::Result<Self> {
+            let groups: Vec<&str> = value.split('-').collect();
+            let lengths: Vec<usize> = groups.iter().map(|group| group.len()).collect();
+            if lengths != [8, 4, 4, 4, 12]
+                || !groups
+                    .iter()
+                    .all(|group| group.chars().all(|c| c.is_ascii_hexdigit()))
+            {
+                bail!("Kobo ID '{value}' is not a UUID");
+            }
+            Ok(Self(value.to_ascii_lowercase()))
+        }
+    }
+
+    impl TryFrom<String> for KoboId {
+        type Error = anyhow::Error;
+
+        fn try_from(value: String) -> anyhow::Result<Self> {
+            value.parse()
+        }
+    }
+
+    impl From<KoboId> for String {
+        fn from(id: KoboId) -> Self {
+            id.0
+        }
+    }
+
+    impl fmt::Display for KoboId {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(&self.0)
+        }
+    }
+
+    /// The entitlement of an account to a book.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct BookEntitlement {
+        /// Always `Full` for owned books.
+        pub accessibility: String,
+        /// When the entitlement was created.
+        #[serde(serialize_with = "serialize_timestamp")]
+        pub created: DateTime<Utc>,
+        /// Stays the same across revisions of the book.
+        pub cross_revision_id: KoboId,
+        /// The entitlement ID.
+        pub id: KoboId,
+        /// Whether the book was removed from the library.
+        pub is_removed: bool,
+        /// Whether the book is hidden from the archive.
+        pub is_hidden_from_archive: bool,
+        /// Whether the book is locked.
+        pub is_locked: bool,
+        /// When the entitlement last changed.
+        #[serde(serialize_with = "serialize_timestamp")]
+        pub last_modified: DateTime<Utc>,
+        /// Where the book came from, e.g. `Imported` or `Purchased`.
+        pub origin_category: String,
+        /// Changes whenever the book content changes.
+        pub revision_id: KoboId,
+        /// Always `Active` for books in the library.
+        pub status: String,
+    }
+
+    /// A download location of a book in one format.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct DownloadUrl {
+        /// The format, e.g. `EPUB3` or `KEPUB`.
+        pub format: String,
+        /// The file size in bytes.
+        pub size: u64,
+        /// Where the file is downloaded from.
+        pub url: String,
+        /// Always `Generic`.
+        pub platform: String,
+    }
+
+    /// The metadata of a book.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct BookMetadata {
+        /// Category IDs the book belongs to.
+        pub categories: Vec<KoboId>,
+        /// The ID the cover image is fetched with.
+        pub cover_image_id: KoboId,
+        /// Stays the same across revisions of the book.
+        pub cross_revision_id: KoboId,
+        /// The book description, which may contain HTML.
+        pub description: String,
+        /// Where the book can be downloaded.
+        pub download_urls: Vec<DownloadUrl>,
+        /// The entitlement the metadata belongs to.
+        pub entitlement_id: KoboId,
+        /// The language code, e.g. `en`.
+        pub language: String,
+        /// When the book was published.
+        #[serde(serialize_with = "serialize_optional_timestamp")]
+        pub publication_date: Option<DateTime<Utc>>,
+        /// Changes whenever the book content changes.
+        pub revision_id: KoboId,
+        /// The book title.
+        pub title: String,
+        /// Groups editions of the same work.
+        pub work_id: KoboId,
+        /// The authors and other contributors.
+        pub contributors: Vec<String>,
+    }
+
+    /// How far a book has been read.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum ReadingStatus {
+        /// Not opened yet.
+        #[default]
+        ReadyToRead,
+        /// Opened and not finished.
+        Reading,
+        /// Finished.
+        Finished,
+    }
+
+    /// The reading status of a book.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct ReadingStatusInfo {
+        /// When the status last changed.
+        #[serde(serialize_with = "serialize_timestamp")]
+        pub last_modified: DateTime<Utc>,
+        /// The status.
+        pub status: ReadingStatus,
+        /// How many times the book was started.
+        pub times_started_reading: u32,
+    }
+
+    /// Reading time of a book.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct ReadingStatistics {
+        /// When the statistics last changed.
+        #[serde(serialize_with = "serialize_timestamp")]
+        pub last_modified: DateTime<Utc>,
+        /// Minutes spent reading.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub spent_reading_minutes: Option<u32>,
+        /// Estimated minutes left.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub remaining_time_minutes: Option<u32>,
+    }
+
+    /// A position in a book.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct BookmarkLocation {
+        /// The position, e.g. a CFI or span ID.
+        pub value: String,
+        /// The kind of position, e.g. `KoboSpan`.
+        #[serde(rename = "Type")]
+        pub kind: String,
+        /// The file within the book the position is in.
+        pub source: String,
+    }
+
+    /// The reading position of a book.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct Bookmark {
+        /// When the position last changed.
+        #[serde(serialize_with = "serialize_timestamp")]
+        pub last_modified: DateTime<Utc>,
+        /// Progress through the whole book, from 0 to 100.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub progress_percent: Option<f64>,
+        /// Progress through the current file, from 0 to 100.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub content_source_progress_percent: Option<f64>,
+        /// The exact position.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub location: Option<BookmarkLocation>,
+    }
+
+    /// The reading state of a book.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct ReadingState {
+        /// The entitlement the state belongs to.
+        pub entitlement_id: KoboId,
+        /// When the state was created.
+        #[serde(serialize_with = "serialize_timestamp")]
+        pub created: DateTime<Utc>,
+        /// When the state last changed.
+        #[serde(serialize_with = "serialize_timestamp")]
+        pub last_modified: DateTime<Utc>,
+        /// Used by devices to pick the newer of two states.
+        #[serde(serialize_with = "serialize_timestamp")]
+        pub priority_timestamp: DateTime<Utc>,
+        /// The reading status.
+        pub status_info: ReadingStatusInfo,
+        /// Reading time.
+        pub statistics: ReadingStatistics,
+        /// The reading position.
+        pub current_bookmark: Bookmark,
+    }
+
+    /// A book entitlement with its metadata and, optionally, its reading state.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct Entitlement {
+        /// The entitlement.
+        pub book_entitlement: BookEntitlement,
+        /// The book metadata.
+        pub book_metadata: BookMetadata,
+        /// The reading state, if known.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub reading_state: Option<ReadingState>,
+    }
+
+    /// An item of the library sync response array.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub enum SyncItem {
+        /// A book added to the library.
+        NewEntitlement(Entitlement),
+        /// A book whose entitlement or metadata changed.
+        ChangedEntitlement(Entitlement),
+        /// A book whose reading state changed.
+        ChangedReadingState {
+            /// The new reading state.
+            #[serde(rename = "ReadingState")]
+            reading_state: ReadingState,
+        },
+    }
+
+    /// Builds an [`Entitlement`], defaulting every field devices need.
+    ///
+    /// The revision IDs default to the entitlement ID; give a new
+    /// [`revision_id`](Self::revision_id) when the book content changes so devices
+    /// download it again.
+    #[derive(Clone, Debug)]
+    pub struct EntitlementBuilder {
+        id: KoboId,
+        title: String,
+        revision_id: Option<KoboId>,
+        description: String,
+        language: String,
+        contributors: Vec<String>,
+        download_urls: Vec<DownloadUrl>,
+        publication_date: Option<DateTime<Utc>>,
+        origin_category: String,
+        created: Option<DateTime<Utc>>,
+        last_modified: DateTime<Utc>,
+        is_removed: bool,
+        reading_state: Option<ReadingState>,
+    }
+
+    impl EntitlementBuilder {
+        /// Starts an entitlement for the book `id` titled `title`, last modified now.
+        #[must_use]
+        pub fn new<T: Into<String>>(id: KoboId, title: T) -> Self {
+            Self {
+                id,
+                title: title.into(),
+                revision_id: None,
+                description: String::new(),
+                language: "en".to_owned(),
+                contributors: Vec::new(),
+                download_urls: Vec::new(),
+                publication_date: None,
+                origin_category: "Imported".to_owned(),
+                created: None,
+                last_modified: Utc::now(),
+                is_removed: false,
+                reading_state: None,
+            }
+        }
+
+        /// Sets the revision ID, which changes whenever the book content changes.
+        #[must_use]
+        pub fn revision_id(mut self, revision_id: KoboId) -> Self {
+            self.revision_id = Some(revision_id);
+            self
+        }
+
+        /// Sets the description.
+        #[must_use]
+        pub fn description<T: Into<String>>(mut self, description: T) -> Self {
+            self.description = description.into();
+            self
+        }
+
+        /// Sets the language code. Defaults to `en`.
+        #[must_use]
+        pub fn language<T: Into<String>>(mut self, language: T) -> Self {
+            self.language = language.into();
+            self
+        }
+
+        /// Adds an author or other contributor.
+        #[must_use]
+        pub fn contributor<T: Into<String>>(mut self, name: T) -> Self {
+            self.contributors.push(name.into());
+            self
+        }
+
+        /// Adds a download URL for the book in `format`, e.g. `EPUB3` or `KEPUB`.
+        #[must_use]
+        pub fn download_url<F: Into<String>, U: Into<String>>(
+            mut self,
+            format: F,
+            url: U,
+            size: u64,
+        ) -> Self {
+            self.download_urls.push(DownloadUrl {
+                format: format.into(),
+                size,
+                url: url.into(),
+                platform: "Generic".to_owned(),
+            });
+            self
+        }
+
+        /// Sets the publication date.
+        #[must_use]
+        pub fn publication_date(mut self, date: DateTime<Utc>) -> Self {
+            self.publication_date = Some(date);
+            self
+        }
+
+        /// Sets where the book came from. Defaults to `Imported`.
+        #[must_use]
+        pub fn origin_category<T: Into<String>>(mut self, origin_category: T) -> Self {
+            self.origin_category = origin_category.into();
+            self
+        }
+
+        /// Sets when the entitlement was created. Defaults to the last modification.
+        #[must_use]
+        pub fn created(mut self, created: DateTime<Utc>) -> Self {
+            self.created = Some(created);
+            self
+        }
+
+        /// Sets when the entitlement last changed. Defaults to now.
+        #[must_use]
+        pub fn last_modified(mut self, last_modified: DateTime<Utc>) -> Self {
+            self.last_modified = last_modified;
+            self
+        }
+
+        /// Marks the book as removed from the library.
+        #[must_use]
+        pub fn removed(mut self, is_removed: bool) -> Self {
+            self.is_removed = is_removed;
+            self
+        }
+
+        /// Includes the book's reading state.
+        #[must_use]
+        pub fn reading_state(mut self, reading_state: ReadingState) -> Self {
+            self.reading_state = Some(reading_state);
+            self
+        }
+
+        /// Builds the entitlement.
+        #[must_use]
+        pub fn build(self) -> Entitlement {
+            let revision_id = self.revision_id.unwrap_or_else(|| self.id.clone());
+            Entitlement {
+                book_entitlement: BookEntitlement {
+                    accessibility: "Full".to_owned(),
+                    created: self.created.unwrap_or(self.last_modified),
+                    cross_revision_id: self.id.clone(),
+                    id: self.id.clone(),
+                    is_removed: self.is_removed,
+                    is_hidden_from_archive: false,
+                    is_locked: false,
+                    last_modified: self.last_modified,
+                    origin_category: self.origin_category,
+                    revision_id: revision_id.clone(),
+                    status: "Active".to_owned(),
+                },
+                book_metadata: BookMetadata {
+                    categories: vec![],
+                    cover_image_id: revision_id.clone(),
+                    cross_revision_id: self.id.clone(),
+                    description: self.description,
+                    download_urls: self.download_urls,
+                    entitlement_id: self.id.clone(),
+                    language: self.language,
+                    publication_date: self.publication_date,
+                    revision_id,
+                    title: self.title,
+                    work_id: self.id,
+                    contributors: self.contributors,
+                },
+                reading_state: self.reading_state,
+            }
+        }
+
+        /// Builds a `NewEntitlement` sync item.
+        #[must_use]
+        pub fn build_new(self) -> SyncItem {
+            SyncItem::NewEntitlement(self.build())
+        }
+
+        /// Builds a `ChangedEntitlement` sync item.
+        #[must_use]
+        pub fn build_changed(self) -> SyncItem {
+            SyncItem::ChangedEntitlement(self.build())
+        }
+    }
+
+    /// Builds a [`ReadingState`], with every part last modified at the same time.
+    #[derive(Clone, Debug)]
+    pub struct ReadingStateBuilder {
+        entitlement_id: KoboId,
+        last_modified: DateTime<Utc>,
+        status: ReadingStatus,
+        times_started_reading: u32,
+        spent_reading_minutes: Option<u32>,
+        remaining_time_minutes: Option<u32>,
+        progress_percent: Option<f64>,
+        content_source_progress_percent: Option<f64>,
+        location: Option<BookmarkLocation>,
+    }
+
+    impl ReadingStateBuilder {
+        /// Starts a reading state for the book `entitlement_id`, last modified now.
+        #[must_use]
+        pub fn new(entitlement_id: KoboId) -> Self {
+            Self {
+                entitlement_id,
+                last_modified: Utc::now(),
+                status: ReadingStatus::default(),
+                times_started_reading: 0,
+                spent_reading_minutes: None,
+                remaining_time_minutes: None,
+                progress_percent: None,
+                content_source_progress_percent: None,
+                location: None,
+            }
+        }
+
+        /// Sets when the state last changed. Defaults to now.
+        #[must_use]
+        pub fn last_modified(mut self, last_modified: DateTime<Utc>) -> Self {
+            self.last_modified = last_modified;
+            self
+        }
+
+        /// Sets the reading status, counting a start of reading if the book is
+        /// being read or finished.
+        #[must_use]
+        pub fn status(mut self, status: ReadingStatus) -> Self {
+            self.status = status;
+            if status != ReadingStatus::ReadyToRead {
+                self.times_started_reading = self.times_started_reading.max(1);
+            }
+            self
+        }
+
+        /// Sets the progress through the whole book and the current file, from 0 to
+        /// 100.
+        #[must_use]
+        pub fn progress(mut self, progress_percent: f64, content_source_percent: f64) -> Self {
+            self.progress_percent = Some(progress_percent);
+            self.content_source_progress_percent = Some(content_source_percent);
+            self
+        }
+
+        /// Sets the exact position, e.g. a `KoboSpan` in the file `source`.
+        #[must_use]
+        pub fn location<V: Into<String>, K: Into<String>, S: Into<String>>(
+            mut self,
+            value: V,
+            kind: K,
+            source: S,
+        ) -> Self {
+            self.location = Some(BookmarkLocation {
+                value: value.into(),
+                kind: kind.into(),
+                source: source.into(),
+            });
+            self
+        }
+
+        /// Sets the minutes spent reading and the estimated minutes left.
+        #[must_use]
+        pub fn reading_time(mut self, spent_minutes: u32, remaining_minutes: u32) -> Self {
+            self.spent_reading_minutes = Some(spent_minutes);
+            self.remaining_time_minutes = Some(remaining_minutes);
+            self
+        }
+
+        /// Builds the reading state.
+        #[must_use]
+        pub fn build(self) -> ReadingState {
+            ReadingState {
+                entitlement_id: self.entitlement_id,
+                created: self.last_modified,
+                last_modified: self.last_modified,
+                priority_timestamp: self.last_modified,
+                status_info: ReadingStatusInfo {
+                    last_modified: self.last_modified,
+                    status: self.status,
+                    times_started_reading: self.times_started_reading,
+                },
+                statistics: ReadingStatistics {
+                    last_modified: self.last_modified,
+                    spent_reading_minutes: self.spent_reading_minutes,
+                    remaining_time_minutes: self.remaining_time_minutes,
+                },
+                current_bookmark: Bookmark {
+                    last_modified: self.last_modified,
+                    progress_percent: self.progress_percent,
+                    content_source_progress_percent: self.content_source_progress_percent,
+                    location: self.location,
+                },
+            }
+        }
+
+        /// Builds a `ChangedReadingState` sync item.
+        #[must_use]
+        pub fn build_changed(self) -> SyncItem {
+            SyncItem::ChangedReadingState {
+                reading_state: self.build(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone as _, Utc};
+    use serde_json::json;
+
+    use super::*;
+
+    fn id(value: &str) -> KoboId {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn kobo_id_requires_uuid_format() {
+        assert_eq!(
+            id("0123ABCD-0000-0000-0000-000000000001").as_str(),
+            "0123abcd-0000-0000-0000-000000000001"
+        );
+        assert!("not-a-uuid".parse::<KoboId>().is_err());
+        assert!(
+            "0123abcd-0000-0000-0000-00000000000g"
+                .parse::<KoboId>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn kobo_id_from_number_is_hyphenated_hex() {
+        assert_eq!(
+            KoboId::from_u128(255).as_str(),
+            "00000000-0000-0000-0000-0000000000ff"
+        );
+    }
+
+    #[test]
+    fn random_kobo_ids_are_valid_and_distinct() {
+        let first = KoboId::random();
+
+        assert_eq!(first.as_str().parse::<KoboId>().unwrap(), first);
+        assert_eq!(&first.as_str()[14..15], "4");
+        assert_ne!(first, KoboId::random());
+    }
+
+    #[test]
+    fn new_entitlement_uses_kobo_field_names_and_timestamps() {
+        let book = id("00000000-0000-0000-0000-000000000001");
+        let modified = Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap();
+
+        let item = EntitlementBuilder::new(book.clone(), "Sample")
+            .contributor("A. Author")
+            .download_url("EPUB3", "https://example.com/book.epub", 1024)
+            .last_modified(modified)
+            .build_new();
+
+        let value = serde_json::to_value(item).unwrap();
+        let entitlement = &value["NewEntitlement"];
+        assert_eq!(
+            entitlement["BookEntitlement"]["Id"],
+            "00000000-0000-0000-0000-000000000001"
+        );
+        assert_eq!(
+            entitlement["BookEntitlement"]["LastModified"],
+            "2024-05-01T12:30:00Z"
+        );
+        assert_eq!(
+            entitlement["BookEntitlement"]["RevisionId"],
+            entitlement["BookEntitlement"]["Id"]
+        );
+        assert_eq!(entitlement["BookMetadata"]["Title"], "Sample");
+        assert_eq!(
+            entitlement["BookMetadata"]["DownloadUrls"][0],
+            json!({
+                "Format": "EPUB3",
+                "Size": 1024,
+                "Url": "https://example.com/book.epub",
+                "Platform": "Generic"
+            })
+        );
+        assert!(entitlement.get("ReadingState").is_none());
+    }
+
+    #[test]
+    fn new_revision_changes_revision_ids_only() {
+        let book = id("00000000-0000-0000-0000-000000000001");
+        let revision = id("00000000-0000-0000-0000-000000000002");
+
+        let entitlement = EntitlementBuilder::new(book.clone(), "Sample")
+            .revision_id(revision.clone())
+            .build();
+
+        assert_eq!(entitlement.book_entitlement.id, book);
+        assert_eq!(entitlement.book_entitlement.cross_revision_id, book);
+        assert_eq!(entitlement.book_entitlement.revision_id, revision);
+        assert_eq!(entitlement.book_metadata.revision_id, revision);
+    }
+
+    #[test]
+    fn changed_reading_state_serializes_bookmark() {
+        let book = id("00000000-0000-0000-0000-000000000001");
+        let modified = Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap();
+
+        let item = ReadingStateBuilder::new(book)
+            .last_modified(modified)
+            .status(ReadingStatus::Reading)
+            .progress(42.0, 10.0)
+            .location("kobo.1.1", "KoboSpan", "OEBPS/chapter1.xhtml")
+            .build_changed();
+
+        let value = serde_json::to_value(item).unwrap();
+        let state = &value["ChangedReadingState"]["ReadingState"];
+        assert_eq!(state["StatusInfo"]["Status"], "Reading");
+        assert_eq!(state["StatusInfo"]["TimesStartedReading"], 1);
+        assert_eq!(state["PriorityTimestamp"], "2024-05-01T12:30:00Z");
+        assert_eq!(state["CurrentBookmark"]["ProgressPercent"], 42.0);
+        assert_eq!(state["CurrentBookmark"]["Location"]["Type"], "KoboSpan");
+        assert!(state["Statistics"].get("SpentReadingMinutes").is_none());
+    }
+}
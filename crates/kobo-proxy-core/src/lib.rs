@@ -0,0 +1,36 @@
+//! Routing, rewriting, and sync logic of the Kobo store API proxy.
+//!
+//! [`ServerBuilder`] assembles the proxy from its options and serves it. Requests
+//! forwarded to the Kobo store API can be modified with a [`RequestHook`], and
+//! [`kobo_protocol`] has typed builders for the payloads devices sync.
+
+mod api;
+mod bench;
+#[cfg(any(test, feature = "test-util"))]
+pub mod fixtures;
+pub mod kobo_protocol;
+pub mod listener;
+mod middleware;
+mod replay;
+mod resource_watchdog;
+mod router;
+mod routes;
+mod self_test;
+mod server_implementation;
+mod snapshot_task;
+mod startup_banner;
+mod state;
+mod supervisor;
+mod utils;
+
+pub use bench::{Bench, BenchResult};
+pub use replay::{Replay, ReplayDifference, ReplayReport};
+pub use routes::constants::KOBO_API_URL;
+pub use server_implementation::{Server, ServerBuilder};
+pub use state::client::RequestHook;
+#[cfg(feature = "admin-tls")]
+pub use utils::mutual_tls::MutualTls;
+pub use utils::{
+    address_family::AddressFamily, cookie_policy::CookiePolicy, loopback::is_loopback_url,
+    schema_validation::SchemaValidation,
+};
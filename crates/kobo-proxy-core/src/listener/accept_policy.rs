@@ -0,0 +1,175 @@
+//! Handling of errors from `accept()` on the server listener.
+//!
+//! Accept errors are counted and logged rather than retried silently. Errors for a
+//! single connection are skipped, while errors that affect the whole listener, such
+//! as running out of file descriptors, pause the accept loop with a backoff so a
+//! struggling host is not spun on.
+
+use std::{
+    io,
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use serde::Serialize;
+
+/// `EMFILE`, the process is out of file descriptors.
+const EMFILE: i32 = 24;
+
+/// `ENFILE`, the system is out of file descriptors.
+const ENFILE: i32 = 23;
+
+/// Checks if an accept error only affects the connection being accepted.
+pub(crate) fn is_connection_error(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// Checks if an accept error means the process or system ran out of file descriptors.
+pub(crate) fn is_descriptor_exhaustion(error: &io::Error) -> bool {
+    matches!(error.raw_os_error(), Some(EMFILE | ENFILE))
+}
+
+/// Counters describing the listener's accept loop.
+#[derive(Debug, Default)]
+pub struct AcceptStats {
+    accepted: AtomicU64,
+    connection_errors: AtomicU64,
+    listener_errors: AtomicU64,
+    descriptor_exhaustion: AtomicU64,
+    pauses: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+/// A point-in-time copy of [`AcceptStats`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct AcceptStatsSnapshot {
+    /// Connections accepted.
+    pub accepted: u64,
+    /// Errors that only affected the connection being accepted.
+    pub connection_errors: u64,
+    /// Errors that affected the listener, each followed by a pause.
+    pub listener_errors: u64,
+    /// Listener errors caused by running out of file descriptors.
+    pub descriptor_exhaustion: u64,
+    /// Times the accept loop paused before retrying.
+    pub pauses: u64,
+    /// The most recent accept error.
+    pub last_error: Option<String>,
+}
+
+impl AcceptStats {
+    /// Records an accepted connection.
+    pub(crate) fn record_accept(&self) {
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records an accept error.
+    pub(crate) fn record_error(&self, error: &io::Error) {
+        if is_connection_error(error) {
+            self.connection_errors.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.listener_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if is_descriptor_exhaustion(error) {
+            self.descriptor_exhaustion.fetch_add(1, Ordering::Relaxed);
+        }
+        *self
+            .last_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(error.to_string());
+    }
+
+    /// Records a pause of the accept loop.
+    pub(crate) fn record_pause(&self) {
+        self.pauses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the current counters.
+    pub fn snapshot(&self) -> AcceptStatsSnapshot {
+        AcceptStatsSnapshot {
+            accepted: self.accepted.load(Ordering::Relaxed),
+            connection_errors: self.connection_errors.load(Ordering::Relaxed),
+            listener_errors: self.listener_errors.load(Ordering::Relaxed),
+            descriptor_exhaustion: self.descriptor_exhaustion.load(Ordering::Relaxed),
+            pauses: self.pauses.load(Ordering::Relaxed),
+            last_error: self
+                .last_error
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        }
+    }
+}
+
+/// How long the accept loop pauses after listener errors. The pause doubles with
+/// each consecutive error, up to `max_pause`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AcceptPolicy {
+    /// The pause after the first consecutive listener error.
+    pub pause: Duration,
+    /// The longest pause between retries.
+    pub max_pause: Duration,
+}
+
+impl Default for AcceptPolicy {
+    fn default() -> Self {
+        Self {
+            pause: Duration::from_secs(1),
+            max_pause: Duration::from_secs(30),
+        }
+    }
+}
+
+impl AcceptPolicy {
+    /// Returns the pause after `consecutive_errors` listener errors in a row.
+    #[must_use]
+    pub fn pause_after(&self, consecutive_errors: u32) -> Duration {
+        let doublings = consecutive_errors.saturating_sub(1).min(16);
+        self.pause
+            .saturating_mul(1 << doublings)
+            .min(self.max_pause.max(self.pause))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pause_doubles_up_to_max_pause() {
+        let policy = AcceptPolicy {
+            pause: Duration::from_millis(100),
+            max_pause: Duration::from_millis(500),
+        };
+
+        assert_eq!(policy.pause_after(1), Duration::from_millis(100));
+        assert_eq!(policy.pause_after(2), Duration::from_millis(200));
+        assert_eq!(policy.pause_after(3), Duration::from_millis(400));
+        assert_eq!(policy.pause_after(4), Duration::from_millis(500));
+        assert_eq!(policy.pause_after(u32::MAX), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn stats_classify_errors() {
+        let stats = AcceptStats::default();
+
+        stats.record_accept();
+        stats.record_error(&io::Error::from(io::ErrorKind::ConnectionAborted));
+        stats.record_error(&io::Error::from_raw_os_error(EMFILE));
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.accepted, 1);
+        assert_eq!(snapshot.connection_errors, 1);
+        assert_eq!(snapshot.listener_errors, 1);
+        assert_eq!(snapshot.descriptor_exhaustion, 1);
+        assert!(snapshot.last_error.is_some());
+    }
+}
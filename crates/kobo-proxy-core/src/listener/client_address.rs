@@ -0,0 +1,28 @@
+//! Connection information for the listeners used by the server.
+
+use std::net::SocketAddr;
+
+use axum::{
+    extract::connect_info::Connected,
+    serve::{IncomingStream, Listener},
+};
+
+/// A listener whose connections are identified by a remote socket address.
+pub trait SocketAddrListener: Listener<Addr = SocketAddr> {}
+
+impl SocketAddrListener for tokio::net::TcpListener {}
+
+/// The remote address of the client that opened a connection. Unlike axum's
+/// `SocketAddr` connect info, this works with any [`SocketAddrListener`], including
+/// the fake listener used in tests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientAddress(pub SocketAddr);
+
+impl<L> Connected<IncomingStream<'_, L>> for ClientAddress
+where
+    L: SocketAddrListener,
+{
+    fn connect_info(stream: IncomingStream<'_, L>) -> Self {
+        Self(*stream.remote_addr())
+    }
+}
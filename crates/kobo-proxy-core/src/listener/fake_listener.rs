@@ -11,6 +11,8 @@ use std::net::{Ipv4Addr, SocketAddr};
 use axum::serve::Listener;
 use tokio::io::{AsyncRead, AsyncWrite};
 
+use crate::listener::client_address::SocketAddrListener;
+
 pub struct FakeIo;
 
 impl AsyncRead for FakeIo {
@@ -76,6 +78,8 @@ impl Listener for FakeListener {
     }
 }
 
+impl SocketAddrListener for FakeListener {}
+
 impl FakeListener {
     /// Creates a new `FakeListener` instance.
     pub fn new(port: u16) -> Self {
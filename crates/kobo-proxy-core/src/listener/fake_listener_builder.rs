@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use crate::{
+    listener::{
+        accept_policy::{AcceptPolicy, AcceptStats},
+        fake_listener::FakeListener,
+        into_listener::IntoListener,
+    },
+    utils::tcp_tuning::TcpTuning,
+};
+
+/// Creates a listener that never accepts connections, for testing servers without
+/// binding a port.
+pub struct FakeListenerBuilder;
+
+#[async_trait::async_trait]
+impl IntoListener for FakeListenerBuilder {
+    type Listener = FakeListener;
+
+    async fn into_listener(
+        self,
+        port: u16,
+        _tcp_tuning: TcpTuning,
+        _accept_policy: AcceptPolicy,
+        _accept_stats: Arc<AcceptStats>,
+    ) -> anyhow::Result<Self::Listener> {
+        Ok(FakeListener::new(port))
+    }
+}
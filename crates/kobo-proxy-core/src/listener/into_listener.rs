@@ -0,0 +1,67 @@
+//! Listener abstraction for configurable server listeners.
+
+use std::sync::Arc;
+
+use axum::serve::Listener;
+
+use crate::{
+    listener::{
+        accept_policy::{AcceptPolicy, AcceptStats},
+        client_address::SocketAddrListener,
+        tuned_tcp_listener::TunedTcpListener,
+    },
+    utils::{port_binding::bind_listener, tcp_tuning::TcpTuning},
+};
+
+/// Trait for types that can be converted into a listener for the server.
+/// This allows abstracting over different listener types (TCP, fake, etc.)
+#[async_trait::async_trait]
+pub trait IntoListener {
+    /// The listener created by the builder.
+    type Listener: SocketAddrListener + Send + 'static;
+
+    /// Convert this value into a listener that can accept connections, applying
+    /// `tcp_tuning` to accepted connections and `accept_policy` to accept errors
+    /// where supported. Accept counters are recorded in `accept_stats`.
+    ///
+    /// # Errors
+    /// May return an error if listener creation fails (e.g., port binding fails).
+    async fn into_listener(
+        self,
+        port: u16,
+        tcp_tuning: TcpTuning,
+        accept_policy: AcceptPolicy,
+        accept_stats: Arc<AcceptStats>,
+    ) -> anyhow::Result<Self::Listener>
+    where
+        <Self::Listener as Listener>::Io: Send + Unpin + 'static;
+}
+
+/// Builds plain TCP listeners bound on all interfaces.
+#[derive(Debug, Default)]
+pub struct TokioTcpListener {
+    /// Whether `SO_REUSEPORT` is set, letting another process bind the same port.
+    pub reuse_port: bool,
+}
+
+/// Implementation for `TokioTcpListener` - creates a TCP listener bound to the specified port.
+#[async_trait::async_trait]
+impl IntoListener for TokioTcpListener {
+    type Listener = TunedTcpListener;
+
+    async fn into_listener(
+        self,
+        port: u16,
+        tcp_tuning: TcpTuning,
+        accept_policy: AcceptPolicy,
+        accept_stats: Arc<AcceptStats>,
+    ) -> anyhow::Result<Self::Listener> {
+        let listener = bind_listener(port, self.reuse_port)?;
+        Ok(TunedTcpListener::new(
+            listener,
+            tcp_tuning,
+            accept_policy,
+            accept_stats,
+        ))
+    }
+}
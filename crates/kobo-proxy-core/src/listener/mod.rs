@@ -0,0 +1,20 @@
+//! Listeners the proxy accepts device and admin connections on.
+
+mod accept_policy;
+mod client_address;
+#[cfg(any(test, feature = "test-util"))]
+mod fake_listener;
+#[cfg(any(test, feature = "test-util"))]
+mod fake_listener_builder;
+mod into_listener;
+#[cfg(feature = "admin-tls")]
+mod tls_listener;
+mod tuned_tcp_listener;
+
+pub use accept_policy::{AcceptPolicy, AcceptStats, AcceptStatsSnapshot};
+pub use client_address::{ClientAddress, SocketAddrListener};
+#[cfg(any(test, feature = "test-util"))]
+pub use fake_listener_builder::FakeListenerBuilder;
+pub use into_listener::{IntoListener, TokioTcpListener};
+#[cfg(feature = "admin-tls")]
+pub use tls_listener::TlsListener;
@@ -0,0 +1,119 @@
+//! A TCP listener that performs a TLS handshake on every accepted connection.
+//!
+//! Handshakes run concurrently on a background task, so a client that connects and
+//! never finishes its handshake cannot hold up anyone else's connection.
+
+use std::{net::SocketAddr, time::Duration};
+
+use axum::serve::Listener;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::{JoinHandle, JoinSet},
+};
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+
+use crate::listener::client_address::SocketAddrListener;
+
+/// How long a client may take to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many TLS handshakes may be in progress at once. Further connections wait to
+/// be accepted until a handshake finishes or times out.
+const MAX_PENDING_HANDSHAKES: usize = 64;
+
+/// A connection that completed the TLS handshake.
+type Handshaken = (TlsStream<TcpStream>, SocketAddr);
+
+/// Wraps a [`TcpListener`], only yielding connections that completed the TLS
+/// handshake, including any client certificate verification.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    connections: mpsc::Receiver<Handshaken>,
+    accept_task: JoinHandle<()>,
+}
+
+impl TlsListener {
+    /// Creates a listener that accepts TLS connections on `listener`, spawning the
+    /// task that accepts connections and runs their handshakes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address `listener` is bound to cannot be read.
+    pub fn new(listener: TcpListener, acceptor: TlsAcceptor) -> std::io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (sender, connections) = mpsc::channel(MAX_PENDING_HANDSHAKES);
+        let accept_task = tokio::spawn(accept_connections(listener, acceptor, sender));
+        Ok(Self {
+            local_addr,
+            connections,
+            accept_task,
+        })
+    }
+}
+
+impl Drop for TlsListener {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+    }
+}
+
+/// Accepts TCP connections on `listener` and runs their TLS handshakes, up to
+/// [`MAX_PENDING_HANDSHAKES`] at a time, sending completed connections to `sender`.
+async fn accept_connections(
+    mut listener: TcpListener,
+    acceptor: TlsAcceptor,
+    sender: mpsc::Sender<Handshaken>,
+) {
+    let mut handshakes = JoinSet::new();
+    loop {
+        tokio::select! {
+            (stream, address) = Listener::accept(&mut listener),
+                if handshakes.len() < MAX_PENDING_HANDSHAKES =>
+            {
+                let acceptor = acceptor.clone();
+                handshakes.spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => Some((stream, address)),
+                        Ok(Err(e)) => {
+                            tracing::warn!("TLS handshake with {address} failed: {e}");
+                            None
+                        }
+                        Err(_) => {
+                            tracing::warn!("TLS handshake with {address} timed out");
+                            None
+                        }
+                    }
+                });
+            }
+            Some(handshake) = handshakes.join_next() => {
+                if let Ok(Some(connection)) = handshake
+                    && sender.send(connection).await.is_err()
+                {
+                    return;
+                }
+            }
+            () = sender.closed() => return,
+        }
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // The accept task only stops once the listener is dropped, so no more
+            // connections will arrive.
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+impl SocketAddrListener for TlsListener {}
@@ -0,0 +1,92 @@
+//! A TCP listener that applies socket tuning to every accepted connection.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::serve::Listener;
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::{
+    listener::{
+        accept_policy::{AcceptPolicy, AcceptStats, is_connection_error, is_descriptor_exhaustion},
+        client_address::SocketAddrListener,
+    },
+    utils::tcp_tuning::TcpTuning,
+};
+
+/// Wraps a [`TcpListener`], applying [`TcpTuning`] to accepted streams. Accept errors
+/// are recorded in [`AcceptStats`] and retried according to an [`AcceptPolicy`].
+pub struct TunedTcpListener {
+    listener: TcpListener,
+    tcp_tuning: TcpTuning,
+    accept_policy: AcceptPolicy,
+    accept_stats: Arc<AcceptStats>,
+    consecutive_errors: u32,
+}
+
+impl TunedTcpListener {
+    /// Creates a listener that tunes connections accepted by `listener`.
+    pub fn new(
+        listener: TcpListener,
+        tcp_tuning: TcpTuning,
+        accept_policy: AcceptPolicy,
+        accept_stats: Arc<AcceptStats>,
+    ) -> Self {
+        Self {
+            listener,
+            tcp_tuning,
+            accept_policy,
+            accept_stats,
+            consecutive_errors: 0,
+        }
+    }
+}
+
+impl Listener for TunedTcpListener {
+    type Io = TcpStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            let e = match self.listener.accept().await {
+                Ok((stream, address)) => {
+                    self.consecutive_errors = 0;
+                    self.accept_stats.record_accept();
+                    if let Err(e) = self.tcp_tuning.apply_to_stream(&stream) {
+                        tracing::warn!("Failed to tune connection from {address}: {e}");
+                    }
+                    return (stream, address);
+                }
+                Err(e) => e,
+            };
+            self.accept_stats.record_error(&e);
+            if is_connection_error(&e) {
+                tracing::debug!("Connection failed before it was accepted: {e}");
+                continue;
+            }
+
+            self.consecutive_errors = self.consecutive_errors.saturating_add(1);
+            let pause = self.accept_policy.pause_after(self.consecutive_errors);
+            if is_descriptor_exhaustion(&e) {
+                tracing::error!(
+                    consecutive_errors = self.consecutive_errors,
+                    "Out of file descriptors accepting connections, pausing for {pause:?}: {e}. \
+                     Raise the open file limit (e.g. `ulimit -n` or LimitNOFILE) or reduce \
+                     idle connections"
+                );
+            } else {
+                tracing::error!(
+                    consecutive_errors = self.consecutive_errors,
+                    "Failed to accept connection, pausing for {pause:?}: {e}"
+                );
+            }
+            self.accept_stats.record_pause();
+            tokio::time::sleep(pause).await;
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Listener::local_addr(&self.listener)
+    }
+}
+
+impl SocketAddrListener for TunedTcpListener {}
@@ -0,0 +1,149 @@
+//! Time-based access rule middleware.
+//!
+//! Answers requests blocked by an access rule locally instead of forwarding them. A
+//! blocked library sync gets an empty sync, so the device finishes syncing quietly
+//! rather than reporting an error; other requests get a `403 Forbidden`.
+
+pub use implementation::enforce_access_schedule;
+
+mod implementation {
+    use axum::{
+        Json,
+        extract::{Request, State},
+        middleware::Next,
+        response::{IntoResponse as _, Response},
+    };
+    use chrono::Local;
+    use hyper::StatusCode;
+    use serde_json::json;
+
+    use crate::{
+        routes::constants::API_PREFIX,
+        state::{devices::identify_device, server_state::ServerState},
+    };
+
+    /// Library sync endpoint, answered with an empty sync while blocked.
+    const LIBRARY_SYNC_PATH: &str = "/v1/library/sync";
+
+    /// Answers requests blocked by an access rule with a local response.
+    pub async fn enforce_access_schedule(
+        State(server_state): State<ServerState>,
+        request: Request,
+        next: Next,
+    ) -> Response {
+        let path = request.uri().path();
+        if path.starts_with(API_PREFIX) {
+            return next.run(request).await;
+        }
+        let device_id = identify_device(&request);
+        let Some(until) = server_state.access_schedule.blocked_until(
+            device_id.as_deref(),
+            path,
+            Local::now().time(),
+        ) else {
+            return next.run(request).await;
+        };
+
+        tracing::info!(
+            device_id,
+            path,
+            "Request blocked by an access rule until {}",
+            until.format("%H:%M")
+        );
+        if path == LIBRARY_SYNC_PATH {
+            return Json(json!([])).into_response();
+        }
+        (
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "Message": format!("Not available until {}", until.format("%H:%M")),
+            })),
+        )
+            .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::StatusCode;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{fake_kobo_client::FakeKoboClient, server_state::ServerState},
+        utils::access_schedule::AccessSchedule,
+    };
+
+    fn build_state(stub: &Arc<FakeKoboClient>, rule: &str) -> ServerState {
+        ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .access_schedule(AccessSchedule::new(&[rule]).unwrap())
+            .build()
+    }
+
+    fn build_request(uri: &str, device_id: &str) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .header("x-kobo-deviceid", device_id)
+            .body(Body::empty())
+            .expect("failed to build request")
+    }
+
+    #[tokio::test]
+    async fn blocked_sync_gets_empty_sync_without_forwarding() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let router = create_router(false, false, build_state(&stub, "kids-kobo=00:00-00:00"));
+
+        let response = router
+            .oneshot(build_request("/v1/library/sync", "kids-kobo"))
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"[]");
+        assert!(stub.recorded_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn blocked_store_request_is_forbidden() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let router = create_router(
+            false,
+            false,
+            build_state(&stub, "kids-kobo=00:00-00:00/store"),
+        );
+
+        let response = router
+            .oneshot(build_request("/v1/products/featured", "kids-kobo"))
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(stub.recorded_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn other_devices_are_forwarded() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let router = create_router(false, false, build_state(&stub, "kids-kobo=00:00-00:00"));
+        stub.enqueue_response(
+            axum::http::Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from("[]"))
+                .expect("failed to build stub response"),
+        );
+
+        let response = router
+            .oneshot(build_request("/v1/library/sync", "parent-kobo"))
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(stub.recorded_requests().len(), 1);
+    }
+}
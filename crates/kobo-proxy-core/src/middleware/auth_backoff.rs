@@ -0,0 +1,210 @@
+//! Authentication failure backoff middleware.
+//!
+//! Answers requests from a device that is backed off after repeated `401
+//! Unauthorized` responses with a local `429 Too Many Requests` and a `Retry-After`
+//! header, so a device stuck in an authentication loop stops reaching the Kobo API.
+//! Sign-in requests are always forwarded, so a backed off device can recover, and a
+//! successful sign-in clears the device's backoff.
+
+pub use implementation::enforce_auth_backoff;
+
+mod implementation {
+    use axum::{
+        Json,
+        extract::{Request, State},
+        middleware::Next,
+        response::{IntoResponse as _, Response},
+    };
+    use hyper::{StatusCode, header};
+    use serde_json::json;
+
+    use crate::{
+        routes::constants::API_PREFIX,
+        state::{devices::identify_device, server_state::ServerState},
+    };
+
+    /// Prefix of the Kobo store API sign-in and token refresh routes.
+    const AUTH_PREFIX: &str = "/v1/auth/";
+
+    /// Answers backed off devices locally and records their authentication failures.
+    pub async fn enforce_auth_backoff(
+        State(server_state): State<ServerState>,
+        request: Request,
+        next: Next,
+    ) -> Response {
+        if request.uri().path().starts_with(API_PREFIX) {
+            return next.run(request).await;
+        }
+        let Some(device_id) = identify_device(&request) else {
+            return next.run(request).await;
+        };
+        let auth_backoff = &server_state.auth_backoff;
+
+        if request.uri().path().starts_with(AUTH_PREFIX) {
+            let response = next.run(request).await;
+            if response.status().is_success() {
+                auth_backoff.record_sign_in(&device_id);
+            }
+            return response;
+        }
+
+        let key = auth_backoff.key(&device_id, request.headers());
+        if let Some(retry_after) = auth_backoff.retry_after(&key) {
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            tracing::debug!(device_id, "Request rejected during authentication backoff");
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, seconds.to_string())],
+                Json(json!({
+                    "Message": format!(
+                        "Too many authentication failures, retry in {seconds} seconds"
+                    ),
+                })),
+            )
+                .into_response();
+        }
+
+        let response = next.run(request).await;
+
+        if response.status() == StatusCode::UNAUTHORIZED {
+            if let Some(backoff) = auth_backoff.record_failure(&key) {
+                tracing::warn!(
+                    device_id,
+                    failures = auth_backoff.consecutive_failures(&key),
+                    "Device keeps failing authentication with the Kobo store and is backed off \
+                     for {} seconds. Sign the device out and back in to fix its credentials",
+                    backoff.as_secs()
+                );
+            }
+        } else {
+            auth_backoff.record_success(&key);
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{HeaderMap, Request, Response},
+    };
+    use hyper::{StatusCode, header::AUTHORIZATION};
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{fake_kobo_client::FakeKoboClient, server_state::ServerState},
+    };
+
+    async fn send_request(state: ServerState, path: &str, token: &str) -> Response<Body> {
+        let request = Request::builder()
+            .uri(path)
+            .header("x-kobo-deviceid", "device-1")
+            .header(AUTHORIZATION, token)
+            .body(Body::empty())
+            .expect("failed to build request");
+
+        create_router(false, false, state)
+            .oneshot(request)
+            .await
+            .expect("service should return a response")
+    }
+
+    async fn sync(state: ServerState, token: &str) -> StatusCode {
+        send_request(state, "/v1/library/sync", token)
+            .await
+            .status()
+    }
+
+    fn unauthorized() -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::empty())
+            .expect("failed to build stub response")
+    }
+
+    fn build_state(stub: Arc<FakeKoboClient>) -> ServerState {
+        ServerState::builder("http://frontend.test")
+            .client(stub)
+            .auth_failure_backoff_after(Some(2))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn repeated_auth_failures_are_answered_locally() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = build_state(stub.clone());
+        stub.enqueue_response(unauthorized());
+        stub.enqueue_response(unauthorized());
+
+        assert_eq!(
+            sync(state.clone(), "Bearer token").await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            sync(state.clone(), "Bearer token").await,
+            StatusCode::UNAUTHORIZED
+        );
+        let response = send_request(state, "/v1/library/sync", "Bearer token").await;
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "30");
+        assert_eq!(stub.recorded_requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn successful_request_resets_failures() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = build_state(stub.clone());
+        stub.enqueue_response(unauthorized());
+        stub.enqueue_response(Response::new(Body::empty()));
+
+        sync(state.clone(), "Bearer token").await;
+        sync(state.clone(), "Bearer token").await;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer token".parse().unwrap());
+        let key = state.auth_backoff.key("device-1", &headers);
+        assert_eq!(state.auth_backoff.consecutive_failures(&key), 0);
+    }
+
+    #[tokio::test]
+    async fn failures_with_other_credentials_do_not_block_the_device() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = build_state(stub.clone());
+        stub.enqueue_response(unauthorized());
+        stub.enqueue_response(unauthorized());
+        stub.enqueue_response(Response::new(Body::empty()));
+
+        sync(state.clone(), "Bearer spoofed").await;
+        sync(state.clone(), "Bearer spoofed").await;
+
+        assert_eq!(
+            sync(state.clone(), "Bearer spoofed").await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(sync(state, "Bearer token").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn sign_in_is_forwarded_and_clears_the_backoff() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = build_state(stub.clone());
+        stub.enqueue_response(unauthorized());
+        stub.enqueue_response(unauthorized());
+        stub.enqueue_response(Response::new(Body::empty()));
+        stub.enqueue_response(Response::new(Body::empty()));
+
+        sync(state.clone(), "Bearer token").await;
+        sync(state.clone(), "Bearer token").await;
+        let sign_in = send_request(state.clone(), "/v1/auth/refresh", "Bearer token").await;
+
+        assert_eq!(sign_in.status(), StatusCode::OK);
+        assert_eq!(sync(state, "Bearer token").await, StatusCode::OK);
+        assert_eq!(stub.recorded_requests().len(), 4);
+    }
+}
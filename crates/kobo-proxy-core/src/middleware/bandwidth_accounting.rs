@@ -0,0 +1,215 @@
+//! Bandwidth accounting middleware.
+//!
+//! Counts the body bytes of each device request and of the response sent back, as
+//! they are streamed, and adds them to the device's monthly usage once each body
+//! is finished or dropped. Bodies are counted as sent over the connection, so
+//! compressed responses count their compressed size.
+
+pub use implementation::account_bandwidth;
+
+mod implementation {
+    use std::{
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Poll},
+    };
+
+    use axum::{
+        body::{Body, Bytes},
+        extract::{Request, State},
+        middleware::Next,
+        response::Response,
+    };
+    use hyper::body::{Frame, SizeHint};
+
+    use crate::{
+        routes::constants::API_PREFIX,
+        state::{
+            bandwidth::{BandwidthUsage, Traffic},
+            devices::identify_device,
+            server_state::ServerState,
+        },
+    };
+
+    /// Which way a counted body travels.
+    #[derive(Clone, Copy, Debug)]
+    enum Direction {
+        FromDevice,
+        ToDevice,
+    }
+
+    /// A body that adds the bytes streamed through it to a device's usage once it
+    /// is finished or dropped.
+    struct CountingBody {
+        inner: Body,
+        usage: Arc<BandwidthUsage>,
+        device_id: String,
+        route: String,
+        direction: Direction,
+        bytes: u64,
+    }
+
+    impl CountingBody {
+        fn wrap(
+            inner: Body,
+            usage: &Arc<BandwidthUsage>,
+            device_id: &str,
+            route: &str,
+            direction: Direction,
+        ) -> Body {
+            Body::new(Self {
+                inner,
+                usage: Arc::clone(usage),
+                device_id: device_id.to_owned(),
+                route: route.to_owned(),
+                direction,
+                bytes: 0,
+            })
+        }
+    }
+
+    impl hyper::body::Body for CountingBody {
+        type Data = Bytes;
+        type Error = axum::Error;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+            let frame = Pin::new(&mut self.inner).poll_frame(cx);
+            if let Poll::Ready(Some(Ok(data))) = &frame
+                && let Some(data) = data.data_ref()
+            {
+                self.bytes = self.bytes.saturating_add(data.len() as u64);
+            }
+            frame
+        }
+
+        fn is_end_stream(&self) -> bool {
+            self.inner.is_end_stream()
+        }
+
+        fn size_hint(&self) -> SizeHint {
+            self.inner.size_hint()
+        }
+    }
+
+    impl Drop for CountingBody {
+        fn drop(&mut self) {
+            if self.bytes == 0 {
+                return;
+            }
+            let traffic = match self.direction {
+                Direction::FromDevice => Traffic {
+                    bytes_in: self.bytes,
+                    ..Traffic::default()
+                },
+                Direction::ToDevice => Traffic {
+                    bytes_out: self.bytes,
+                    ..Traffic::default()
+                },
+            };
+            self.usage.record(&self.device_id, &self.route, traffic);
+        }
+    }
+
+    /// Counts the bytes exchanged with each device, by route template.
+    pub async fn account_bandwidth(
+        State(server_state): State<ServerState>,
+        request: Request,
+        next: Next,
+    ) -> Response {
+        if request.uri().path().starts_with(API_PREFIX) {
+            return next.run(request).await;
+        }
+        let Some(device_id) = identify_device(&request) else {
+            return next.run(request).await;
+        };
+        let usage = &server_state.bandwidth;
+        let route = server_state
+            .route_templates
+            .normalize(request.uri().path())
+            .into_owned();
+
+        let request = request
+            .map(|body| CountingBody::wrap(body, usage, &device_id, &route, Direction::FromDevice));
+        let response = next.run(request).await;
+        usage.record(
+            &device_id,
+            &route,
+            Traffic {
+                requests: 1,
+                ..Traffic::default()
+            },
+        );
+
+        response
+            .map(|body| CountingBody::wrap(body, usage, &device_id, &route, Direction::ToDevice))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{Request, Response},
+    };
+    use http_body_util::BodyExt as _;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{
+            bandwidth::BandwidthUsage, fake_kobo_client::FakeKoboClient, server_state::ServerState,
+        },
+    };
+
+    #[tokio::test]
+    async fn request_and_response_bytes_are_counted_per_route() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+        stub.enqueue_response(Response::new(Body::from("0123456789")));
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/analytics/event")
+            .header("x-kobo-deviceid", "device-1")
+            .body(Body::from("abcd"))
+            .expect("failed to build request");
+
+        let response = create_router(false, false, state.clone())
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+        response.into_body().collect().await.unwrap();
+
+        let report = state.bandwidth.report(&BandwidthUsage::current_month());
+        let device = &report.devices[0];
+        assert_eq!(device.device_id, "device-1");
+        assert_eq!(device.routes[0].route, "/v1/analytics/event");
+        assert_eq!(device.traffic.requests, 1);
+        assert_eq!(device.traffic.bytes_in, 4);
+        assert_eq!(device.traffic.bytes_out, 10);
+    }
+
+    #[tokio::test]
+    async fn local_api_requests_are_not_counted() {
+        let state = ServerState::builder("http://frontend.test").build();
+        let request = Request::builder()
+            .uri("/api/status")
+            .header("x-kobo-deviceid", "device-1")
+            .body(Body::empty())
+            .expect("failed to build request");
+
+        create_router(false, false, state.clone())
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        let report = state.bandwidth.report(&BandwidthUsage::current_month());
+        assert!(report.devices.is_empty());
+    }
+}
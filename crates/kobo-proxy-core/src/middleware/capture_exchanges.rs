@@ -0,0 +1,127 @@
+//! Capture middleware recording device exchanges for the `replay-sync` subcommand.
+//!
+//! Requests and responses are buffered so they can be written to the capture file
+//! in full, which delays streamed responses while capture is enabled. Local API
+//! requests and upgraded connections are not recorded.
+
+pub use implementation::capture_exchanges;
+
+mod implementation {
+    use axum::{
+        body::Body,
+        extract::{Request, State},
+        middleware::Next,
+        response::{IntoResponse as _, Response},
+    };
+
+    use crate::{
+        routes::constants::API_PREFIX,
+        state::{
+            capture_log::{CapturedExchange, CapturedRequest, CapturedResponse, UpstreamCapture},
+            server_state::ServerState,
+        },
+        utils::{
+            http_body::buffer_body, protected_content::is_protected_content,
+            upgrade::is_upgrade_request,
+        },
+    };
+
+    /// Records the request, the upstream response, and the response to the capture
+    /// file.
+    pub async fn capture_exchanges(
+        State(server_state): State<ServerState>,
+        request: Request,
+        next: Next,
+    ) -> Response {
+        let Some(capture_log) = &server_state.capture_log else {
+            return next.run(request).await;
+        };
+        if request.uri().path().starts_with(API_PREFIX) || is_upgrade_request(request.headers()) {
+            return next.run(request).await;
+        }
+
+        let (mut parts, body) = request.into_parts();
+        let body = match buffer_body(body).await {
+            Ok(body) => body,
+            Err(error) => return error.into_response(),
+        };
+        let captured_request = CapturedRequest::new(&parts, body.clone());
+        let upstream = UpstreamCapture::default();
+        parts.extensions.insert(upstream.clone());
+
+        let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+        if is_protected_content(response.headers()) {
+            return response;
+        }
+        let (parts, body) = response.into_parts();
+        let body = match buffer_body(body).await {
+            Ok(body) => body,
+            Err(error) => return error.into_response(),
+        };
+        capture_log.record(&CapturedExchange {
+            request: captured_request,
+            upstream: upstream.take(),
+            response: CapturedResponse::new(&parts, body.clone()),
+        });
+
+        Response::from_parts(parts, Body::from(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request};
+    use hyper::{Response, StatusCode};
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{
+            capture_log::{CaptureLog, read_capture_file},
+            fake_kobo_client::FakeKoboClient,
+            server_state::ServerState,
+        },
+    };
+
+    #[tokio::test]
+    async fn records_request_upstream_response_and_device_response() {
+        let path = std::env::temp_dir().join(format!(
+            "kobo-capture-{}-middleware.jsonl",
+            std::process::id()
+        ));
+        let stub = Arc::new(FakeKoboClient::new());
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Body::from("upstream body"))
+                .unwrap(),
+        );
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub)
+            .capture_log(Some(CaptureLog::open(&path).unwrap()))
+            .build();
+        let router = create_router(false, false, state);
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/v1/library/tags")
+                    .body(Body::from("request body"))
+                    .unwrap(),
+            )
+            .await
+            .expect("service should return a response");
+        let exchanges = read_capture_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(exchanges.len(), 1);
+        assert_eq!(exchanges[0].request.uri, "/v1/library/tags");
+        assert_eq!(&exchanges[0].request.body[..], b"request body");
+        let upstream = exchanges[0].upstream.as_ref().unwrap();
+        assert_eq!(&upstream.body[..], b"upstream body");
+        assert_eq!(exchanges[0].response.status, 201);
+    }
+}
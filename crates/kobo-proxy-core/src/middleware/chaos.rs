@@ -0,0 +1,145 @@
+//! Chaos testing middleware.
+//!
+//! Injects the faults picked by the configured chaos rules: delays, aborted
+//! connections, and server errors. Only enabled with `--enable-chaos-mode`.
+
+pub use implementation::inject_chaos;
+
+mod implementation {
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use axum::{
+        body::{Body, Bytes},
+        extract::{Request, State},
+        middleware::Next,
+        response::{IntoResponse as _, Response},
+    };
+    use hyper::body::{Body as HttpBody, Frame};
+
+    use crate::{
+        routes::constants::API_PREFIX, state::server_state::ServerState, utils::chaos::ChaosFault,
+    };
+
+    /// A body that fails as soon as it is read, aborting the connection.
+    struct DroppedBody;
+
+    impl HttpBody for DroppedBody {
+        type Data = Bytes;
+        type Error = io::Error;
+
+        fn poll_frame(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+            Poll::Ready(Some(Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "connection dropped by chaos mode",
+            ))))
+        }
+    }
+
+    /// Injects the faults picked by the chaos rules for the request's route.
+    pub async fn inject_chaos(
+        State(server_state): State<ServerState>,
+        request: Request,
+        next: Next,
+    ) -> Response {
+        let path = request.uri().path();
+        if path.starts_with(API_PREFIX) {
+            return next.run(request).await;
+        }
+        let route = server_state.route_templates.normalize(path).into_owned();
+        let faults = server_state
+            .chaos_rules
+            .faults_for(&route, &mut rand::rng());
+
+        for fault in faults {
+            tracing::warn!(route, "Chaos mode injected {fault:?}");
+            match fault {
+                ChaosFault::Delay(delay) => tokio::time::sleep(delay).await,
+                ChaosFault::Drop => return Response::new(Body::new(DroppedBody)),
+                ChaosFault::Status(status) => return status.into_response(),
+            }
+        }
+        next.run(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::StatusCode;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{fake_kobo_client::FakeKoboClient, server_state::ServerState},
+        utils::chaos::ChaosRules,
+    };
+
+    fn build_state(stub: &Arc<FakeKoboClient>, rule: &str) -> ServerState {
+        ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .chaos_rules(ChaosRules::new(&[rule]).unwrap())
+            .build()
+    }
+
+    fn build_request(uri: &str) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .body(Body::empty())
+            .expect("failed to build request")
+    }
+
+    #[tokio::test]
+    async fn status_fault_replaces_response() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let router = create_router(
+            false,
+            false,
+            build_state(&stub, "/v1/library/sync=1:status=503"),
+        );
+
+        let response = router
+            .oneshot(build_request("/v1/library/sync"))
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(stub.recorded_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn drop_fault_aborts_response_body() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let router = create_router(false, false, build_state(&stub, "*=1:drop"));
+
+        let response = router
+            .oneshot(build_request("/v1/library/sync"))
+            .await
+            .expect("service should return a response");
+
+        assert!(response.into_body().collect().await.is_err());
+        assert!(stub.recorded_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn local_api_is_not_affected() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let router = create_router(false, false, build_state(&stub, "*=1:status=500"));
+
+        let response = router
+            .oneshot(build_request("/api/devices"))
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
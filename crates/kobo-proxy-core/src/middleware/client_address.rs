@@ -0,0 +1,94 @@
+//! Client address resolution middleware.
+//!
+//! Replaces the connection's client address with the one forwarded by a trusted
+//! reverse proxy, so device identification, rate limits and logs see the real
+//! client. Only enabled when trusted proxies are configured.
+
+pub use implementation::resolve_client_address;
+
+mod implementation {
+    use std::net::SocketAddr;
+
+    use axum::{
+        extract::{ConnectInfo, Request, State},
+        middleware::Next,
+        response::Response,
+    };
+
+    use crate::{listener::ClientAddress, state::server_state::ServerState};
+
+    /// Resolves the client address of a request forwarded by a trusted proxy.
+    pub async fn resolve_client_address(
+        State(server_state): State<ServerState>,
+        mut request: Request,
+        next: Next,
+    ) -> Response {
+        if let Some(ConnectInfo(ClientAddress(peer))) = request
+            .extensions()
+            .get::<ConnectInfo<ClientAddress>>()
+            .copied()
+        {
+            let client_ip = server_state
+                .trusted_proxies
+                .client_ip(peer.ip(), request.headers());
+            if client_ip != peer.ip() {
+                tracing::debug!("Resolved client {client_ip} forwarded by proxy {peer}");
+                request
+                    .extensions_mut()
+                    .insert(ConnectInfo(ClientAddress(SocketAddr::new(client_ip, 0))));
+            }
+        }
+        next.run(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use axum::{
+        body::Body,
+        extract::ConnectInfo,
+        http::{Request, Response},
+    };
+    use tower::ServiceExt as _;
+
+    use crate::{
+        listener::ClientAddress,
+        router::create_router,
+        state::{fake_kobo_client::FakeKoboClient, server_state::ServerState},
+        utils::trusted_proxies::TrustedProxies,
+    };
+
+    async fn device_seen_for(trusted_proxies: &[&str]) -> String {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .trusted_proxies(TrustedProxies::new(trusted_proxies).unwrap())
+            .build();
+        stub.enqueue_response(Response::new(Body::empty()));
+        let router = create_router(false, false, state.clone());
+
+        let mut request = Request::builder()
+            .uri("/v1/library/sync")
+            .header("x-forwarded-for", "198.51.100.1")
+            .body(Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(ConnectInfo(ClientAddress(
+            "10.0.0.2:4000".parse::<SocketAddr>().unwrap(),
+        )));
+        router.oneshot(request).await.unwrap();
+
+        state.devices.devices()[0].id.clone()
+    }
+
+    #[tokio::test]
+    async fn trusted_proxy_forwards_client_address() {
+        assert_eq!(device_seen_for(&["10.0.0.0/8"]).await, "198.51.100.1");
+    }
+
+    #[tokio::test]
+    async fn untrusted_peer_cannot_spoof_client_address() {
+        assert_eq!(device_seen_for(&["192.168.0.0/16"]).await, "10.0.0.2");
+    }
+}
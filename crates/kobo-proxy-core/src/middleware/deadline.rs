@@ -0,0 +1,133 @@
+//! Request deadline middleware.
+//!
+//! Gives each request for a route with a configured timeout a deadline, attached as
+//! a request extension so the upstream request can carry the remaining budget. When
+//! the deadline passes, handling is aborted, including any body buffering, and a
+//! `504 Gateway Timeout` with a structured reason is returned.
+
+pub use implementation::enforce_deadlines;
+
+mod implementation {
+    use axum::{
+        Json,
+        extract::{Request, State},
+        middleware::Next,
+        response::{IntoResponse as _, Response},
+    };
+    use hyper::StatusCode;
+    use serde_json::json;
+    use tokio::time::Instant;
+
+    use crate::{
+        routes::constants::API_PREFIX, state::server_state::ServerState,
+        utils::route_timeouts::Deadline,
+    };
+
+    /// Aborts requests that outlive their route's timeout.
+    pub async fn enforce_deadlines(
+        State(server_state): State<ServerState>,
+        mut request: Request,
+        next: Next,
+    ) -> Response {
+        let path = request.uri().path();
+        if path.starts_with(API_PREFIX) {
+            return next.run(request).await;
+        }
+        let route = server_state.route_templates.normalize(path).into_owned();
+        let Some(timeout) = server_state.route_timeouts.timeout_for(&route) else {
+            return next.run(request).await;
+        };
+        let deadline = Instant::now() + timeout;
+        request.extensions_mut().insert(Deadline(deadline));
+
+        if let Ok(response) = tokio::time::timeout_at(deadline, next.run(request)).await {
+            return response;
+        }
+        let timeout_ms = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+        tracing::warn!(route, timeout_ms, "Request exceeded its deadline");
+        (
+            StatusCode::GATEWAY_TIMEOUT,
+            Json(json!({
+                "Message": format!("Request exceeded its {timeout_ms} ms deadline"),
+                "Reason": "DeadlineExceeded",
+                "Route": route,
+                "TimeoutMs": timeout_ms,
+            })),
+        )
+            .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::{Response, StatusCode};
+    use serde_json::json;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{fake_kobo_client::FakeKoboClient, server_state::ServerState},
+        utils::{
+            chaos::ChaosRules,
+            route_timeouts::{BUDGET_HEADER, RouteTimeouts},
+        },
+    };
+
+    fn sync_request() -> Request<Body> {
+        Request::builder()
+            .uri("/v1/library/sync")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn forwards_remaining_budget_upstream() {
+        let stub = Arc::new(FakeKoboClient::new());
+        stub.enqueue_response(Response::new(Body::from("[]")));
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .route_timeouts(RouteTimeouts::new(&["/v1/library/sync=30000"]).unwrap())
+            .build();
+
+        let response = create_router(false, false, state)
+            .oneshot(sync_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let recorded = stub.recorded_requests();
+        let budget: u64 = recorded[0].headers[BUDGET_HEADER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((29_000..=30_000).contains(&budget), "{budget}");
+    }
+
+    #[tokio::test]
+    async fn returns_gateway_timeout_when_deadline_expires() {
+        let stub = Arc::new(FakeKoboClient::new());
+        stub.enqueue_response(Response::new(Body::from("[]")));
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub)
+            .route_timeouts(RouteTimeouts::new(&["*=50"]).unwrap())
+            .chaos_rules(ChaosRules::new(&["*=1:delay=5000"]).unwrap())
+            .build();
+
+        let response = create_router(false, false, state)
+            .oneshot(sync_request())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["Reason"], json!("DeadlineExceeded"));
+        assert_eq!(body["Route"], json!("/v1/library/sync"));
+        assert_eq!(body["TimeoutMs"], json!(50));
+    }
+}
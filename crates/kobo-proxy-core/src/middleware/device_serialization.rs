@@ -0,0 +1,83 @@
+//! Per-device request serialization middleware.
+//!
+//! Kobo devices occasionally fire overlapping library requests that race on upstream
+//! state. When enabled, library requests from the same device are queued and forwarded
+//! one at a time.
+
+pub use implementation::serialize_device_requests;
+
+mod implementation {
+    use axum::{
+        extract::{Request, State},
+        middleware::Next,
+        response::Response,
+    };
+
+    use crate::state::{devices::identify_device, server_state::ServerState};
+
+    /// Prefix of the library endpoints (sync, reading state, tags) that are serialized.
+    const SERIALIZED_PATH_PREFIX: &str = "/v1/library";
+
+    /// Queues library requests so only one per device is processed at a time.
+    pub async fn serialize_device_requests(
+        State(server_state): State<ServerState>,
+        request: Request,
+        next: Next,
+    ) -> Response {
+        if !request.uri().path().starts_with(SERIALIZED_PATH_PREFIX) {
+            return next.run(request).await;
+        }
+        let Some(device_id) = identify_device(&request) else {
+            return next.run(request).await;
+        };
+
+        server_state
+            .device_locks
+            .run_serialized(&device_id, next.run(request))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{Request, Response, StatusCode},
+    };
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{fake_kobo_client::FakeKoboClient, server_state::ServerState},
+    };
+
+    #[tokio::test]
+    async fn serialized_requests_are_forwarded() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .serialize_device_requests(true)
+            .build();
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::empty())
+                .expect("failed to build stub response"),
+        );
+
+        let request = Request::builder()
+            .uri("/v1/library/sync")
+            .header("x-kobo-deviceid", "device-1")
+            .body(Body::empty())
+            .expect("failed to build request");
+        let response = create_router(false, false, state.clone())
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(state.device_locks.active_devices(), 0);
+    }
+}
@@ -0,0 +1,178 @@
+//! Device tracking middleware.
+//!
+//! Records every device that makes a request through the server and compares the
+//! `Date` header sent by the device with the `Date` header of the response (or the
+//! local clock when the response has none). Devices with significant clock skew
+//! commonly fail TLS validation and authentication, so skew above the configured
+//! threshold is logged as a warning.
+
+pub use implementation::track_devices;
+
+mod implementation {
+    use axum::{
+        extract::{Request, State},
+        middleware::Next,
+        response::Response,
+    };
+    use chrono::{DateTime, Utc};
+    use hyper::{HeaderMap, header};
+
+    use crate::{
+        routes::constants::API_PREFIX,
+        state::{devices::identify_device, server_state::ServerState},
+    };
+
+    /// Parses the `Date` header of a request or response.
+    pub(super) fn parse_date_header(headers: &HeaderMap) -> Option<DateTime<Utc>> {
+        let value = headers.get(header::DATE)?.to_str().ok()?;
+        DateTime::parse_from_rfc2822(value)
+            .ok()
+            .map(|date| date.with_timezone(&Utc))
+    }
+
+    /// Records the requesting device and measures its clock skew.
+    pub async fn track_devices(
+        State(server_state): State<ServerState>,
+        request: Request,
+        next: Next,
+    ) -> Response {
+        if request.uri().path().starts_with(API_PREFIX) {
+            return next.run(request).await;
+        }
+        let Some(device_id) = identify_device(&request) else {
+            return next.run(request).await;
+        };
+
+        let user_agent = request
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok());
+        server_state.devices.record_request(&device_id, user_agent);
+        let device_date = parse_date_header(request.headers());
+
+        let response = next.run(request).await;
+
+        if let Some(device_date) = device_date {
+            let reference_date = parse_date_header(response.headers()).unwrap_or_else(Utc::now);
+            let skew_seconds = (device_date - reference_date).num_seconds();
+            server_state
+                .devices
+                .record_clock_skew(&device_id, skew_seconds);
+
+            let threshold = server_state.clock_skew_warning_seconds;
+            if threshold > 0 && skew_seconds.unsigned_abs() >= threshold {
+                tracing::warn!(
+                    device_id,
+                    skew_seconds,
+                    "Device clock differs from the server by {skew_seconds} seconds. Set the \
+                     device's date and time correctly, otherwise TLS and authentication may fail"
+                );
+            }
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{Request, Response},
+    };
+    use hyper::HeaderMap;
+    use tower::ServiceExt as _;
+    use tracing_test::traced_test;
+
+    use super::implementation::parse_date_header;
+    use crate::{
+        router::create_router,
+        state::{fake_kobo_client::FakeKoboClient, server_state::ServerState},
+    };
+
+    const UPSTREAM_DATE: &str = "Sun, 18 Oct 2026 12:00:00 GMT";
+
+    async fn send_request(device_date: &str, state: ServerState, stub: &FakeKoboClient) {
+        stub.enqueue_response(
+            Response::builder()
+                .header("date", UPSTREAM_DATE)
+                .body(Body::empty())
+                .expect("failed to build stub response"),
+        );
+        let request = Request::builder()
+            .uri("/v1/library/sync")
+            .header("x-kobo-deviceid", "device-1")
+            .header("date", device_date)
+            .body(Body::empty())
+            .expect("failed to build request");
+
+        let _response = create_router(false, false, state)
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+    }
+
+    #[test]
+    fn parse_date_header_parses_http_dates() {
+        let mut headers = HeaderMap::new();
+        headers.insert("date", UPSTREAM_DATE.parse().unwrap());
+
+        let date = parse_date_header(&headers).unwrap();
+
+        assert_eq!(date.to_rfc3339(), "2026-10-18T12:00:00+00:00");
+    }
+
+    #[test]
+    fn parse_date_header_returns_none_for_invalid_dates() {
+        let mut headers = HeaderMap::new();
+        headers.insert("date", "yesterday".parse().unwrap());
+
+        assert!(parse_date_header(&headers).is_none());
+    }
+
+    #[tokio::test]
+    async fn records_device_and_clock_skew() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+
+        send_request("Sun, 18 Oct 2026 11:50:00 GMT", state.clone(), &stub).await;
+
+        let devices = state.devices.devices();
+        assert_eq!(devices[0].id, "device-1");
+        assert_eq!(devices[0].clock_skew_seconds, Some(-600));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn warns_about_clock_skew_above_threshold() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .clock_skew_warning_seconds(300)
+            .build();
+
+        send_request("Sun, 18 Oct 2026 11:50:00 GMT", state, &stub).await;
+
+        assert!(logs_contain(
+            "Device clock differs from the server by -600 seconds"
+        ));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn does_not_warn_about_clock_skew_below_threshold() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .clock_skew_warning_seconds(300)
+            .build();
+
+        send_request("Sun, 18 Oct 2026 11:59:00 GMT", state, &stub).await;
+
+        assert!(!logs_contain("Device clock differs"));
+    }
+}
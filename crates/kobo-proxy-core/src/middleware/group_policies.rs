@@ -0,0 +1,171 @@
+//! Device group policy middleware.
+//!
+//! Resolves the group of the requesting device and applies its policies: blocked
+//! endpoints are answered locally like requests blocked by an access rule, devices
+//! over their rate limit get a `429 Too Many Requests`, and the group's logging
+//! level is passed on to the logging middleware as a request extension.
+
+pub use implementation::enforce_group_policies;
+
+mod implementation {
+    use axum::{
+        Json,
+        extract::{Request, State},
+        middleware::Next,
+        response::{IntoResponse as _, Response},
+    };
+    use hyper::{StatusCode, header};
+    use serde_json::json;
+
+    use crate::{
+        routes::constants::API_PREFIX,
+        state::{devices::identify_device, server_state::ServerState},
+    };
+
+    /// Library sync endpoint, answered with an empty sync while blocked.
+    const LIBRARY_SYNC_PATH: &str = "/v1/library/sync";
+
+    /// Applies the policies of the requesting device's group.
+    pub async fn enforce_group_policies(
+        State(server_state): State<ServerState>,
+        mut request: Request,
+        next: Next,
+    ) -> Response {
+        let path = request.uri().path();
+        if path.starts_with(API_PREFIX) {
+            return next.run(request).await;
+        }
+        let Some(device_id) = identify_device(&request) else {
+            return next.run(request).await;
+        };
+        let Some(policy) = server_state.device_groups.policy_for(&device_id) else {
+            return next.run(request).await;
+        };
+
+        if policy.blocks(path) {
+            tracing::info!(
+                device_id,
+                path,
+                group = policy.name,
+                "Request blocked by group policy"
+            );
+            if path == LIBRARY_SYNC_PATH {
+                return Json(json!([])).into_response();
+            }
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({ "Message": "Not available for this device" })),
+            )
+                .into_response();
+        }
+        if let Err(retry_after) = policy.check_rate_limit(&device_id) {
+            tracing::warn!(
+                device_id,
+                path,
+                group = policy.name,
+                "Device rate limit exceeded, retry after {}ms",
+                retry_after.as_millis()
+            );
+            // Round up so devices never retry before the budget has refilled.
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, seconds.to_string())],
+            )
+                .into_response();
+        }
+
+        request.extensions_mut().insert(policy.logging);
+        next.run(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::{StatusCode, header};
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{
+            device_groups::DeviceGroups, fake_kobo_client::FakeKoboClient,
+            server_state::ServerState,
+        },
+    };
+
+    fn build_state(stub: &Arc<FakeKoboClient>, policy: &str) -> ServerState {
+        ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .device_groups(DeviceGroups::new(&["kids-kobo=kids"], &[policy]).unwrap())
+            .build()
+    }
+
+    fn build_request(uri: &str, device_id: &str) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .header("x-kobo-deviceid", device_id)
+            .body(Body::empty())
+            .expect("failed to build request")
+    }
+
+    #[tokio::test]
+    async fn blocked_sync_gets_empty_sync_without_forwarding() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let router = create_router(false, false, build_state(&stub, "kids=block:/v1/library"));
+
+        let response = router
+            .oneshot(build_request("/v1/library/sync", "kids-kobo"))
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"[]");
+        assert!(stub.recorded_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn blocked_endpoint_is_forbidden_only_for_the_group() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let router = create_router(false, false, build_state(&stub, "kids=block:/v1/products"));
+
+        let blocked = router
+            .clone()
+            .oneshot(build_request("/v1/products/featured", "kids-kobo"))
+            .await
+            .expect("service should return a response");
+        router
+            .oneshot(build_request("/v1/products/featured", "adult-kobo"))
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(blocked.status(), StatusCode::FORBIDDEN);
+        let requests = stub.recorded_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].headers["x-kobo-deviceid"], "adult-kobo");
+    }
+
+    #[tokio::test]
+    async fn device_over_rate_limit_is_told_to_retry() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let router = create_router(false, false, build_state(&stub, "kids=rate-limit:1"));
+
+        router
+            .clone()
+            .oneshot(build_request("/v1/library/sync", "kids-kobo"))
+            .await
+            .expect("service should return a response");
+        let second = router
+            .oneshot(build_request("/v1/library/sync", "kids-kobo"))
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(second.headers()[header::RETRY_AFTER], "60");
+        assert_eq!(stub.recorded_requests().len(), 1);
+    }
+}
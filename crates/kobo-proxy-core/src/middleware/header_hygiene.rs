@@ -0,0 +1,213 @@
+//! Request smuggling and header hygiene middleware.
+//!
+//! The proxy sits between a quirky device and a strict upstream, so requests are
+//! cleaned up before they are handled: requests with ambiguous body framing are
+//! rejected, and hop-by-hop headers (RFC 7230 section 6.1) are removed so they are
+//! not forwarded. Folded (obs-fold) header values are already rejected by hyper, so
+//! other header values are forwarded byte for byte.
+
+pub use implementation::harden_requests;
+
+mod implementation {
+    use std::collections::HashSet;
+
+    use axum::{
+        extract::Request,
+        middleware::Next,
+        response::{IntoResponse as _, Response},
+    };
+    use hyper::{
+        HeaderMap, StatusCode,
+        header::{self, HeaderName},
+    };
+
+    use crate::utils::upgrade::is_upgrade_request;
+
+    /// Hop-by-hop headers that only apply to a single connection.
+    const HOP_BY_HOP_HEADERS: &[HeaderName] = &[
+        header::CONNECTION,
+        header::PROXY_AUTHENTICATE,
+        header::PROXY_AUTHORIZATION,
+        header::TE,
+        header::TRAILER,
+        header::TRANSFER_ENCODING,
+        header::UPGRADE,
+    ];
+
+    /// The `Keep-Alive` header, which `hyper::header` has no constant for.
+    const KEEP_ALIVE: &str = "keep-alive";
+
+    /// Checks if the body framing is ambiguous: both `Content-Length` and
+    /// `Transfer-Encoding` are present, or `Content-Length` has conflicting values.
+    fn has_conflicting_framing(headers: &HeaderMap) -> bool {
+        if headers.contains_key(header::TRANSFER_ENCODING)
+            && headers.contains_key(header::CONTENT_LENGTH)
+        {
+            return true;
+        }
+        let lengths = headers
+            .get_all(header::CONTENT_LENGTH)
+            .iter()
+            .flat_map(|value| value.as_bytes().split(|&byte| byte == b','))
+            .map(<[u8]>::trim_ascii)
+            .collect::<HashSet<_>>();
+        lengths.len() > 1
+    }
+
+    /// Removes hop-by-hop headers and the headers listed in `Connection`. The
+    /// `Connection` and `Upgrade` headers of upgrade requests are kept, since the
+    /// upgrade is forwarded to the upstream.
+    fn strip_hop_by_hop_headers(headers: &mut HeaderMap) {
+        let keep_upgrade = is_upgrade_request(headers);
+        let listed = headers
+            .get_all(header::CONNECTION)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|token| HeaderName::try_from(token.trim()).ok())
+            .collect::<Vec<_>>();
+
+        for name in HOP_BY_HOP_HEADERS.iter().chain(&listed) {
+            if keep_upgrade && (name == header::CONNECTION || name == header::UPGRADE) {
+                continue;
+            }
+            if headers.remove(name).is_some() {
+                tracing::debug!("Removed hop-by-hop header {name}");
+            }
+        }
+        headers.remove(KEEP_ALIVE);
+    }
+
+    /// Rejects requests with ambiguous body framing and cleans up the headers of the
+    /// rest before they are handled.
+    pub async fn harden_requests(mut request: Request, next: Next) -> Response {
+        if has_conflicting_framing(request.headers()) {
+            tracing::warn!(
+                path = request.uri().path(),
+                "Rejected request with conflicting Content-Length and Transfer-Encoding"
+            );
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+        strip_hop_by_hop_headers(request.headers_mut());
+
+        next.run(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{Request, Response, StatusCode, header},
+    };
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{fake_kobo_client::FakeKoboClient, server_state::ServerState},
+    };
+
+    async fn forward(request: Request<Body>) -> (StatusCode, Arc<FakeKoboClient>) {
+        let stub = Arc::new(FakeKoboClient::new());
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::empty())
+                .expect("failed to build stub response"),
+        );
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+
+        let response = create_router(false, false, state)
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+        (response.status(), stub)
+    }
+
+    #[tokio::test]
+    async fn conflicting_framing_is_rejected() {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/library/sync")
+            .header(header::CONTENT_LENGTH, "4")
+            .header(header::TRANSFER_ENCODING, "chunked")
+            .body(Body::from("test"))
+            .expect("failed to build request");
+
+        let (status, stub) = forward(request).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(stub.recorded_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn conflicting_content_lengths_are_rejected() {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/library/sync")
+            .header(header::CONTENT_LENGTH, "4")
+            .header(header::CONTENT_LENGTH, "40")
+            .body(Body::from("test"))
+            .expect("failed to build request");
+
+        let (status, _) = forward(request).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn hop_by_hop_headers_are_not_forwarded() {
+        let request = Request::builder()
+            .uri("/v1/library/sync")
+            .header(header::CONNECTION, "keep-alive, x-device-hint")
+            .header("keep-alive", "timeout=5")
+            .header("x-device-hint", "1")
+            .header(header::TE, "trailers")
+            .header(header::PROXY_AUTHORIZATION, "Basic abc")
+            .header(header::AUTHORIZATION, "Bearer token")
+            .body(Body::empty())
+            .expect("failed to build request");
+
+        let (status, stub) = forward(request).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let recorded = stub.recorded_requests();
+        let headers = &recorded[0].headers;
+        for name in [
+            "connection",
+            "keep-alive",
+            "x-device-hint",
+            "te",
+            "proxy-authorization",
+        ] {
+            assert!(!headers.contains_key(name), "{name} was forwarded");
+        }
+        assert_eq!(headers.get(header::AUTHORIZATION).unwrap(), "Bearer token");
+    }
+
+    #[tokio::test]
+    async fn header_whitespace_is_forwarded_unchanged() {
+        let request = Request::builder()
+            .uri("/v1/library/sync")
+            .header(header::USER_AGENT, "Mozilla/5.0 (Linux;\t  Kobo)")
+            .header(header::COOKIE, "session=\"a  b\"")
+            .body(Body::empty())
+            .expect("failed to build request");
+
+        let (_, stub) = forward(request).await;
+
+        let recorded = stub.recorded_requests();
+        assert_eq!(
+            recorded[0].headers.get(header::USER_AGENT).unwrap(),
+            "Mozilla/5.0 (Linux;\t  Kobo)"
+        );
+        assert_eq!(
+            recorded[0].headers.get(header::COOKIE).unwrap(),
+            "session=\"a  b\""
+        );
+    }
+}
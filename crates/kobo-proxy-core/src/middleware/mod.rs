@@ -0,0 +1,17 @@
+//! Middleware components used by the Kobo server.
+
+pub mod access_schedule;
+pub mod auth_backoff;
+pub mod bandwidth_accounting;
+pub mod capture_exchanges;
+pub mod chaos;
+pub mod client_address;
+pub mod deadline;
+pub mod device_serialization;
+pub mod device_tracking;
+pub mod group_policies;
+pub mod header_hygiene;
+pub mod request_logging;
+pub mod response_patches;
+pub mod schema_validation;
+pub mod snapshot_requests;
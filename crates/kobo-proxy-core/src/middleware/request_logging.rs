@@ -0,0 +1,760 @@
+//! Request and response logging middleware for the Axum server.
+//!
+//! This module provides middleware functions that can be used to log incoming
+//! HTTP requests and outgoing HTTP responses, including their headers and body
+//! content. The middleware supports both plain text and gzip-compressed content.
+//! Bodies larger than the configured ceiling are logged as a head and tail with
+//! a marker describing how much was omitted. Response bodies are teed rather than
+//! buffered: chunks are forwarded as soon as they arrive and the response is logged
+//! once its body ends. Upgraded connections and event streams are logged without
+//! their bodies.
+
+pub use implementation::{log_requests, log_responses};
+
+mod implementation {
+    use std::{
+        borrow::Cow,
+        collections::VecDeque,
+        io::{self, Write},
+        pin::Pin,
+        task::{Context, Poll, ready},
+    };
+
+    use anyhow::Result;
+    use axum::{
+        body::{Body, Bytes},
+        extract::{ConnectInfo, Request, State},
+        middleware::Next,
+        response::{IntoResponse, Response},
+    };
+    use flate2::write::MultiGzDecoder;
+    use hyper::{
+        HeaderMap, StatusCode,
+        body::{Body as HttpBody, Frame, SizeHint},
+        header,
+    };
+
+    use crate::{
+        listener::ClientAddress,
+        state::{device_groups::GroupLogging, server_state::ServerState},
+        utils::{
+            http_body::{buffer_body, decode_response_body, is_gzip_encoded},
+            protected_content::is_protected_content,
+            upgrade::is_upgrade_request,
+        },
+    };
+
+    /// Checks if a response must be streamed to the client rather than buffered.
+    fn is_streaming_response(response: &Response) -> bool {
+        response.status() == StatusCode::SWITCHING_PROTOCOLS
+            || response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.starts_with("text/event-stream"))
+    }
+
+    /// Returns how verbosely the request's device group is logged, set by the group
+    /// policy middleware.
+    fn group_logging(request: &Request) -> GroupLogging {
+        request
+            .extensions()
+            .get::<GroupLogging>()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Truncates a body representation to at most `max_bytes`, keeping the head and
+    /// tail and replacing the middle with a marker such as `[... truncated 4.2 MB ...]`.
+    /// Cuts are moved to the nearest character boundary so the result stays valid UTF-8.
+    pub(super) fn truncate_body(body: Cow<'_, str>, max_bytes: Option<usize>) -> Cow<'_, str> {
+        let Some(max_bytes) = max_bytes else {
+            return body;
+        };
+        if body.len() <= max_bytes {
+            return body;
+        }
+
+        let mut head_end = max_bytes / 2;
+        while !body.is_char_boundary(head_end) {
+            head_end -= 1;
+        }
+        let mut tail_start = body.len() - (max_bytes - max_bytes / 2);
+        while !body.is_char_boundary(tail_start) {
+            tail_start += 1;
+        }
+
+        let omitted = format_byte_count(tail_start - head_end);
+        Cow::Owned(format!(
+            "{}[... truncated {omitted} ...]{}",
+            &body[..head_end],
+            &body[tail_start..]
+        ))
+    }
+
+    /// Formats a byte count using decimal units (e.g. `512 B`, `4.2 MB`).
+    pub(super) fn format_byte_count(bytes: usize) -> String {
+        const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+        if bytes < 1000 {
+            return format!("{bytes} B");
+        }
+
+        #[expect(
+            clippy::cast_precision_loss,
+            reason = "Only used for a human readable approximation."
+        )]
+        let mut value = bytes as f64 / 1000.0;
+        let mut unit = UNITS[0];
+        for next_unit in &UNITS[1..] {
+            if value < 1000.0 {
+                break;
+            }
+            value /= 1000.0;
+            unit = next_unit;
+        }
+
+        format!("{value:.1} {unit}")
+    }
+
+    /// Keeps the head and tail of a byte stream, up to an optional total size.
+    pub(super) struct BodyCapture {
+        max_bytes: Option<usize>,
+        head: Vec<u8>,
+        tail: VecDeque<u8>,
+        total: usize,
+    }
+
+    impl BodyCapture {
+        pub(super) fn new(max_bytes: Option<usize>) -> Self {
+            Self {
+                max_bytes,
+                head: Vec::new(),
+                tail: VecDeque::new(),
+                total: 0,
+            }
+        }
+
+        /// Records the next bytes of the stream.
+        pub(super) fn push(&mut self, bytes: &[u8]) {
+            self.total += bytes.len();
+            let Some(max_bytes) = self.max_bytes else {
+                self.head.extend_from_slice(bytes);
+                return;
+            };
+
+            let head_room = (max_bytes / 2).saturating_sub(self.head.len());
+            let (head, rest) = bytes.split_at(head_room.min(bytes.len()));
+            self.head.extend_from_slice(head);
+            let tail_max = max_bytes - max_bytes / 2;
+            self.tail
+                .extend(&rest[rest.len().saturating_sub(tail_max)..]);
+            let excess = self.tail.len().saturating_sub(tail_max);
+            self.tail.drain(..excess);
+        }
+
+        /// Renders the recorded bytes like [`truncate_body`], replacing the omitted
+        /// middle with a marker and dropping characters cut in half.
+        pub(super) fn render(&self) -> String {
+            let omitted = self.total - self.head.len() - self.tail.len();
+            if omitted == 0 {
+                let bytes: Vec<u8> = self.head.iter().chain(&self.tail).copied().collect();
+                return String::from_utf8_lossy(&bytes).into_owned();
+            }
+
+            let head_end = match std::str::from_utf8(&self.head) {
+                Err(error) if error.error_len().is_none() => error.valid_up_to(),
+                _ => self.head.len(),
+            };
+            let tail_start = self
+                .tail
+                .iter()
+                .take_while(|&&byte| byte & 0xC0 == 0x80)
+                .count();
+            let tail: Vec<u8> = self.tail.iter().skip(tail_start).copied().collect();
+            let omitted = self.total - head_end - tail.len();
+            format!(
+                "{}[... truncated {} ...]{}",
+                String::from_utf8_lossy(&self.head[..head_end]),
+                format_byte_count(omitted),
+                String::from_utf8_lossy(&tail)
+            )
+        }
+    }
+
+    impl Write for BodyCapture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.push(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Where the bytes of a logged body are recorded, decoding gzip as it streams.
+    enum CaptureSink {
+        Plain(BodyCapture),
+        Gzip(Box<MultiGzDecoder<BodyCapture>>),
+        Unprintable,
+    }
+
+    impl CaptureSink {
+        fn record(&mut self, bytes: &[u8]) {
+            match self {
+                Self::Plain(capture) => capture.push(bytes),
+                Self::Gzip(decoder) => {
+                    if let Err(e) = decoder.write_all(bytes) {
+                        tracing::warn!("Failed to decode response body: {e}");
+                        *self = Self::Unprintable;
+                    }
+                }
+                Self::Unprintable => {}
+            }
+        }
+
+        fn render(self) -> String {
+            match self {
+                Self::Plain(capture) => capture.render(),
+                Self::Gzip(mut decoder) => match decoder.try_finish() {
+                    Ok(()) => decoder.get_ref().render(),
+                    Err(e) => {
+                        tracing::warn!("Failed to decode response body: {e}");
+                        "<unprintable body>".to_owned()
+                    }
+                },
+                Self::Unprintable => "<unprintable body>".to_owned(),
+            }
+        }
+    }
+
+    /// The parts of a response that are logged once its body has been sent.
+    struct ResponseLog {
+        route: String,
+        status: StatusCode,
+        headers: HeaderMap,
+        sink: CaptureSink,
+    }
+
+    impl ResponseLog {
+        fn emit(self) {
+            let body = self.sink.render();
+            tracing::info!(
+                route = %self.route,
+                status = %self.status,
+                headers = ?self.headers,
+                body = %body,
+                "Outgoing Response"
+            );
+        }
+    }
+
+    /// A response body that forwards each chunk unchanged while recording it, logging
+    /// the response when the body ends or is dropped (e.g. when the client goes away).
+    struct LoggedBody {
+        inner: Body,
+        log: Option<ResponseLog>,
+    }
+
+    impl HttpBody for LoggedBody {
+        type Data = Bytes;
+        type Error = axum::Error;
+
+        fn poll_frame(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+            let this = self.get_mut();
+            let frame = ready!(Pin::new(&mut this.inner).poll_frame(cx));
+            match &frame {
+                Some(Ok(frame)) => {
+                    if let (Some(data), Some(log)) = (frame.data_ref(), this.log.as_mut()) {
+                        log.sink.record(data);
+                    }
+                }
+                Some(Err(_)) | None => {
+                    if let Some(log) = this.log.take() {
+                        log.emit();
+                    }
+                }
+            }
+            Poll::Ready(frame)
+        }
+
+        fn is_end_stream(&self) -> bool {
+            self.inner.is_end_stream()
+        }
+
+        fn size_hint(&self) -> SizeHint {
+            self.inner.size_hint()
+        }
+    }
+
+    impl Drop for LoggedBody {
+        fn drop(&mut self) {
+            if let Some(log) = self.log.take() {
+                log.emit();
+            }
+        }
+    }
+
+    /// Logs an incoming HTTP request (method, URI, headers, body; gzip-aware).
+    pub async fn log_requests(
+        State(server_state): State<ServerState>,
+        request: Request,
+        next: Next,
+    ) -> Result<impl IntoResponse, (StatusCode, String)> {
+        let logging = group_logging(&request);
+        if logging == GroupLogging::Off {
+            return Ok(next.run(request).await);
+        }
+        if logging == GroupLogging::Headers || is_upgrade_request(request.headers()) {
+            tracing::info!(
+                method = %request.method(),
+                uri = %request.uri(),
+                route = %server_state.route_templates.normalize(request.uri().path()),
+                headers = ?request.headers(),
+                "Incoming {}",
+                if logging == GroupLogging::Headers { "Request" } else { "Upgrade Request" }
+            );
+            return Ok(next.run(request).await);
+        }
+
+        let (parts, body) = request.into_parts();
+        let bytes = buffer_body(body).await?;
+
+        let is_gzipped = is_gzip_encoded(&parts.headers);
+        let body_repr = decode_response_body(&bytes, is_gzipped)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to decode request body: {e}");
+                Cow::Owned("<unprintable body>".into())
+            });
+        let body_repr = truncate_body(body_repr, server_state.log_body_max_bytes);
+        let client = parts
+            .extensions
+            .get::<ConnectInfo<ClientAddress>>()
+            .map(|ConnectInfo(ClientAddress(address))| address.ip());
+
+        tracing::info!(
+            method = %parts.method,
+            uri = %parts.uri,
+            client = ?client,
+            route = %server_state.route_templates.normalize(parts.uri.path()),
+            headers = ?parts.headers,
+            body = %body_repr,
+            "Incoming Request"
+        );
+        let req = Request::from_parts(parts, Body::from(bytes));
+
+        Ok(next.run(req).await)
+    }
+
+    /// Logs an outgoing HTTP response (status, headers, body; gzip-aware). The body
+    /// is streamed to the client as it is logged, so logging does not delay it.
+    pub async fn log_responses(
+        State(server_state): State<ServerState>,
+        request: Request,
+        next: Next,
+    ) -> Response {
+        let logging = group_logging(&request);
+        if logging == GroupLogging::Off {
+            return next.run(request).await;
+        }
+        let route = server_state
+            .route_templates
+            .normalize(request.uri().path())
+            .into_owned();
+        let res = next.run(request).await;
+        let kind = if logging == GroupLogging::Headers {
+            Some("Response")
+        } else if is_protected_content(res.headers()) {
+            Some("Protected Response")
+        } else if is_streaming_response(&res) {
+            Some("Streaming Response")
+        } else {
+            None
+        };
+        if let Some(kind) = kind {
+            tracing::info!(
+                route = %route,
+                status = %res.status(),
+                headers = ?res.headers(),
+                "Outgoing {kind}"
+            );
+            return res;
+        }
+
+        let (parts, body) = res.into_parts();
+        let capture = BodyCapture::new(server_state.log_body_max_bytes);
+        let sink = if is_gzip_encoded(&parts.headers) {
+            CaptureSink::Gzip(Box::new(MultiGzDecoder::new(capture)))
+        } else {
+            CaptureSink::Plain(capture)
+        };
+        let log = ResponseLog {
+            route,
+            status: parts.status,
+            headers: parts.headers.clone(),
+            sink,
+        };
+
+        Response::from_parts(
+            parts,
+            Body::new(LoggedBody {
+                inner: body,
+                log: Some(log),
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::io::Write as _;
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{Request, Response},
+    };
+    use flate2::{Compression, write::GzEncoder};
+    use http_body_util::BodyExt as _;
+    use tower::ServiceExt as _;
+    use tracing_test::traced_test;
+
+    use super::implementation::{BodyCapture, format_byte_count, truncate_body};
+    use crate::{
+        router::create_router,
+        state::{
+            device_groups::DeviceGroups, fake_kobo_client::FakeKoboClient,
+            server_state::ServerState,
+        },
+    };
+
+    const TEST_BODY: &str = "test body";
+    const TEST_RESPONSE: &str = "stubbed response";
+
+    fn build_request() -> Request<Body> {
+        Request::builder()
+            .uri("/")
+            .body(Body::from(TEST_BODY))
+            .expect("failed to build request")
+    }
+
+    fn gzip_bytes(input: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(input.as_bytes())
+            .expect("failed to write gzip contents");
+        encoder.finish().expect("failed to finish gzip encoding")
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn request_logging_layer_logs_requests() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+        let router = create_router(true, false, state);
+
+        stub.enqueue_response(
+            Response::builder()
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let _request = router
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+
+        assert!(logs_contain(TEST_BODY));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn group_logging_policy_omits_bodies() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .device_groups(
+                DeviceGroups::new(&["quiet-kobo=quiet"], &["quiet=logging:headers"]).unwrap(),
+            )
+            .build();
+        let router = create_router(true, true, state);
+
+        stub.enqueue_response(
+            Response::builder()
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .header("x-kobo-deviceid", "quiet-kobo")
+                    .body(Body::from(TEST_BODY))
+                    .expect("failed to build request"),
+            )
+            .await
+            .expect("service should return a response");
+        response.into_body().collect().await.unwrap();
+
+        assert!(logs_contain("Incoming Request"));
+        assert!(logs_contain("Outgoing Response"));
+        assert!(!logs_contain(TEST_BODY));
+        assert!(!logs_contain(TEST_RESPONSE));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn response_logging_layer_logs_responses() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+        let router = create_router(false, true, state);
+
+        stub.enqueue_response(
+            Response::builder()
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let response = router
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+        let _body = response.into_body().collect().await.unwrap();
+
+        assert!(logs_contain(TEST_RESPONSE));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn response_logging_layer_handles_gzip_body() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+        let router = create_router(false, true, state);
+
+        let gzip_body = gzip_bytes(TEST_RESPONSE);
+        stub.enqueue_response(
+            Response::builder()
+                .header("content-encoding", "gzip")
+                .body(Body::from(gzip_body))
+                .expect("failed to build stub response"),
+        );
+
+        let response = router
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+        let _body = response.into_body().collect().await.unwrap();
+
+        assert!(logs_contain(TEST_RESPONSE));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn response_logging_layer_decodes_every_gzip_member() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+        let router = create_router(false, true, state);
+
+        let mut gzip_body = gzip_bytes("first member, ");
+        gzip_body.extend(gzip_bytes("second member"));
+        stub.enqueue_response(
+            Response::builder()
+                .header("content-encoding", "gzip")
+                .body(Body::from(gzip_body))
+                .expect("failed to build stub response"),
+        );
+
+        let response = router
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+        let _body = response.into_body().collect().await.unwrap();
+
+        assert!(logs_contain("first member, second member"));
+    }
+
+    #[test]
+    fn truncate_body_without_limit_returns_body() {
+        let body = truncate_body(Cow::Borrowed(TEST_BODY), None);
+
+        assert_eq!(body, TEST_BODY);
+    }
+
+    #[test]
+    fn truncate_body_under_limit_returns_body() {
+        let body = truncate_body(Cow::Borrowed(TEST_BODY), Some(TEST_BODY.len()));
+
+        assert_eq!(body, TEST_BODY);
+    }
+
+    #[test]
+    fn truncate_body_keeps_head_and_tail() {
+        let body = truncate_body(Cow::Borrowed("0123456789"), Some(4));
+
+        assert_eq!(body, "01[... truncated 6 B ...]89");
+    }
+
+    #[test]
+    fn truncate_body_respects_char_boundaries() {
+        let body = truncate_body(Cow::Borrowed("🚀🚀🚀"), Some(6));
+
+        assert_eq!(body, "[... truncated 12 B ...]");
+    }
+
+    #[test]
+    fn body_capture_keeps_head_and_tail_across_chunks() {
+        let mut capture = BodyCapture::new(Some(4));
+        for chunk in ["01", "2345", "6", "789"] {
+            capture.push(chunk.as_bytes());
+        }
+
+        assert_eq!(capture.render(), "01[... truncated 6 B ...]89");
+    }
+
+    #[test]
+    fn body_capture_respects_char_boundaries() {
+        let mut capture = BodyCapture::new(Some(6));
+        capture.push("🚀🚀🚀".as_bytes());
+
+        assert_eq!(capture.render(), "[... truncated 12 B ...]");
+    }
+
+    #[test]
+    fn body_capture_without_limit_keeps_body() {
+        let mut capture = BodyCapture::new(None);
+        capture.push(TEST_BODY.as_bytes());
+
+        assert_eq!(capture.render(), TEST_BODY);
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn response_logging_layer_forwards_body_unchanged() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+        let router = create_router(false, true, state);
+
+        let gzip_body = gzip_bytes(TEST_RESPONSE);
+        stub.enqueue_response(
+            Response::builder()
+                .header("content-encoding", "gzip")
+                .body(Body::from(gzip_body.clone()))
+                .expect("failed to build stub response"),
+        );
+
+        let response = router
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+        assert!(!logs_contain("Outgoing Response"));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+
+        assert_eq!(&body[..], &gzip_body[..]);
+        assert!(logs_contain("Outgoing Response"));
+    }
+
+    #[test]
+    fn format_byte_count_uses_bytes_below_one_kilobyte() {
+        assert_eq!(format_byte_count(999), "999 B");
+    }
+
+    #[test]
+    fn format_byte_count_uses_megabytes() {
+        assert_eq!(format_byte_count(4_200_000), "4.2 MB");
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn response_logging_layer_truncates_large_bodies() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .log_body_max_bytes(Some(4))
+            .build();
+        let router = create_router(false, true, state);
+
+        stub.enqueue_response(
+            Response::builder()
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let response = router
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+        let _body = response.into_body().collect().await.unwrap();
+
+        assert!(logs_contain("st[... truncated 12 B ...]se"));
+        assert!(!logs_contain(TEST_RESPONSE));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn response_logging_layer_does_not_buffer_event_streams() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+        let router = create_router(false, true, state);
+
+        stub.enqueue_response(
+            Response::builder()
+                .header("content-type", "text/event-stream")
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let _request = router
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+
+        assert!(logs_contain("Outgoing Streaming Response"));
+        assert!(!logs_contain(TEST_RESPONSE));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn request_logging_layer_logs_route_template() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+        let router = create_router(true, false, state);
+
+        stub.enqueue_response(
+            Response::builder()
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let request = Request::builder()
+            .uri("/v1/library/3f2a9c4e-8b1d-4e6f-9a7c-2d5b8e1f0a3c/state")
+            .body(Body::empty())
+            .expect("failed to build request");
+        let _request = router
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        assert!(logs_contain("route=/v1/library/{id}/state"));
+    }
+}
@@ -0,0 +1,157 @@
+//! Response patch middleware.
+//!
+//! Applies the configured JSON Patch documents to successful JSON responses. Bodies
+//! are decompressed before patching and re-encoded afterwards.
+
+pub use implementation::apply_response_patches;
+
+mod implementation {
+    use axum::{
+        body::Body,
+        extract::{Request, State},
+        middleware::Next,
+        response::Response,
+    };
+    use hyper::header;
+
+    use crate::{
+        routes::constants::API_PREFIX,
+        state::{
+            audit_log::{AuditEntry, AuditRule, byte_delta, request_id},
+            server_state::ServerState,
+        },
+        utils::{
+            http_body::{
+                decode_response_body, encode_response_body, is_gzip_encoded, is_json_response,
+                read_response_body,
+            },
+            protected_content::is_protected_content,
+        },
+    };
+
+    /// Applies the JSON Patch configured for the request's route to the response.
+    /// Responses are forwarded unchanged if the patch cannot be applied.
+    pub async fn apply_response_patches(
+        State(server_state): State<ServerState>,
+        request: Request,
+        next: Next,
+    ) -> Result<Response, hyper::StatusCode> {
+        let path = request.uri().path();
+        if path.starts_with(API_PREFIX) {
+            return Ok(next.run(request).await);
+        }
+        let route = server_state.route_templates.normalize(path).into_owned();
+        if !server_state.response_patches.applies_to(&route) {
+            return Ok(next.run(request).await);
+        }
+        let request_id = request_id(request.headers());
+        let response = next.run(request).await;
+        if !response.status().is_success()
+            || !is_json_response(&response)
+            || is_protected_content(response.headers())
+        {
+            return Ok(response);
+        }
+
+        let (mut parts, bytes) = read_response_body(response).await?;
+        let gz = is_gzip_encoded(&parts.headers);
+        let body_text = decode_response_body(&bytes, gz).await?;
+        let patched = match server_state.response_patches.apply(&route, &body_text) {
+            Ok(Some(patched)) => patched,
+            Ok(None) => return Ok(Response::from_parts(parts, Body::from(bytes))),
+            Err(e) => {
+                tracing::warn!(route, "Failed to apply response patch: {e}");
+                return Ok(Response::from_parts(parts, Body::from(bytes)));
+            }
+        };
+        server_state.audit_log.record(AuditEntry::new(
+            route,
+            AuditRule::ResponsePatch,
+            byte_delta(body_text.len(), patched.len()),
+            request_id,
+        ));
+
+        parts.headers.remove(header::CONTENT_LENGTH);
+        let body =
+            encode_response_body(&patched, gz.then_some(server_state.gzip_compression)).await?;
+        Ok(Response::from_parts(parts, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use axum::{
+        body::Body,
+        http::{Request, Response, StatusCode},
+    };
+    use http_body_util::BodyExt as _;
+    use serde_json::json;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{
+            audit_log::AuditRule, fake_kobo_client::FakeKoboClient, server_state::ServerState,
+        },
+        utils::response_patches::ResponsePatches,
+    };
+
+    fn build_state(stub: Arc<FakeKoboClient>) -> ServerState {
+        ServerState::builder("http://frontend.test")
+            .client(stub)
+            .response_patches(ResponsePatches::new(HashMap::from([(
+                "/v1/products/books/{id}".to_owned(),
+                serde_json::from_value(json!([
+                    {"op": "replace", "path": "/Price", "value": 0},
+                    {"op": "test", "path": "/Currency", "value": "CAD"}
+                ]))
+                .unwrap(),
+            )])))
+            .build()
+    }
+
+    async fn request_product(body: &str) -> (String, ServerState) {
+        let stub = Arc::new(FakeKoboClient::new());
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(Body::from(body.to_owned()))
+                .unwrap(),
+        );
+        let state = build_state(stub);
+        let request = Request::builder()
+            .uri("/v1/products/books/abc")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = create_router(false, false, state.clone())
+            .oneshot(request)
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (String::from_utf8(body.to_vec()).unwrap(), state)
+    }
+
+    #[tokio::test]
+    async fn patches_responses_for_configured_routes() {
+        let (body, state) = request_product(r#"{"Currency": "CAD", "Price": 9.99}"#).await;
+
+        assert_eq!(body, r#"{"Currency":"CAD","Price":0}"#);
+        let entries = state.audit_log.entries(None, 10);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].rule, AuditRule::ResponsePatch);
+    }
+
+    #[tokio::test]
+    async fn forwards_response_unchanged_when_patch_fails() {
+        let original = r#"{"Currency": "USD", "Price": 9.99}"#;
+
+        let (body, state) = request_product(original).await;
+
+        assert_eq!(body, original);
+        assert!(state.audit_log.entries(None, 10).is_empty());
+    }
+}
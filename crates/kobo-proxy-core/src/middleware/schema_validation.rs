@@ -0,0 +1,137 @@
+//! Schema validation middleware.
+//!
+//! Checks successful JSON responses for Kobo store API endpoints with typed payloads
+//! against them, as received from upstream. Mismatches are logged, and in strict mode
+//! the device is answered with `502 Bad Gateway` instead.
+
+pub use implementation::validate_response_schemas;
+
+mod implementation {
+    use axum::{
+        body::Body,
+        extract::{Request, State},
+        middleware::Next,
+        response::Response,
+    };
+    use hyper::{Method, StatusCode};
+
+    use crate::{
+        state::server_state::ServerState,
+        utils::{
+            http_body::{
+                decode_response_body, is_gzip_encoded, is_json_response, read_response_body,
+            },
+            protected_content::is_protected_content,
+            schema_validation::{SchemaValidation, has_schema, validate_response},
+        },
+    };
+
+    /// Validates the response to a `GET` request for an endpoint with typed payloads.
+    /// Responses are forwarded unchanged unless they do not match in strict mode.
+    pub async fn validate_response_schemas(
+        State(server_state): State<ServerState>,
+        request: Request,
+        next: Next,
+    ) -> Result<Response, StatusCode> {
+        let route = server_state
+            .route_templates
+            .normalize(request.uri().path())
+            .into_owned();
+        if request.method() != Method::GET || !has_schema(&route) {
+            return Ok(next.run(request).await);
+        }
+        let response = next.run(request).await;
+        if !response.status().is_success()
+            || !is_json_response(&response)
+            || is_protected_content(response.headers())
+        {
+            return Ok(response);
+        }
+
+        let (parts, bytes) = read_response_body(response).await?;
+        let body_text = decode_response_body(&bytes, is_gzip_encoded(&parts.headers)).await?;
+        if let Some(Err(e)) = validate_response(&route, &body_text) {
+            if server_state.schema_validation == SchemaValidation::Strict {
+                tracing::error!(route, "Upstream response does not match its schema: {e:#}");
+                return Err(StatusCode::BAD_GATEWAY);
+            }
+            tracing::warn!(route, "Upstream response does not match its schema: {e:#}");
+        }
+        Ok(Response::from_parts(parts, Body::from(bytes)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use http_body_util::BodyExt as _;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        fixtures::Fixture,
+        router::create_router,
+        state::{fake_kobo_client::FakeKoboClient, server_state::ServerState},
+        utils::schema_validation::SchemaValidation,
+    };
+
+    async fn sync_with_body(mode: SchemaValidation, body: String) -> (StatusCode, String) {
+        let stub = Arc::new(FakeKoboClient::new());
+        let mut response = Fixture::LibrarySync.response();
+        *response.body_mut() = Body::from(body);
+        stub.enqueue_response(response);
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub)
+            .schema_validation(mode)
+            .build();
+        let request = Request::builder()
+            .uri("/v1/library/sync")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = create_router(false, false, state)
+            .oneshot(request)
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn changed_sync_body() -> String {
+        Fixture::LibrarySync
+            .body()
+            .replace("\"CrossRevisionId\"", "\"CrossRevisionID\"")
+    }
+
+    #[tokio::test]
+    async fn forwards_matching_responses_in_strict_mode() {
+        let (status, body) = sync_with_body(
+            SchemaValidation::Strict,
+            Fixture::LibrarySync.body().to_owned(),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, Fixture::LibrarySync.body());
+    }
+
+    #[tokio::test]
+    async fn forwards_mismatched_responses_when_warning() {
+        let (status, body) = sync_with_body(SchemaValidation::Warn, changed_sync_body()).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, changed_sync_body());
+    }
+
+    #[tokio::test]
+    async fn rejects_mismatched_responses_in_strict_mode() {
+        let (status, _) = sync_with_body(SchemaValidation::Strict, changed_sync_body()).await;
+
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+    }
+}
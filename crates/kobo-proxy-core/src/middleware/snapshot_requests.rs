@@ -0,0 +1,98 @@
+//! Snapshot request capture middleware.
+//!
+//! Remembers each device's most recent request to a snapshot route, so the periodic
+//! snapshot task can replay it with the device's credentials.
+
+pub use implementation::remember_snapshot_requests;
+
+mod implementation {
+    use axum::{
+        extract::{Request, State},
+        middleware::Next,
+        response::Response,
+    };
+    use hyper::Method;
+
+    use crate::state::{
+        devices::identify_device, server_state::ServerState, snapshots::SnapshotRequest,
+    };
+
+    /// Remembers `GET` requests to snapshot routes before forwarding them.
+    pub async fn remember_snapshot_requests(
+        State(server_state): State<ServerState>,
+        request: Request,
+        next: Next,
+    ) -> Response {
+        if request.method() != Method::GET {
+            return next.run(request).await;
+        }
+        let route = server_state.route_templates.normalize(request.uri().path());
+        if !server_state.snapshots.is_snapshot_route(&route) {
+            return next.run(request).await;
+        }
+        let (Some(device_id), Some(path_and_query)) =
+            (identify_device(&request), request.uri().path_and_query())
+        else {
+            return next.run(request).await;
+        };
+
+        server_state.snapshots.remember_request(SnapshotRequest {
+            device_id,
+            route: route.into_owned(),
+            path_and_query: path_and_query.as_str().to_owned(),
+            headers: request.headers().clone(),
+        });
+
+        next.run(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{Request, Response, StatusCode},
+    };
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{fake_kobo_client::FakeKoboClient, server_state::ServerState},
+    };
+
+    #[tokio::test]
+    async fn snapshot_route_requests_are_remembered() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .snapshots_enabled(true)
+            .build();
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from("{}"))
+                .expect("failed to build stub response"),
+        );
+
+        let request = Request::builder()
+            .uri("/v1/initialization")
+            .header("x-kobo-deviceid", "device-1")
+            .header("authorization", "Bearer token")
+            .body(Body::empty())
+            .expect("failed to build request");
+        let _response = create_router(false, false, state.clone())
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        let requests = state.snapshots.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].device_id, "device-1");
+        assert_eq!(
+            requests[0].headers.get("authorization").unwrap(),
+            "Bearer token"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! Replay of captured device exchanges, run by the `replay-sync` subcommand.
+//!
+//! Each recorded request is sent through the in-process router with a client that
+//! answers with the recorded Kobo store API response, and the response is compared
+//! with the one the device received when it was recorded. Differences show how the
+//! current code would treat real traffic differently, e.g. after an upgrade.
+
+pub use implementation::{Replay, ReplayDifference, ReplayReport};
+
+mod implementation {
+    use std::{
+        borrow::Cow,
+        fmt,
+        path::Path,
+        sync::{Arc, Mutex, PoisonError},
+    };
+
+    use anyhow::{Result, anyhow};
+    use axum::{body::Body, extract::Request};
+    use hyper::Response;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{
+            capture_log::{CapturedExchange, CapturedResponse, read_capture_file},
+            client::KoboClient,
+            server_state::ServerState,
+        },
+        utils::{
+            http_body::{buffer_body, decode_response_body},
+            json_diff::diff_json,
+        },
+    };
+
+    /// A client answering with the upstream response of the exchange being replayed.
+    #[derive(Default)]
+    struct ReplayClient {
+        upstream: Mutex<Option<CapturedResponse>>,
+    }
+
+    impl ReplayClient {
+        fn load(&self, upstream: Option<CapturedResponse>) {
+            *self.upstream.lock().unwrap_or_else(PoisonError::into_inner) = upstream;
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl KoboClient for ReplayClient {
+        async fn request(&self, _request: Request) -> Result<Response<Body>> {
+            self.upstream
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take()
+                .ok_or_else(|| anyhow!("No upstream response was recorded for this request"))?
+                .to_response()
+        }
+    }
+
+    /// Decodes a recorded body for comparison.
+    async fn decoded_body(response: &CapturedResponse) -> String {
+        let is_gzipped = response.header("content-encoding") == Some("gzip");
+        decode_response_body(&response.body, is_gzipped)
+            .await
+            .map_or_else(|_| "<unprintable body>".to_owned(), Cow::into_owned)
+    }
+
+    /// A replayed request whose response differs from the recording.
+    #[derive(Clone, Debug)]
+    pub struct ReplayDifference {
+        /// Position of the exchange in the capture file, starting at 1.
+        pub index: usize,
+        /// The request method.
+        pub method: String,
+        /// The request URI.
+        pub uri: String,
+        /// The changes, such as a different status or `~ /path` for changed JSON.
+        pub changes: Vec<String>,
+    }
+
+    /// The outcome of replaying a capture file.
+    #[derive(Clone, Debug)]
+    pub struct ReplayReport {
+        /// Number of exchanges replayed.
+        pub exchanges: usize,
+        /// The exchanges whose responses differ from the recording.
+        pub differences: Vec<ReplayDifference>,
+    }
+
+    impl fmt::Display for ReplayReport {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            for difference in &self.differences {
+                writeln!(
+                    f,
+                    "#{} {} {}",
+                    difference.index, difference.method, difference.uri
+                )?;
+                for change in &difference.changes {
+                    writeln!(f, "  {change}")?;
+                }
+            }
+            write!(
+                f,
+                "{} of {} responses differ from the recording",
+                self.differences.len(),
+                self.exchanges
+            )
+        }
+    }
+
+    /// Replays captured exchanges through the router.
+    pub struct Replay {
+        exchanges: Vec<CapturedExchange>,
+        frontend_url: String,
+    }
+
+    impl Replay {
+        /// Loads the exchanges of a capture file, to be replayed with the frontend URL
+        /// they were recorded with.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the capture file cannot be read.
+        pub fn from_file(path: &Path, frontend_url: String) -> Result<Self> {
+            Ok(Self::new(read_capture_file(path)?, frontend_url))
+        }
+
+        pub(crate) fn new(exchanges: Vec<CapturedExchange>, frontend_url: String) -> Self {
+            Self {
+                exchanges,
+                frontend_url,
+            }
+        }
+
+        /// Replays every exchange in order, comparing status codes and decoded bodies.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if a recorded request cannot be rebuilt or a response body
+        /// cannot be read.
+        pub async fn run(&self) -> Result<ReplayReport> {
+            let client = Arc::new(ReplayClient::default());
+            let state = ServerState::builder(self.frontend_url.clone())
+                .client(client.clone())
+                .build();
+            let router = create_router(false, false, state);
+
+            let mut differences = Vec::new();
+            for (index, exchange) in self.exchanges.iter().enumerate() {
+                client.load(exchange.upstream.clone());
+                let Ok(response) = router.clone().oneshot(exchange.request.to_request()?).await;
+                let (parts, body) = response.into_parts();
+                let body = buffer_body(body)
+                    .await
+                    .map_err(|(_, message)| anyhow!(message))?;
+                let replayed = CapturedResponse::new(&parts, body);
+
+                let mut changes = Vec::new();
+                if replayed.status != exchange.response.status {
+                    changes.push(format!(
+                        "status {} -> {}",
+                        exchange.response.status, replayed.status
+                    ));
+                }
+                changes.extend(diff_json(
+                    &decoded_body(&exchange.response).await,
+                    &decoded_body(&replayed).await,
+                ));
+                if !changes.is_empty() {
+                    differences.push(ReplayDifference {
+                        index: index + 1,
+                        method: exchange.request.method.clone(),
+                        uri: exchange.request.uri.clone(),
+                        changes,
+                    });
+                }
+            }
+
+            Ok(ReplayReport {
+                exchanges: self.exchanges.len(),
+                differences,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Bytes;
+
+    use super::*;
+    use crate::{
+        fixtures::Fixture,
+        state::capture_log::{CapturedExchange, CapturedRequest, CapturedResponse},
+    };
+
+    fn json_response(status: u16, body: &'static str) -> CapturedResponse {
+        CapturedResponse {
+            status,
+            headers: vec![("content-type".to_owned(), "application/json".to_owned())],
+            body: Bytes::from_static(body.as_bytes()),
+        }
+    }
+
+    fn exchange(recorded_body: &'static str) -> CapturedExchange {
+        CapturedExchange {
+            request: CapturedRequest {
+                method: "GET".to_owned(),
+                uri: "/v1/library/tags".to_owned(),
+                headers: vec![("x-kobo-deviceid".to_owned(), "kobo-1".to_owned())],
+                body: Bytes::new(),
+            },
+            upstream: Some(json_response(200, r#"{"Tags":[1,2]}"#)),
+            response: json_response(200, recorded_body),
+        }
+    }
+
+    #[tokio::test]
+    async fn matching_responses_report_no_differences() {
+        let replay = Replay::new(
+            vec![exchange(r#"{"Tags":[1,2]}"#)],
+            "http://frontend.test".to_owned(),
+        );
+
+        let report = replay.run().await.unwrap();
+
+        assert_eq!(report.exchanges, 1);
+        assert!(report.differences.is_empty());
+    }
+
+    #[tokio::test]
+    async fn changed_responses_are_reported() {
+        let replay = Replay::new(
+            vec![exchange(r#"{"Tags":[1,2]}"#), exchange(r#"{"Tags":[1,3]}"#)],
+            "http://frontend.test".to_owned(),
+        );
+
+        let report = replay.run().await.unwrap();
+
+        assert_eq!(report.differences.len(), 1);
+        assert_eq!(report.differences[0].index, 2);
+        assert_eq!(report.differences[0].changes, vec!["~ /Tags/1"]);
+        assert!(
+            report
+                .to_string()
+                .ends_with("1 of 2 responses differ from the recording")
+        );
+    }
+
+    #[tokio::test]
+    async fn fixtures_replay_with_only_the_initialization_urls_rewritten() {
+        let replay = Replay::new(
+            Fixture::ALL.map(Fixture::exchange).to_vec(),
+            "http://frontend.test".to_owned(),
+        );
+
+        let report = replay.run().await.unwrap();
+
+        assert_eq!(report.exchanges, Fixture::ALL.len());
+        assert_eq!(report.differences.len(), 1);
+        assert_eq!(report.differences[0].uri, "/v1/initialization");
+        assert!(
+            report.differences[0]
+                .changes
+                .contains(&"~ /Resources/library_sync".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_upstream_response_is_a_difference() {
+        let mut exchange = exchange(r#"{"Tags":[1,2]}"#);
+        exchange.upstream = None;
+        let replay = Replay::new(vec![exchange], "http://frontend.test".to_owned());
+
+        let report = replay.run().await.unwrap();
+
+        assert_eq!(report.differences[0].changes[0], "status 200 -> 502");
+    }
+}
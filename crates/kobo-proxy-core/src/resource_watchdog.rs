@@ -0,0 +1,110 @@
+//! Periodic task that samples resource usage and warns when limits are near.
+
+pub use implementation::{
+    RESOURCE_WATCHDOG_TASK, cache_sizes, run_resource_watchdog, sample_resources,
+};
+
+mod implementation {
+    use std::time::Duration;
+
+    use tokio::time::MissedTickBehavior;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        state::{
+            resource_usage::{CacheSizes, ResourceUsage},
+            server_state::ServerState,
+        },
+        utils::process_resources::ProcessResources,
+    };
+
+    /// Name of the resource watchdog in the background task registry.
+    pub const RESOURCE_WATCHDOG_TASK: &str = "resource-watchdog";
+
+    /// Counts the entries in the in-memory caches.
+    pub fn cache_sizes(state: &ServerState) -> CacheSizes {
+        CacheSizes {
+            devices: state.devices.devices().len(),
+            audit_entries: state.audit_log.entry_count(),
+            snapshot_versions: state.snapshots.version_count(),
+            fallback_responses: state.upstream_fallbacks.cached_count(),
+        }
+    }
+
+    /// Samples the process resources and the sizes of the in-memory caches.
+    pub fn sample_resources(state: &ServerState) -> ResourceUsage {
+        ResourceUsage::new(
+            ProcessResources::sample(),
+            cache_sizes(state),
+            state.resource_monitor.memory_warning_bytes(),
+        )
+    }
+
+    /// Samples resource usage every `interval` until `cancellation_token` is
+    /// cancelled, logging a warning for each limit that is close to being reached.
+    pub async fn run_resource_watchdog(
+        state: ServerState,
+        interval: Duration,
+        cancellation_token: CancellationToken,
+    ) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        cancellation_token
+            .run_until_cancelled(async {
+                loop {
+                    ticker.tick().await;
+                    state.background_tasks.heartbeat(RESOURCE_WATCHDOG_TASK);
+                    let usage = sample_resources(&state);
+                    tracing::debug!(
+                        open_file_descriptors = usage.process.open_file_descriptors,
+                        resident_memory_bytes = usage.process.resident_memory_bytes,
+                        caches = ?usage.caches,
+                        "Sampled resource usage"
+                    );
+                    for warning in &usage.warnings {
+                        tracing::warn!("{warning}");
+                    }
+                    state.resource_monitor.record(usage);
+                }
+            })
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio_util::sync::CancellationToken;
+
+    use super::*;
+    use crate::state::server_state::ServerState;
+
+    #[test]
+    fn sample_resources_counts_cache_entries() {
+        let state = ServerState::builder("http://frontend.test").build();
+        state.devices.record_request("device-1", None);
+
+        let usage = sample_resources(&state);
+
+        assert_eq!(usage.caches.devices, 1);
+        assert_eq!(usage.caches.audit_entries, 0);
+    }
+
+    #[tokio::test]
+    async fn run_resource_watchdog_records_samples() {
+        let state = ServerState::builder("http://frontend.test").build();
+        let cancellation_token = CancellationToken::new();
+        let task = tokio::spawn(run_resource_watchdog(
+            state.clone(),
+            Duration::from_secs(60),
+            cancellation_token.clone(),
+        ));
+
+        while state.resource_monitor.latest().is_none() {
+            tokio::task::yield_now().await;
+        }
+        cancellation_token.cancel();
+        task.await.unwrap();
+    }
+}
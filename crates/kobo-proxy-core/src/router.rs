@@ -0,0 +1,252 @@
+//! The Router module for the Kobo server, defining routes and middleware.
+
+pub use implementation::{create_admin_router, create_router};
+
+mod implementation {
+    use axum::{
+        Router, middleware,
+        routing::{any, get, post},
+    };
+    use hyper::StatusCode;
+    use tower::{Layer as _, ServiceBuilder};
+    use tower_http::normalize_path::{NormalizePath, NormalizePathLayer};
+
+    use crate::{
+        api::rate_limit,
+        middleware::{
+            access_schedule, auth_backoff, bandwidth_accounting, capture_exchanges, chaos,
+            client_address, deadline, device_serialization, device_tracking, group_policies,
+            header_hygiene, request_logging, response_patches, schema_validation,
+            snapshot_requests,
+        },
+        routes::{
+            audit::audit_handler, bandwidth::bandwidth_handler, devices::devices_handler,
+            initialization::initialization_handler, kobo_store_request::kobo_store_request,
+            landing_page::landing_page_handler, listener_stats::listener_stats_handler,
+            preview_rewrite::preview_rewrite_handler, readiness::readiness_handler,
+            resources::resources_handler, setup::setup_handler, snapshots::snapshots_handler,
+            state_export::state_export_handler, status::status_handler,
+            synthetic_auth::synthetic_device_auth_handler, user_profile::user_profile_handler,
+        },
+        state::server_state::ServerState,
+    };
+
+    /// The local API routes, which are not forwarded to the Kobo API.
+    fn admin_routes(server_state: &ServerState) -> Router<ServerState> {
+        Router::new()
+            .route("/api/audit", get(audit_handler))
+            .route("/api/devices", get(devices_handler))
+            .route("/api/listener", get(listener_stats_handler))
+            .route("/api/resources", get(resources_handler))
+            .route("/api/snapshots", get(snapshots_handler))
+            .route("/api/stats/bandwidth", get(bandwidth_handler))
+            .route("/api/status", get(status_handler))
+            .route("/api/admin/preview-rewrite", post(preview_rewrite_handler))
+            .route("/api/admin/state", get(state_export_handler))
+            .route_layer(middleware::from_fn_with_state(
+                server_state.clone(),
+                rate_limit::enforce_rate_limits,
+            ))
+    }
+
+    /// The routes answered by the server itself rather than forwarded to the Kobo API.
+    fn local_routes(server_state: &ServerState) -> Router<ServerState> {
+        let router = Router::new()
+            .route("/readyz", get(readiness_handler))
+            .route("/v1/initialization", get(initialization_handler))
+            .route("/v1/user/profile", get(user_profile_handler));
+        let router = if server_state.synthetic_device_auth {
+            router.route("/v1/auth/device", post(synthetic_device_auth_handler))
+        } else {
+            router
+        };
+        let router = if server_state.landing_page {
+            router
+                .route("/", get(landing_page_handler))
+                .route("/setup", get(setup_handler))
+        } else {
+            router
+        };
+        // When the local API is served on the admin listener, its paths are answered
+        // here rather than falling through to the Kobo API.
+        if server_state.serve_admin_api {
+            router.merge(admin_routes(server_state))
+        } else {
+            router
+                .route("/api", any(StatusCode::NOT_FOUND))
+                .route("/api/{*path}", any(StatusCode::NOT_FOUND))
+        }
+    }
+
+    /// Creates and configures the Axum router with default server state.
+    pub fn create_router(
+        enable_request_logging: bool,
+        enable_response_logging: bool,
+        server_state: ServerState,
+    ) -> NormalizePath<Router<()>> {
+        let router = local_routes(&server_state)
+            .fallback(kobo_store_request)
+            .layer(
+                ServiceBuilder::new()
+                    .option_layer(server_state.capture_log.is_some().then(|| {
+                        middleware::from_fn_with_state(
+                            server_state.clone(),
+                            capture_exchanges::capture_exchanges,
+                        )
+                    }))
+                    .layer(middleware::from_fn(header_hygiene::harden_requests))
+                    .option_layer((!server_state.trusted_proxies.is_empty()).then(|| {
+                        middleware::from_fn_with_state(
+                            server_state.clone(),
+                            client_address::resolve_client_address,
+                        )
+                    }))
+                    .layer(middleware::from_fn_with_state(
+                        server_state.clone(),
+                        device_tracking::track_devices,
+                    ))
+                    .layer(middleware::from_fn_with_state(
+                        server_state.clone(),
+                        bandwidth_accounting::account_bandwidth,
+                    ))
+                    .option_layer(server_state.auth_backoff.is_enabled().then(|| {
+                        middleware::from_fn_with_state(
+                            server_state.clone(),
+                            auth_backoff::enforce_auth_backoff,
+                        )
+                    }))
+                    .option_layer((!server_state.route_timeouts.is_empty()).then(|| {
+                        middleware::from_fn_with_state(
+                            server_state.clone(),
+                            deadline::enforce_deadlines,
+                        )
+                    }))
+                    .option_layer((!server_state.access_schedule.is_empty()).then(|| {
+                        middleware::from_fn_with_state(
+                            server_state.clone(),
+                            access_schedule::enforce_access_schedule,
+                        )
+                    }))
+                    .option_layer((!server_state.device_groups.is_empty()).then(|| {
+                        middleware::from_fn_with_state(
+                            server_state.clone(),
+                            group_policies::enforce_group_policies,
+                        )
+                    }))
+                    .option_layer((!server_state.chaos_rules.is_empty()).then(|| {
+                        middleware::from_fn_with_state(server_state.clone(), chaos::inject_chaos)
+                    }))
+                    .option_layer((!server_state.response_patches.is_empty()).then(|| {
+                        middleware::from_fn_with_state(
+                            server_state.clone(),
+                            response_patches::apply_response_patches,
+                        )
+                    }))
+                    .option_layer(server_state.schema_validation.is_enabled().then(|| {
+                        middleware::from_fn_with_state(
+                            server_state.clone(),
+                            schema_validation::validate_response_schemas,
+                        )
+                    }))
+                    .option_layer(server_state.snapshots_enabled.then(|| {
+                        middleware::from_fn_with_state(
+                            server_state.clone(),
+                            snapshot_requests::remember_snapshot_requests,
+                        )
+                    }))
+                    .option_layer(server_state.serialize_device_requests.then(|| {
+                        middleware::from_fn_with_state(
+                            server_state.clone(),
+                            device_serialization::serialize_device_requests,
+                        )
+                    }))
+                    .option_layer(enable_request_logging.then(|| {
+                        middleware::from_fn_with_state(
+                            server_state.clone(),
+                            request_logging::log_requests,
+                        )
+                    }))
+                    .option_layer(enable_response_logging.then(|| {
+                        middleware::from_fn_with_state(
+                            server_state.clone(),
+                            request_logging::log_responses,
+                        )
+                    })),
+            )
+            .with_state(server_state);
+
+        // Removes double leading slashes and removes trailing slashes. The Kobo device
+        // always sends a double leading slash in its requests (e.g.,
+        // "//library"), so we normalize the path to ensure consistent
+        // routing.
+        NormalizePathLayer::trim_trailing_slash().layer(router)
+    }
+
+    /// Creates the router for the dedicated admin listener, serving only the local API.
+    pub fn create_admin_router(server_state: ServerState) -> NormalizePath<Router<()>> {
+        let router = admin_routes(&server_state)
+            .fallback(any(StatusCode::NOT_FOUND))
+            .with_state(server_state);
+
+        NormalizePathLayer::trim_trailing_slash().layer(router)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt as _;
+
+    use super::*;
+    use crate::state::{fake_kobo_client::FakeKoboClient, server_state::ServerState};
+
+    #[tokio::test]
+    async fn multiple_leading_and_trailing_slashes_are_normalized_by_layer() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+        stub.enqueue_response(
+            axum::http::Response::builder()
+                .status(200)
+                .body(Body::from("ok"))
+                .unwrap(),
+        );
+        let router = create_router(false, false, state);
+
+        let request = Request::builder()
+            .uri("////some/path///")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), 200);
+        // We can't directly read normalized path from response, but normalization allowed fallback
+        // to match and stub to be consumed. Indirect evidence: stub saw request with normalized
+        // path.
+        let recorded = stub.recorded_requests();
+        let forwarded = recorded.first().expect("expected forwarded request");
+        assert_eq!(forwarded.uri.path(), "/some/path");
+    }
+
+    #[tokio::test]
+    async fn api_routes_are_not_forwarded_when_served_on_admin_listener() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .serve_admin_api(false)
+            .build();
+        let router = create_router(false, false, state);
+
+        let request = Request::builder()
+            .uri("/api/devices")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), 404);
+        assert!(stub.recorded_requests().is_empty());
+    }
+}
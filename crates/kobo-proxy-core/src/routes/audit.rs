@@ -0,0 +1,136 @@
+//! Handler for the audit log API route.
+
+pub use implementation::audit_handler;
+
+mod implementation {
+    use axum::{Json, extract::State};
+    use serde::Deserialize;
+
+    use crate::{
+        api::{
+            error::ApiError,
+            pagination::{Page, Pagination, SortOrder},
+            query::ApiQuery,
+        },
+        state::{audit_log::AuditEntry, server_state::ServerState},
+    };
+
+    /// Query parameters accepted by the audit endpoint.
+    #[derive(Debug, Deserialize)]
+    pub struct AuditQuery {
+        /// Only return entries for this route template.
+        route: Option<String>,
+    }
+
+    /// Handler for the `/api/audit` endpoint. Lists the most recent response
+    /// modifications, newest first.
+    pub async fn audit_handler(
+        State(state): State<ServerState>,
+        ApiQuery(query): ApiQuery<AuditQuery>,
+        pagination: Pagination,
+    ) -> Result<Json<Page<AuditEntry>>, ApiError> {
+        let entries = state.audit_log.entries(query.route.as_deref(), usize::MAX);
+        Ok(Json(pagination.page(
+            entries,
+            SortOrder::Descending,
+            |entry| entry.sequence,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::StatusCode;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{
+            audit_log::{AuditEntry, AuditRule},
+            server_state::ServerState,
+        },
+    };
+
+    #[tokio::test]
+    async fn audit_handler_filters_by_route() {
+        let state = ServerState::builder("http://frontend.test").build();
+        state.audit_log.record(AuditEntry::new(
+            "/v1/initialization",
+            AuditRule::UrlRewrite,
+            -12,
+            Some("request-1".to_owned()),
+        ));
+        state.audit_log.record(AuditEntry::new(
+            "/v1/library/sync",
+            AuditRule::HeaderStrip,
+            0,
+            None,
+        ));
+        let router = create_router(false, false, state);
+
+        let request = Request::builder()
+            .uri("/api/audit?route=/v1/initialization")
+            .body(Body::empty())
+            .expect("failed to build request");
+        let response = router
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let entries = &page["items"];
+        assert_eq!(entries.as_array().unwrap().len(), 1);
+        assert!(page["next_cursor"].is_null());
+        assert_eq!(entries[0]["rule"], "url_rewrite");
+        assert_eq!(entries[0]["byte_delta"], -12);
+        assert_eq!(entries[0]["request_id"], "request-1");
+    }
+
+    async fn audit_page(state: &ServerState, uri: &str) -> serde_json::Value {
+        let request = Request::builder()
+            .uri(uri)
+            .body(Body::empty())
+            .expect("failed to build request");
+        let response = create_router(false, false, state.clone())
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn routes(page: &serde_json::Value) -> Vec<&str> {
+        page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["route"].as_str().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn audit_pages_are_stable_while_entries_are_recorded() {
+        let state = ServerState::builder("http://frontend.test").build();
+        for route in ["/first", "/second", "/third"] {
+            state
+                .audit_log
+                .record(AuditEntry::new(route, AuditRule::UrlRewrite, 0, None));
+        }
+
+        let first = audit_page(&state, "/api/audit?limit=2").await;
+        assert_eq!(routes(&first), ["/third", "/second"]);
+        state
+            .audit_log
+            .record(AuditEntry::new("/fourth", AuditRule::UrlRewrite, 0, None));
+        let cursor = first["next_cursor"].as_str().unwrap();
+        let second = audit_page(&state, &format!("/api/audit?limit=2&cursor={cursor}")).await;
+
+        assert_eq!(routes(&second), ["/first"]);
+        assert!(second["next_cursor"].is_null());
+    }
+}
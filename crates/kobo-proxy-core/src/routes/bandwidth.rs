@@ -0,0 +1,104 @@
+//! Handler for the bandwidth stats API route.
+
+pub use implementation::bandwidth_handler;
+
+mod implementation {
+    use axum::{Json, extract::State};
+    use chrono::NaiveDate;
+    use serde::Deserialize;
+
+    use crate::{
+        api::{error::ApiError, query::ApiQuery},
+        state::{
+            bandwidth::{BandwidthReport, BandwidthUsage},
+            server_state::ServerState,
+        },
+    };
+
+    /// Query parameters accepted by the bandwidth endpoint.
+    #[derive(Debug, Deserialize)]
+    pub struct BandwidthQuery {
+        /// The month to report, as `YYYY-MM`. Defaults to the current month.
+        month: Option<String>,
+    }
+
+    /// Handler for the `/api/stats/bandwidth` endpoint. Reports the body bytes
+    /// exchanged with each device in a month, in total and by route template.
+    pub async fn bandwidth_handler(
+        State(state): State<ServerState>,
+        ApiQuery(query): ApiQuery<BandwidthQuery>,
+    ) -> Result<Json<BandwidthReport>, ApiError> {
+        let month = match query.month {
+            Some(month) => {
+                NaiveDate::parse_from_str(&format!("{month}-01"), "%Y-%m-%d").map_err(|_| {
+                    ApiError::bad_request(format!("Invalid month '{month}', expected YYYY-MM"))
+                })?;
+                month
+            }
+            None => BandwidthUsage::current_month(),
+        };
+
+        Ok(Json(state.bandwidth.report(&month)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::StatusCode;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{bandwidth::Traffic, server_state::ServerState},
+    };
+
+    async fn get(state: ServerState, uri: &str) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder()
+            .uri(uri)
+            .body(Body::empty())
+            .expect("failed to build request");
+        let response = create_router(false, false, state)
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn bandwidth_handler_reports_current_month() {
+        let state = ServerState::builder("http://frontend.test").build();
+        state.bandwidth.record(
+            "device-1",
+            "/v1/library/sync",
+            Traffic {
+                requests: 1,
+                bytes_in: 0,
+                bytes_out: 2048,
+            },
+        );
+
+        let (status, report) = get(state, "/api/stats/bandwidth").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(report["bytes_out"], 2048);
+        assert_eq!(report["devices"][0]["device_id"], "device-1");
+        assert_eq!(
+            report["devices"][0]["routes"][0]["route"],
+            "/v1/library/sync"
+        );
+    }
+
+    #[tokio::test]
+    async fn bandwidth_handler_rejects_invalid_month() {
+        let state = ServerState::builder("http://frontend.test").build();
+
+        let (status, body) = get(state, "/api/stats/bandwidth?month=October").await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "bad_request");
+    }
+}
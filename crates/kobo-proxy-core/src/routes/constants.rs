@@ -5,3 +5,6 @@ pub const KOBO_API_BASE_URI: &str = "storeapi.kobo.com";
 
 /// Full URL for the Kobo API.
 pub const KOBO_API_URL: &str = "https://storeapi.kobo.com";
+
+/// Prefix of the local API routes, which are not made by devices.
+pub const API_PREFIX: &str = "/api/";
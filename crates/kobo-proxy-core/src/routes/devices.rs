@@ -0,0 +1,63 @@
+//! Handler for the devices API route.
+
+pub use implementation::devices_handler;
+
+mod implementation {
+    use axum::{Json, extract::State};
+
+    use crate::{
+        api::{
+            error::ApiError,
+            pagination::{Page, Pagination, SortOrder},
+        },
+        state::{devices::DeviceRecord, server_state::ServerState},
+    };
+
+    /// Handler for the `/api/devices` endpoint. Lists every device seen by the server,
+    /// including its most recently measured clock skew.
+    pub async fn devices_handler(
+        State(state): State<ServerState>,
+        pagination: Pagination,
+    ) -> Result<Json<Page<DeviceRecord>>, ApiError> {
+        Ok(Json(pagination.page(
+            state.devices.devices(),
+            SortOrder::Ascending,
+            |device| device.id.clone(),
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::StatusCode;
+    use tower::ServiceExt as _;
+
+    use crate::{router::create_router, state::server_state::ServerState};
+
+    #[tokio::test]
+    async fn devices_handler_lists_recorded_devices() {
+        let state = ServerState::builder("http://frontend.test").build();
+        state.devices.record_request("device-1", Some("Kobo"));
+        state.devices.record_clock_skew("device-1", 42);
+        let router = create_router(false, false, state);
+
+        let request = Request::builder()
+            .uri("/api/devices")
+            .body(Body::empty())
+            .expect("failed to build request");
+        let response = router
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let devices = &page["items"];
+        assert_eq!(devices[0]["id"], "device-1");
+        assert_eq!(devices[0]["user_agent"], "Kobo");
+        assert_eq!(devices[0]["clock_skew_seconds"], 42);
+    }
+}
@@ -0,0 +1,305 @@
+//! Handler for the initialization route.
+
+pub use implementation::{INITIALIZATION_ROUTE, initialization_handler, rewrite_urls};
+
+mod implementation {
+    use axum::response::Response;
+
+    use crate::{
+        routes::{constants::KOBO_API_URL, kobo_store_request::kobo_store_request},
+        state::{
+            audit_log::{AuditEntry, AuditRule, byte_delta, request_id},
+            devices::identify_device,
+            server_state::ServerState,
+            upstream_fallbacks::DEGRADED_HEADER,
+        },
+        utils::http_body::{
+            decode_response_body, encode_response_body, is_gzip_encoded, read_response_body,
+        },
+    };
+
+    /// Route template of the initialization endpoint, under which successful responses
+    /// are cached.
+    pub const INITIALIZATION_ROUTE: &str = "/v1/initialization";
+
+    /// Rewrites Kobo API base URLs in an initialization body to `frontend_url`.
+    pub fn rewrite_urls(body_text: &str, frontend_url: &str) -> String {
+        body_text.replace(KOBO_API_URL, frontend_url)
+    }
+
+    /// Handler for the `/v1/initialization` endpoint. Forwards to Kobo API and rewrites
+    /// Kobo API base URLs in the JSON body to the device's frontend URL, preserving
+    /// gzip encoding if present. Successful responses are cached before rewriting so
+    /// they can be replayed, and rewritten for the requesting device, if the Kobo API
+    /// later fails.
+    pub async fn initialization_handler(
+        state: axum::extract::State<ServerState>,
+        request: axum::extract::Request,
+    ) -> Result<Response, hyper::StatusCode> {
+        let frontend_url = state
+            .frontend_url_for(identify_device(&request).as_deref())
+            .to_owned();
+        let upstream_fallbacks = state.upstream_fallbacks.clone();
+        let audit_log = state.audit_log.clone();
+        let gzip_compression = state.gzip_compression;
+        let request_id = request_id(request.headers());
+        let response = kobo_store_request(state, request).await?;
+        let degraded = response.headers().contains_key(DEGRADED_HEADER);
+        let (parts, bytes) = read_response_body(response).await?;
+        if parts.status.is_success() && !degraded {
+            upstream_fallbacks.cache(INITIALIZATION_ROUTE, &parts.headers, &bytes);
+        }
+        let gz = is_gzip_encoded(&parts.headers);
+        let body_text = decode_response_body(&bytes, gz).await?;
+        let modified = rewrite_urls(&body_text, &frontend_url);
+        if modified != body_text {
+            audit_log.record(AuditEntry::new(
+                INITIALIZATION_ROUTE,
+                AuditRule::UrlRewrite,
+                byte_delta(body_text.len(), modified.len()),
+                request_id,
+            ));
+        }
+        let body = encode_response_body(&modified, gz.then_some(gzip_compression)).await?;
+        Ok(Response::from_parts(parts, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use anyhow::anyhow;
+    use axum::{
+        body::Body,
+        http::{Request, Response, StatusCode},
+    };
+    use flate2::Compression;
+    use http_body_util::BodyExt as _;
+    use hyper::Method;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{
+            audit_log::AuditRule, fake_kobo_client::FakeKoboClient, server_state::ServerState,
+            upstream_fallbacks::UpstreamFallbacks,
+        },
+        utils::{
+            device_frontend_urls::DeviceFrontendUrls,
+            http_body::{compress_gzip, decompress_gzip},
+        },
+    };
+
+    #[tokio::test]
+    async fn test_initialization_handler_replaces_urls_in_gzipped_response() {
+        let original_json = r#"{"Resources":{"library_sync":"https://storeapi.kobo.com/v1/library/sync","user_profile":"https://storeapi.kobo.com/v1/user/profile"}}"#;
+        let compressed_json =
+            compress_gzip(original_json, Compression::default()).expect("Failed to compress JSON");
+        let configured_frontend = "http://placeholder.local";
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder(configured_frontend)
+            .client(stub.clone())
+            .build();
+        let router = create_router(false, false, state);
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json; charset=utf-8")
+                .header("content-encoding", "gzip")
+                .body(Body::from(compressed_json))
+                .expect("Failed to build stub response"),
+        );
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("http://example.com:8080/v1/initialization")
+            .body(Body::empty())
+            .expect("Failed to build request");
+        let response = router
+            .oneshot(request)
+            .await
+            .expect("Service should return a response");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("content-encoding").unwrap(), "gzip");
+        let (_, body) = response.into_parts();
+        let bytes = body
+            .collect()
+            .await
+            .expect("Failed to collect body")
+            .to_bytes();
+        let decompressed = decompress_gzip(&bytes).expect("Failed to decompress response");
+        assert!(!decompressed.contains("https://storeapi.kobo.com"));
+        assert!(decompressed.contains(configured_frontend));
+        assert!(decompressed.contains(&format!("{configured_frontend}/v1/library/sync")));
+        assert!(decompressed.contains(&format!("{configured_frontend}/v1/user/profile")));
+    }
+
+    #[tokio::test]
+    async fn test_initialization_handler_replaces_urls_in_plain_response() {
+        let original_json = r#"{"Resources":{"library_sync":"https://storeapi.kobo.com/v1/library/sync","user_profile":"https://storeapi.kobo.com/v1/user/profile"}}"#;
+        let configured_frontend = "https://frontend.example";
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder(configured_frontend)
+            .client(stub.clone())
+            .build();
+        let router = create_router(false, false, state);
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(Body::from(original_json))
+                .expect("Failed to build stub response"),
+        );
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("https://localhost:3000/v1/initialization")
+            .body(Body::empty())
+            .expect("Failed to build request");
+        let response = router
+            .oneshot(request)
+            .await
+            .expect("Service should return a response");
+        assert_eq!(response.status(), StatusCode::OK);
+        let (_, body) = response.into_parts();
+        let bytes = body
+            .collect()
+            .await
+            .expect("Failed to collect body")
+            .to_bytes();
+        let body_text = String::from_utf8(bytes.to_vec()).expect("Failed to decode response");
+        assert!(!body_text.contains("https://storeapi.kobo.com"));
+        assert!(body_text.contains(configured_frontend));
+        assert!(body_text.contains(&format!("{configured_frontend}/v1/library/sync")));
+        assert!(body_text.contains(&format!("{configured_frontend}/v1/user/profile")));
+    }
+
+    #[tokio::test]
+    async fn test_initialization_handler_replays_last_response_when_upstream_fails() {
+        let original_json =
+            r#"{"Resources":{"library_sync":"https://storeapi.kobo.com/v1/library/sync"}}"#;
+        let configured_frontend = "https://frontend.example";
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder(configured_frontend)
+            .client(stub.clone())
+            .upstream_fallbacks(UpstreamFallbacks::new(true, HashMap::new()))
+            .build();
+        let router = create_router(false, false, state);
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(Body::from(original_json))
+                .expect("Failed to build stub response"),
+        );
+        stub.enqueue_error(anyhow!("stubbed failure"));
+        let build_request = || {
+            Request::builder()
+                .method(Method::GET)
+                .uri("/v1/initialization")
+                .body(Body::empty())
+                .expect("Failed to build request")
+        };
+
+        let first = router
+            .clone()
+            .oneshot(build_request())
+            .await
+            .expect("Service should return a response");
+        let first_body = first.into_body().collect().await.unwrap().to_bytes();
+        let second = router
+            .oneshot(build_request())
+            .await
+            .expect("Service should return a response");
+
+        assert_eq!(second.status(), StatusCode::OK);
+        assert_eq!(
+            second.headers().get("x-kobo-proxy-degraded").unwrap(),
+            "true"
+        );
+        let second_body = second.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(first_body, second_body);
+        assert!(String::from_utf8_lossy(&second_body).contains(configured_frontend));
+    }
+
+    #[tokio::test]
+    async fn test_initialization_handler_audits_url_rewrite() {
+        let original_json = r#"{"library_sync":"https://storeapi.kobo.com/v1/library/sync"}"#;
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://kobo.lan")
+            .client(stub.clone())
+            .build();
+        let router = create_router(false, false, state.clone());
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(original_json))
+                .expect("Failed to build stub response"),
+        );
+        let request = Request::builder()
+            .uri("/v1/initialization")
+            .body(Body::empty())
+            .expect("Failed to build request");
+
+        let _response = router
+            .oneshot(request)
+            .await
+            .expect("Service should return a response");
+
+        let entries = state.audit_log.entries(None, 10);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].route, "/v1/initialization");
+        assert_eq!(entries[0].rule, AuditRule::UrlRewrite);
+        let expected_delta = i64::try_from("http://kobo.lan".len()).unwrap()
+            - i64::try_from("https://storeapi.kobo.com".len()).unwrap();
+        assert_eq!(entries[0].byte_delta, expected_delta);
+    }
+
+    #[tokio::test]
+    async fn test_initialization_handler_uses_device_frontend_url() {
+        let original_json =
+            r#"{"Resources":{"library_sync":"https://storeapi.kobo.com/v1/library/sync"}}"#;
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://kobo.lan")
+            .client(stub.clone())
+            .device_frontend_urls(
+                DeviceFrontendUrls::new(&["kobo-travel=https://kobo.tailnet.ts.net"]).unwrap(),
+            )
+            .upstream_fallbacks(UpstreamFallbacks::new(true, HashMap::new()))
+            .build();
+        let router = create_router(false, false, state);
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(original_json))
+                .expect("Failed to build stub response"),
+        );
+        stub.enqueue_error(anyhow!("stubbed failure"));
+        let build_request = |device_id: &str| {
+            Request::builder()
+                .uri("/v1/initialization")
+                .header("x-kobo-deviceid", device_id)
+                .body(Body::empty())
+                .expect("Failed to build request")
+        };
+
+        let travel = router
+            .clone()
+            .oneshot(build_request("kobo-travel"))
+            .await
+            .expect("Service should return a response");
+        let travel_body = travel.into_body().collect().await.unwrap().to_bytes();
+        // The replayed fallback is rewritten for the requesting device, not the
+        // device whose response was cached.
+        let lan = router
+            .oneshot(build_request("kobo-lan"))
+            .await
+            .expect("Service should return a response");
+        let lan_body = lan.into_body().collect().await.unwrap().to_bytes();
+
+        assert!(
+            String::from_utf8_lossy(&travel_body)
+                .contains("https://kobo.tailnet.ts.net/v1/library/sync")
+        );
+        assert!(String::from_utf8_lossy(&lan_body).contains("http://kobo.lan/v1/library/sync"));
+    }
+}
@@ -0,0 +1,904 @@
+//! Fallback handler for requests to the Kobo store API
+
+pub use implementation::kobo_store_request;
+
+mod implementation {
+    use anyhow::Result;
+    use axum::{
+        extract::{Request, State},
+        http::{
+            HeaderMap, Uri,
+            header::SET_COOKIE,
+            uri::{Parts, Scheme},
+        },
+        response::{IntoResponse as _, Response},
+    };
+    use hyper::body::Body as _;
+
+    use crate::{
+        routes::constants::KOBO_API_BASE_URI,
+        state::{
+            audit_log::{AuditEntry, AuditRule, byte_delta, request_id},
+            cookie_jar::SessionKey,
+            devices::identify_device,
+            server_state::ServerState,
+        },
+        utils::{
+            cookie_policy::CookiePolicy,
+            device_info::DeviceInfo,
+            protected_content::is_protected_content,
+            route_timeouts::{BUDGET_HEADER, Deadline},
+            upgrade::{is_upgrade_request, tunnel_upgrade},
+        },
+    };
+
+    /// Generate URI parts for the Kobo API given a path and query string.
+    fn generate_kobo_uri_parts(path_and_query: &str) -> Result<Parts> {
+        let mut parts = Parts::default();
+        parts.scheme = Some(Scheme::HTTPS);
+        parts.authority = Some(KOBO_API_BASE_URI.parse()?);
+        parts.path_and_query = Some(path_and_query.parse()?);
+        Ok(parts)
+    }
+
+    /// Generate a full URI for the Kobo API given a path and query string.
+    fn generate_kobo_uri(path_and_query: &str) -> Result<Uri> {
+        let parts = generate_kobo_uri_parts(path_and_query)?;
+        Ok(Uri::from_parts(parts)?)
+    }
+
+    /// Checks if an upstream status means the Kobo API itself is unavailable.
+    fn is_gateway_failure(status: hyper::StatusCode) -> bool {
+        matches!(
+            status,
+            hyper::StatusCode::BAD_GATEWAY
+                | hyper::StatusCode::SERVICE_UNAVAILABLE
+                | hyper::StatusCode::GATEWAY_TIMEOUT
+        )
+    }
+
+    /// Records a fallback response in the audit log.
+    fn record_fallback(
+        server_state: &ServerState,
+        route: String,
+        request_id: Option<String>,
+        fallback: &Response,
+    ) {
+        let size = fallback.body().size_hint().exact().unwrap_or_default();
+        server_state.audit_log.record(AuditEntry::new(
+            route,
+            AuditRule::UpstreamFallback,
+            byte_delta(0, usize::try_from(size).unwrap_or(usize::MAX)),
+            request_id,
+        ));
+    }
+
+    /// Sets the headers of a request being forwarded to the Kobo API.
+    fn prepare_upstream_headers(
+        server_state: &ServerState,
+        device_id: Option<&str>,
+        request: &mut Request,
+    ) {
+        let deadline = request.extensions().get::<Deadline>().copied();
+        let headers = request.headers_mut();
+        // Replace the `host` header to match the Kobo API host. Required since
+        // we're forwarding the request to a different host.
+        headers.insert(
+            hyper::header::HOST,
+            hyper::header::HeaderValue::from_static(KOBO_API_BASE_URI),
+        );
+        server_state.region_override.apply_to_headers(headers);
+        server_state
+            .header_injection
+            .apply_to_headers(headers, device_id);
+        if let Some(deadline) = deadline {
+            headers.insert(
+                BUDGET_HEADER,
+                hyper::header::HeaderValue::from(
+                    u64::try_from(deadline.remaining().as_millis()).unwrap_or(u64::MAX),
+                ),
+            );
+        }
+    }
+
+    /// Applies the cookie policy to the `Set-Cookie` headers of an upstream response,
+    /// storing the cookies for the request's session and upstream path under the
+    /// `store` policy. Returns whether any headers were removed.
+    fn apply_cookie_policy(
+        server_state: &ServerState,
+        cookie_session: Option<&(SessionKey, String)>,
+        headers: &mut HeaderMap,
+    ) -> bool {
+        if !headers.contains_key(SET_COOKIE) {
+            return false;
+        }
+        match server_state.cookie_policy {
+            CookiePolicy::Pass => return false,
+            CookiePolicy::Strip => {}
+            CookiePolicy::Store => {
+                if let Some((session, path)) = cookie_session {
+                    server_state.cookie_jar.store(*session, path, headers);
+                } else {
+                    tracing::warn!("Dropping upstream cookies for an unauthenticated request");
+                }
+            }
+        }
+        headers.remove(SET_COOKIE);
+        true
+    }
+
+    /// Fallback handler that forwards requests to the Kobo store API. Intended to
+    /// be used as an axum fallback handler.
+    ///
+    /// Upgrade requests (e.g. WebSocket handshakes) are forwarded as-is over HTTP/1.1,
+    /// and if the upstream switches protocols the two connections are tunnelled
+    /// together.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `hyper::StatusCode` error if the request could not be forwarded
+    /// and no fallback response is configured for the route, or if the URI is invalid.
+    #[tracing::instrument(
+        skip_all,
+        fields(method = %request.method(), path = request.uri().path())
+    )]
+    pub async fn kobo_store_request(
+        server_state: State<ServerState>,
+        mut request: Request,
+    ) -> Result<Response, hyper::StatusCode> {
+        let path_and_query = if let Some(pq) = request.uri().path_and_query() {
+            pq.as_str()
+        } else {
+            tracing::error!("Request URI missing path and query");
+            return Err(hyper::StatusCode::BAD_REQUEST);
+        };
+
+        let route = server_state
+            .route_templates
+            .normalize(request.uri().path())
+            .into_owned();
+        let request_id = request_id(request.headers());
+        let device_id = identify_device(&request);
+        let firmware = request
+            .headers()
+            .get(hyper::header::USER_AGENT)
+            .and_then(|user_agent| user_agent.to_str().ok())
+            .and_then(DeviceInfo::parse)
+            .map(|device_info| device_info.firmware);
+        let path_and_query = server_state
+            .region_override
+            .apply_to_path_and_query(path_and_query);
+        *request.uri_mut() = generate_kobo_uri(&path_and_query).map_err(|e| {
+            tracing::error!("Invalid URI: {e}");
+            hyper::StatusCode::BAD_REQUEST
+        })?;
+
+        prepare_upstream_headers(&server_state, device_id.as_deref(), &mut request);
+        let cookie_session = (server_state.cookie_policy == CookiePolicy::Store)
+            .then(|| server_state.cookie_jar.session_key(request.headers()))
+            .flatten()
+            .map(|session| (session, request.uri().path().to_owned()));
+        if let Some((session, path)) = &cookie_session {
+            server_state
+                .cookie_jar
+                .apply_to_headers(*session, path, request.headers_mut());
+        }
+
+        let downstream_upgrade =
+            is_upgrade_request(request.headers()).then(|| hyper::upgrade::on(&mut request));
+
+        let client = if downstream_upgrade.is_some() {
+            &server_state.upgrade_client
+        } else {
+            &server_state.client
+        };
+        match client.request(request).await {
+            Ok(mut resp) => {
+                if let Some(downstream_upgrade) = downstream_upgrade
+                    && resp.status() == hyper::StatusCode::SWITCHING_PROTOCOLS
+                {
+                    tunnel_upgrade(downstream_upgrade, hyper::upgrade::on(&mut resp));
+                    return Ok(resp);
+                }
+
+                if is_gateway_failure(resp.status())
+                    && let Some(fallback) = server_state.upstream_fallbacks.response_for(&route)
+                {
+                    tracing::warn!(
+                        "Upstream responded with {} for {route}, serving fallback response",
+                        resp.status()
+                    );
+                    record_fallback(&server_state, route, request_id, &fallback);
+                    return Ok(fallback);
+                }
+
+                if is_protected_content(resp.headers()) {
+                    server_state
+                        .protected_passthroughs
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    tracing::debug!(route, "Passing DRM-protected response through untouched");
+                }
+
+                if apply_cookie_policy(&server_state, cookie_session.as_ref(), resp.headers_mut()) {
+                    server_state.audit_log.record(AuditEntry::new(
+                        route.clone(),
+                        AuditRule::HeaderStrip,
+                        0,
+                        request_id.clone(),
+                    ));
+                }
+
+                // Remove `transfer-encoding` header. The Kobo sync hangs if this
+                // header is present in the response on affected firmware.
+                if server_state
+                    .strip_transfer_encoding
+                    .matches(firmware.as_ref())
+                    && resp.headers_mut().remove("transfer-encoding").is_some()
+                {
+                    server_state.audit_log.record(AuditEntry::new(
+                        route,
+                        AuditRule::HeaderStrip,
+                        0,
+                        request_id,
+                    ));
+                }
+                Ok(resp.into_response())
+            }
+            Err(e) => {
+                tracing::error!("Error forwarding request: {e}");
+                if let Some(fallback) = server_state.upstream_fallbacks.response_for(&route) {
+                    tracing::warn!("Serving fallback response for {route}");
+                    record_fallback(&server_state, route, request_id, &fallback);
+                    return Ok(fallback);
+                }
+                Err(hyper::StatusCode::BAD_GATEWAY)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+    use anyhow::anyhow;
+    use axum::{
+        Router,
+        body::Body,
+        http::{
+            Request, Response, StatusCode,
+            header::{ACCEPT_LANGUAGE, CONNECTION, COOKIE, HOST, SET_COOKIE, UPGRADE},
+        },
+    };
+    use http_body_util::BodyExt as _;
+    use hyper::{Method, body::Incoming, service::service_fn};
+    use hyper_util::rt::TokioIo;
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::{TcpListener, TcpStream},
+    };
+    use tower::ServiceExt as _;
+    use tower_http::normalize_path::NormalizePath;
+
+    use crate::{
+        router::create_router,
+        state::{
+            audit_log::AuditRule, capture_log::CaptureLog, client::KoboClient,
+            fake_kobo_client::FakeKoboClient, server_state::ServerState,
+            upstream_fallbacks::UpstreamFallbacks,
+        },
+        utils::{
+            cookie_policy::CookiePolicy, header_injection::HeaderInjection,
+            region_override::RegionOverride, response_patches::ResponsePatches,
+        },
+    };
+
+    const TEST_BODY: &str = "test body";
+    const TEST_RESPONSE: &str = "stubbed response";
+
+    fn build_request() -> Request<Body> {
+        Request::builder()
+            .uri("/")
+            .body(Body::from(TEST_BODY))
+            .expect("failed to build request")
+    }
+
+    fn build_router_with_stub() -> (NormalizePath<Router<()>>, Arc<FakeKoboClient>) {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+        (create_router(false, false, state), stub)
+    }
+
+    #[tokio::test]
+    async fn fallback_returns_stubbed_response() {
+        let (router, stub) = build_router_with_stub();
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let response = router
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], TEST_RESPONSE.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn fallback_overwrites_host_header_for_forwarded_requests() {
+        let (router, stub) = build_router_with_stub();
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let response = router
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let recorded = stub.recorded_requests();
+        let forwarded = recorded.first().expect("expected a recorded request");
+
+        assert_eq!(
+            forwarded.uri.authority().unwrap().as_str(),
+            "storeapi.kobo.com"
+        );
+        assert_eq!(forwarded.uri.scheme_str(), Some("https"));
+        let host_header = forwarded
+            .headers
+            .get(HOST)
+            .expect("host header should be set");
+        assert_eq!(host_header, "storeapi.kobo.com");
+        assert_eq!(forwarded.method, Method::GET);
+        assert_eq!(forwarded.body, TEST_BODY.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn fallback_removes_transfer_encoding_header() {
+        let (router, stub) = build_router_with_stub();
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("transfer-encoding", "chunked")
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let response = router
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get("transfer-encoding").is_none());
+    }
+
+    async fn transfer_encoding_for_firmware(range: &str, user_agent: &str) -> Option<String> {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .strip_transfer_encoding(range.parse().unwrap())
+            .build();
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("transfer-encoding", "chunked")
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let request = Request::builder()
+            .uri("/")
+            .header("user-agent", user_agent)
+            .body(Body::empty())
+            .expect("failed to build request");
+        let response = create_router(false, false, state)
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        response
+            .headers()
+            .get("transfer-encoding")
+            .map(|value| value.to_str().unwrap().to_owned())
+    }
+
+    #[tokio::test]
+    async fn fallback_strips_transfer_encoding_only_for_configured_firmware() {
+        let old = "Mozilla/5.0 (Linux; U; Android 2.0; en-us;) (Kobo Touch 0377/4.20.14622)";
+        let new = "Mozilla/5.0 (Linux; U; Android 2.0; en-us;) (Kobo Touch 0390/4.38.21908)";
+
+        assert_eq!(transfer_encoding_for_firmware("<4.38", old).await, None);
+        assert_eq!(
+            transfer_encoding_for_firmware("<4.38", new)
+                .await
+                .as_deref(),
+            Some("chunked")
+        );
+        assert_eq!(
+            transfer_encoding_for_firmware("<4.38", "curl/8.0").await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn fallback_returns_bad_gateway_when_client_errors() {
+        let (router, stub) = build_router_with_stub();
+        stub.enqueue_error(anyhow!("stubbed failure"));
+
+        let response = router
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn multiple_leading_slashes_are_normalized() {
+        let (router, stub) = build_router_with_stub();
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let request = Request::builder()
+            .uri("///some/path")
+            .body(Body::from(TEST_BODY))
+            .expect("failed to build request");
+
+        let response = router
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let recorded = stub.recorded_requests();
+        let forwarded = recorded.first().expect("expected a recorded request");
+
+        assert_eq!(forwarded.uri.path(), "/some/path");
+    }
+
+    #[tokio::test]
+    async fn fallback_forwards_upgrade_headers() {
+        let (router, stub) = build_router_with_stub();
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::SWITCHING_PROTOCOLS)
+                .header(CONNECTION, "upgrade")
+                .header(UPGRADE, "websocket")
+                .body(Body::empty())
+                .expect("failed to build stub response"),
+        );
+
+        let request = Request::builder()
+            .uri("/ws")
+            .header(CONNECTION, "Upgrade")
+            .header(UPGRADE, "websocket")
+            .body(Body::empty())
+            .expect("failed to build request");
+
+        let response = router
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(response.headers().get(UPGRADE).unwrap(), "websocket");
+
+        let recorded = stub.recorded_requests();
+        let forwarded = recorded.first().expect("expected a recorded request");
+        assert_eq!(forwarded.headers.get(CONNECTION).unwrap(), "Upgrade");
+        assert_eq!(forwarded.headers.get(UPGRADE).unwrap(), "websocket");
+    }
+
+    /// Forwards requests over HTTP/1.1 to a local server, keeping connections
+    /// upgradable.
+    struct LocalUpstream(SocketAddr);
+
+    #[async_trait::async_trait]
+    impl KoboClient for LocalUpstream {
+        async fn request(&self, request: Request<Body>) -> anyhow::Result<Response<Body>> {
+            let stream = TcpStream::connect(self.0).await?;
+            let (mut sender, connection) =
+                hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+            tokio::spawn(connection.with_upgrades());
+            Ok(sender.send_request(request).await?.map(Body::new))
+        }
+    }
+
+    /// Starts a server that switches protocols on the first request and echoes the
+    /// bytes it then receives.
+    async fn spawn_echo_upstream() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(|mut request: Request<Incoming>| async move {
+                let upgrade = hyper::upgrade::on(&mut request);
+                tokio::spawn(async move {
+                    let mut upgraded = TokioIo::new(upgrade.await.unwrap());
+                    let mut buffer = [0; 4];
+                    upgraded.read_exact(&mut buffer).await.unwrap();
+                    upgraded.write_all(&buffer).await.unwrap();
+                });
+                Response::builder()
+                    .status(StatusCode::SWITCHING_PROTOCOLS)
+                    .header(CONNECTION, "upgrade")
+                    .header(UPGRADE, "echo")
+                    .body(Body::empty())
+            });
+            hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .with_upgrades()
+                .await
+                .unwrap();
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn fallback_tunnels_upgraded_connections() {
+        let upstream = spawn_echo_upstream().await;
+        let state = ServerState::builder("http://frontend.test")
+            .client(Arc::new(FakeKoboClient::new()))
+            .upgrade_client(Arc::new(LocalUpstream(upstream)))
+            .build();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = listener.local_addr().unwrap();
+        let app = axum::ServiceExt::<Request<Body>>::into_make_service(create_router(
+            false, false, state,
+        ));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let stream = TcpStream::connect(proxy).await.unwrap();
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(connection.with_upgrades());
+        let request = Request::builder()
+            .uri("/ws")
+            .header(HOST, "frontend.test")
+            .header(CONNECTION, "Upgrade")
+            .header(UPGRADE, "echo")
+            .body(Body::empty())
+            .unwrap();
+        let response = sender.send_request(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+        let mut tunnel = TokioIo::new(hyper::upgrade::on(response).await.unwrap());
+        tunnel.write_all(b"ping").await.unwrap();
+        let mut echoed = [0; 4];
+        tunnel.read_exact(&mut echoed).await.unwrap();
+
+        assert_eq!(&echoed, b"ping");
+    }
+
+    #[tokio::test]
+    async fn fallback_injects_upstream_headers() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .header_injection(
+                HeaderInjection::new(&["X-Affiliate=proxy", "X-Trace=kobo-{device_id}"]).unwrap(),
+            )
+            .build();
+        let router = create_router(false, false, state);
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let request = Request::builder()
+            .uri("/v1/user/profile")
+            .header("x-kobo-deviceid", "device-1")
+            .header("x-affiliate", "device")
+            .body(Body::empty())
+            .expect("failed to build request");
+        let _response = router
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        let recorded = stub.recorded_requests();
+        let forwarded = recorded.first().expect("expected a recorded request");
+        assert_eq!(forwarded.headers.get("x-affiliate").unwrap(), "proxy");
+        assert_eq!(forwarded.headers.get("x-trace").unwrap(), "kobo-device-1");
+    }
+
+    #[tokio::test]
+    async fn fallback_applies_region_override() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .region_override(RegionOverride::new(Some("fr-CA"), &["Locale=fr-CA"]).unwrap())
+            .build();
+        let router = create_router(false, false, state);
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let request = Request::builder()
+            .uri("/v1/products/featured?Locale=en-US&PageSize=10")
+            .header(ACCEPT_LANGUAGE, "en-US")
+            .body(Body::empty())
+            .expect("failed to build request");
+        let _response = router
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        let recorded = stub.recorded_requests();
+        let forwarded = recorded.first().expect("expected a recorded request");
+        assert_eq!(
+            forwarded.uri.path_and_query().unwrap().as_str(),
+            "/v1/products/featured?Locale=fr-CA&PageSize=10"
+        );
+        assert_eq!(forwarded.headers.get(ACCEPT_LANGUAGE).unwrap(), "fr-CA");
+    }
+
+    fn build_router_with_fallbacks() -> (NormalizePath<Router<()>>, Arc<FakeKoboClient>) {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .upstream_fallbacks(UpstreamFallbacks::new(true, HashMap::new()))
+            .build();
+        (create_router(false, false, state), stub)
+    }
+
+    fn build_sync_request() -> Request<Body> {
+        Request::builder()
+            .uri("/v1/library/sync")
+            .body(Body::empty())
+            .expect("failed to build request")
+    }
+
+    #[tokio::test]
+    async fn fallback_serves_empty_sync_when_client_errors() {
+        let (router, stub) = build_router_with_fallbacks();
+        stub.enqueue_error(anyhow!("stubbed failure"));
+
+        let response = router
+            .oneshot(build_sync_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"[]");
+    }
+
+    #[tokio::test]
+    async fn fallback_serves_empty_sync_when_upstream_unavailable() {
+        let (router, stub) = build_router_with_fallbacks();
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(Body::empty())
+                .expect("failed to build stub response"),
+        );
+
+        let response = router
+            .oneshot(build_sync_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("x-kobo-proxy-degraded").unwrap(),
+            "true"
+        );
+    }
+
+    #[tokio::test]
+    async fn fallback_returns_bad_gateway_for_routes_without_fallback() {
+        let (router, stub) = build_router_with_fallbacks();
+        stub.enqueue_error(anyhow!("stubbed failure"));
+
+        let response = router
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn fallback_audits_stripped_transfer_encoding_header() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+        let router = create_router(false, false, state.clone());
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("transfer-encoding", "chunked")
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let request = Request::builder()
+            .uri("/v1/library/sync")
+            .header("x-request-id", "request-1")
+            .body(Body::empty())
+            .expect("failed to build request");
+        let _response = router
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        let entries = state.audit_log.entries(None, 10);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].route, "/v1/library/sync");
+        assert_eq!(entries[0].rule, AuditRule::HeaderStrip);
+        assert_eq!(entries[0].request_id.as_deref(), Some("request-1"));
+    }
+
+    #[tokio::test]
+    async fn fallback_stores_and_replays_cookies_per_session() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .cookie_policy(CookiePolicy::Store)
+            .build();
+        let router = create_router(false, false, state.clone());
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(SET_COOKIE, "session=abc; Path=/; HttpOnly")
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let build_device_request = |token: &'static str| {
+            Request::builder()
+                .uri("/v1/library/sync")
+                .header("x-kobo-deviceid", "device-1")
+                .header("authorization", token)
+                .body(Body::empty())
+                .expect("failed to build request")
+        };
+        let response = router
+            .clone()
+            .oneshot(build_device_request("Bearer first"))
+            .await
+            .expect("service should return a response");
+        assert!(response.headers().get(SET_COOKIE).is_none());
+        router
+            .clone()
+            .oneshot(build_device_request("Bearer first"))
+            .await
+            .expect("service should return a response");
+        router
+            .oneshot(build_device_request("Bearer other"))
+            .await
+            .expect("service should return a response");
+
+        let recorded = stub.recorded_requests();
+        assert!(recorded[0].headers.get(COOKIE).is_none());
+        assert_eq!(recorded[1].headers.get(COOKIE).unwrap(), "session=abc");
+        assert!(recorded[2].headers.get(COOKIE).is_none());
+        let entries = state.audit_log.entries(None, 10);
+        assert_eq!(entries[0].rule, AuditRule::HeaderStrip);
+    }
+
+    #[tokio::test]
+    async fn fallback_passes_cookies_through_by_default() {
+        let (router, stub) = build_router_with_stub();
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(SET_COOKIE, "session=abc")
+                .body(Body::from(TEST_RESPONSE))
+                .expect("failed to build stub response"),
+        );
+
+        let response = router
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.headers().get(SET_COOKIE).unwrap(), "session=abc");
+    }
+
+    #[tokio::test]
+    async fn fallback_audits_upstream_fallbacks() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .upstream_fallbacks(UpstreamFallbacks::new(true, HashMap::new()))
+            .build();
+        let router = create_router(false, false, state.clone());
+        stub.enqueue_error(anyhow!("stubbed failure"));
+
+        let _response = router
+            .oneshot(build_sync_request())
+            .await
+            .expect("service should return a response");
+
+        let entries = state.audit_log.entries(None, 10);
+        assert_eq!(entries[0].rule, AuditRule::UpstreamFallback);
+        assert_eq!(entries[0].byte_delta, 2);
+    }
+
+    #[tokio::test]
+    async fn fallback_passes_protected_content_through_untouched() {
+        let path = std::env::temp_dir().join(format!(
+            "kobo-capture-{}-protected.jsonl",
+            std::process::id()
+        ));
+        // Gzip magic followed by bytes that are not valid UTF-8.
+        let book = vec![0x1f, 0x8b, 0xff, 0xfe, 0x00, 0x7b, 0x22];
+        let stub = Arc::new(FakeKoboClient::new());
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/x-kobo-kdrm")
+                .header("content-encoding", "gzip")
+                .body(Body::from(book.clone()))
+                .unwrap(),
+        );
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub)
+            .capture_log(Some(CaptureLog::open(&path).unwrap()))
+            .response_patches(ResponsePatches::new(HashMap::from([(
+                "/download/{id}".to_owned(),
+                serde_json::from_value(serde_json::json!([
+                    {"op": "add", "path": "/Patched", "value": true}
+                ]))
+                .unwrap(),
+            )])))
+            .build();
+        let router = create_router(true, true, state.clone());
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/download/abc")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .expect("service should return a response");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let captured = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(body.to_vec(), book);
+        assert!(captured.is_empty());
+        assert!(state.audit_log.entries(None, 10).is_empty());
+        assert_eq!(
+            state
+                .protected_passthroughs
+                .load(std::sync::atomic::Ordering::Relaxed),
+            1
+        );
+    }
+}
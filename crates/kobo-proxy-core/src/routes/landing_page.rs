@@ -0,0 +1,128 @@
+//! Handler for the local status and setup page served at `/`.
+
+pub use implementation::landing_page_handler;
+
+mod implementation {
+    use axum::{extract::State, response::Html};
+    use chrono::Utc;
+
+    use crate::{
+        routes::{constants::KOBO_API_URL, setup::api_endpoint_line, status::active_features},
+        state::server_state::ServerState,
+    };
+
+    /// Escapes text for use in HTML element content.
+    fn escape_html(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    /// Handler for `/`. Shows the server status and how to point a device at the
+    /// server, with the exact `api_endpoint` line for its `Kobo eReader.conf`.
+    pub async fn landing_page_handler(State(state): State<ServerState>) -> Html<String> {
+        let uptime = (Utc::now() - state.started_at).num_seconds();
+        let features = active_features(&state);
+        let features = if features.is_empty() {
+            "none".to_owned()
+        } else {
+            features.join(", ")
+        };
+        let api_endpoint = escape_html(&api_endpoint_line(&state.frontend_url));
+
+        let status_link = if state.serve_admin_api {
+            "<p>Details are available from <a href=\"/api/status\">the status API</a>.</p>\n"
+        } else {
+            ""
+        };
+
+        Html(format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>kobo-server</title>\n</head>\n<body>\n<h1>kobo-server</h1>\n\
+             <h2>Status</h2>\n<ul>\n\
+             <li>Version: {version}</li>\n\
+             <li>Up for {uptime} seconds, since {started_at}</li>\n\
+             <li>Devices seen: {devices}</li>\n\
+             <li>Active features: {features}</li>\n\
+             <li>Requests are forwarded to {upstream}</li>\n\
+             </ul>\n\
+             <h2>Device setup</h2>\n<ol>\n\
+             <li>Connect the e-reader to a computer over USB.</li>\n\
+             <li>Open <code>.kobo/Kobo/Kobo eReader.conf</code> on the e-reader.</li>\n\
+             <li>In the <code>[OneStoreServices]</code> section, replace the \
+             <code>api_endpoint</code> line with:\n<pre>{api_endpoint}</pre></li>\n\
+             <li>Save the file, eject the e-reader, and sync.</li>\n\
+             </ol>\n\
+             <p>The snippet can also be <a href=\"/setup?download=true\">downloaded</a>.</p>\n{status_link}</body>\n</html>\n",
+            version = env!("CARGO_PKG_VERSION"),
+            started_at = state.started_at.to_rfc3339(),
+            devices = state.devices.devices().len(),
+            features = escape_html(&features),
+            upstream = KOBO_API_URL,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::{StatusCode, header};
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{fake_kobo_client::FakeKoboClient, server_state::ServerState},
+    };
+
+    fn root_request() -> Request<Body> {
+        Request::builder()
+            .uri("/")
+            .body(Body::empty())
+            .expect("failed to build request")
+    }
+
+    #[tokio::test]
+    async fn landing_page_shows_api_endpoint() {
+        let state = ServerState::builder("http://kobo.local:8089?a=1&b=2")
+            .landing_page(true)
+            .build();
+        let router = create_router(false, false, state);
+
+        let response = router
+            .oneshot(root_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            response.headers()[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("text/html")
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let page = String::from_utf8(body.to_vec()).unwrap();
+        assert!(page.contains("<pre>api_endpoint=http://kobo.local:8089?a=1&amp;b=2</pre>"));
+        assert!(page.contains(env!("CARGO_PKG_VERSION")));
+    }
+
+    #[tokio::test]
+    async fn root_is_forwarded_when_landing_page_is_disabled() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+        let router = create_router(false, false, state);
+
+        router
+            .oneshot(root_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(stub.recorded_requests().len(), 1);
+    }
+}
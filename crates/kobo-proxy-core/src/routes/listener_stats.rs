@@ -0,0 +1,53 @@
+//! Handler for the listener stats API route.
+
+pub use implementation::listener_stats_handler;
+
+mod implementation {
+    use axum::{Json, extract::State};
+
+    use crate::{listener::AcceptStatsSnapshot, state::server_state::ServerState};
+
+    /// Handler for the `/api/listener` endpoint. Reports the accept loop counters:
+    /// accepted connections, accept errors, file descriptor exhaustion, and pauses.
+    pub async fn listener_stats_handler(
+        State(state): State<ServerState>,
+    ) -> Json<AcceptStatsSnapshot> {
+        Json(state.accept_stats.snapshot())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::StatusCode;
+    use tower::ServiceExt as _;
+
+    use crate::{router::create_router, state::server_state::ServerState};
+
+    #[tokio::test]
+    async fn listener_stats_handler_reports_accept_counters() {
+        let state = ServerState::builder("http://frontend.test").build();
+        state.accept_stats.record_accept();
+        state
+            .accept_stats
+            .record_error(&std::io::Error::from_raw_os_error(24));
+        let router = create_router(false, false, state);
+
+        let request = Request::builder()
+            .uri("/api/listener")
+            .body(Body::empty())
+            .expect("failed to build request");
+        let response = router
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let counters: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(counters["accepted"], 1);
+        assert_eq!(counters["listener_errors"], 1);
+        assert_eq!(counters["descriptor_exhaustion"], 1);
+    }
+}
@@ -0,0 +1,19 @@
+//! Route handlers for the Kobo server.
+
+pub mod audit;
+pub mod bandwidth;
+pub mod constants;
+pub mod devices;
+pub mod initialization;
+pub mod kobo_store_request;
+pub mod landing_page;
+pub mod listener_stats;
+pub mod preview_rewrite;
+pub mod readiness;
+pub mod resources;
+pub mod setup;
+pub mod snapshots;
+pub mod state_export;
+pub mod status;
+pub mod synthetic_auth;
+pub mod user_profile;
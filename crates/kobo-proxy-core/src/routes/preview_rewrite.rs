@@ -0,0 +1,153 @@
+//! Handler for the rewrite preview API route.
+
+pub use implementation::preview_rewrite_handler;
+
+mod implementation {
+    use axum::{Json, extract::State};
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        routes::initialization::{INITIALIZATION_ROUTE, rewrite_urls},
+        state::{audit_log::AuditRule, server_state::ServerState},
+        utils::json_diff::diff_json,
+    };
+
+    /// A sample response to preview rewrites for.
+    #[derive(Debug, Deserialize)]
+    pub struct PreviewRequest {
+        /// The request path the response would be for.
+        path: String,
+        /// The sample response body.
+        body: String,
+        /// The device to preview for, which selects its frontend URL.
+        device_id: Option<String>,
+    }
+
+    /// The result of applying the configured rewrites to a sample response.
+    #[derive(Debug, Serialize)]
+    pub struct PreviewResponse {
+        /// The route template the path matches.
+        route: String,
+        /// The rewrites that changed the body.
+        rules: Vec<AuditRule>,
+        /// The rewritten body.
+        body: String,
+        /// Paths that changed in the body.
+        changes: Vec<String>,
+    }
+
+    /// Handler for the `/api/admin/preview-rewrite` endpoint. Applies the configured
+    /// rewrites to a sample body without contacting the Kobo API, so rules can be
+    /// checked without a device.
+    pub async fn preview_rewrite_handler(
+        State(state): State<ServerState>,
+        Json(request): Json<PreviewRequest>,
+    ) -> Json<PreviewResponse> {
+        let route = state.route_templates.normalize(&request.path).into_owned();
+        let body = if route == INITIALIZATION_ROUTE {
+            rewrite_urls(
+                &request.body,
+                state.frontend_url_for(request.device_id.as_deref()),
+            )
+        } else {
+            request.body.clone()
+        };
+        let (rules, changes) = if body == request.body {
+            (Vec::new(), Vec::new())
+        } else {
+            (vec![AuditRule::UrlRewrite], diff_json(&request.body, &body))
+        };
+
+        Json(PreviewResponse {
+            route,
+            rules,
+            body,
+            changes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::{Method, StatusCode, header};
+    use serde_json::{Value, json};
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{fake_kobo_client::FakeKoboClient, server_state::ServerState},
+        utils::device_frontend_urls::DeviceFrontendUrls,
+    };
+
+    async fn preview(request_body: Value) -> Value {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://kobo.lan")
+            .client(stub.clone())
+            .device_frontend_urls(
+                DeviceFrontendUrls::new(&["kobo-travel=https://kobo.tailnet.ts.net"]).unwrap(),
+            )
+            .build();
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/admin/preview-rewrite")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(request_body.to_string()))
+            .expect("failed to build request");
+
+        let response = create_router(false, false, state)
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(stub.recorded_requests().is_empty());
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn preview_rewrites_initialization_urls() {
+        let preview = preview(json!({
+            "path": "/v1/initialization",
+            "body": r#"{"Resources":{"library_sync":"https://storeapi.kobo.com/v1/library/sync"}}"#,
+        }))
+        .await;
+
+        assert_eq!(preview["route"], "/v1/initialization");
+        assert_eq!(preview["rules"], json!(["url_rewrite"]));
+        assert_eq!(
+            preview["body"],
+            r#"{"Resources":{"library_sync":"http://kobo.lan/v1/library/sync"}}"#
+        );
+        assert_eq!(preview["changes"], json!(["~ /Resources/library_sync"]));
+    }
+
+    #[tokio::test]
+    async fn preview_uses_device_frontend_url() {
+        let preview = preview(json!({
+            "path": "/v1/initialization",
+            "body": r#"{"url":"https://storeapi.kobo.com/v1/library/sync"}"#,
+            "device_id": "kobo-travel",
+        }))
+        .await;
+
+        assert_eq!(
+            preview["body"],
+            r#"{"url":"https://kobo.tailnet.ts.net/v1/library/sync"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn preview_leaves_other_routes_unchanged() {
+        let body = r#"{"url":"https://storeapi.kobo.com/v1/library/sync"}"#;
+
+        let preview = preview(json!({ "path": "/v1/library/sync", "body": body })).await;
+
+        assert_eq!(preview["rules"], json!([]));
+        assert_eq!(preview["body"], body);
+    }
+}
@@ -0,0 +1,98 @@
+//! Handler for the readiness probe.
+
+pub use implementation::readiness_handler;
+
+mod implementation {
+    use axum::{Json, extract::State};
+    use hyper::StatusCode;
+    use serde::Serialize;
+
+    use crate::state::{background_tasks::TaskHealth, server_state::ServerState};
+
+    /// The readiness of the server and the health of its background tasks.
+    #[derive(Debug, Serialize)]
+    pub struct Readiness {
+        /// Whether every critical background task is healthy.
+        ready: bool,
+        /// The health of each supervised background task.
+        tasks: Vec<TaskHealth>,
+    }
+
+    /// Handler for the `/readyz` endpoint. Responds with 503 Service Unavailable when
+    /// a critical background task has died or stopped sending heartbeats.
+    pub async fn readiness_handler(
+        State(state): State<ServerState>,
+    ) -> (StatusCode, Json<Readiness>) {
+        let ready = state.background_tasks.is_ready();
+        let status = if ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        let tasks = state.background_tasks.health();
+        (status, Json(Readiness { ready, tasks }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::StatusCode;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{background_tasks::TaskState, server_state::ServerState},
+    };
+
+    async fn get_readiness(state: ServerState) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder()
+            .uri("/readyz")
+            .body(Body::empty())
+            .expect("failed to build request");
+        let response = create_router(false, false, state)
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn readiness_handler_reports_healthy_tasks() {
+        let state = ServerState::builder("http://frontend.test").build();
+        state
+            .background_tasks
+            .register("resource-watchdog", true, Duration::from_secs(60));
+
+        let (status, body) = get_readiness(state).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ready"], true);
+        assert_eq!(body["tasks"][0]["name"], "resource-watchdog");
+        assert_eq!(body["tasks"][0]["state"], "running");
+    }
+
+    #[tokio::test]
+    async fn readiness_handler_fails_when_critical_task_died() {
+        let state = ServerState::builder("http://frontend.test").build();
+        state
+            .background_tasks
+            .register("resource-watchdog", true, Duration::from_secs(60));
+        state.background_tasks.set_state(
+            "resource-watchdog",
+            TaskState::Dead("task panicked".to_owned()),
+        );
+
+        let (status, body) = get_readiness(state).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["tasks"][0]["state"], "dead");
+        assert_eq!(body["tasks"][0]["reason"], "task panicked");
+    }
+}
@@ -0,0 +1,55 @@
+//! Handler for the resource usage API route.
+
+pub use implementation::resources_handler;
+
+mod implementation {
+    use axum::{Json, extract::State};
+
+    use crate::{
+        resource_watchdog::sample_resources,
+        state::{resource_usage::ResourceUsage, server_state::ServerState},
+    };
+
+    /// Handler for the `/api/resources` endpoint. Returns the latest sample from the
+    /// resource watchdog, or a fresh sample if the watchdog is disabled or has not run.
+    pub async fn resources_handler(State(state): State<ServerState>) -> Json<ResourceUsage> {
+        Json(
+            state
+                .resource_monitor
+                .latest()
+                .unwrap_or_else(|| sample_resources(&state)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::StatusCode;
+    use tower::ServiceExt as _;
+
+    use crate::{router::create_router, state::server_state::ServerState};
+
+    #[tokio::test]
+    async fn resources_handler_reports_cache_sizes() {
+        let state = ServerState::builder("http://frontend.test").build();
+        state.devices.record_request("device-1", None);
+        let router = create_router(false, false, state);
+
+        let request = Request::builder()
+            .uri("/api/resources")
+            .body(Body::empty())
+            .expect("failed to build request");
+        let response = router
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let usage: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(usage["caches"]["devices"], 1);
+        assert!(usage["warnings"].is_array());
+    }
+}
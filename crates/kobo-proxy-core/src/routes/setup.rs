@@ -0,0 +1,122 @@
+//! Handler for the device setup snippet served at `/setup`.
+
+pub use implementation::{api_endpoint_line, setup_handler};
+
+mod implementation {
+    use axum::{
+        extract::{Query, State},
+        response::{IntoResponse as _, Response},
+    };
+    use hyper::header;
+    use serde::Deserialize;
+
+    use crate::state::server_state::ServerState;
+
+    /// File name offered when the snippet is downloaded.
+    const DOWNLOAD_FILE_NAME: &str = "kobo-ereader-conf-snippet.txt";
+
+    /// Query parameters accepted by the setup endpoint.
+    #[derive(Debug, Deserialize)]
+    pub struct SetupQuery {
+        /// Use the frontend URL configured for this device, if it has its own.
+        device: Option<String>,
+        /// Offer the snippet as a file download.
+        #[serde(default)]
+        download: bool,
+    }
+
+    /// The `api_endpoint` line pointing a device's `Kobo eReader.conf` at
+    /// `frontend_url`.
+    pub fn api_endpoint_line(frontend_url: &str) -> String {
+        format!("api_endpoint={frontend_url}")
+    }
+
+    /// Handler for `/setup`. Returns the `Kobo eReader.conf` lines that point a
+    /// device at the server, using the frontend URL devices are given.
+    pub async fn setup_handler(
+        State(state): State<ServerState>,
+        Query(query): Query<SetupQuery>,
+    ) -> Response {
+        let frontend_url = state.frontend_url_for(query.device.as_deref());
+        let snippet = format!(
+            "# Replace the api_endpoint line in the [OneStoreServices] section of\n\
+             # .kobo/Kobo/Kobo eReader.conf on the e-reader with:\n\
+             [OneStoreServices]\n{}\n",
+            api_endpoint_line(frontend_url)
+        );
+
+        if query.download {
+            (
+                [
+                    (header::CONTENT_TYPE, "text/plain; charset=utf-8".to_owned()),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"{DOWNLOAD_FILE_NAME}\""),
+                    ),
+                ],
+                snippet,
+            )
+                .into_response()
+        } else {
+            snippet.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::{StatusCode, header};
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router, state::server_state::ServerState,
+        utils::device_frontend_urls::DeviceFrontendUrls,
+    };
+
+    async fn get_setup(uri: &str) -> (hyper::HeaderMap, String) {
+        let state = ServerState::builder("http://kobo.local:8089")
+            .device_frontend_urls(
+                DeviceFrontendUrls::new(&["travel-kobo=https://kobo.example.com"]).unwrap(),
+            )
+            .landing_page(true)
+            .build();
+        let router = create_router(false, false, state);
+
+        let response = router
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers().clone();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (headers, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn setup_returns_api_endpoint_snippet() {
+        let (headers, body) = get_setup("/setup").await;
+
+        assert!(body.contains("[OneStoreServices]\napi_endpoint=http://kobo.local:8089\n"));
+        assert!(!headers.contains_key(header::CONTENT_DISPOSITION));
+    }
+
+    #[tokio::test]
+    async fn setup_uses_device_frontend_url() {
+        let (_, body) = get_setup("/setup?device=travel-kobo").await;
+
+        assert!(body.contains("api_endpoint=https://kobo.example.com\n"));
+    }
+
+    #[tokio::test]
+    async fn setup_can_be_downloaded() {
+        let (headers, _) = get_setup("/setup?download=true").await;
+
+        assert_eq!(
+            headers[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"kobo-ereader-conf-snippet.txt\""
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Handler for the snapshots API route.
+
+pub use implementation::snapshots_handler;
+
+mod implementation {
+    use axum::{Json, extract::State};
+    use serde::Deserialize;
+
+    use crate::{
+        api::{
+            error::ApiError,
+            pagination::{Page, Pagination, SortOrder},
+            query::ApiQuery,
+        },
+        state::{server_state::ServerState, snapshots::SnapshotHistory},
+    };
+
+    /// Query parameters accepted by the snapshots endpoint.
+    #[derive(Debug, Deserialize)]
+    pub struct SnapshotQuery {
+        /// Only return snapshots for this device.
+        device: Option<String>,
+        /// Only return snapshots for this route template.
+        route: Option<String>,
+        /// Include the response bodies. Only allowed on the admin listener, since the
+        /// bodies hold account data.
+        #[serde(default)]
+        bodies: bool,
+    }
+
+    /// Handler for the `/api/snapshots` endpoint. Lists the stored snapshot versions
+    /// per device and route, with the changes between versions.
+    pub async fn snapshots_handler(
+        State(state): State<ServerState>,
+        ApiQuery(query): ApiQuery<SnapshotQuery>,
+        pagination: Pagination,
+    ) -> Result<Json<Page<SnapshotHistory>>, ApiError> {
+        // Without an admin listener the API is served unauthenticated on the device
+        // port.
+        if query.bodies && state.serve_admin_api {
+            return Err(ApiError::forbidden(
+                "Snapshot bodies are only served on the admin listener",
+            ));
+        }
+        let histories = state.snapshots.histories(
+            query.device.as_deref(),
+            query.route.as_deref(),
+            query.bodies,
+        );
+        Ok(Json(pagination.page(
+            histories,
+            SortOrder::Ascending,
+            |history| (history.device_id.clone(), history.route.clone()),
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::StatusCode;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::{create_admin_router, create_router},
+        state::server_state::ServerState,
+    };
+
+    fn snapshots_request(uri: &str) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .body(Body::empty())
+            .expect("failed to build request")
+    }
+
+    #[tokio::test]
+    async fn snapshots_handler_lists_versions() {
+        let state = ServerState::builder("http://frontend.test").build();
+        state
+            .snapshots
+            .record("device-1", "/v1/initialization", 200, "{}".to_owned());
+        let router = create_router(false, false, state);
+
+        let response = router
+            .oneshot(snapshots_request("/api/snapshots?device=device-1"))
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let histories = &page["items"];
+        assert_eq!(histories[0]["route"], "/v1/initialization");
+        assert_eq!(histories[0]["versions"][0]["version"], 1);
+        assert!(histories[0]["versions"][0].get("body").is_none());
+    }
+
+    #[tokio::test]
+    async fn snapshots_handler_serves_bodies_only_on_admin_listener() {
+        let state = ServerState::builder("http://frontend.test").build();
+        state
+            .snapshots
+            .record("device-1", "/v1/initialization", 200, "{}".to_owned());
+        let admin_state = ServerState {
+            serve_admin_api: false,
+            ..state.clone()
+        };
+
+        let device_port = create_router(false, false, state)
+            .oneshot(snapshots_request("/api/snapshots?bodies=true"))
+            .await
+            .expect("service should return a response");
+        let admin_listener = create_admin_router(admin_state)
+            .oneshot(snapshots_request("/api/snapshots?bodies=true"))
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(device_port.status(), StatusCode::FORBIDDEN);
+        assert_eq!(admin_listener.status(), StatusCode::OK);
+        let body = admin_listener
+            .into_body()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes();
+        let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(page["items"][0]["versions"][0]["body"], "{}");
+    }
+}
@@ -0,0 +1,87 @@
+//! Handler for the state export API route.
+
+pub use implementation::state_export_handler;
+
+mod implementation {
+    use axum::{Json, extract::State};
+    use chrono::{DateTime, Utc};
+    use serde::Serialize;
+
+    use crate::state::{devices::DeviceRecord, server_state::ServerState};
+
+    /// Optional cargo features the server was built with.
+    #[derive(Debug, Serialize)]
+    pub struct Features {
+        /// Whether gzip uses the zlib-rs backend.
+        zlib_rs: bool,
+    }
+
+    /// A snapshot of the server configuration and state, for bug reports.
+    #[derive(Debug, Serialize)]
+    pub struct StateExport {
+        /// The server version.
+        version: &'static str,
+        /// When the export was generated.
+        generated_at: DateTime<Utc>,
+        /// The optional features the server was built with.
+        features: Features,
+        /// The configuration, including rewrite and access rules, with secrets redacted.
+        config: serde_json::Value,
+        /// Every device seen by the server.
+        devices: Vec<DeviceRecord>,
+        /// Number of routes with stored response snapshots.
+        snapshot_histories: usize,
+    }
+
+    /// Handler for the `/api/admin/state` endpoint. Exports the configuration and
+    /// runtime state as a single JSON document to attach to bug reports.
+    pub async fn state_export_handler(State(state): State<ServerState>) -> Json<StateExport> {
+        Json(StateExport {
+            version: env!("CARGO_PKG_VERSION"),
+            generated_at: Utc::now(),
+            features: Features {
+                zlib_rs: cfg!(feature = "zlib-rs"),
+            },
+            config: state.config.as_ref().clone(),
+            devices: state.devices.devices(),
+            snapshot_histories: state.snapshots.histories(None, None, false).len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::StatusCode;
+    use serde_json::json;
+    use tower::ServiceExt as _;
+
+    use crate::{router::create_router, state::server_state::ServerState};
+
+    #[tokio::test]
+    async fn state_export_includes_config_and_devices() {
+        let state = ServerState::builder("http://frontend.test")
+            .config(json!({ "port": 8089 }))
+            .build();
+        state.devices.record_request("device-1", Some("Kobo"));
+        let router = create_router(false, false, state);
+
+        let request = Request::builder()
+            .uri("/api/admin/state")
+            .body(Body::empty())
+            .expect("failed to build request");
+        let response = router
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let export: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(export["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(export["config"]["port"], 8089);
+        assert_eq!(export["devices"][0]["id"], "device-1");
+        assert!(export["features"]["zlib_rs"].is_boolean());
+    }
+}
@@ -0,0 +1,141 @@
+//! Handler for the status API route.
+
+pub use implementation::{active_features, status_handler};
+
+mod implementation {
+    use std::{net::SocketAddr, sync::atomic::Ordering};
+
+    use axum::{Json, extract::State};
+    use chrono::{DateTime, Utc};
+    use serde::Serialize;
+
+    use crate::{
+        resource_watchdog::cache_sizes,
+        routes::constants::KOBO_API_URL,
+        state::{resource_usage::CacheSizes, server_state::ServerState},
+    };
+
+    /// Addresses the server listens on.
+    #[derive(Debug, Serialize)]
+    pub struct Listeners {
+        /// The address devices connect to.
+        server: Option<SocketAddr>,
+        /// The address of the admin listener, if enabled.
+        admin: Option<SocketAddr>,
+    }
+
+    /// The running server's status, the machine-readable counterpart to the startup logs.
+    #[derive(Debug, Serialize)]
+    pub struct Status {
+        /// The server version.
+        version: &'static str,
+        /// When the server started.
+        started_at: DateTime<Utc>,
+        /// Seconds since the server started.
+        uptime_seconds: i64,
+        /// Optional features that are active, from build features and configuration.
+        features: Vec<&'static str>,
+        /// How `Set-Cookie` headers from the Kobo store API are handled.
+        cookie_policy: String,
+        /// The frontend URL devices are pointed to.
+        frontend_url: String,
+        /// The Kobo store API requests are forwarded to.
+        upstream_url: &'static str,
+        /// Addresses the server listens on.
+        listeners: Listeners,
+        /// Number of entries in the in-memory caches.
+        caches: CacheSizes,
+        /// Number of DRM-protected responses passed through untouched.
+        protected_passthroughs: u64,
+    }
+
+    /// Lists the optional features that are active.
+    pub fn active_features(state: &ServerState) -> Vec<&'static str> {
+        [
+            ("zlib-rs", cfg!(feature = "zlib-rs")),
+            ("synthetic-device-auth", state.synthetic_device_auth),
+            ("landing-page", state.landing_page),
+            ("preserve-header-case", state.preserve_header_case),
+            ("upstream-dns-cache", state.upstream_dns_cache),
+            ("serialize-device-requests", state.serialize_device_requests),
+            ("snapshots", state.snapshots_enabled),
+            ("chaos", !state.chaos_rules.is_empty()),
+            ("access-schedule", !state.access_schedule.is_empty()),
+            ("auth-failure-backoff", state.auth_backoff.is_enabled()),
+            ("device-groups", !state.device_groups.is_empty()),
+            ("response-patches", !state.response_patches.is_empty()),
+            ("schema-validation", state.schema_validation.is_enabled()),
+            ("route-timeouts", !state.route_timeouts.is_empty()),
+            ("trusted-proxies", !state.trusted_proxies.is_empty()),
+        ]
+        .into_iter()
+        .filter_map(|(name, active)| active.then_some(name))
+        .collect()
+    }
+
+    /// Handler for the `/api/status` endpoint. Reports the version, uptime, active
+    /// features, upstream and listener configuration, and cache sizes.
+    pub async fn status_handler(State(state): State<ServerState>) -> Json<Status> {
+        Json(Status {
+            version: env!("CARGO_PKG_VERSION"),
+            started_at: state.started_at,
+            uptime_seconds: (Utc::now() - state.started_at).num_seconds(),
+            features: active_features(&state),
+            cookie_policy: state.cookie_policy.to_string(),
+            frontend_url: state.frontend_url.clone(),
+            upstream_url: KOBO_API_URL,
+            listeners: Listeners {
+                server: state.server_address,
+                admin: state.admin_address,
+            },
+            caches: cache_sizes(&state),
+            protected_passthroughs: state.protected_passthroughs.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::StatusCode;
+    use tower::ServiceExt as _;
+
+    use crate::{router::create_router, state::server_state::ServerState};
+
+    #[tokio::test]
+    async fn status_handler_reports_configuration() {
+        let state = ServerState::builder("http://frontend.test")
+            .synthetic_device_auth(true)
+            .listener_addresses(Some("127.0.0.1:8080".parse().unwrap()), None)
+            .build();
+        state.devices.record_request("device-1", None);
+        let router = create_router(false, false, state);
+
+        let request = Request::builder()
+            .uri("/api/status")
+            .body(Body::empty())
+            .expect("failed to build request");
+        let response = router
+            .oneshot(request)
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let status: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(status["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(status["frontend_url"], "http://frontend.test");
+        assert_eq!(status["upstream_url"], "https://storeapi.kobo.com");
+        assert_eq!(status["listeners"]["server"], "127.0.0.1:8080");
+        assert!(status["listeners"]["admin"].is_null());
+        assert!(
+            status["features"]
+                .as_array()
+                .unwrap()
+                .contains(&"synthetic-device-auth".into())
+        );
+        assert_eq!(status["cookie_policy"], "pass");
+        assert_eq!(status["caches"]["devices"], 1);
+    }
+}
@@ -0,0 +1,135 @@
+//! Handler for synthetic device authentication.
+//!
+//! A developer mode for devices that are not linked to a Kobo account: the device
+//! authentication request is answered locally with made-up tokens instead of being
+//! forwarded. Requests forwarded to the Kobo API with these tokens are rejected, so
+//! this is only useful together with responses served by the proxy itself.
+
+pub use implementation::synthetic_device_auth_handler;
+
+mod implementation {
+    use axum::Json;
+    use rand::Rng as _;
+    use serde::{Deserialize, Serialize};
+
+    /// The fields of a device authentication request that are used.
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct DeviceAuthRequest {
+        /// The user key the device already has, if any.
+        user_key: Option<String>,
+    }
+
+    /// A device authentication response with synthetic tokens.
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct DeviceAuthResponse {
+        access_token: String,
+        refresh_token: String,
+        token_type: &'static str,
+        tracking_id: String,
+        user_key: String,
+    }
+
+    /// Generates a random token of 32 hex digits.
+    fn random_token() -> String {
+        format!("{:032x}", rand::rng().random::<u128>())
+    }
+
+    /// Generates a random ID in UUID form.
+    fn random_id() -> String {
+        let token = random_token();
+        format!(
+            "{}-{}-{}-{}-{}",
+            &token[..8],
+            &token[8..12],
+            &token[12..16],
+            &token[16..20],
+            &token[20..]
+        )
+    }
+
+    /// Handler for the `/v1/auth/device` endpoint in synthetic authentication mode.
+    /// Responds with random tokens, keeping the device's user key if it sent one.
+    pub async fn synthetic_device_auth_handler(
+        Json(request): Json<DeviceAuthRequest>,
+    ) -> Json<DeviceAuthResponse> {
+        tracing::warn!("Answering device authentication with synthetic tokens");
+        Json(DeviceAuthResponse {
+            access_token: random_token(),
+            refresh_token: random_token(),
+            token_type: "Bearer",
+            tracking_id: random_id(),
+            user_key: request.user_key.unwrap_or_else(random_id),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{Method, Request, Response},
+    };
+    use http_body_util::BodyExt as _;
+    use hyper::{StatusCode, header};
+    use serde_json::json;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{fake_kobo_client::FakeKoboClient, server_state::ServerState},
+    };
+
+    fn build_request() -> Request<Body> {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/v1/auth/device")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({ "DeviceId": "device-1", "UserKey": "user-key-1" }).to_string(),
+            ))
+            .expect("failed to build request")
+    }
+
+    #[tokio::test]
+    async fn synthetic_auth_answers_locally() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .synthetic_device_auth(true)
+            .build();
+
+        let response = create_router(false, false, state)
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let auth: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(auth["TokenType"], "Bearer");
+        assert_eq!(auth["UserKey"], "user-key-1");
+        assert_eq!(auth["AccessToken"].as_str().unwrap().len(), 32);
+        assert_ne!(auth["AccessToken"], auth["RefreshToken"]);
+        assert!(stub.recorded_requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn auth_is_forwarded_without_synthetic_auth() {
+        let stub = Arc::new(FakeKoboClient::new());
+        stub.enqueue_response(Response::new(Body::from("{}")));
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+
+        create_router(false, false, state)
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(stub.recorded_requests().len(), 1);
+    }
+}
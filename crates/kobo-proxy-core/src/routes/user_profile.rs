@@ -0,0 +1,235 @@
+//! Handler for the user profile route.
+
+pub use implementation::user_profile_handler;
+
+mod implementation {
+    use axum::{body::Body, response::Response};
+    use hyper::header;
+
+    use crate::{
+        routes::kobo_store_request::kobo_store_request,
+        state::{
+            audit_log::{AuditEntry, AuditRule, byte_delta, request_id},
+            devices::identify_device,
+            server_state::ServerState,
+            upstream_fallbacks::DEGRADED_HEADER,
+        },
+        utils::{
+            http_body::{
+                decode_response_body, encode_response_body, is_gzip_encoded, read_response_body,
+            },
+            profile_rewrite::ProfileRewrite,
+        },
+    };
+
+    /// Route template of the user profile endpoint, under which successful responses
+    /// are cached.
+    pub const USER_PROFILE_ROUTE: &str = "/v1/user/profile";
+
+    /// Handler for the `/v1/user/profile` endpoint. Forwards to the Kobo API, caching
+    /// successful responses so they can be replayed while the Kobo API is unreachable,
+    /// then applies the configured profile rewrites: masking account identifiers and
+    /// rewriting Kobo API URLs to the device's frontend URL.
+    pub async fn user_profile_handler(
+        state: axum::extract::State<ServerState>,
+        request: axum::extract::Request,
+    ) -> Result<Response, hyper::StatusCode> {
+        let frontend_url = state
+            .frontend_url_for(identify_device(&request).as_deref())
+            .to_owned();
+        let profile_rewrite = state.profile_rewrite;
+        let upstream_fallbacks = state.upstream_fallbacks.clone();
+        let audit_log = state.audit_log.clone();
+        let gzip_compression = state.gzip_compression;
+        let request_id = request_id(request.headers());
+        let response = kobo_store_request(state, request).await?;
+        let degraded = response.headers().contains_key(DEGRADED_HEADER);
+        let (mut parts, bytes) = read_response_body(response).await?;
+        if parts.status.is_success() && !degraded {
+            upstream_fallbacks.cache(USER_PROFILE_ROUTE, &parts.headers, &bytes);
+        }
+        if !parts.status.is_success() || !profile_rewrite.is_enabled() {
+            return Ok(Response::from_parts(parts, Body::from(bytes)));
+        }
+
+        let gz = is_gzip_encoded(&parts.headers);
+        let body_text = decode_response_body(&bytes, gz).await?;
+        let mut modified = body_text.clone().into_owned();
+        if profile_rewrite.mask_identifiers {
+            let masked = ProfileRewrite::mask_identifiers(&modified);
+            if masked != modified {
+                audit_log.record(AuditEntry::new(
+                    USER_PROFILE_ROUTE,
+                    AuditRule::IdentifierMask,
+                    byte_delta(modified.len(), masked.len()),
+                    request_id.clone(),
+                ));
+                modified = masked;
+            }
+        }
+        if profile_rewrite.rewrite_urls {
+            let rewritten = ProfileRewrite::rewrite_urls(&modified, &frontend_url);
+            if rewritten != modified {
+                audit_log.record(AuditEntry::new(
+                    USER_PROFILE_ROUTE,
+                    AuditRule::UrlRewrite,
+                    byte_delta(modified.len(), rewritten.len()),
+                    request_id,
+                ));
+                modified = rewritten;
+            }
+        }
+        if modified == body_text {
+            return Ok(Response::from_parts(parts, Body::from(bytes)));
+        }
+
+        parts.headers.remove(header::CONTENT_LENGTH);
+        let body = encode_response_body(&modified, gz.then_some(gzip_compression)).await?;
+        Ok(Response::from_parts(parts, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use axum::{
+        body::Body,
+        http::{Request, Response, StatusCode},
+    };
+    use http_body_util::BodyExt as _;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        fixtures::Fixture,
+        router::create_router,
+        state::{
+            audit_log::AuditRule, fake_kobo_client::FakeKoboClient, server_state::ServerState,
+            upstream_fallbacks::UpstreamFallbacks,
+        },
+        utils::profile_rewrite::ProfileRewrite,
+    };
+
+    const PROFILE: &str =
+        r#"{"AvatarUrl":"https://storeapi.kobo.com/v1/user/avatar","UserId":"0123456789abcdef"}"#;
+
+    fn build_request() -> Request<Body> {
+        Request::builder()
+            .uri("/v1/user/profile")
+            .body(Body::empty())
+            .expect("failed to build request")
+    }
+
+    fn enqueue_profile(stub: &FakeKoboClient) {
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "application/json")
+                .body(Body::from(PROFILE))
+                .expect("failed to build stub response"),
+        );
+    }
+
+    async fn body_text(response: axum::response::Response) -> String {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn profile_is_passed_through_without_rewrites() {
+        let stub = Arc::new(FakeKoboClient::new());
+        enqueue_profile(&stub);
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .build();
+
+        let response = create_router(false, false, state)
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_text(response).await, PROFILE);
+    }
+
+    #[tokio::test]
+    async fn profile_identifiers_are_masked_and_urls_rewritten() {
+        let stub = Arc::new(FakeKoboClient::new());
+        enqueue_profile(&stub);
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .profile_rewrite(ProfileRewrite {
+                mask_identifiers: true,
+                rewrite_urls: true,
+            })
+            .build();
+        let audit_log = state.audit_log.clone();
+
+        let response = create_router(false, false, state)
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+
+        assert_eq!(
+            body_text(response).await,
+            r#"{"AvatarUrl":"http://frontend.test/v1/user/avatar","UserId":"************cdef"}"#
+        );
+        let rules = audit_log
+            .entries(None, 10)
+            .into_iter()
+            .map(|entry| entry.rule)
+            .collect::<Vec<_>>();
+        assert!(rules.contains(&AuditRule::IdentifierMask));
+        assert!(rules.contains(&AuditRule::UrlRewrite));
+    }
+
+    #[tokio::test]
+    async fn recorded_profile_identifiers_are_all_masked() {
+        let stub = Arc::new(FakeKoboClient::new());
+        stub.enqueue_response(Fixture::UserProfile.response());
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .profile_rewrite(ProfileRewrite {
+                mask_identifiers: true,
+                rewrite_urls: false,
+            })
+            .build();
+
+        let response = create_router(false, false, state)
+            .oneshot(build_request())
+            .await
+            .expect("service should return a response");
+
+        let profile: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        for field in ["Email", "PartnerUserId", "UserId", "UserKey", "UserName"] {
+            assert!(
+                profile[field].as_str().unwrap().starts_with("****"),
+                "{field}"
+            );
+        }
+        assert_eq!(profile["DisplayName"], "Sample Reader");
+    }
+
+    #[tokio::test]
+    async fn cached_profile_is_served_while_upstream_is_down() {
+        let stub = Arc::new(FakeKoboClient::new());
+        enqueue_profile(&stub);
+        stub.enqueue_error(anyhow::anyhow!("connection refused"));
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .upstream_fallbacks(UpstreamFallbacks::new(true, HashMap::new()))
+            .profile_rewrite(ProfileRewrite {
+                mask_identifiers: true,
+                rewrite_urls: false,
+            })
+            .build();
+        let router = create_router(false, false, state);
+
+        let first = router.clone().oneshot(build_request()).await.unwrap();
+        let first = body_text(first).await;
+        let replayed = router.oneshot(build_request()).await.unwrap();
+
+        assert_eq!(replayed.status(), StatusCode::OK);
+        assert_eq!(body_text(replayed).await, first);
+    }
+}
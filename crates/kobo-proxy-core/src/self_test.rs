@@ -0,0 +1,219 @@
+//! Startup self-test of the configured response rewrites.
+//!
+//! Built-in sample responses for key endpoints are sent through the router with the
+//! configured rewrites, such as URL rewriting and response patches. Startup fails if
+//! a rewritten sample is not valid JSON or is missing a field devices rely on, so a
+//! broken rewrite is caught before a device syncs through it.
+
+pub use implementation::verify_rewrites;
+
+mod implementation {
+    use std::sync::Arc;
+
+    use anyhow::{Context as _, Result, anyhow, bail};
+    use axum::{body::Body, extract::Request};
+    use hyper::{Response, header};
+    use serde_json::Value;
+    use tower::ServiceExt as _;
+
+    use crate::{
+        router::create_router,
+        state::{client::KoboClient, server_state::ServerState},
+        utils::{
+            http_body::{decode_response_body, is_gzip_encoded, read_response_body},
+            json_diff::diff_json,
+        },
+    };
+
+    /// A sample upstream response and the fields that must survive rewriting.
+    struct Fixture {
+        path: &'static str,
+        body: &'static str,
+        /// JSON pointers to fields devices require in the response.
+        required_fields: &'static [&'static str],
+    }
+
+    const FIXTURES: &[Fixture] = &[
+        Fixture {
+            path: "/v1/initialization",
+            body: r#"{"Resources": {
+                "image_host": "https://cdn.kobo.com/book-images/",
+                "image_url_template": "https://cdn.kobo.com/book-images/{ImageId}/{Width}/{Height}/false/image.jpg",
+                "library_sync": "https://storeapi.kobo.com/v1/library/sync",
+                "user_profile": "https://storeapi.kobo.com/v1/user/profile"
+            }}"#,
+            required_fields: &[
+                "/Resources/image_host",
+                "/Resources/image_url_template",
+                "/Resources/library_sync",
+                "/Resources/user_profile",
+            ],
+        },
+        Fixture {
+            path: "/v1/library/sync",
+            body: r#"[{"NewEntitlement": {
+                "BookEntitlement": {"Id": "00000000-0000-0000-0000-000000000001", "Accessibility": "Full"},
+                "BookMetadata": {
+                    "Title": "Self-Test Sample",
+                    "DownloadUrls": [{"Format": "EPUB3", "Url": "https://storeapi.kobo.com/v1/download/sample"}]
+                }
+            }}]"#,
+            required_fields: &[
+                "/0/NewEntitlement/BookEntitlement/Id",
+                "/0/NewEntitlement/BookMetadata/Title",
+                "/0/NewEntitlement/BookMetadata/DownloadUrls/0/Url",
+            ],
+        },
+    ];
+
+    /// Client that answers every request with the matching fixture.
+    struct FixtureClient;
+
+    #[async_trait::async_trait]
+    impl KoboClient for FixtureClient {
+        async fn request(&self, request: Request) -> Result<Response<Body>> {
+            let fixture = FIXTURES
+                .iter()
+                .find(|fixture| fixture.path == request.uri().path())
+                .with_context(|| format!("No self-test fixture for {}", request.uri().path()))?;
+            Ok(Response::builder()
+                .header(header::CONTENT_TYPE, "application/json; charset=utf-8")
+                .body(Body::from(fixture.body))?)
+        }
+    }
+
+    /// Returns a copy of the state that answers from the fixtures, with fresh caches
+    /// and logs and without rules that would block or delay the samples.
+    fn isolated(state: &ServerState) -> ServerState {
+        ServerState {
+            client: Arc::new(FixtureClient),
+            devices: Arc::default(),
+            access_schedule: Arc::default(),
+            chaos_rules: Arc::default(),
+            route_timeouts: Arc::default(),
+            serialize_device_requests: false,
+            device_locks: Arc::default(),
+            upstream_fallbacks: Arc::default(),
+            audit_log: Arc::default(),
+            snapshots_enabled: false,
+            snapshots: Arc::default(),
+            ..state.clone()
+        }
+    }
+
+    /// Checks that a rewritten fixture is valid JSON with every required field.
+    fn check_fixture(fixture: &Fixture, body: &str) -> Result<()> {
+        let value: Value = serde_json::from_str(body).with_context(|| {
+            format!(
+                "Rewriting the {} sample produced invalid JSON",
+                fixture.path
+            )
+        })?;
+        let missing = fixture
+            .required_fields
+            .iter()
+            .filter(|field| value.pointer(field).is_none())
+            .copied()
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            bail!(
+                "Rewriting the {} sample dropped required fields {}. Changes:\n  {}",
+                fixture.path,
+                missing.join(", "),
+                diff_json(fixture.body, body).join("\n  ")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Sends the built-in samples through the router with the configured rewrites.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, with the changes made to the sample, if a rewritten sample
+    /// is not valid JSON or is missing a field devices require.
+    pub async fn verify_rewrites(state: &ServerState) -> Result<()> {
+        let router = create_router(false, false, isolated(state));
+        for fixture in FIXTURES {
+            let response = router
+                .clone()
+                .oneshot(Request::get(fixture.path).body(Body::empty())?)
+                .await?;
+            if !response.status().is_success() {
+                bail!(
+                    "The {} sample was answered with {}",
+                    fixture.path,
+                    response.status()
+                );
+            }
+            let (parts, bytes) = read_response_body(response).await.map_err(|status| {
+                anyhow!("Failed to read the {} sample: {status}", fixture.path)
+            })?;
+            let body = decode_response_body(&bytes, is_gzip_encoded(&parts.headers))
+                .await
+                .map_err(|status| {
+                    anyhow!("Failed to decode the {} sample: {status}", fixture.path)
+                })?;
+            check_fixture(fixture, &body)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{state::server_state::ServerState, utils::response_patches::ResponsePatches};
+
+    fn state_with_patch(route: &str, patch: serde_json::Value) -> ServerState {
+        ServerState::builder("http://frontend.test")
+            .response_patches(ResponsePatches::new(HashMap::from([(
+                route.to_owned(),
+                serde_json::from_value(patch).unwrap(),
+            )])))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn verify_rewrites_passes_default_configuration() {
+        let state = ServerState::builder("http://frontend.test").build();
+
+        verify_rewrites(&state).await.unwrap();
+        assert!(state.audit_log.entries(None, 10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn verify_rewrites_passes_harmless_patch() {
+        let state = state_with_patch(
+            "/v1/initialization",
+            json!([{"op": "add", "path": "/Resources/extra", "value": "x"}]),
+        );
+
+        verify_rewrites(&state).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn verify_rewrites_reports_dropped_fields() {
+        let state = state_with_patch(
+            "/v1/library/sync",
+            json!([{"op": "remove", "path": "/0/NewEntitlement/BookMetadata"}]),
+        );
+
+        let error = verify_rewrites(&state).await.unwrap_err().to_string();
+
+        assert!(
+            error.contains("/0/NewEntitlement/BookMetadata/Title"),
+            "{error}"
+        );
+        assert!(
+            error.contains("- /0/NewEntitlement/BookMetadata/Title"),
+            "{error}"
+        );
+    }
+}
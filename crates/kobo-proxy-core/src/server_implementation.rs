@@ -0,0 +1,1477 @@
+//! Server builder module for configurable server construction.
+
+pub use self::implementation::{Server, ServerBuilder};
+mod implementation {
+    use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+
+    use anyhow::bail;
+    use axum::{
+        Router, ServiceExt,
+        body::Body,
+        extract::{ConnectInfo, connect_info::IntoMakeServiceWithConnectInfo},
+        serve::Listener,
+    };
+    use hyper::{Request, body::Incoming, service::service_fn};
+    use hyper_util::{
+        rt::{TokioExecutor, TokioIo},
+        server::{conn::auto, graceful::GracefulShutdown},
+    };
+    use tokio::task::JoinHandle;
+    use tokio_util::sync::CancellationToken;
+    use tower::ServiceExt as _;
+    use tower_http::normalize_path::NormalizePath;
+
+    #[cfg(feature = "admin-tls")]
+    use crate::{
+        listener::TlsListener,
+        startup_banner::log_certificate_expiry,
+        utils::{mutual_tls::MutualTls, port_binding::bind_listener},
+    };
+    use crate::{
+        listener::{
+            AcceptPolicy, AcceptStats, ClientAddress, IntoListener, SocketAddrListener,
+            TokioTcpListener,
+        },
+        resource_watchdog::{RESOURCE_WATCHDOG_TASK, run_resource_watchdog},
+        router::{create_admin_router, create_router},
+        self_test::verify_rewrites,
+        snapshot_task::{SNAPSHOT_TASK, run_snapshot_task},
+        startup_banner::log_startup_banner,
+        state::{
+            capture_log::CaptureLog, client::RequestHook, device_groups::DeviceGroups,
+            server_state::ServerState, upstream_fallbacks::UpstreamFallbacks,
+        },
+        supervisor::{RestartPolicy, supervise},
+        utils::{
+            access_schedule::AccessSchedule,
+            address_family::{AddressFamily, FamilyResolver},
+            chaos::ChaosRules,
+            cookie_policy::CookiePolicy,
+            device_frontend_urls::DeviceFrontendUrls,
+            dns_resolver::UpstreamResolver,
+            firmware_range::FirmwareRange,
+            header_injection::HeaderInjection,
+            privileges::PrivilegeDrop,
+            profile_rewrite::ProfileRewrite,
+            region_override::RegionOverride,
+            response_patches::ResponsePatches,
+            route_template::RouteTemplates,
+            route_timeouts::RouteTimeouts,
+            schema_validation::SchemaValidation,
+            tcp_tuning::TcpTuning,
+            trusted_proxies::TrustedProxies,
+        },
+    };
+
+    /// Handle of a task serving a listener.
+    type ServerHandle = JoinHandle<anyhow::Result<()>>;
+
+    /// Listener serving the local API to remote clients over mutual TLS.
+    #[cfg(feature = "admin-tls")]
+    type AdminListener = TlsListener;
+
+    /// Stand-in for the admin listener, which is never bound without TLS support.
+    #[cfg(not(feature = "admin-tls"))]
+    type AdminListener = tokio::net::TcpListener;
+
+    /// How the snapshot task is restarted. Snapshots are diagnostics, so the server
+    /// stays ready if the task dies.
+    const SNAPSHOT_RESTART_POLICY: RestartPolicy = RestartPolicy {
+        max_restarts: 3,
+        backoff: Duration::from_secs(30),
+    };
+
+    /// How the resource watchdog is restarted. Without it resource exhaustion goes
+    /// unnoticed, so the server becomes unready if it dies.
+    const RESOURCE_WATCHDOG_RESTART_POLICY: RestartPolicy = RestartPolicy {
+        max_restarts: 5,
+        backoff: Duration::from_secs(5),
+    };
+
+    /// Server struct that manages the Axum server lifecycle
+    pub struct Server {
+        /// The address the server is bound to
+        address: SocketAddr,
+        /// Cancellation token for graceful shutdown
+        cancellation_token: CancellationToken,
+        /// Handle to the server task
+        handle: ServerHandle,
+        /// The address the admin listener is bound to, if enabled
+        admin_address: Option<SocketAddr>,
+        /// Handle to the admin listener task, if enabled
+        admin_handle: Option<ServerHandle>,
+    }
+
+    impl Server {
+        /// Gets the address the server is bound to
+        #[must_use]
+        pub fn address(&self) -> SocketAddr {
+            self.address
+        }
+
+        /// Gets the address the admin listener is bound to, if enabled
+        #[must_use]
+        pub fn admin_address(&self) -> Option<SocketAddr> {
+            self.admin_address
+        }
+
+        /// Gracefully shuts down the server
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the server fails to shut down cleanly.
+        pub async fn shutdown(self) -> anyhow::Result<()> {
+            self.cancellation_token.cancel();
+            let result = self.handle.await?;
+            if let Some(admin_handle) = self.admin_handle {
+                admin_handle.await??;
+            }
+            result
+        }
+    }
+
+    /// Builder for configuring and creating Server instances.
+    #[must_use]
+    #[expect(
+        clippy::struct_excessive_bools,
+        reason = "each bool is an independent feature toggle"
+    )]
+    pub struct ServerBuilder<L> {
+        listener_builder: L,
+        cancellation_token: CancellationToken,
+        port: u16,
+        frontend_url: String,
+        config: serde_json::Value,
+        device_frontend_urls: Vec<String>,
+        enable_request_logging: bool,
+        enable_response_logging: bool,
+        log_body_max_bytes: Option<usize>,
+        route_templates: Vec<String>,
+        clock_skew_warning_seconds: u64,
+        upstream_accept_language: Option<String>,
+        upstream_query_overrides: Vec<String>,
+        upstream_headers: Vec<String>,
+        access_rules: Vec<String>,
+        device_groups: Vec<String>,
+        group_policies: Vec<String>,
+        mask_profile_identifiers: bool,
+        rewrite_profile_urls: bool,
+        chaos_mode: bool,
+        chaos_rules: Vec<String>,
+        synthetic_device_auth: bool,
+        landing_page: bool,
+        preserve_header_case: bool,
+        strip_transfer_encoding_firmware: Option<String>,
+        serialize_device_requests: bool,
+        upstream_failure_fallbacks: bool,
+        upstream_failure_responses: Vec<String>,
+        response_patches: Vec<String>,
+        route_timeouts: Vec<String>,
+        trusted_proxies: Vec<String>,
+        tcp_nodelay: bool,
+        reuse_port: bool,
+        tcp_keepalive: Option<Duration>,
+        tcp_keepalive_interval: Option<Duration>,
+        accept_policy: AcceptPolicy,
+        upstream_idle_timeout: Option<Duration>,
+        gzip_level: u32,
+        upstream_address_family: AddressFamily,
+        upstream_dns: Vec<String>,
+        cookie_policy: CookiePolicy,
+        schema_validation: SchemaValidation,
+        upstream_happy_eyeballs_timeout: Option<Duration>,
+        snapshot_interval: Option<Duration>,
+        snapshot_routes: Vec<String>,
+        resource_watchdog_interval: Option<Duration>,
+        memory_warning_bytes: Option<u64>,
+        auth_failure_backoff_after: Option<u32>,
+        api_rate_limit: Option<u32>,
+        run_as_user: Option<String>,
+        run_as_group: Option<String>,
+        admin_port: Option<u16>,
+        admin_tls_certificate: Option<PathBuf>,
+        admin_tls_key: Option<PathBuf>,
+        admin_tls_client_ca: Option<PathBuf>,
+        capture_file: Option<PathBuf>,
+        request_hooks: Vec<Arc<dyn RequestHook>>,
+    }
+
+    impl ServerBuilder<TokioTcpListener> {
+        /// Creates a new `ServerBuilder` with default configuration.
+        ///
+        /// # Arguments
+        /// * `frontend_url` - The frontend URL to use for URL rewriting
+        pub fn new(cancellation_token: CancellationToken) -> Self {
+            Self {
+                listener_builder: TokioTcpListener::default(),
+                cancellation_token,
+                port: 8080,
+                frontend_url: "http://localhost:8080".to_owned(),
+                config: serde_json::Value::Null,
+                device_frontend_urls: Vec::new(),
+                enable_request_logging: false,
+                enable_response_logging: false,
+                log_body_max_bytes: None,
+                route_templates: Vec::new(),
+                clock_skew_warning_seconds: 300,
+                upstream_accept_language: None,
+                upstream_query_overrides: Vec::new(),
+                upstream_headers: Vec::new(),
+                access_rules: Vec::new(),
+                device_groups: Vec::new(),
+                group_policies: Vec::new(),
+                mask_profile_identifiers: false,
+                rewrite_profile_urls: false,
+                chaos_mode: false,
+                chaos_rules: Vec::new(),
+                synthetic_device_auth: false,
+                landing_page: false,
+                preserve_header_case: false,
+                strip_transfer_encoding_firmware: None,
+                serialize_device_requests: false,
+                upstream_failure_fallbacks: false,
+                upstream_failure_responses: Vec::new(),
+                response_patches: Vec::new(),
+                route_timeouts: Vec::new(),
+                trusted_proxies: Vec::new(),
+                tcp_nodelay: false,
+                reuse_port: false,
+                tcp_keepalive: None,
+                tcp_keepalive_interval: None,
+                accept_policy: AcceptPolicy::default(),
+                upstream_idle_timeout: None,
+                gzip_level: 6,
+                upstream_address_family: AddressFamily::default(),
+                upstream_dns: Vec::new(),
+                cookie_policy: CookiePolicy::default(),
+                schema_validation: SchemaValidation::default(),
+                upstream_happy_eyeballs_timeout: Some(Duration::from_millis(300)),
+                snapshot_interval: None,
+                resource_watchdog_interval: None,
+                memory_warning_bytes: None,
+                auth_failure_backoff_after: None,
+                api_rate_limit: None,
+                snapshot_routes: Vec::new(),
+                run_as_user: None,
+                run_as_group: None,
+                admin_port: None,
+                admin_tls_certificate: None,
+                admin_tls_key: None,
+                admin_tls_client_ca: None,
+                capture_file: None,
+                request_hooks: Vec::new(),
+            }
+        }
+
+        /// Sets `SO_REUSEPORT` on the device and admin listeners, so a newly started
+        /// process can bind the same ports while this one drains its connections.
+        pub fn reuse_port(mut self, enable: bool) -> Self {
+            self.reuse_port = enable;
+            self.listener_builder.reuse_port = enable;
+            self
+        }
+    }
+
+    impl<L> ServerBuilder<L> {
+        /// Enables request logging middleware.
+        pub fn enable_request_logging(mut self, enable: bool) -> Self {
+            self.enable_request_logging = enable;
+            self
+        }
+
+        /// Enables response logging middleware.
+        pub fn enable_response_logging(mut self, enable: bool) -> Self {
+            self.enable_response_logging = enable;
+            self
+        }
+
+        /// Sets the maximum number of body bytes included in request and response logs.
+        ///
+        /// # Arguments
+        /// * `max_bytes` - The logging ceiling, or `None` to log bodies in full
+        pub fn log_body_max_bytes(mut self, max_bytes: Option<usize>) -> Self {
+            self.log_body_max_bytes = max_bytes;
+            self
+        }
+
+        /// Sets additional route templates used to group request paths in logs.
+        ///
+        /// # Arguments
+        /// * `route_templates` - Templates such as `/v1/custom/{id}/state`
+        pub fn route_templates(mut self, route_templates: Vec<String>) -> Self {
+            self.route_templates = route_templates;
+            self
+        }
+
+        /// Sets the device clock skew, in seconds, at which a warning is logged.
+        ///
+        /// # Arguments
+        /// * `seconds` - The warning threshold, or 0 to disable the warning
+        pub fn clock_skew_warning_seconds(mut self, seconds: u64) -> Self {
+            self.clock_skew_warning_seconds = seconds;
+            self
+        }
+
+        /// Sets the `Accept-Language` header sent to the Kobo store API.
+        ///
+        /// # Arguments
+        /// * `accept_language` - The header value, or `None` to forward the device's value
+        pub fn upstream_accept_language(mut self, accept_language: Option<String>) -> Self {
+            self.upstream_accept_language = accept_language;
+            self
+        }
+
+        /// Sets query parameter overrides for requests forwarded to the Kobo store API.
+        ///
+        /// # Arguments
+        /// * `query_overrides` - Overrides in `NAME=VALUE` form
+        pub fn upstream_query_overrides(mut self, query_overrides: Vec<String>) -> Self {
+            self.upstream_query_overrides = query_overrides;
+            self
+        }
+
+        /// Sets headers added to requests forwarded to the Kobo store API.
+        ///
+        /// # Arguments
+        /// * `headers` - Headers in `NAME=VALUE` form, where the value may contain the
+        ///   `{device_id}` and `{user_agent}` placeholders
+        pub fn upstream_headers(mut self, headers: Vec<String>) -> Self {
+            self.upstream_headers = headers;
+            self
+        }
+
+        /// Sets time-based rules that block requests from specific devices.
+        ///
+        /// # Arguments
+        /// * `rules` - Rules in `DEVICE=HH:MM-HH:MM[/SCOPE]` form
+        pub fn access_rules(mut self, rules: Vec<String>) -> Self {
+            self.access_rules = rules;
+            self
+        }
+
+        /// Assigns devices to named groups.
+        ///
+        /// # Arguments
+        /// * `memberships` - Memberships in `DEVICE=GROUP` form
+        pub fn device_groups(mut self, memberships: Vec<String>) -> Self {
+            self.device_groups = memberships;
+            self
+        }
+
+        /// Sets the policies applied to the requests of each device group.
+        ///
+        /// # Arguments
+        /// * `policies` - Policies in `GROUP=POLICY` form, where POLICY is `block:PATH`,
+        ///   `rate-limit:N`, or `logging:LEVEL`
+        pub fn group_policies(mut self, policies: Vec<String>) -> Self {
+            self.group_policies = policies;
+            self
+        }
+
+        /// Masks account identifiers in the user profile sent to devices.
+        pub fn mask_profile_identifiers(mut self, enable: bool) -> Self {
+            self.mask_profile_identifiers = enable;
+            self
+        }
+
+        /// Rewrites Kobo API URLs, such as the avatar URL, in the user profile to the
+        /// frontend URL.
+        pub fn rewrite_profile_urls(mut self, enable: bool) -> Self {
+            self.rewrite_profile_urls = enable;
+            self
+        }
+
+        /// Enables chaos mode, which is required for chaos rules to be accepted.
+        pub fn chaos_mode(mut self, enable: bool) -> Self {
+            self.chaos_mode = enable;
+            self
+        }
+
+        /// Sets rules that inject faults into requests, for testing how devices cope
+        /// with a misbehaving proxy or upstream.
+        ///
+        /// # Arguments
+        /// * `rules` - Rules in `ROUTE=PROBABILITY:FAULT` form
+        pub fn chaos_rules(mut self, rules: Vec<String>) -> Self {
+            self.chaos_rules = rules;
+            self
+        }
+
+        /// Answers device authentication locally with synthetic tokens, for devices
+        /// that are not linked to a Kobo account.
+        pub fn synthetic_device_auth(mut self, enable: bool) -> Self {
+            self.synthetic_device_auth = enable;
+            self
+        }
+
+        /// Serves a local status and setup page at `/`, and the device configuration
+        /// snippet at `/setup`, instead of forwarding them to the Kobo API.
+        pub fn landing_page(mut self, enable: bool) -> Self {
+            self.landing_page = enable;
+            self
+        }
+
+        /// Preserves the original casing of HTTP/1 header names, in requests forwarded
+        /// to the Kobo API and in responses returned to devices, for embedded HTTP
+        /// stacks that match header names case-sensitively.
+        pub fn preserve_header_case(mut self, enable: bool) -> Self {
+            self.preserve_header_case = enable;
+            self
+        }
+
+        /// Sets the firmware versions for which the `transfer-encoding` header is
+        /// removed from Kobo API responses.
+        ///
+        /// # Arguments
+        /// * `range` - Space-separated comparators such as `<4.38`, or `None` for every version
+        pub fn strip_transfer_encoding_firmware(mut self, range: Option<String>) -> Self {
+            self.strip_transfer_encoding_firmware = range;
+            self
+        }
+
+        /// Enables serialization of library requests from the same device.
+        pub fn serialize_device_requests(mut self, enable: bool) -> Self {
+            self.serialize_device_requests = enable;
+            self
+        }
+
+        /// Enables device-friendly responses, such as an empty sync or the last
+        /// successful initialization, when the Kobo store API fails.
+        pub fn upstream_failure_fallbacks(mut self, enable: bool) -> Self {
+            self.upstream_failure_fallbacks = enable;
+            self
+        }
+
+        /// Sets custom responses served when the Kobo store API fails.
+        ///
+        /// # Arguments
+        /// * `responses` - JSON files per route template in `ROUTE=FILE` form
+        pub fn upstream_failure_responses(mut self, responses: Vec<String>) -> Self {
+            self.upstream_failure_responses = responses;
+            self
+        }
+
+        /// Sets JSON Patch documents applied to successful JSON responses.
+        ///
+        /// # Arguments
+        /// * `patches` - RFC 6902 JSON Patch files per route template in `ROUTE=FILE` form
+        pub fn response_patches(mut self, patches: Vec<String>) -> Self {
+            self.response_patches = patches;
+            self
+        }
+
+        /// Sets request timeouts per route template.
+        ///
+        /// # Arguments
+        /// * `timeouts` - Timeouts in `ROUTE=MS` form, where `ROUTE` may be `*` for every other
+        ///   route
+        pub fn route_timeouts(mut self, timeouts: Vec<String>) -> Self {
+            self.route_timeouts = timeouts;
+            self
+        }
+
+        /// Sets the reverse proxies whose `X-Forwarded-For` and `X-Real-IP` headers
+        /// identify the client address.
+        ///
+        /// # Arguments
+        /// * `networks` - CIDRs or addresses of the trusted proxies
+        pub fn trusted_proxies(mut self, networks: Vec<String>) -> Self {
+            self.trusted_proxies = networks;
+            self
+        }
+
+        /// Sets `TCP_NODELAY` on inbound and upstream connections.
+        pub fn tcp_nodelay(mut self, enable: bool) -> Self {
+            self.tcp_nodelay = enable;
+            self
+        }
+
+        /// Enables TCP keepalives on inbound and upstream connections.
+        ///
+        /// # Arguments
+        /// * `keepalive` - Idle time before the first probe, or `None` to disable keepalives
+        pub fn tcp_keepalive(mut self, keepalive: Option<Duration>) -> Self {
+            self.tcp_keepalive = keepalive;
+            self
+        }
+
+        /// Sets the time between TCP keepalive probes.
+        ///
+        /// # Arguments
+        /// * `interval` - The probe interval, or `None` for the system default
+        pub fn tcp_keepalive_interval(mut self, interval: Option<Duration>) -> Self {
+            self.tcp_keepalive_interval = interval;
+            self
+        }
+
+        /// Sets how long the listener pauses after accept errors before retrying.
+        pub fn accept_policy(mut self, accept_policy: AcceptPolicy) -> Self {
+            self.accept_policy = accept_policy;
+            self
+        }
+
+        /// Sets how long idle upstream connections are kept alive for reuse.
+        ///
+        /// # Arguments
+        /// * `timeout` - The idle timeout, or `None` for the client default
+        pub fn upstream_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+            self.upstream_idle_timeout = timeout;
+            self
+        }
+
+        /// Sets the gzip level used when re-encoding rewritten response bodies.
+        ///
+        /// # Arguments
+        /// * `level` - 0 (no compression, fastest) to 9 (smallest, slowest)
+        pub fn gzip_level(mut self, level: u32) -> Self {
+            self.gzip_level = level;
+            self
+        }
+
+        /// Sets which address family connections to the Kobo store API prefer.
+        pub fn upstream_address_family(mut self, address_family: AddressFamily) -> Self {
+            self.upstream_address_family = address_family;
+            self
+        }
+
+        /// Sets the DNS servers used to resolve the Kobo store API, replacing the
+        /// uncached system resolver with one that caches answers for their TTL.
+        ///
+        /// # Arguments
+        /// * `servers` - `system` to cache answers from the system's DNS servers, or DNS servers in
+        ///   `[SCHEME://]IP[:PORT][#NAME]` form, where SCHEME is `udp`, `tls`, or `https` and NAME
+        ///   is the certificate name of an encrypted server
+        pub fn upstream_dns(mut self, servers: Vec<String>) -> Self {
+            self.upstream_dns = servers;
+            self
+        }
+
+        /// Sets how `Set-Cookie` headers from the Kobo store API are handled.
+        pub fn cookie_policy(mut self, cookie_policy: CookiePolicy) -> Self {
+            self.cookie_policy = cookie_policy;
+            self
+        }
+
+        /// Sets whether responses for Kobo store API endpoints with typed payloads are
+        /// checked against them, so changes to the API are noticed before devices
+        /// misbehave.
+        pub fn schema_validation(mut self, schema_validation: SchemaValidation) -> Self {
+            self.schema_validation = schema_validation;
+            self
+        }
+
+        /// Sets how long a connection attempt to the preferred address family may take
+        /// before the other family is tried in parallel.
+        ///
+        /// # Arguments
+        /// * `timeout` - The fallback delay, or `None` to try addresses one at a time
+        pub fn upstream_happy_eyeballs_timeout(mut self, timeout: Option<Duration>) -> Self {
+            self.upstream_happy_eyeballs_timeout = timeout;
+            self
+        }
+
+        /// Enables periodic snapshots of upstream responses for key endpoints.
+        ///
+        /// # Arguments
+        /// * `interval` - Time between snapshots, or `None` to disable snapshots
+        pub fn snapshot_interval(mut self, interval: Option<Duration>) -> Self {
+            self.snapshot_interval = interval;
+            self
+        }
+
+        /// Enables the resource watchdog, which samples open file descriptors, resident
+        /// memory, and cache sizes, and warns when a limit is close to being reached.
+        ///
+        /// # Arguments
+        /// * `interval` - Time between samples, or `None` to disable the watchdog
+        pub fn resource_watchdog_interval(mut self, interval: Option<Duration>) -> Self {
+            self.resource_watchdog_interval = interval;
+            self
+        }
+
+        /// Sets the resident memory at which the resource watchdog warns.
+        ///
+        /// # Arguments
+        /// * `bytes` - Resident memory in bytes, or `None` to not warn about memory
+        pub fn memory_warning_bytes(mut self, bytes: Option<u64>) -> Self {
+            self.memory_warning_bytes = bytes;
+            self
+        }
+
+        /// Sets how many consecutive `401 Unauthorized` responses a device may get
+        /// before it is backed off. A backed off device is answered with `429 Too Many
+        /// Requests` for a period that doubles with each further failure.
+        ///
+        /// # Arguments
+        /// * `failures` - The failure threshold, or `None` to never back devices off
+        pub fn auth_failure_backoff_after(mut self, failures: Option<u32>) -> Self {
+            self.auth_failure_backoff_after = failures;
+            self
+        }
+
+        /// Limits how many local API requests each caller may make per minute. Callers
+        /// are identified by their client IP address.
+        ///
+        /// # Arguments
+        /// * `requests_per_minute` - The request budget per caller, or `None` for no limit
+        pub fn api_rate_limit(mut self, requests_per_minute: Option<u32>) -> Self {
+            self.api_rate_limit = requests_per_minute;
+            self
+        }
+
+        /// Sets the route templates that are snapshotted.
+        ///
+        /// # Arguments
+        /// * `routes` - Route templates, or empty for `/v1/initialization`
+        pub fn snapshot_routes(mut self, routes: Vec<String>) -> Self {
+            self.snapshot_routes = routes;
+            self
+        }
+
+        /// Sets the user the server switches to after binding its port.
+        ///
+        /// # Arguments
+        /// * `user` - The user name, or `None` to keep the current user
+        pub fn run_as_user(mut self, user: Option<String>) -> Self {
+            self.run_as_user = user;
+            self
+        }
+
+        /// Sets the group the server switches to after binding its port.
+        ///
+        /// # Arguments
+        /// * `group` - The group name, or `None` for the primary group of the `run_as_user` user
+        pub fn run_as_group(mut self, group: Option<String>) -> Self {
+            self.run_as_group = group;
+            self
+        }
+
+        /// Serves the local API on a dedicated listener that requires TLS client
+        /// certificates, instead of alongside the device routes.
+        ///
+        /// # Arguments
+        /// * `port` - The admin port, or `None` to serve the local API on the main port
+        pub fn admin_port(mut self, port: Option<u16>) -> Self {
+            self.admin_port = port;
+            self
+        }
+
+        /// Sets the certificate chain the admin listener presents.
+        ///
+        /// # Arguments
+        /// * `path` - A PEM file with the certificate chain
+        pub fn admin_tls_certificate(mut self, path: Option<PathBuf>) -> Self {
+            self.admin_tls_certificate = path;
+            self
+        }
+
+        /// Sets the private key of the admin listener certificate.
+        ///
+        /// # Arguments
+        /// * `path` - A PEM file with the private key
+        pub fn admin_tls_key(mut self, path: Option<PathBuf>) -> Self {
+            self.admin_tls_key = path;
+            self
+        }
+
+        /// Sets the CA that admin client certificates must be signed by.
+        ///
+        /// # Arguments
+        /// * `path` - A PEM file with the CA certificates
+        pub fn admin_tls_client_ca(mut self, path: Option<PathBuf>) -> Self {
+            self.admin_tls_client_ca = path;
+            self
+        }
+
+        /// Records device exchanges to a capture file for the `replay-sync` subcommand.
+        ///
+        /// # Arguments
+        /// * `path` - The capture file, or `None` to disable capture
+        pub fn capture_file(mut self, path: Option<PathBuf>) -> Self {
+            self.capture_file = path;
+            self
+        }
+
+        /// Adds a hook that modifies requests before they are forwarded to the Kobo
+        /// store API. Hooks run in the order they are added.
+        ///
+        /// # Arguments
+        /// * `hook` - The hook to run on every forwarded request
+        pub fn request_hook(mut self, hook: Arc<dyn RequestHook>) -> Self {
+            self.request_hooks.push(hook);
+            self
+        }
+
+        /// Sets the frontend URL for URL rewriting.
+        ///
+        /// # Arguments
+        /// * `frontend_url` - The frontend URL to use
+        pub fn frontend_url<T: Into<String>>(mut self, frontend_url: T) -> Self {
+            self.frontend_url = frontend_url.into();
+            self
+        }
+
+        /// Sets the redacted configuration reported by the state export endpoint.
+        ///
+        /// # Arguments
+        /// * `config` - The configuration, with secrets already redacted
+        pub fn config(mut self, config: serde_json::Value) -> Self {
+            self.config = config;
+            self
+        }
+
+        /// Sets frontend URLs that replace the default for specific devices.
+        ///
+        /// # Arguments
+        /// * `mappings` - Mappings in `DEVICE=URL` form
+        pub fn device_frontend_urls(mut self, mappings: Vec<String>) -> Self {
+            self.device_frontend_urls = mappings;
+            self
+        }
+
+        /// Sets the port for the server to bind to.
+        ///
+        /// # Arguments
+        /// * `port` - The port number to bind the server to
+        pub fn port(mut self, port: u16) -> Self {
+            self.port = port;
+            self
+        }
+
+        /// Sets the listener builder for the server.
+        ///
+        /// # Arguments
+        /// * `listener_builder` - The listener builder to use for creating the listener
+        #[cfg(any(test, feature = "test-util"))]
+        pub fn listener_builder<N>(self, listener_builder: N) -> ServerBuilder<N> {
+            ServerBuilder {
+                listener_builder,
+                cancellation_token: self.cancellation_token,
+                port: self.port,
+                frontend_url: self.frontend_url,
+                config: self.config,
+                device_frontend_urls: self.device_frontend_urls,
+                enable_request_logging: self.enable_request_logging,
+                enable_response_logging: self.enable_response_logging,
+                log_body_max_bytes: self.log_body_max_bytes,
+                route_templates: self.route_templates,
+                clock_skew_warning_seconds: self.clock_skew_warning_seconds,
+                upstream_accept_language: self.upstream_accept_language,
+                upstream_query_overrides: self.upstream_query_overrides,
+                upstream_headers: self.upstream_headers,
+                access_rules: self.access_rules,
+                device_groups: self.device_groups,
+                group_policies: self.group_policies,
+                mask_profile_identifiers: self.mask_profile_identifiers,
+                rewrite_profile_urls: self.rewrite_profile_urls,
+                chaos_mode: self.chaos_mode,
+                chaos_rules: self.chaos_rules,
+                synthetic_device_auth: self.synthetic_device_auth,
+                landing_page: self.landing_page,
+                preserve_header_case: self.preserve_header_case,
+                strip_transfer_encoding_firmware: self.strip_transfer_encoding_firmware,
+                serialize_device_requests: self.serialize_device_requests,
+                upstream_failure_fallbacks: self.upstream_failure_fallbacks,
+                upstream_failure_responses: self.upstream_failure_responses,
+                response_patches: self.response_patches,
+                route_timeouts: self.route_timeouts,
+                trusted_proxies: self.trusted_proxies,
+                tcp_nodelay: self.tcp_nodelay,
+                reuse_port: self.reuse_port,
+                tcp_keepalive: self.tcp_keepalive,
+                tcp_keepalive_interval: self.tcp_keepalive_interval,
+                accept_policy: self.accept_policy,
+                upstream_idle_timeout: self.upstream_idle_timeout,
+                gzip_level: self.gzip_level,
+                upstream_address_family: self.upstream_address_family,
+                upstream_dns: self.upstream_dns,
+                cookie_policy: self.cookie_policy,
+                schema_validation: self.schema_validation,
+                upstream_happy_eyeballs_timeout: self.upstream_happy_eyeballs_timeout,
+                snapshot_interval: self.snapshot_interval,
+                snapshot_routes: self.snapshot_routes,
+                resource_watchdog_interval: self.resource_watchdog_interval,
+                memory_warning_bytes: self.memory_warning_bytes,
+                auth_failure_backoff_after: self.auth_failure_backoff_after,
+                api_rate_limit: self.api_rate_limit,
+                run_as_user: self.run_as_user,
+                run_as_group: self.run_as_group,
+                admin_port: self.admin_port,
+                admin_tls_certificate: self.admin_tls_certificate,
+                admin_tls_key: self.admin_tls_key,
+                admin_tls_client_ca: self.admin_tls_client_ca,
+                capture_file: self.capture_file,
+                request_hooks: self.request_hooks,
+            }
+        }
+
+        /// Builds and starts the server with the configured options.
+        ///
+        /// # Arguments
+        /// * `cancellation_token` - Token for graceful shutdown coordination
+        ///
+        /// # Errors
+        /// Returns an error if a device frontend URL, route template, region override,
+        /// upstream header, access rule, chaos rule, firmware range, upstream failure response,
+        /// response patch, route timeout, trusted proxy, address family, upstream DNS server,
+        /// cookie policy, user or group to run as, or admin TLS file is invalid, if chaos rules
+        /// are set without chaos mode, if the admin listener is only partially configured, if
+        /// privileges cannot be dropped, if the configured rewrites break the built-in sample
+        /// responses, or if the server fails to start.
+        pub async fn build(self) -> anyhow::Result<Server>
+        where
+            L: IntoListener + Send,
+            <L::Listener as Listener>::Io: Send + Unpin + 'static,
+        {
+            let device_frontend_urls = DeviceFrontendUrls::new(&self.device_frontend_urls)?;
+            let route_templates = RouteTemplates::new(&self.route_templates)?;
+            let route_timeouts = RouteTimeouts::new(&self.route_timeouts)?;
+            let trusted_proxies = TrustedProxies::new(&self.trusted_proxies)?;
+            let region_override = self.parse_region_override()?;
+            let header_injection = HeaderInjection::new(&self.upstream_headers)?;
+            let access_schedule = AccessSchedule::new(&self.access_rules)?;
+            let device_groups = DeviceGroups::new(&self.device_groups, &self.group_policies)?;
+            let capture_log = self.open_capture_log()?;
+            let chaos_rules = self.parse_chaos_rules()?;
+            let strip_transfer_encoding = self.parse_strip_transfer_encoding()?;
+            let resolver = self.upstream_resolver()?;
+            let privilege_drop = self.privilege_drop()?;
+            let admin_listener = self.bind_admin_listener()?;
+            let upstream_fallbacks = self.read_upstream_fallbacks().await?;
+            let response_patches = ResponsePatches::read(&self.response_patches).await?;
+            let tcp_tuning = self.tcp_tuning();
+            let profile_rewrite = self.profile_rewrite();
+            let accept_stats = Arc::new(AcceptStats::default());
+            let listener = self
+                .listener_builder
+                .into_listener(
+                    self.port,
+                    tcp_tuning,
+                    self.accept_policy,
+                    accept_stats.clone(),
+                )
+                .await?;
+            if let Some(privilege_drop) = privilege_drop {
+                privilege_drop.apply()?;
+            }
+            let (address, admin_address) = local_addresses(&listener, admin_listener.as_ref())?;
+            let app_state = ServerState::builder(self.frontend_url)
+                .request_hooks(self.request_hooks)
+                .config(self.config)
+                .device_frontend_urls(device_frontend_urls)
+                .log_body_max_bytes(self.log_body_max_bytes)
+                .route_templates(route_templates)
+                .route_timeouts(route_timeouts)
+                .trusted_proxies(trusted_proxies)
+                .clock_skew_warning_seconds(self.clock_skew_warning_seconds)
+                .region_override(region_override)
+                .header_injection(header_injection)
+                .access_schedule(access_schedule)
+                .auth_failure_backoff_after(self.auth_failure_backoff_after)
+                .device_groups(device_groups)
+                .capture_log(capture_log)
+                .chaos_rules(chaos_rules)
+                .synthetic_device_auth(self.synthetic_device_auth)
+                .landing_page(self.landing_page)
+                .preserve_header_case(self.preserve_header_case)
+                .strip_transfer_encoding(strip_transfer_encoding)
+                .profile_rewrite(profile_rewrite)
+                .serialize_device_requests(self.serialize_device_requests)
+                .upstream_fallbacks(upstream_fallbacks)
+                .cookie_policy(self.cookie_policy)
+                .schema_validation(self.schema_validation)
+                .response_patches(response_patches)
+                .tcp_tuning(tcp_tuning)
+                .upstream_idle_timeout(self.upstream_idle_timeout)
+                .gzip_level(self.gzip_level)
+                .resolver(resolver)
+                .happy_eyeballs_timeout(self.upstream_happy_eyeballs_timeout)
+                .snapshots_enabled(self.snapshot_interval.is_some())
+                .snapshot_routes(self.snapshot_routes)
+                .accept_stats(accept_stats)
+                .memory_warning_bytes(self.memory_warning_bytes)
+                .api_rate_limit(self.api_rate_limit)
+                .listener_addresses(Some(address), admin_address)
+                .serve_admin_api(admin_listener.is_none())
+                .build();
+            verify_rewrites(&app_state).await?;
+            log_startup_banner(&app_state);
+            spawn_background_tasks(
+                &app_state,
+                self.snapshot_interval,
+                self.resource_watchdog_interval,
+                &self.cancellation_token,
+            );
+            let admin_handle = serve_admin(admin_listener, &app_state, &self.cancellation_token);
+            let server_handle = serve(
+                listener,
+                create_router(
+                    self.enable_request_logging,
+                    self.enable_response_logging,
+                    app_state,
+                ),
+                self.preserve_header_case,
+                self.cancellation_token.clone(),
+            );
+
+            Ok(Server {
+                address,
+                cancellation_token: self.cancellation_token,
+                handle: server_handle,
+                admin_address,
+                admin_handle,
+            })
+        }
+
+        /// Returns the socket options applied to inbound and upstream connections.
+        fn tcp_tuning(&self) -> TcpTuning {
+            TcpTuning {
+                nodelay: self.tcp_nodelay,
+                keepalive_time: self.tcp_keepalive,
+                keepalive_interval: self.tcp_keepalive_interval,
+            }
+        }
+
+        /// Returns the rewrites applied to the user profile.
+        fn profile_rewrite(&self) -> ProfileRewrite {
+            ProfileRewrite {
+                mask_identifiers: self.mask_profile_identifiers,
+                rewrite_urls: self.rewrite_profile_urls,
+            }
+        }
+
+        /// Reads the custom upstream failure responses and sets up the fallbacks.
+        async fn read_upstream_fallbacks(&self) -> anyhow::Result<UpstreamFallbacks> {
+            Ok(UpstreamFallbacks::new(
+                self.upstream_failure_fallbacks,
+                UpstreamFallbacks::read_custom(&self.upstream_failure_responses).await?,
+            ))
+        }
+
+        /// Parses the language and query parameter overrides sent upstream.
+        fn parse_region_override(&self) -> anyhow::Result<RegionOverride> {
+            RegionOverride::new(
+                self.upstream_accept_language.as_deref(),
+                &self.upstream_query_overrides,
+            )
+        }
+
+        /// Looks up the user and group to run as, if configured.
+        fn privilege_drop(&self) -> anyhow::Result<Option<PrivilegeDrop>> {
+            PrivilegeDrop::new(self.run_as_user.as_deref(), self.run_as_group.as_deref())
+        }
+
+        /// Opens the capture file, if capture is enabled.
+        fn open_capture_log(&self) -> anyhow::Result<Option<CaptureLog>> {
+            self.capture_file
+                .as_deref()
+                .map(CaptureLog::open)
+                .transpose()
+        }
+
+        /// Parses the chaos rules, which are only accepted in chaos mode.
+        fn parse_chaos_rules(&self) -> anyhow::Result<ChaosRules> {
+            let chaos_rules = ChaosRules::new(&self.chaos_rules)?;
+            if !chaos_rules.is_empty() && !self.chaos_mode {
+                bail!("Chaos rules are only applied with chaos mode enabled");
+            }
+            Ok(chaos_rules)
+        }
+
+        /// Creates the resolver for the Kobo store API from the upstream DNS servers and
+        /// address family.
+        fn upstream_resolver(&self) -> anyhow::Result<FamilyResolver> {
+            Ok(FamilyResolver::new(
+                UpstreamResolver::new(&self.upstream_dns)?,
+                self.upstream_address_family,
+            ))
+        }
+
+        /// Parses the firmware versions that get the `transfer-encoding` workaround,
+        /// defaulting to every version.
+        fn parse_strip_transfer_encoding(&self) -> anyhow::Result<FirmwareRange> {
+            Ok(self
+                .strip_transfer_encoding_firmware
+                .as_deref()
+                .map(str::parse)
+                .transpose()?
+                .unwrap_or_default())
+        }
+
+        /// Loads the admin TLS configuration and binds the admin listener, if
+        /// configured.
+        #[cfg(feature = "admin-tls")]
+        fn bind_admin_listener(&self) -> anyhow::Result<Option<AdminListener>> {
+            let (port, mutual_tls) = match (
+                self.admin_port,
+                &self.admin_tls_certificate,
+                &self.admin_tls_key,
+                &self.admin_tls_client_ca,
+            ) {
+                (None, None, None, None) => return Ok(None),
+                (Some(port), Some(certificate), Some(key), Some(client_ca)) => {
+                    (port, MutualTls::new(certificate, key, client_ca)?)
+                }
+                _ => bail!(
+                    "The admin listener needs a port, TLS certificate, TLS key, and client CA"
+                ),
+            };
+            log_certificate_expiry(mutual_tls.certificate_expiry());
+            let listener = bind_listener(port, self.reuse_port)?;
+            Ok(Some(TlsListener::new(listener, mutual_tls.acceptor())?))
+        }
+
+        /// Rejects an admin listener configuration, since serving one needs TLS
+        /// support.
+        #[cfg(not(feature = "admin-tls"))]
+        fn bind_admin_listener(&self) -> anyhow::Result<Option<AdminListener>> {
+            match (
+                self.admin_port,
+                &self.admin_tls_certificate,
+                &self.admin_tls_key,
+                &self.admin_tls_client_ca,
+            ) {
+                (None, None, None, None) => Ok(None),
+                _ => bail!("The admin listener needs the `admin-tls` feature"),
+            }
+        }
+    }
+
+    /// Returns the addresses the listener and admin listener, if any, are bound to.
+    fn local_addresses(
+        listener: &impl SocketAddrListener,
+        admin_listener: Option<&AdminListener>,
+    ) -> std::io::Result<(SocketAddr, Option<SocketAddr>)> {
+        Ok((
+            listener.local_addr()?,
+            admin_listener.map(Listener::local_addr).transpose()?,
+        ))
+    }
+
+    /// Spawns the supervised periodic snapshot and resource watchdog tasks, if enabled.
+    fn spawn_background_tasks(
+        app_state: &ServerState,
+        snapshot_interval: Option<Duration>,
+        resource_watchdog_interval: Option<Duration>,
+        cancellation_token: &CancellationToken,
+    ) {
+        if let Some(interval) = snapshot_interval {
+            let state = app_state.clone();
+            let token = cancellation_token.clone();
+            supervise(
+                app_state.background_tasks.clone(),
+                SNAPSHOT_TASK,
+                false,
+                interval,
+                SNAPSHOT_RESTART_POLICY,
+                cancellation_token.clone(),
+                move || run_snapshot_task(state.clone(), interval, token.clone()),
+            );
+        }
+        if let Some(interval) = resource_watchdog_interval {
+            let state = app_state.clone();
+            let token = cancellation_token.clone();
+            supervise(
+                app_state.background_tasks.clone(),
+                RESOURCE_WATCHDOG_TASK,
+                true,
+                interval,
+                RESOURCE_WATCHDOG_RESTART_POLICY,
+                cancellation_token.clone(),
+                move || run_resource_watchdog(state.clone(), interval, token.clone()),
+            );
+        }
+    }
+
+    /// Serves the local API on the admin listener, if one is configured, returning its
+    /// task handle.
+    fn serve_admin(
+        admin_listener: Option<AdminListener>,
+        app_state: &ServerState,
+        cancellation_token: &CancellationToken,
+    ) -> Option<ServerHandle> {
+        admin_listener.map(|admin_listener| {
+            serve(
+                admin_listener,
+                create_admin_router(app_state.clone()),
+                false,
+                cancellation_token.clone(),
+            )
+        })
+    }
+
+    /// Serves `app` on `listener` until `cancellation_token` is cancelled, preserving
+    /// the casing of HTTP/1 header names if `preserve_header_case` is set.
+    fn serve<L>(
+        listener: L,
+        app: NormalizePath<Router<()>>,
+        preserve_header_case: bool,
+        cancellation_token: CancellationToken,
+    ) -> ServerHandle
+    where
+        L: SocketAddrListener + Send + 'static,
+        L::Io: Send + Unpin + 'static,
+    {
+        tokio::spawn(async move {
+            if preserve_header_case {
+                serve_preserving_header_case(listener, app, cancellation_token).await;
+                return Ok(());
+            }
+            let make_service: IntoMakeServiceWithConnectInfo<_, ClientAddress> =
+                ServiceExt::<hyper::Request<Body>>::into_make_service_with_connect_info(app);
+            axum::serve(listener, make_service)
+                .with_graceful_shutdown(async move {
+                    cancellation_token.cancelled().await;
+                })
+                .await
+                .map_err(Into::into)
+        })
+    }
+
+    /// Serves `app` on `listener` with the original casing of HTTP/1 header names
+    /// recorded on requests, until `cancellation_token` is cancelled. `axum::serve`
+    /// does not expose the connection options, so connections are served with hyper.
+    async fn serve_preserving_header_case<L>(
+        mut listener: L,
+        app: NormalizePath<Router<()>>,
+        cancellation_token: CancellationToken,
+    ) where
+        L: SocketAddrListener + Send + 'static,
+        L::Io: Send + Unpin + 'static,
+    {
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder.http1().preserve_header_case(true);
+        let graceful = GracefulShutdown::new();
+        loop {
+            let (io, remote_address) = tokio::select! {
+                accepted = listener.accept() => accepted,
+                () = cancellation_token.cancelled() => break,
+            };
+            let app = app.clone();
+            let service = service_fn(move |mut request: Request<Incoming>| {
+                request
+                    .extensions_mut()
+                    .insert(ConnectInfo(ClientAddress(remote_address)));
+                app.clone().oneshot(request)
+            });
+            let connection = graceful.watch(
+                builder
+                    .serve_connection_with_upgrades(TokioIo::new(io), service)
+                    .into_owned(),
+            );
+            tokio::spawn(async move {
+                if let Err(e) = connection.await {
+                    tracing::debug!("Failed to serve connection: {e}");
+                }
+            });
+        }
+        graceful.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio_util::sync::CancellationToken;
+
+    use super::*;
+    use crate::listener::FakeListenerBuilder;
+
+    // Helper function to create a basic server builder for testing
+    fn create_test_server_builder() -> ServerBuilder<FakeListenerBuilder> {
+        ServerBuilder::new(CancellationToken::new()).listener_builder(FakeListenerBuilder)
+    }
+
+    #[tokio::test]
+    async fn server_starts_with_fake_listener() {
+        let server = create_test_server_builder().build().await;
+        assert!(server.is_ok());
+    }
+
+    #[tokio::test]
+    async fn server_returns_correct_ip_address() {
+        let server = create_test_server_builder().build().await.unwrap();
+        assert_eq!(server.address().ip().to_string(), "0.0.0.0");
+    }
+
+    #[tokio::test]
+    async fn server_fails_to_start_with_malformed_route_template() {
+        let server = create_test_server_builder()
+            .route_templates(vec!["v1/missing/leading/slash".to_owned()])
+            .build()
+            .await;
+        assert!(server.is_err());
+    }
+
+    #[tokio::test]
+    async fn server_fails_to_start_with_malformed_query_override() {
+        let server = create_test_server_builder()
+            .upstream_query_overrides(vec!["Locale".to_owned()])
+            .build()
+            .await;
+        assert!(server.is_err());
+    }
+
+    #[tokio::test]
+    async fn server_fails_to_start_with_malformed_upstream_header() {
+        let server = create_test_server_builder()
+            .upstream_headers(vec!["X-Trace={serial}".to_owned()])
+            .build()
+            .await;
+        assert!(server.is_err());
+    }
+
+    #[tokio::test]
+    async fn server_fails_to_start_with_invalid_upstream_dns() {
+        let server = create_test_server_builder()
+            .upstream_dns(vec!["tls://1.1.1.1".to_owned()])
+            .build()
+            .await;
+        assert!(server.is_err());
+    }
+
+    #[tokio::test]
+    async fn server_fails_to_start_with_invalid_trusted_proxy() {
+        let server = create_test_server_builder()
+            .trusted_proxies(vec!["10.0.0.0/33".to_owned()])
+            .build()
+            .await;
+        assert!(server.is_err());
+    }
+
+    #[tokio::test]
+    async fn server_fails_to_start_with_unknown_run_as_user() {
+        let server = create_test_server_builder()
+            .run_as_user(Some("kobo-server-no-such-user".to_owned()))
+            .build()
+            .await;
+        assert!(server.is_err());
+    }
+
+    #[tokio::test]
+    async fn server_preserving_header_case_serves_requests() {
+        let server = ServerBuilder::new(CancellationToken::new())
+            .port(0)
+            .preserve_header_case(true)
+            .build()
+            .await
+            .unwrap();
+        let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", server.address().port()))
+            .await
+            .unwrap();
+
+        stream
+            .write_all(b"GET /api/status HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn server_shutdown_completes_successfully() {
+        let server = create_test_server_builder().build().await.unwrap();
+        let shutdown_result = server.shutdown().await;
+        assert!(shutdown_result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn server_fails_to_start_with_partial_admin_listener_config() {
+        let server = create_test_server_builder()
+            .admin_port(Some(0))
+            .build()
+            .await;
+        assert!(server.is_err());
+    }
+
+    #[tokio::test]
+    async fn server_fails_to_start_with_chaos_rules_without_chaos_mode() {
+        let server = create_test_server_builder()
+            .chaos_rules(vec!["*=0.1:drop".to_owned()])
+            .build()
+            .await;
+        assert!(server.is_err());
+    }
+
+    #[cfg(feature = "admin-tls")]
+    mod admin_listener {
+        use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+        use axum::body::Body;
+        use hyper::{Request, header};
+        use hyper_util::rt::TokioIo;
+        use rcgen::{
+            BasicConstraints, CertificateParams, CertifiedIssuer, ExtendedKeyUsagePurpose, IsCa,
+            KeyPair,
+        };
+        use rustls::{
+            ClientConfig, RootCertStore,
+            crypto::aws_lc_rs,
+            pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+        };
+        use tokio_rustls::TlsConnector;
+
+        use super::*;
+
+        /// A CA with a server certificate and a client certificate it signed, written to
+        /// a temporary directory as PEM files.
+        struct TestCertificates {
+            directory: PathBuf,
+            ca: CertificateDer<'static>,
+            client_certificate: CertificateDer<'static>,
+            client_key: PrivateKeyDer<'static>,
+        }
+
+        impl TestCertificates {
+            fn generate(name: &str) -> Self {
+                let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+                ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+                let ca =
+                    CertifiedIssuer::self_signed(ca_params, KeyPair::generate().unwrap()).unwrap();
+
+                let server_key = KeyPair::generate().unwrap();
+                let server_certificate = CertificateParams::new(vec!["localhost".to_owned()])
+                    .unwrap()
+                    .signed_by(&server_key, &ca)
+                    .unwrap();
+
+                let client_key = KeyPair::generate().unwrap();
+                let mut client_params = CertificateParams::new(Vec::new()).unwrap();
+                client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+                let client_certificate = client_params.signed_by(&client_key, &ca).unwrap();
+
+                let directory =
+                    std::env::temp_dir().join(format!("kobo-server-{name}-{}", std::process::id()));
+                std::fs::create_dir_all(&directory).unwrap();
+                std::fs::write(directory.join("ca.pem"), ca.pem()).unwrap();
+                std::fs::write(directory.join("server.pem"), server_certificate.pem()).unwrap();
+                std::fs::write(directory.join("server.key"), server_key.serialize_pem()).unwrap();
+
+                Self {
+                    directory,
+                    ca: ca.der().clone(),
+                    client_certificate: client_certificate.der().clone(),
+                    client_key: PrivateKeyDer::try_from(client_key.serialize_der()).unwrap(),
+                }
+            }
+
+            fn path(&self, file: &str) -> PathBuf {
+                self.directory.join(file)
+            }
+        }
+
+        impl Drop for TestCertificates {
+            fn drop(&mut self) {
+                drop(std::fs::remove_dir_all(&self.directory));
+            }
+        }
+
+        /// Requests `/api/devices` from the admin listener, optionally presenting the
+        /// client certificate, and returns the response status.
+        async fn request_admin_api(
+            address: SocketAddr,
+            certificates: &TestCertificates,
+            present_client_certificate: bool,
+        ) -> anyhow::Result<hyper::StatusCode> {
+            let mut roots = RootCertStore::empty();
+            roots.add(certificates.ca.clone())?;
+            let builder =
+                ClientConfig::builder_with_provider(Arc::new(aws_lc_rs::default_provider()))
+                    .with_safe_default_protocol_versions()?
+                    .with_root_certificates(roots);
+            let config = if present_client_certificate {
+                builder.with_client_auth_cert(
+                    vec![certificates.client_certificate.clone()],
+                    certificates.client_key.clone_key(),
+                )?
+            } else {
+                builder.with_no_client_auth()
+            };
+
+            let stream = tokio::net::TcpStream::connect(("127.0.0.1", address.port())).await?;
+            let stream = TlsConnector::from(Arc::new(config))
+                .connect(ServerName::try_from("localhost")?, stream)
+                .await?;
+            let (mut sender, connection) =
+                hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+            tokio::spawn(connection);
+            let request = Request::get("/api/devices")
+                .header(header::HOST, "localhost")
+                .body(Body::empty())?;
+
+            Ok(sender.send_request(request).await?.status())
+        }
+
+        fn create_admin_server_builder(
+            certificates: &TestCertificates,
+        ) -> ServerBuilder<FakeListenerBuilder> {
+            create_test_server_builder()
+                .admin_port(Some(0))
+                .admin_tls_certificate(Some(certificates.path("server.pem")))
+                .admin_tls_key(Some(certificates.path("server.key")))
+                .admin_tls_client_ca(Some(certificates.path("ca.pem")))
+        }
+
+        #[tokio::test]
+        async fn admin_listener_accepts_clients_with_certificates_signed_by_ca() {
+            let certificates = TestCertificates::generate("admin-accepts");
+            let server = create_admin_server_builder(&certificates)
+                .build()
+                .await
+                .unwrap();
+            let address = server
+                .admin_address()
+                .expect("admin listener should be bound");
+
+            let status = request_admin_api(address, &certificates, true).await;
+
+            assert_eq!(status.unwrap(), hyper::StatusCode::OK);
+            server.shutdown().await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn admin_listener_rejects_clients_without_certificates() {
+            let certificates = TestCertificates::generate("admin-rejects");
+            let server = create_admin_server_builder(&certificates)
+                .build()
+                .await
+                .unwrap();
+            let address = server
+                .admin_address()
+                .expect("admin listener should be bound");
+
+            let status = request_admin_api(address, &certificates, false).await;
+
+            assert!(status.is_err());
+            server.shutdown().await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn admin_listener_is_not_blocked_by_a_stalled_handshake() {
+            let certificates = TestCertificates::generate("admin-stalled");
+            let server = create_admin_server_builder(&certificates)
+                .build()
+                .await
+                .unwrap();
+            let address = server
+                .admin_address()
+                .expect("admin listener should be bound");
+
+            let _stalled = tokio::net::TcpStream::connect(("127.0.0.1", address.port()))
+                .await
+                .unwrap();
+            let status = tokio::time::timeout(
+                std::time::Duration::from_secs(5),
+                request_admin_api(address, &certificates, true),
+            )
+            .await
+            .expect("request should not wait for the stalled handshake");
+
+            assert_eq!(status.unwrap(), hyper::StatusCode::OK);
+            server.shutdown().await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Periodic task that snapshots upstream responses for key endpoints.
+
+#[cfg(test)]
+pub use implementation::refresh_snapshots;
+pub use implementation::{SNAPSHOT_TASK, run_snapshot_task};
+
+mod implementation {
+    use std::time::Duration;
+
+    use axum::{body::Body, extract::State};
+    use hyper::{Request, StatusCode};
+    use tokio::time::MissedTickBehavior;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        routes::kobo_store_request::kobo_store_request,
+        state::{server_state::ServerState, upstream_fallbacks::DEGRADED_HEADER},
+        utils::http_body::{decode_response_body, is_gzip_encoded, read_response_body},
+    };
+
+    /// Name of the snapshot task in the background task registry.
+    pub const SNAPSHOT_TASK: &str = "snapshots";
+
+    /// Replays every remembered snapshot request once, recording changed responses.
+    /// A request whose credentials are rejected is forgotten until the device makes
+    /// it again.
+    pub async fn refresh_snapshots(state: &ServerState) {
+        for snapshot_request in state.snapshots.requests() {
+            let mut request = Request::get(snapshot_request.path_and_query.as_str());
+            if let Some(headers) = request.headers_mut() {
+                headers.extend(snapshot_request.headers.clone());
+            }
+            let Ok(request) = request.body(Body::empty()) else {
+                tracing::warn!(
+                    "Invalid snapshot request for {}",
+                    snapshot_request.path_and_query
+                );
+                continue;
+            };
+
+            let response = match kobo_store_request(State(state.clone()), request).await {
+                Ok(response) if !response.headers().contains_key(DEGRADED_HEADER) => response,
+                Ok(_) | Err(_) => {
+                    tracing::warn!(
+                        device_id = snapshot_request.device_id,
+                        route = snapshot_request.route,
+                        "Failed to snapshot upstream response"
+                    );
+                    continue;
+                }
+            };
+            if response.status() == StatusCode::UNAUTHORIZED {
+                tracing::info!(
+                    device_id = snapshot_request.device_id,
+                    route = snapshot_request.route,
+                    "Snapshot credentials rejected, no longer replaying the request"
+                );
+                state
+                    .snapshots
+                    .forget_request(&snapshot_request.device_id, &snapshot_request.route);
+            }
+            let Ok((parts, bytes)) = read_response_body(response).await else {
+                continue;
+            };
+            let Ok(body) = decode_response_body(&bytes, is_gzip_encoded(&parts.headers)).await
+            else {
+                continue;
+            };
+
+            if let Some(changes) = state.snapshots.record(
+                &snapshot_request.device_id,
+                &snapshot_request.route,
+                parts.status.as_u16(),
+                body.into_owned(),
+            ) && !changes.is_empty()
+            {
+                tracing::info!(
+                    device_id = snapshot_request.device_id,
+                    route = snapshot_request.route,
+                    ?changes,
+                    "Upstream response changed"
+                );
+            }
+        }
+    }
+
+    /// Refreshes snapshots every `interval` until `cancellation_token` is cancelled.
+    pub async fn run_snapshot_task(
+        state: ServerState,
+        interval: Duration,
+        cancellation_token: CancellationToken,
+    ) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        cancellation_token
+            .run_until_cancelled(async {
+                loop {
+                    ticker.tick().await;
+                    state.background_tasks.heartbeat(SNAPSHOT_TASK);
+                    refresh_snapshots(&state).await;
+                }
+            })
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::anyhow;
+    use axum::{
+        body::Body,
+        http::{HeaderMap, Response, StatusCode},
+    };
+
+    use super::*;
+    use crate::state::{
+        fake_kobo_client::FakeKoboClient, server_state::ServerState, snapshots::SnapshotRequest,
+    };
+
+    fn state_with_request(stub: &Arc<FakeKoboClient>) -> ServerState {
+        let state = ServerState::builder("http://frontend.test")
+            .client(stub.clone())
+            .snapshots_enabled(true)
+            .build();
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "Bearer token".parse().unwrap());
+        state.snapshots.remember_request(SnapshotRequest {
+            device_id: "device-1".to_owned(),
+            route: "/v1/initialization".to_owned(),
+            path_and_query: "/v1/initialization".to_owned(),
+            headers,
+        });
+        state
+    }
+
+    fn ok_response(body: &'static str) -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::from(body))
+            .expect("failed to build stub response")
+    }
+
+    #[tokio::test]
+    async fn refresh_snapshots_replays_requests_with_device_headers() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = state_with_request(&stub);
+        stub.enqueue_response(ok_response("{}"));
+
+        refresh_snapshots(&state).await;
+
+        let recorded = stub.recorded_requests();
+        assert_eq!(recorded[0].uri.path(), "/v1/initialization");
+        assert_eq!(
+            recorded[0].headers.get("authorization").unwrap(),
+            "Bearer token"
+        );
+    }
+
+    #[tokio::test]
+    async fn refresh_snapshots_records_changed_versions() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = state_with_request(&stub);
+        stub.enqueue_response(ok_response(r#"{"a":1}"#));
+        stub.enqueue_response(ok_response(r#"{"a":2}"#));
+
+        refresh_snapshots(&state).await;
+        refresh_snapshots(&state).await;
+
+        let histories = state.snapshots.histories(None, None, false);
+        assert_eq!(histories[0].versions.len(), 2);
+        assert_eq!(histories[0].versions[1].changes, ["~ /a"]);
+    }
+
+    #[tokio::test]
+    async fn refresh_snapshots_stops_replaying_rejected_credentials() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = state_with_request(&stub);
+        stub.enqueue_response(
+            Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(Body::empty())
+                .expect("failed to build stub response"),
+        );
+
+        refresh_snapshots(&state).await;
+        refresh_snapshots(&state).await;
+
+        assert_eq!(stub.recorded_requests().len(), 1);
+        assert!(state.snapshots.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn refresh_snapshots_skips_failed_requests() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let state = state_with_request(&stub);
+        stub.enqueue_error(anyhow!("stubbed failure"));
+
+        refresh_snapshots(&state).await;
+
+        assert!(state.snapshots.histories(None, None, false).is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+//! Configuration summary logged when the server starts.
+//!
+//! The banner lists the effective configuration and active features, warns about
+//! settings that commonly keep devices from syncing, and checks in the background
+//! that the frontend URL leads back to this server.
+
+#[cfg(feature = "admin-tls")]
+pub use implementation::log_certificate_expiry;
+pub use implementation::log_startup_banner;
+
+mod implementation {
+    use std::time::Duration;
+
+    use axum::body::Body;
+    #[cfg(feature = "admin-tls")]
+    use chrono::TimeDelta;
+    use chrono::{DateTime, Utc};
+    use http_body_util::BodyExt as _;
+    use hyper::{Request, Uri};
+    use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+    use serde::Deserialize;
+
+    use crate::{
+        is_loopback_url,
+        routes::{constants::KOBO_API_URL, status::active_features},
+        state::server_state::ServerState,
+    };
+
+    /// How long the frontend URL check may take before it is reported as failed.
+    const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// How long before the admin TLS certificate expires to start warning.
+    #[cfg(feature = "admin-tls")]
+    const CERTIFICATE_EXPIRY_WARNING: TimeDelta = TimeDelta::days(30);
+
+    /// Logs the configuration summary and warnings, and spawns the frontend URL check.
+    pub fn log_startup_banner(state: &ServerState) {
+        tracing::info!(
+            version = env!("CARGO_PKG_VERSION"),
+            frontend_url = state.frontend_url,
+            upstream_url = KOBO_API_URL,
+            server_address = ?state.server_address,
+            admin_address = ?state.admin_address,
+            features = ?active_features(state),
+            config = %state.config,
+            "Starting kobo-server"
+        );
+        for warning in configuration_warnings(state) {
+            tracing::warn!("{warning}");
+        }
+        if state.serve_admin_api {
+            tokio::spawn(check_frontend_url(
+                state.frontend_url.clone(),
+                state.started_at,
+            ));
+        }
+    }
+
+    /// Lists the settings that are likely to keep devices from syncing, or that
+    /// should not be left on by accident.
+    pub(crate) fn configuration_warnings(state: &ServerState) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let frontend_uri = state.frontend_url.parse::<Uri>().ok();
+        if frontend_uri.as_ref().and_then(Uri::scheme).is_none() {
+            warnings.push(format!(
+                "Frontend URL '{}' has no scheme, so devices cannot follow it",
+                state.frontend_url
+            ));
+        }
+        if is_loopback_url(&state.frontend_url) {
+            if state
+                .server_address
+                .is_some_and(|address| address.ip().is_unspecified())
+            {
+                warnings.push(format!(
+                    "Frontend URL '{}' points at this machine only, while the server listens \
+                     on every interface. Devices on the network cannot reach it",
+                    state.frontend_url
+                ));
+            }
+            if let (Some(frontend_port), Some(address)) = (
+                frontend_uri.as_ref().and_then(Uri::port_u16),
+                state.server_address,
+            ) && frontend_port != address.port()
+            {
+                warnings.push(format!(
+                    "Frontend URL port {frontend_port} does not match listening port {}",
+                    address.port()
+                ));
+            }
+        }
+        if !state.chaos_rules.is_empty() {
+            warnings.push("Chaos rules are active, so some responses are faulted".to_owned());
+        }
+        if state.capture_log.is_some() {
+            warnings.push(
+                "Exchanges are captured with their headers, so the capture file holds device \
+                 tokens"
+                    .to_owned(),
+            );
+        }
+        warnings
+    }
+
+    /// Logs when the admin TLS certificate expires, warning if it has expired or
+    /// expires soon.
+    #[cfg(feature = "admin-tls")]
+    pub fn log_certificate_expiry(expiry: Option<DateTime<Utc>>) {
+        let Some(expiry) = expiry else {
+            tracing::warn!("Could not read the admin TLS certificate expiry");
+            return;
+        };
+        if let Some(warning) = certificate_expiry_warning(expiry, Utc::now()) {
+            tracing::warn!("{warning}");
+        } else {
+            tracing::info!("Admin TLS certificate expires at {expiry}");
+        }
+    }
+
+    /// Describes how soon the admin TLS certificate expires, if it has expired or
+    /// expires soon.
+    #[cfg(feature = "admin-tls")]
+    pub(crate) fn certificate_expiry_warning(
+        expiry: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Option<String> {
+        if expiry <= now {
+            Some(format!("Admin TLS certificate expired at {expiry}"))
+        } else if expiry - now <= CERTIFICATE_EXPIRY_WARNING {
+            Some(format!(
+                "Admin TLS certificate expires in {} days, at {expiry}",
+                (expiry - now).num_days()
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// The part of the status response used to recognise this server.
+    #[derive(Deserialize)]
+    struct StatusResponse {
+        started_at: DateTime<Utc>,
+    }
+
+    /// Requests the status route through the frontend URL and checks that it was
+    /// answered by this server, by comparing start times.
+    async fn check_frontend_url(frontend_url: String, started_at: DateTime<Utc>) {
+        let url = format!("{}/api/status", frontend_url.trim_end_matches('/'));
+        match tokio::time::timeout(REACHABILITY_TIMEOUT, fetch_started_at(&url)).await {
+            Ok(Ok(remote_started_at)) if remote_started_at == started_at => {
+                tracing::info!(frontend_url, "Frontend URL routes back to this server");
+            }
+            Ok(Ok(_)) => tracing::warn!(
+                frontend_url,
+                "Frontend URL reaches a different kobo-server instance"
+            ),
+            Ok(Err(e)) => tracing::warn!(frontend_url, "Frontend URL is not reachable: {e}"),
+            Err(_) => tracing::warn!(
+                frontend_url,
+                "Frontend URL did not respond within {REACHABILITY_TIMEOUT:?}"
+            ),
+        }
+    }
+
+    /// Fetches the start time reported by the status route at `url`.
+    async fn fetch_started_at(url: &str) -> anyhow::Result<DateTime<Utc>> {
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        let client = Client::builder(TokioExecutor::new()).build::<_, Body>(connector);
+        let response = client
+            .request(Request::get(url.parse::<Uri>()?).body(Body::empty())?)
+            .await?;
+        let body = response.into_body().collect().await?.to_bytes();
+        let status: StatusResponse = serde_json::from_slice(&body)?;
+        Ok(status.started_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::implementation::configuration_warnings;
+    use crate::state::server_state::ServerState;
+
+    fn warnings_for(frontend_url: &str, server_address: &str) -> Vec<String> {
+        let state = ServerState::builder(frontend_url)
+            .listener_addresses(Some(server_address.parse().unwrap()), None)
+            .build();
+        configuration_warnings(&state)
+    }
+
+    #[test]
+    fn reachable_frontend_url_has_no_warnings() {
+        assert!(warnings_for("http://192.168.1.10:8089", "0.0.0.0:8089").is_empty());
+        assert!(warnings_for("http://localhost:8089", "127.0.0.1:8089").is_empty());
+    }
+
+    #[test]
+    fn warns_when_loopback_frontend_url_is_served_on_every_interface() {
+        let warnings = warnings_for("http://localhost:8089", "0.0.0.0:8089");
+
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("listens on every interface"));
+    }
+
+    #[test]
+    fn warns_when_loopback_frontend_url_uses_another_port() {
+        let warnings = warnings_for("http://127.0.0.1:9000", "127.0.0.1:8089");
+
+        assert_eq!(
+            warnings,
+            vec!["Frontend URL port 9000 does not match listening port 8089"]
+        );
+    }
+
+    #[test]
+    fn warns_when_frontend_url_has_no_scheme() {
+        let warnings = warnings_for("kobo.example.com", "0.0.0.0:8089");
+
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("has no scheme"));
+    }
+
+    #[test]
+    #[cfg(feature = "admin-tls")]
+    fn certificate_expiry_warns_only_when_close() {
+        use chrono::{TimeDelta, Utc};
+
+        use super::implementation::certificate_expiry_warning;
+
+        let now = Utc::now();
+
+        assert!(certificate_expiry_warning(now + TimeDelta::days(90), now).is_none());
+        assert!(
+            certificate_expiry_warning(now + TimeDelta::days(10), now)
+                .unwrap()
+                .contains("expires in 10 days")
+        );
+        assert!(
+            certificate_expiry_warning(now - TimeDelta::days(1), now)
+                .unwrap()
+                .contains("expired")
+        );
+    }
+}
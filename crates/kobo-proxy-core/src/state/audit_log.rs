@@ -0,0 +1,240 @@
+//! Audit log of response modifications made by the proxy.
+//!
+//! Every time the proxy changes a response on its way to a device, an entry is
+//! recorded so "why does my device see X" can be answered from the admin API.
+
+pub use implementation::{AuditEntry, AuditLog, AuditRule, byte_delta, request_id};
+
+mod implementation {
+    use std::{
+        collections::VecDeque,
+        sync::{
+            Mutex, MutexGuard, PoisonError,
+            atomic::{AtomicU64, Ordering},
+        },
+    };
+
+    use chrono::{DateTime, Utc};
+    use hyper::HeaderMap;
+    use serde::Serialize;
+
+    /// Number of entries kept before the oldest are discarded.
+    const DEFAULT_CAPACITY: usize = 1000;
+
+    /// Header carrying a request ID, typically set by a reverse proxy.
+    const REQUEST_ID_HEADER: &str = "x-request-id";
+
+    /// Returns the request ID from the request headers, if one was provided.
+    pub fn request_id(headers: &HeaderMap) -> Option<String> {
+        headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+    }
+
+    /// Returns the change in size from `before` to `after` bytes.
+    pub fn byte_delta(before: usize, after: usize) -> i64 {
+        let before = i64::try_from(before).unwrap_or(i64::MAX);
+        let after = i64::try_from(after).unwrap_or(i64::MAX);
+        after.saturating_sub(before)
+    }
+
+    /// The kind of modification made to a response.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum AuditRule {
+        /// Kobo API URLs in the body were rewritten to the frontend URL.
+        UrlRewrite,
+        /// A response header was removed.
+        HeaderStrip,
+        /// A fallback response replaced a failed upstream response.
+        UpstreamFallback,
+        /// Account identifiers in the body were masked.
+        IdentifierMask,
+        /// A configured JSON Patch was applied to the body.
+        ResponsePatch,
+    }
+
+    /// A single response modification.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+    pub struct AuditEntry {
+        /// The sequence number of the entry, which increases with every recorded
+        /// entry.
+        pub sequence: u64,
+        /// When the modification was made.
+        pub timestamp: DateTime<Utc>,
+        /// The route template of the request.
+        pub route: String,
+        /// The modification that was made.
+        pub rule: AuditRule,
+        /// The change in body size, in bytes.
+        pub byte_delta: i64,
+        /// The request ID, if one was provided.
+        pub request_id: Option<String>,
+    }
+
+    impl AuditEntry {
+        /// Creates an entry timestamped now.
+        pub fn new<T: Into<String>>(
+            route: T,
+            rule: AuditRule,
+            byte_delta: i64,
+            request_id: Option<String>,
+        ) -> Self {
+            Self {
+                sequence: 0,
+                timestamp: Utc::now(),
+                route: route.into(),
+                rule,
+                byte_delta,
+                request_id,
+            }
+        }
+    }
+
+    /// Thread-safe, bounded log of response modifications, oldest first.
+    #[derive(Debug)]
+    pub struct AuditLog {
+        entries: Mutex<VecDeque<AuditEntry>>,
+        next_sequence: AtomicU64,
+        capacity: usize,
+    }
+
+    impl Default for AuditLog {
+        fn default() -> Self {
+            Self::with_capacity(DEFAULT_CAPACITY)
+        }
+    }
+
+    impl AuditLog {
+        /// Creates a log that keeps at most `capacity` entries.
+        pub fn with_capacity(capacity: usize) -> Self {
+            Self {
+                entries: Mutex::default(),
+                next_sequence: AtomicU64::new(0),
+                capacity,
+            }
+        }
+
+        fn get_entries_lock(&self) -> MutexGuard<'_, VecDeque<AuditEntry>> {
+            self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Records an entry, discarding the oldest entry if the log is full.
+        pub fn record(&self, mut entry: AuditEntry) {
+            tracing::debug!(
+                route = %entry.route,
+                rule = ?entry.rule,
+                byte_delta = entry.byte_delta,
+                request_id = ?entry.request_id,
+                "Response modified"
+            );
+            let mut entries = self.get_entries_lock();
+            // Assigned under the lock, so sequence numbers follow the log order.
+            entry.sequence = self.next_sequence.fetch_add(1, Ordering::Relaxed);
+            if entries.len() >= self.capacity {
+                entries.pop_front();
+            }
+            if self.capacity > 0 {
+                entries.push_back(entry);
+            }
+        }
+
+        /// Returns the number of entries kept.
+        pub fn entry_count(&self) -> usize {
+            self.get_entries_lock().len()
+        }
+
+        /// Returns the most recent entries, newest first, optionally limited to a
+        /// route template.
+        pub fn entries(&self, route: Option<&str>, limit: usize) -> Vec<AuditEntry> {
+            self.get_entries_lock()
+                .iter()
+                .rev()
+                .filter(|entry| route.is_none_or(|route| entry.route == route))
+                .take(limit)
+                .cloned()
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::HeaderMap;
+
+    use super::*;
+
+    fn entry(route: &str) -> AuditEntry {
+        AuditEntry::new(route, AuditRule::UrlRewrite, 10, None)
+    }
+
+    #[test]
+    fn entries_are_returned_newest_first() {
+        let log = AuditLog::default();
+
+        log.record(entry("/v1/initialization"));
+        log.record(entry("/v1/library/sync"));
+
+        let entries = log.entries(None, 10);
+        assert_eq!(entries[0].route, "/v1/library/sync");
+        assert_eq!(entries[1].route, "/v1/initialization");
+    }
+
+    #[test]
+    fn entries_can_be_filtered_by_route() {
+        let log = AuditLog::default();
+
+        log.record(entry("/v1/initialization"));
+        log.record(entry("/v1/library/sync"));
+
+        let entries = log.entries(Some("/v1/initialization"), 10);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].route, "/v1/initialization");
+    }
+
+    #[test]
+    fn entries_are_limited() {
+        let log = AuditLog::default();
+
+        log.record(entry("/v1/library/sync"));
+        log.record(entry("/v1/library/sync"));
+
+        assert_eq!(log.entries(None, 1).len(), 1);
+    }
+
+    #[test]
+    fn oldest_entries_are_discarded_at_capacity() {
+        let log = AuditLog::with_capacity(2);
+
+        log.record(entry("/first"));
+        log.record(entry("/second"));
+        log.record(entry("/third"));
+
+        let routes: Vec<_> = log
+            .entries(None, 10)
+            .into_iter()
+            .map(|entry| entry.route)
+            .collect();
+        assert_eq!(routes, ["/third", "/second"]);
+    }
+
+    #[test]
+    fn sequence_numbers_increase_with_each_entry() {
+        let log = AuditLog::with_capacity(1);
+
+        log.record(entry("/first"));
+        log.record(entry("/second"));
+
+        assert_eq!(log.entries(None, 10)[0].sequence, 1);
+    }
+
+    #[test]
+    fn request_id_reads_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", "abc".parse().unwrap());
+
+        assert_eq!(request_id(&headers).as_deref(), Some("abc"));
+        assert_eq!(request_id(&HeaderMap::new()), None);
+    }
+}
@@ -0,0 +1,275 @@
+//! Backoff for devices stuck in authentication failure loops.
+//!
+//! A device with a revoked or corrupted token can retry authentication thousands of
+//! times an hour, each attempt reaching the Kobo API. After a number of consecutive
+//! `401 Unauthorized` responses, a device is answered locally with `429 Too Many
+//! Requests` for a period that doubles with each further failure, until a request
+//! succeeds again.
+//!
+//! Failures are tracked per device ID and `Authorization` header together. Any client
+//! can claim a device ID, so keying on it alone would let one client lock another
+//! device out by failing with its ID.
+
+pub use implementation::AuthBackoff;
+
+mod implementation {
+    use std::{
+        collections::HashMap,
+        hash::{BuildHasher as _, RandomState},
+        sync::{Mutex, MutexGuard, PoisonError},
+        time::{Duration, Instant},
+    };
+
+    use hyper::{
+        HeaderMap,
+        header::{AUTHORIZATION, HeaderValue},
+    };
+
+    /// Backoff applied when a device first reaches the failure threshold.
+    const INITIAL_BACKOFF: Duration = Duration::from_secs(30);
+
+    /// Longest backoff applied to a device.
+    const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+    /// Number of failing devices and credentials tracked before the one that failed
+    /// least recently is forgotten.
+    pub(crate) const MAX_TRACKED_DEVICES: usize = 1024;
+
+    /// Identifies a device and the credentials it authenticates with.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct BackoffKey {
+        device_id: String,
+        credentials: u64,
+    }
+
+    #[derive(Debug)]
+    struct AuthFailures {
+        consecutive: u32,
+        blocked_until: Option<Instant>,
+        last_failure: Instant,
+    }
+
+    /// Consecutive authentication failures of each device and its credentials.
+    #[derive(Debug, Default)]
+    pub struct AuthBackoff {
+        failures_before_backoff: Option<u32>,
+        hasher: RandomState,
+        devices: Mutex<HashMap<BackoffKey, AuthFailures>>,
+    }
+
+    impl AuthBackoff {
+        /// Creates a tracker that backs a device off after `failures_before_backoff`
+        /// consecutive authentication failures. `None` or zero disables backoff.
+        pub fn new(failures_before_backoff: Option<u32>) -> Self {
+            Self {
+                failures_before_backoff: failures_before_backoff.filter(|&count| count > 0),
+                hasher: RandomState::new(),
+                devices: Mutex::default(),
+            }
+        }
+
+        fn get_devices_lock(&self) -> MutexGuard<'_, HashMap<BackoffKey, AuthFailures>> {
+            self.devices.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Whether devices are backed off after repeated failures.
+        pub fn is_enabled(&self) -> bool {
+            self.failures_before_backoff.is_some()
+        }
+
+        /// Returns the key of a request from `device_id` with `headers`, from a hash of
+        /// its `Authorization` header.
+        pub fn key(&self, device_id: &str, headers: &HeaderMap) -> BackoffKey {
+            BackoffKey {
+                device_id: device_id.to_owned(),
+                credentials: self
+                    .hasher
+                    .hash_one(headers.get(AUTHORIZATION).map(HeaderValue::as_bytes)),
+            }
+        }
+
+        /// Returns how long the device must wait before its next request with the same
+        /// credentials is forwarded, if it is backed off.
+        pub fn retry_after(&self, key: &BackoffKey) -> Option<Duration> {
+            let blocked_until = self.get_devices_lock().get(key)?.blocked_until?;
+            let remaining = blocked_until.saturating_duration_since(Instant::now());
+            (!remaining.is_zero()).then_some(remaining)
+        }
+
+        /// Records an authentication failure, returning the backoff applied to the
+        /// device if it has now failed too many times in a row.
+        pub fn record_failure(&self, key: &BackoffKey) -> Option<Duration> {
+            let threshold = self.failures_before_backoff?;
+            let now = Instant::now();
+            let mut devices = self.get_devices_lock();
+            if devices.len() >= MAX_TRACKED_DEVICES
+                && !devices.contains_key(key)
+                && let Some(oldest) = devices
+                    .iter()
+                    .min_by_key(|(_, failures)| failures.last_failure)
+                    .map(|(key, _)| key.clone())
+            {
+                devices.remove(&oldest);
+            }
+            let failures = devices.entry(key.clone()).or_insert(AuthFailures {
+                consecutive: 0,
+                blocked_until: None,
+                last_failure: now,
+            });
+            failures.consecutive = failures.consecutive.saturating_add(1);
+            failures.last_failure = now;
+            let excess = failures.consecutive.checked_sub(threshold)?;
+
+            let backoff = INITIAL_BACKOFF
+                .checked_mul(2_u32.saturating_pow(excess))
+                .map_or(MAX_BACKOFF, |backoff| backoff.min(MAX_BACKOFF));
+            failures.blocked_until = Some(now + backoff);
+            Some(backoff)
+        }
+
+        /// Records a request that did not fail authentication, clearing the failures of
+        /// its credentials.
+        pub fn record_success(&self, key: &BackoffKey) {
+            self.get_devices_lock().remove(key);
+        }
+
+        /// Clears the failures of every credential of a device, e.g. after it signs in
+        /// again.
+        pub fn record_sign_in(&self, device_id: &str) {
+            self.get_devices_lock()
+                .retain(|key, _| key.device_id != device_id);
+        }
+
+        /// Returns the consecutive authentication failures of a device's credentials.
+        pub fn consecutive_failures(&self, key: &BackoffKey) -> u32 {
+            self.get_devices_lock()
+                .get(key)
+                .map_or(0, |failures| failures.consecutive)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hyper::{HeaderMap, header::AUTHORIZATION};
+
+    use super::{
+        implementation::{BackoffKey, MAX_TRACKED_DEVICES},
+        *,
+    };
+
+    fn key(backoff: &AuthBackoff, device_id: &str, token: &'static str) -> BackoffKey {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, token.parse().unwrap());
+        backoff.key(device_id, &headers)
+    }
+
+    #[test]
+    fn disabled_backoff_never_blocks() {
+        let backoff = AuthBackoff::new(Some(0));
+        let device = key(&backoff, "device-1", "Bearer token");
+
+        for _ in 0..10 {
+            assert_eq!(backoff.record_failure(&device), None);
+        }
+        assert!(!backoff.is_enabled());
+        assert_eq!(backoff.retry_after(&device), None);
+    }
+
+    #[test]
+    fn backoff_starts_at_threshold_and_doubles() {
+        let backoff = AuthBackoff::new(Some(3));
+        let device = key(&backoff, "device-1", "Bearer token");
+
+        assert_eq!(backoff.record_failure(&device), None);
+        assert_eq!(backoff.record_failure(&device), None);
+        assert_eq!(
+            backoff.record_failure(&device),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            backoff.record_failure(&device),
+            Some(Duration::from_secs(60))
+        );
+        assert!(backoff.retry_after(&device).is_some());
+        assert_eq!(
+            backoff.retry_after(&key(&backoff, "device-2", "Bearer token")),
+            None
+        );
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let backoff = AuthBackoff::new(Some(1));
+        let device = key(&backoff, "device-1", "Bearer token");
+
+        for _ in 0..40 {
+            backoff.record_failure(&device);
+        }
+
+        assert_eq!(
+            backoff.record_failure(&device),
+            Some(Duration::from_secs(60 * 60))
+        );
+    }
+
+    #[test]
+    fn success_clears_failures() {
+        let backoff = AuthBackoff::new(Some(1));
+        let device = key(&backoff, "device-1", "Bearer token");
+        backoff.record_failure(&device);
+
+        backoff.record_success(&device);
+
+        assert_eq!(backoff.consecutive_failures(&device), 0);
+        assert_eq!(backoff.retry_after(&device), None);
+    }
+
+    #[test]
+    fn backoff_only_applies_to_the_failing_credentials() {
+        let backoff = AuthBackoff::new(Some(1));
+        let spoofed = key(&backoff, "device-1", "Bearer wrong");
+        backoff.record_failure(&spoofed);
+
+        assert!(backoff.retry_after(&spoofed).is_some());
+        assert_eq!(
+            backoff.retry_after(&key(&backoff, "device-1", "Bearer token")),
+            None
+        );
+    }
+
+    #[test]
+    fn sign_in_clears_every_credential_of_the_device() {
+        let backoff = AuthBackoff::new(Some(1));
+        let device = key(&backoff, "device-1", "Bearer old");
+        let other = key(&backoff, "device-2", "Bearer old");
+        backoff.record_failure(&device);
+        backoff.record_failure(&other);
+
+        backoff.record_sign_in("device-1");
+
+        assert_eq!(backoff.retry_after(&device), None);
+        assert!(backoff.retry_after(&other).is_some());
+    }
+
+    #[test]
+    fn tracked_devices_are_capped() {
+        let backoff = AuthBackoff::new(Some(5));
+        let device = |index| key(&backoff, &format!("device-{index}"), "Bearer token");
+
+        for index in 0..=MAX_TRACKED_DEVICES {
+            backoff.record_failure(&device(index));
+        }
+
+        let tracked = (0..=MAX_TRACKED_DEVICES)
+            .filter(|&index| backoff.consecutive_failures(&device(index)) > 0)
+            .count();
+        assert_eq!(tracked, MAX_TRACKED_DEVICES);
+        assert_eq!(
+            backoff.consecutive_failures(&device(MAX_TRACKED_DEVICES)),
+            1
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Health of the supervised background tasks, reported by `/readyz`.
+//!
+//! Each task registers itself when spawned and sends a heartbeat on every iteration.
+//! A task that panics is restarted by its supervisor until its restart policy gives
+//! up; a critical task that has died, or stopped sending heartbeats, makes the
+//! server unready.
+
+pub use implementation::{BackgroundTasks, TaskHealth, TaskState};
+
+mod implementation {
+    use std::{
+        collections::BTreeMap,
+        sync::{Mutex, MutexGuard, PoisonError},
+        time::{Duration, Instant},
+    };
+
+    use serde::Serialize;
+
+    /// Number of missed heartbeat intervals after which a task is considered stalled.
+    const STALLED_HEARTBEATS: u32 = 3;
+
+    /// The lifecycle state of a background task.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "snake_case", tag = "state", content = "reason")]
+    pub enum TaskState {
+        /// The task is running.
+        Running,
+        /// The task panicked and is waiting to be restarted.
+        Restarting(String),
+        /// The task panicked and will not be restarted.
+        Dead(String),
+        /// The task finished, e.g. during shutdown.
+        Stopped,
+    }
+
+    /// The health of a background task, as reported by `/readyz`.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+    pub struct TaskHealth {
+        /// The task name.
+        pub name: &'static str,
+        /// Whether the server is unready when this task is unhealthy.
+        pub critical: bool,
+        /// The lifecycle state of the task.
+        #[serde(flatten)]
+        pub state: TaskState,
+        /// How many times the task has been restarted.
+        pub restarts: u32,
+        /// Seconds since the last heartbeat, if one was sent.
+        pub seconds_since_heartbeat: Option<u64>,
+        /// Whether the task is running but has missed several heartbeats.
+        pub stalled: bool,
+    }
+
+    impl TaskHealth {
+        /// Whether the task is running, or about to be, and sending heartbeats.
+        pub fn is_healthy(&self) -> bool {
+            match self.state {
+                TaskState::Running | TaskState::Restarting(_) => !self.stalled,
+                TaskState::Dead(_) | TaskState::Stopped => false,
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct Task {
+        critical: bool,
+        heartbeat_interval: Duration,
+        state: TaskState,
+        restarts: u32,
+        last_heartbeat: Option<Instant>,
+        registered_at: Instant,
+    }
+
+    /// The registry of supervised background tasks.
+    #[derive(Debug, Default)]
+    pub struct BackgroundTasks {
+        tasks: Mutex<BTreeMap<&'static str, Task>>,
+    }
+
+    impl BackgroundTasks {
+        fn get_tasks_lock(&self) -> MutexGuard<'_, BTreeMap<&'static str, Task>> {
+            self.tasks.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Registers a running task that sends a heartbeat every `heartbeat_interval`.
+        pub fn register(&self, name: &'static str, critical: bool, heartbeat_interval: Duration) {
+            self.get_tasks_lock().insert(
+                name,
+                Task {
+                    critical,
+                    heartbeat_interval,
+                    state: TaskState::Running,
+                    restarts: 0,
+                    last_heartbeat: None,
+                    registered_at: Instant::now(),
+                },
+            );
+        }
+
+        /// Records that the task is alive.
+        pub fn heartbeat(&self, name: &str) {
+            if let Some(task) = self.get_tasks_lock().get_mut(name) {
+                task.last_heartbeat = Some(Instant::now());
+            }
+        }
+
+        /// Records a change in the task's lifecycle state.
+        pub fn set_state(&self, name: &str, state: TaskState) {
+            if let Some(task) = self.get_tasks_lock().get_mut(name) {
+                task.state = state;
+            }
+        }
+
+        /// Records that the task was restarted after panicking.
+        pub fn record_restart(&self, name: &str) {
+            if let Some(task) = self.get_tasks_lock().get_mut(name) {
+                task.state = TaskState::Running;
+                task.restarts += 1;
+                task.last_heartbeat = None;
+                task.registered_at = Instant::now();
+            }
+        }
+
+        /// Returns the health of every registered task, ordered by name.
+        pub fn health(&self) -> Vec<TaskHealth> {
+            let now = Instant::now();
+            self.get_tasks_lock()
+                .iter()
+                .map(|(&name, task)| {
+                    let alive_at = task.last_heartbeat.unwrap_or(task.registered_at);
+                    TaskHealth {
+                        name,
+                        critical: task.critical,
+                        state: task.state.clone(),
+                        restarts: task.restarts,
+                        seconds_since_heartbeat: task
+                            .last_heartbeat
+                            .map(|heartbeat| now.duration_since(heartbeat).as_secs()),
+                        stalled: task.state == TaskState::Running
+                            && now.duration_since(alive_at)
+                                > task.heartbeat_interval * STALLED_HEARTBEATS,
+                    }
+                })
+                .collect()
+        }
+
+        /// Whether every critical task is healthy.
+        pub fn is_ready(&self) -> bool {
+            self.health()
+                .iter()
+                .all(|task| !task.critical || task.is_healthy())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn registered_tasks_are_ready() {
+        let tasks = BackgroundTasks::default();
+        tasks.register("watchdog", true, Duration::from_secs(60));
+        tasks.heartbeat("watchdog");
+
+        let health = tasks.health();
+
+        assert!(tasks.is_ready());
+        assert_eq!(health[0].state, TaskState::Running);
+        assert_eq!(health[0].seconds_since_heartbeat, Some(0));
+    }
+
+    #[test]
+    fn dead_critical_task_is_not_ready() {
+        let tasks = BackgroundTasks::default();
+        tasks.register("watchdog", true, Duration::from_secs(60));
+
+        tasks.set_state("watchdog", TaskState::Dead("boom".to_owned()));
+
+        assert!(!tasks.is_ready());
+    }
+
+    #[test]
+    fn dead_optional_task_keeps_server_ready() {
+        let tasks = BackgroundTasks::default();
+        tasks.register("snapshots", false, Duration::from_secs(60));
+
+        tasks.set_state("snapshots", TaskState::Dead("boom".to_owned()));
+
+        assert!(tasks.is_ready());
+        assert!(!tasks.health()[0].is_healthy());
+    }
+
+    #[test]
+    fn task_without_recent_heartbeat_is_stalled() {
+        let tasks = BackgroundTasks::default();
+        tasks.register("watchdog", true, Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(1));
+
+        assert!(tasks.health()[0].stalled);
+        assert!(!tasks.is_ready());
+    }
+
+    #[test]
+    fn restart_counts_and_resets_state() {
+        let tasks = BackgroundTasks::default();
+        tasks.register("watchdog", true, Duration::from_secs(60));
+        tasks.set_state("watchdog", TaskState::Restarting("boom".to_owned()));
+
+        tasks.record_restart("watchdog");
+
+        let health = tasks.health();
+        assert_eq!(health[0].state, TaskState::Running);
+        assert_eq!(health[0].restarts, 1);
+    }
+}
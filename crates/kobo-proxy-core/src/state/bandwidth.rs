@@ -0,0 +1,236 @@
+//! Bandwidth used by each device, by calendar month.
+//!
+//! Counts the body bytes received from and sent to each device, as they cross the
+//! connection (after compression), grouped by route template. Totals are kept for
+//! the most recent months so the usage of metered connections can be reported by
+//! `/api/stats/bandwidth`. Each month tracks a bounded number of device and route
+//! pairs, forgetting the least recently used when full.
+
+pub use implementation::{BandwidthReport, BandwidthUsage, Traffic};
+
+mod implementation {
+    use std::{
+        collections::BTreeMap,
+        sync::{Mutex, MutexGuard, PoisonError},
+        time::Instant,
+    };
+
+    use chrono::Utc;
+    use serde::Serialize;
+
+    /// Number of calendar months whose usage is kept.
+    const RETAINED_MONTHS: usize = 12;
+
+    /// Number of device and route pairs tracked per month before the least recently
+    /// used is forgotten. Device IDs and routes come from clients, so without a limit
+    /// a month's usage could grow forever.
+    pub(crate) const MAX_TRACKED_ENTRIES: usize = 4096;
+
+    /// Requests and body bytes exchanged with a device.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+    pub struct Traffic {
+        /// Number of requests made
+        pub requests: u64,
+        /// Body bytes received from the device
+        pub bytes_in: u64,
+        /// Body bytes sent to the device
+        pub bytes_out: u64,
+    }
+
+    impl Traffic {
+        fn add(&mut self, other: Self) {
+            self.requests = self.requests.saturating_add(other.requests);
+            self.bytes_in = self.bytes_in.saturating_add(other.bytes_in);
+            self.bytes_out = self.bytes_out.saturating_add(other.bytes_out);
+        }
+    }
+
+    /// Traffic of a device on one route.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+    pub struct RouteBandwidth {
+        /// The route template
+        pub route: String,
+        /// The traffic on the route
+        #[serde(flatten)]
+        pub traffic: Traffic,
+    }
+
+    /// Traffic of a device in a month.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+    pub struct DeviceBandwidth {
+        /// The device identifier
+        pub device_id: String,
+        /// The traffic on every route
+        #[serde(flatten)]
+        pub traffic: Traffic,
+        /// The traffic of each route, ordered by route
+        pub routes: Vec<RouteBandwidth>,
+    }
+
+    /// Traffic of every device in a month.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+    pub struct BandwidthReport {
+        /// The month, as `YYYY-MM`
+        pub month: String,
+        /// The traffic of every device
+        #[serde(flatten)]
+        pub traffic: Traffic,
+        /// The traffic of each device, ordered by device ID
+        pub devices: Vec<DeviceBandwidth>,
+        /// The months with recorded traffic, oldest first
+        pub available_months: Vec<String>,
+    }
+
+    /// Traffic of a device on a route, with when it was last recorded.
+    #[derive(Debug)]
+    struct Usage {
+        traffic: Traffic,
+        last_recorded: Instant,
+    }
+
+    /// Traffic keyed by device and route template.
+    type MonthUsage = BTreeMap<(String, String), Usage>;
+
+    /// Thread-safe record of the traffic of each device, by month.
+    #[derive(Debug, Default)]
+    pub struct BandwidthUsage {
+        months: Mutex<BTreeMap<String, MonthUsage>>,
+    }
+
+    impl BandwidthUsage {
+        fn get_months_lock(&self) -> MutexGuard<'_, BTreeMap<String, MonthUsage>> {
+            self.months.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// The current month, as `YYYY-MM`.
+        pub fn current_month() -> String {
+            Utc::now().format("%Y-%m").to_string()
+        }
+
+        /// Adds traffic of a device on a route to the current month.
+        pub fn record(&self, device_id: &str, route: &str, traffic: Traffic) {
+            let now = Instant::now();
+            let mut months = self.get_months_lock();
+            let month = months.entry(Self::current_month()).or_default();
+            let key = (device_id.to_owned(), route.to_owned());
+            if month.len() >= MAX_TRACKED_ENTRIES
+                && !month.contains_key(&key)
+                && let Some(oldest) = month
+                    .iter()
+                    .min_by_key(|(_, usage)| usage.last_recorded)
+                    .map(|(key, _)| key.clone())
+            {
+                month.remove(&oldest);
+            }
+            let usage = month.entry(key).or_insert(Usage {
+                traffic: Traffic::default(),
+                last_recorded: now,
+            });
+            usage.traffic.add(traffic);
+            usage.last_recorded = now;
+            while months.len() > RETAINED_MONTHS {
+                months.pop_first();
+            }
+        }
+
+        /// Returns the traffic of every device in `month`, formatted as `YYYY-MM`.
+        pub fn report(&self, month: &str) -> BandwidthReport {
+            let months = self.get_months_lock();
+            let mut report = BandwidthReport {
+                month: month.to_owned(),
+                traffic: Traffic::default(),
+                devices: Vec::new(),
+                available_months: months.keys().cloned().collect(),
+            };
+
+            for ((device_id, route), Usage { traffic, .. }) in
+                months.get(month).into_iter().flatten()
+            {
+                report.traffic.add(*traffic);
+                // Entries are ordered by device, so each device's routes are adjacent.
+                if report
+                    .devices
+                    .last()
+                    .is_none_or(|device| device.device_id != *device_id)
+                {
+                    report.devices.push(DeviceBandwidth {
+                        device_id: device_id.clone(),
+                        traffic: Traffic::default(),
+                        routes: Vec::new(),
+                    });
+                }
+                if let Some(device) = report.devices.last_mut() {
+                    device.traffic.add(*traffic);
+                    device.routes.push(RouteBandwidth {
+                        route: route.clone(),
+                        traffic: *traffic,
+                    });
+                }
+            }
+
+            report
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{implementation::MAX_TRACKED_ENTRIES, *};
+
+    fn traffic(bytes_in: u64, bytes_out: u64) -> Traffic {
+        Traffic {
+            requests: 1,
+            bytes_in,
+            bytes_out,
+        }
+    }
+
+    #[test]
+    fn report_totals_devices_and_routes() {
+        let usage = BandwidthUsage::default();
+        usage.record("device-2", "/v1/library/sync", traffic(0, 500));
+        usage.record("device-1", "/v1/library/sync", traffic(0, 1000));
+        usage.record("device-1", "/v1/library/sync", traffic(0, 200));
+        usage.record("device-1", "/v1/analytics/event", traffic(300, 10));
+
+        let report = usage.report(&BandwidthUsage::current_month());
+
+        assert_eq!(report.traffic.requests, 4);
+        assert_eq!(report.traffic.bytes_in, 300);
+        assert_eq!(report.traffic.bytes_out, 1710);
+        assert_eq!(report.devices.len(), 2);
+        let device = &report.devices[0];
+        assert_eq!(device.device_id, "device-1");
+        assert_eq!(device.traffic.bytes_out, 1210);
+        assert_eq!(device.routes[0].route, "/v1/analytics/event");
+        assert_eq!(device.routes[1].traffic.requests, 2);
+    }
+
+    #[test]
+    fn tracked_entries_are_capped() {
+        let usage = BandwidthUsage::default();
+
+        for index in 0..=MAX_TRACKED_ENTRIES {
+            usage.record(
+                &format!("device-{index}"),
+                "/v1/library/sync",
+                traffic(0, 1),
+            );
+        }
+
+        let report = usage.report(&BandwidthUsage::current_month());
+        assert_eq!(report.devices.len(), MAX_TRACKED_ENTRIES);
+    }
+
+    #[test]
+    fn report_of_month_without_traffic_is_empty() {
+        let usage = BandwidthUsage::default();
+        usage.record("device-1", "/v1/library/sync", traffic(0, 1000));
+
+        let report = usage.report("2000-01");
+
+        assert_eq!(report.traffic, Traffic::default());
+        assert!(report.devices.is_empty());
+        assert_eq!(report.available_months, [BandwidthUsage::current_month()]);
+    }
+}
@@ -0,0 +1,402 @@
+//! Recording of device exchanges, replayed by the `replay-sync` subcommand.
+//!
+//! Each exchange is a device request, the Kobo store API response it led to, and the
+//! response the device received. Exchanges are appended to the capture file as lines
+//! of JSON, with bodies base64 encoded so gzip responses replay byte for byte.
+//! Credential headers are left out, since replay does not need them, and the file
+//! is only readable by its owner.
+
+pub use implementation::{
+    CaptureLog, CapturedExchange, CapturedRequest, CapturedResponse, CapturingClient,
+    UpstreamCapture, read_capture_file,
+};
+
+mod implementation {
+    use std::{
+        fs::{File, OpenOptions},
+        io::Write as _,
+        path::Path,
+        sync::{Arc, Mutex, MutexGuard, PoisonError},
+    };
+
+    use anyhow::{Context as _, Result, anyhow};
+    use axum::{
+        body::{Body, Bytes},
+        extract::Request,
+    };
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+    use hyper::{
+        HeaderMap, Response,
+        header::{AUTHORIZATION, COOKIE, HeaderName, HeaderValue, PROXY_AUTHORIZATION, SET_COOKIE},
+        http::{request, response},
+    };
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::{
+        state::client::KoboClient,
+        utils::{http_body::buffer_body, protected_content::is_protected_content},
+    };
+
+    /// Headers carrying credentials, which are not recorded.
+    const CREDENTIAL_HEADERS: [HeaderName; 4] =
+        [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, SET_COOKIE];
+
+    fn serialize_body<S: Serializer>(body: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(body))
+    }
+
+    fn deserialize_body<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD
+            .decode(encoded)
+            .map(Bytes::from)
+            .map_err(serde::de::Error::custom)
+    }
+
+    /// Lists the headers with printable values as name and value pairs, leaving out
+    /// credentials.
+    fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .filter(|(name, _)| !CREDENTIAL_HEADERS.contains(name))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+            .collect()
+    }
+
+    /// Rebuilds a header map from name and value pairs.
+    fn header_map(pairs: &[(String, String)]) -> Result<HeaderMap> {
+        pairs
+            .iter()
+            .map(|(name, value)| {
+                Ok((
+                    HeaderName::try_from(name.as_str())?,
+                    HeaderValue::try_from(value.as_str())?,
+                ))
+            })
+            .collect()
+    }
+
+    /// A recorded device request.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct CapturedRequest {
+        /// The request method.
+        pub method: String,
+        /// The request URI as sent by the device.
+        pub uri: String,
+        /// The request headers.
+        pub headers: Vec<(String, String)>,
+        /// The request body.
+        #[serde(
+            serialize_with = "serialize_body",
+            deserialize_with = "deserialize_body"
+        )]
+        pub body: Bytes,
+    }
+
+    impl CapturedRequest {
+        /// Records a request from its parts and buffered body.
+        pub fn new(parts: &request::Parts, body: Bytes) -> Self {
+            Self {
+                method: parts.method.to_string(),
+                uri: parts.uri.to_string(),
+                headers: header_pairs(&parts.headers),
+                body,
+            }
+        }
+
+        /// Rebuilds the request.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the method, URI, or a header is invalid.
+        pub fn to_request(&self) -> Result<Request> {
+            let mut request = Request::builder()
+                .method(self.method.as_str())
+                .uri(self.uri.as_str())
+                .body(Body::from(self.body.clone()))?;
+            *request.headers_mut() = header_map(&self.headers)?;
+            Ok(request)
+        }
+    }
+
+    /// A recorded response, from the Kobo store API or to the device.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct CapturedResponse {
+        /// The response status code.
+        pub status: u16,
+        /// The response headers.
+        pub headers: Vec<(String, String)>,
+        /// The response body, still content-encoded.
+        #[serde(
+            serialize_with = "serialize_body",
+            deserialize_with = "deserialize_body"
+        )]
+        pub body: Bytes,
+    }
+
+    impl CapturedResponse {
+        /// Records a response from its parts and buffered body.
+        pub fn new(parts: &response::Parts, body: Bytes) -> Self {
+            Self {
+                status: parts.status.as_u16(),
+                headers: header_pairs(&parts.headers),
+                body,
+            }
+        }
+
+        /// Returns the value of a header, if it was recorded.
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+
+        /// Rebuilds the response.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the status code or a header is invalid.
+        pub fn to_response(&self) -> Result<Response<Body>> {
+            let mut response = Response::builder()
+                .status(self.status)
+                .body(Body::from(self.body.clone()))?;
+            *response.headers_mut() = header_map(&self.headers)?;
+            Ok(response)
+        }
+    }
+
+    /// A device request with the upstream response it led to and the response the
+    /// device received.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct CapturedExchange {
+        /// The request from the device.
+        pub request: CapturedRequest,
+        /// The Kobo store API response, or `None` if the request was answered locally.
+        pub upstream: Option<CapturedResponse>,
+        /// The response sent to the device.
+        pub response: CapturedResponse,
+    }
+
+    /// Slot, carried in request extensions, that the capturing client fills with the
+    /// upstream response to the request.
+    #[derive(Clone, Debug, Default)]
+    pub struct UpstreamCapture(Arc<Mutex<Option<CapturedResponse>>>);
+
+    impl UpstreamCapture {
+        fn get_lock(&self) -> MutexGuard<'_, Option<CapturedResponse>> {
+            self.0.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Takes the recorded upstream response, if the request was forwarded.
+        pub fn take(&self) -> Option<CapturedResponse> {
+            self.get_lock().take()
+        }
+    }
+
+    /// A client that records upstream responses into the request's
+    /// [`UpstreamCapture`], if it has one, before returning them.
+    pub struct CapturingClient {
+        client: Arc<dyn KoboClient>,
+    }
+
+    impl CapturingClient {
+        /// Wraps `client`, recording the responses it returns.
+        pub fn new(client: Arc<dyn KoboClient>) -> Self {
+            Self { client }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl KoboClient for CapturingClient {
+        async fn request(&self, request: Request) -> Result<Response<Body>> {
+            let capture = request.extensions().get::<UpstreamCapture>().cloned();
+            let response = self.client.request(request).await?;
+            let Some(capture) = capture.filter(|_| !is_protected_content(response.headers()))
+            else {
+                return Ok(response);
+            };
+
+            let (parts, body) = response.into_parts();
+            let body = buffer_body(body)
+                .await
+                .map_err(|(_, message)| anyhow!(message))?;
+            *capture.get_lock() = Some(CapturedResponse::new(&parts, body.clone()));
+            Ok(Response::from_parts(parts, Body::from(body)))
+        }
+    }
+
+    /// Appends exchanges to a capture file.
+    #[derive(Debug)]
+    pub struct CaptureLog {
+        file: Mutex<File>,
+    }
+
+    impl CaptureLog {
+        /// Opens a capture file for appending, creating it if needed. On Unix, a new
+        /// file is only readable and writable by its owner.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the file cannot be opened.
+        pub fn open(path: &Path) -> Result<Self> {
+            let mut options = OpenOptions::new();
+            options.create(true).append(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            let file = options
+                .open(path)
+                .with_context(|| format!("Failed to open capture file {}", path.display()))?;
+            Ok(Self {
+                file: Mutex::new(file),
+            })
+        }
+
+        /// Appends an exchange to the capture file.
+        pub fn record(&self, exchange: &CapturedExchange) {
+            let mut line = match serde_json::to_vec(exchange) {
+                Ok(line) => line,
+                Err(e) => {
+                    tracing::warn!("Failed to serialize captured exchange: {e}");
+                    return;
+                }
+            };
+            line.push(b'\n');
+            let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Err(e) = file.write_all(&line) {
+                tracing::warn!("Failed to write captured exchange: {e}");
+            }
+        }
+    }
+
+    /// Reads the exchanges recorded in a capture file, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or a line is not a valid exchange.
+    pub fn read_capture_file(path: &Path) -> Result<Vec<CapturedExchange>> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read capture file {}", path.display()))?;
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).with_context(|| {
+                    format!(
+                        "Invalid exchange on line {} of {}",
+                        index + 1,
+                        path.display()
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::body::{Body, Bytes};
+    use hyper::{Request, Response, StatusCode};
+
+    use super::*;
+    use crate::state::{client::KoboClient as _, fake_kobo_client::FakeKoboClient};
+
+    fn exchange() -> CapturedExchange {
+        let (request, ()) = Request::builder()
+            .method("POST")
+            .uri("/v1/library/sync?page=2")
+            .header("x-kobo-deviceid", "kobo-1")
+            .body(())
+            .unwrap()
+            .into_parts();
+        let (response, ()) = Response::builder()
+            .status(StatusCode::OK)
+            .header("content-encoding", "gzip")
+            .body(())
+            .unwrap()
+            .into_parts();
+        CapturedExchange {
+            request: CapturedRequest::new(&request, Bytes::from_static(b"{}")),
+            upstream: None,
+            response: CapturedResponse::new(&response, Bytes::from_static(&[0x1f, 0x8b, 0xff])),
+        }
+    }
+
+    #[test]
+    fn exchanges_round_trip_through_capture_file() {
+        let path = std::env::temp_dir().join(format!(
+            "kobo-capture-{}-round-trip.jsonl",
+            std::process::id()
+        ));
+        let log = CaptureLog::open(&path).unwrap();
+
+        log.record(&exchange());
+        log.record(&exchange());
+        let exchanges = read_capture_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(exchanges, vec![exchange(), exchange()]);
+    }
+
+    #[test]
+    fn credential_headers_are_not_recorded() {
+        let (request, ()) = Request::builder()
+            .header("authorization", "Bearer token")
+            .header("cookie", "session=abc")
+            .header("x-kobo-deviceid", "kobo-1")
+            .body(())
+            .unwrap()
+            .into_parts();
+
+        let captured = CapturedRequest::new(&request, Bytes::new());
+
+        assert_eq!(
+            captured.headers,
+            [("x-kobo-deviceid".to_owned(), "kobo-1".to_owned())]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn capture_file_is_only_readable_by_its_owner() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let path = std::env::temp_dir().join(format!(
+            "kobo-capture-{}-permissions.jsonl",
+            std::process::id()
+        ));
+        drop(CaptureLog::open(&path).unwrap());
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn captured_request_rebuilds_request() {
+        let request = exchange().request.to_request().unwrap();
+
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.uri(), "/v1/library/sync?page=2");
+        assert_eq!(request.headers()["x-kobo-deviceid"], "kobo-1");
+    }
+
+    #[tokio::test]
+    async fn capturing_client_records_upstream_response() {
+        let stub = Arc::new(FakeKoboClient::new());
+        stub.enqueue_response(Response::new(Body::from("upstream")));
+        let client = CapturingClient::new(stub);
+        let capture = UpstreamCapture::default();
+        let mut request = Request::new(Body::empty());
+        request.extensions_mut().insert(capture.clone());
+
+        client.request(request).await.unwrap();
+
+        let upstream = capture.take().unwrap();
+        assert_eq!(upstream.status, 200);
+        assert_eq!(&upstream.body[..], b"upstream");
+    }
+}
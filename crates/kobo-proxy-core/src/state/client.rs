@@ -0,0 +1,168 @@
+//! Client abstraction for making requests to the Kobo API.
+
+pub use implementation::{HookedClient, HttpsConnector, KoboClient, RequestHook};
+
+mod implementation {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use axum::{body::Body, extract::Request};
+    use http_body_util::BodyExt as _;
+    use hyper::Response;
+    use hyper_util::client::legacy::{Client, connect::HttpConnector};
+    use tracing::Instrument as _;
+
+    use crate::{
+        state::upstream_timing::{TimedBody, TimedConnector, UpstreamTimings},
+        utils::address_family::FamilyResolver,
+    };
+
+    /// HTTPS connector using rustls, resolving addresses with a family preference and
+    /// timing new connections
+    pub type HttpsConnector =
+        TimedConnector<hyper_rustls::HttpsConnector<TimedConnector<HttpConnector<FamilyResolver>>>>;
+
+    /// Trait representing a client capable of forwarding requests to the Kobo API.
+    #[async_trait::async_trait]
+    pub trait KoboClient: Send + Sync {
+        /// Forwards a request to the Kobo API and returns the response.
+        async fn request(&self, request: Request) -> Result<Response<Body>>;
+    }
+
+    /// Hook that can modify requests before they are forwarded to the Kobo API, e.g.
+    /// to add OAuth tokens refreshed from a secrets store for an authenticated egress
+    /// gateway.
+    #[async_trait::async_trait]
+    pub trait RequestHook: Send + Sync {
+        /// Modifies a request before it is forwarded.
+        ///
+        /// # Errors
+        ///
+        /// Returning an error fails the request as if the Kobo API were unreachable.
+        async fn modify_request(&self, request: &mut Request) -> Result<()>;
+    }
+
+    /// A client that runs request hooks, in order, before forwarding requests with
+    /// another client.
+    pub struct HookedClient {
+        client: Arc<dyn KoboClient>,
+        hooks: Vec<Arc<dyn RequestHook>>,
+    }
+
+    impl HookedClient {
+        /// Wraps `client`, running `hooks` on every request it forwards.
+        pub fn new(client: Arc<dyn KoboClient>, hooks: Vec<Arc<dyn RequestHook>>) -> Self {
+            Self { client, hooks }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl KoboClient for HookedClient {
+        async fn request(&self, mut request: Request) -> Result<Response<Body>> {
+            for hook in &self.hooks {
+                hook.modify_request(&mut request).await?;
+            }
+            self.client.request(request).await
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl KoboClient for Client<HttpsConnector, Body> {
+        async fn request(&self, request: Request) -> Result<Response<Body>> {
+            let timings = UpstreamTimings::start();
+            let response = timings
+                .scope(Client::request(self, request))
+                .instrument(timings.span().clone())
+                .await;
+            timings.mark_first_byte();
+            let response = match response {
+                Ok(response) => response,
+                Err(e) => {
+                    timings.finish();
+                    tracing::debug!(parent: timings.span(), "Upstream request failed");
+                    return Err(e.into());
+                }
+            };
+            let (parts, body) = response.into_parts();
+            Ok(Response::from_parts(
+                parts,
+                Body::from_stream(TimedBody::new(body, timings).into_data_stream()),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::{Result, anyhow};
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{HeaderValue, Response, header},
+    };
+
+    use super::*;
+    use crate::state::fake_kobo_client::FakeKoboClient;
+
+    struct BearerToken(&'static str);
+
+    #[async_trait::async_trait]
+    impl RequestHook for BearerToken {
+        async fn modify_request(&self, request: &mut Request) -> Result<()> {
+            request.headers_mut().insert(
+                header::PROXY_AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", self.0))?,
+            );
+            Ok(())
+        }
+    }
+
+    struct FailingHook;
+
+    #[async_trait::async_trait]
+    impl RequestHook for FailingHook {
+        async fn modify_request(&self, _request: &mut Request) -> Result<()> {
+            Err(anyhow!("secrets store unavailable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn hooks_modify_requests_in_order() {
+        let stub = Arc::new(FakeKoboClient::new());
+        stub.enqueue_response(Response::new(Body::empty()));
+        let client = HookedClient::new(
+            stub.clone(),
+            vec![
+                Arc::new(BearerToken("first")),
+                Arc::new(BearerToken("second")),
+            ],
+        );
+
+        client
+            .request(Request::new(Body::empty()))
+            .await
+            .expect("request should be forwarded");
+
+        let recorded = stub.recorded_requests();
+        assert_eq!(
+            recorded[0]
+                .headers
+                .get(header::PROXY_AUTHORIZATION)
+                .unwrap(),
+            "Bearer second"
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_hook_prevents_forwarding() {
+        let stub = Arc::new(FakeKoboClient::new());
+        let client = HookedClient::new(stub.clone(), vec![Arc::new(FailingHook)]);
+
+        let result = client.request(Request::new(Body::empty())).await;
+
+        assert!(result.is_err());
+        assert!(stub.recorded_requests().is_empty());
+    }
+}
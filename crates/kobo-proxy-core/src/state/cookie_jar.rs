@@ -0,0 +1,397 @@
+//! Cookies kept on behalf of each device session when the cookie policy is `store`.
+//!
+//! Cookies are keyed by a hash of the request's `Authorization` header rather than
+//! the device ID, which any client can claim, so a session's cookies are only sent
+//! upstream with requests that carry the same bearer token. Requests without one
+//! neither store nor receive cookies.
+
+pub use implementation::{CookieJar, SessionKey};
+
+mod implementation {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        hash::{BuildHasher as _, RandomState},
+        sync::{Mutex, MutexGuard, PoisonError},
+        time::Instant,
+    };
+
+    use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+    use hyper::{
+        HeaderMap,
+        header::{AUTHORIZATION, COOKIE, HeaderValue, SET_COOKIE},
+    };
+
+    use crate::routes::constants::KOBO_API_BASE_URI;
+
+    /// Number of sessions whose cookies are kept before those of the session used
+    /// least recently are forgotten.
+    pub(crate) const MAX_TRACKED_SESSIONS: usize = 1024;
+
+    /// Identifies the session a request belongs to, from its `Authorization` header.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct SessionKey(u64);
+
+    /// A cookie kept for a session.
+    #[derive(Debug)]
+    struct StoredCookie {
+        value: String,
+        path: String,
+        expires: Option<DateTime<Utc>>,
+    }
+
+    /// The cookies kept for a session.
+    #[derive(Debug)]
+    struct SessionCookies {
+        cookies: BTreeMap<String, StoredCookie>,
+        last_used: Instant,
+    }
+
+    impl StoredCookie {
+        fn is_expired(&self, now: DateTime<Utc>) -> bool {
+            self.expires.is_some_and(|expires| expires <= now)
+        }
+    }
+
+    /// Parses a cookie date such as `Wed, 21 Oct 2015 07:28:00 GMT`, including the
+    /// older form with dashes between the date parts.
+    fn parse_cookie_date(date: &str) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc2822(date)
+            .map(|date| date.to_utc())
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(date, "%a, %d-%b-%Y %H:%M:%S GMT")
+                    .map(|date| date.and_utc())
+            })
+            .ok()
+    }
+
+    /// The default path of a cookie set in response to `request_path`: the request
+    /// path up to, but not including, its last `/`.
+    fn default_path(request_path: &str) -> String {
+        match request_path.rfind('/') {
+            Some(0) | None => "/".to_owned(),
+            Some(end) => request_path[..end].to_owned(),
+        }
+    }
+
+    /// Whether a cookie with `cookie_path` applies to `request_path`.
+    fn path_matches(cookie_path: &str, request_path: &str) -> bool {
+        request_path.strip_prefix(cookie_path).is_some_and(|rest| {
+            rest.is_empty() || cookie_path.ends_with('/') || rest.starts_with('/')
+        })
+    }
+
+    /// Whether a cookie's `Domain` attribute covers the Kobo store API host.
+    fn domain_matches(domain: &str) -> bool {
+        let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+        KOBO_API_BASE_URI == domain
+            || KOBO_API_BASE_URI
+                .strip_suffix(domain.as_str())
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    }
+
+    /// Parses a `Set-Cookie` header received in response to `request_path`. The
+    /// cookie is `None` when the header deletes it, either with an empty value or an
+    /// expiry in the past. Cookies for other domains are ignored.
+    fn parse_set_cookie(
+        header: &str,
+        request_path: &str,
+        now: DateTime<Utc>,
+    ) -> Option<(String, Option<StoredCookie>)> {
+        let mut attributes = header.split(';').map(str::trim);
+        let (name, value) = attributes.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut path = None;
+        let mut max_age = None;
+        let mut expires = None;
+        for (key, attribute) in attributes.filter_map(|attribute| attribute.split_once('=')) {
+            let attribute = attribute.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "domain" if !domain_matches(attribute) => return None,
+                "path" if attribute.starts_with('/') => path = Some(attribute.to_owned()),
+                "max-age" => {
+                    max_age = attribute
+                        .parse::<i64>()
+                        .ok()
+                        .map(|seconds| now + TimeDelta::seconds(seconds.max(0)));
+                }
+                "expires" => expires = parse_cookie_date(attribute),
+                _ => {}
+            }
+        }
+
+        let value = value.trim();
+        let cookie = StoredCookie {
+            value: value.to_owned(),
+            path: path.unwrap_or_else(|| default_path(request_path)),
+            expires: max_age.or(expires),
+        };
+        let cookie = (!value.is_empty() && !cookie.is_expired(now)).then_some(cookie);
+        Some((name.to_owned(), cookie))
+    }
+
+    /// Cookies set by the Kobo store API, keyed by session.
+    #[derive(Debug, Default)]
+    pub struct CookieJar {
+        hasher: RandomState,
+        sessions: Mutex<HashMap<SessionKey, SessionCookies>>,
+    }
+
+    impl CookieJar {
+        fn get_sessions_lock(&self) -> MutexGuard<'_, HashMap<SessionKey, SessionCookies>> {
+            self.sessions.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Returns the session of a request with `headers`, if it is authenticated.
+        pub fn session_key(&self, headers: &HeaderMap) -> Option<SessionKey> {
+            headers
+                .get(AUTHORIZATION)
+                .map(|authorization| SessionKey(self.hasher.hash_one(authorization.as_bytes())))
+        }
+
+        /// Stores the cookies set by `headers` in response to `request_path` for
+        /// `session`, removing any that the headers delete. The cookies of the
+        /// session used least recently are forgotten once [`MAX_TRACKED_SESSIONS`]
+        /// sessions have cookies.
+        pub fn store(&self, session: SessionKey, request_path: &str, headers: &HeaderMap) {
+            let now = Utc::now();
+            let mut sessions = self.get_sessions_lock();
+            if sessions.len() >= MAX_TRACKED_SESSIONS
+                && !sessions.contains_key(&session)
+                && let Some(oldest) = sessions
+                    .iter()
+                    .min_by_key(|(_, cookies)| cookies.last_used)
+                    .map(|(key, _)| *key)
+            {
+                sessions.remove(&oldest);
+            }
+            let session_cookies = sessions.entry(session).or_insert_with(|| SessionCookies {
+                cookies: BTreeMap::new(),
+                last_used: Instant::now(),
+            });
+            session_cookies.last_used = Instant::now();
+            let session_cookies = &mut session_cookies.cookies;
+            for (name, cookie) in headers
+                .get_all(SET_COOKIE)
+                .iter()
+                .filter_map(|header| header.to_str().ok())
+                .filter_map(|header| parse_set_cookie(header, request_path, now))
+            {
+                match cookie {
+                    Some(cookie) => session_cookies.insert(name, cookie),
+                    None => session_cookies.remove(&name),
+                };
+            }
+            session_cookies.retain(|_, cookie| !cookie.is_expired(now));
+            if session_cookies.is_empty() {
+                sessions.remove(&session);
+            }
+        }
+
+        /// Adds the unexpired cookies stored for `session` that apply to
+        /// `request_path` to the `Cookie` header of a request. Cookies the device
+        /// sends itself take precedence.
+        pub fn apply_to_headers(
+            &self,
+            session: SessionKey,
+            request_path: &str,
+            headers: &mut HeaderMap,
+        ) {
+            let now = Utc::now();
+            let mut sessions = self.get_sessions_lock();
+            let Some(session_cookies) = sessions.get_mut(&session) else {
+                return;
+            };
+            session_cookies.last_used = Instant::now();
+            let existing = headers
+                .get(COOKIE)
+                .and_then(|header| header.to_str().ok())
+                .unwrap_or_default()
+                .to_owned();
+            let sent: Vec<&str> = existing
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(name, _)| name.trim())
+                .collect();
+            let stored: Vec<String> = session_cookies
+                .cookies
+                .iter()
+                .filter(|(name, cookie)| {
+                    !sent.contains(&name.as_str())
+                        && !cookie.is_expired(now)
+                        && path_matches(&cookie.path, request_path)
+                })
+                .map(|(name, cookie)| format!("{name}={}", cookie.value))
+                .collect();
+            if stored.is_empty() {
+                return;
+            }
+            let stored = stored.join("; ");
+            let cookie = if existing.is_empty() {
+                stored
+            } else {
+                format!("{existing}; {stored}")
+            };
+            match HeaderValue::from_str(&cookie) {
+                Ok(value) => {
+                    headers.insert(COOKIE, value);
+                }
+                Err(error) => tracing::warn!("Failed to replay stored cookies: {error}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{
+        HeaderMap,
+        header::{AUTHORIZATION, COOKIE, HeaderValue, SET_COOKIE},
+    };
+
+    use super::{implementation::MAX_TRACKED_SESSIONS, *};
+
+    fn set_cookies(values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(SET_COOKIE, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn session(jar: &CookieJar, token: &'static str) -> SessionKey {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static(token));
+        jar.session_key(&headers).unwrap()
+    }
+
+    fn cookies_for(jar: &CookieJar, session: SessionKey, path: &str) -> Option<HeaderValue> {
+        let mut headers = HeaderMap::new();
+        jar.apply_to_headers(session, path, &mut headers);
+        headers.remove(COOKIE)
+    }
+
+    #[test]
+    fn replays_stored_cookies_for_the_same_session() {
+        let jar = CookieJar::default();
+        let first = session(&jar, "Bearer first");
+        jar.store(
+            first,
+            "/v1/library/sync",
+            &set_cookies(&["session=\"abc\"; Path=/; HttpOnly", "region=ca; Path=/"]),
+        );
+
+        assert_eq!(
+            cookies_for(&jar, first, "/v1/library/sync").unwrap(),
+            "region=ca; session=\"abc\""
+        );
+        assert!(cookies_for(&jar, session(&jar, "Bearer second"), "/v1/library/sync").is_none());
+    }
+
+    #[test]
+    fn requests_without_authorization_have_no_session() {
+        assert!(
+            CookieJar::default()
+                .session_key(&HeaderMap::new())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn device_cookies_take_precedence() {
+        let jar = CookieJar::default();
+        let session = session(&jar, "Bearer token");
+        jar.store(session, "/", &set_cookies(&["session=abc", "region=ca"]));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::from_static("session=own"));
+        jar.apply_to_headers(session, "/v1/library/sync", &mut headers);
+
+        assert_eq!(headers[COOKIE], "session=own; region=ca");
+    }
+
+    #[test]
+    fn deleted_and_expired_cookies_are_forgotten() {
+        let jar = CookieJar::default();
+        let session = session(&jar, "Bearer token");
+        jar.store(session, "/", &set_cookies(&["session=abc", "region=ca"]));
+        jar.store(
+            session,
+            "/",
+            &set_cookies(&[
+                "session=; Max-Age=0",
+                "region=ca; Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+            ]),
+        );
+
+        assert!(cookies_for(&jar, session, "/v1/library/sync").is_none());
+    }
+
+    #[test]
+    fn cookies_are_only_sent_on_matching_paths() {
+        let jar = CookieJar::default();
+        let session = session(&jar, "Bearer token");
+        jar.store(
+            session,
+            "/v1/library/sync",
+            &set_cookies(&["library=1", "user=2; Path=/v1/user"]),
+        );
+
+        assert_eq!(
+            cookies_for(&jar, session, "/v1/library/tags").unwrap(),
+            "library=1"
+        );
+        assert_eq!(
+            cookies_for(&jar, session, "/v1/user/profile").unwrap(),
+            "user=2"
+        );
+        assert!(cookies_for(&jar, session, "/v1/userx").is_none());
+    }
+
+    #[test]
+    fn cookies_for_other_domains_are_ignored() {
+        let jar = CookieJar::default();
+        let session = session(&jar, "Bearer token");
+        jar.store(
+            session,
+            "/",
+            &set_cookies(&["other=1; Domain=example.com", "kobo=2; Domain=.kobo.com"]),
+        );
+
+        assert_eq!(cookies_for(&jar, session, "/").unwrap(), "kobo=2");
+    }
+
+    #[test]
+    fn least_recently_used_sessions_are_forgotten() {
+        let jar = CookieJar::default();
+        let session = |index: usize| {
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, format!("Bearer {index}").parse().unwrap());
+            jar.session_key(&headers).unwrap()
+        };
+
+        jar.store(session(0), "/", &set_cookies(&["session=0"]));
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        for index in 1..MAX_TRACKED_SESSIONS {
+            jar.store(session(index), "/", &set_cookies(&["session=1"]));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        // Replaying the first session's cookies marks it as recently used.
+        assert!(cookies_for(&jar, session(0), "/").is_some());
+        jar.store(
+            session(MAX_TRACKED_SESSIONS),
+            "/",
+            &set_cookies(&["session=new"]),
+        );
+
+        let tracked = (0..=MAX_TRACKED_SESSIONS)
+            .filter(|&index| cookies_for(&jar, session(index), "/").is_some())
+            .count();
+        assert_eq!(tracked, MAX_TRACKED_SESSIONS);
+        assert!(cookies_for(&jar, session(0), "/").is_some());
+        assert!(cookies_for(&jar, session(MAX_TRACKED_SESSIONS), "/").is_some());
+    }
+}
@@ -0,0 +1,227 @@
+//! Device groups and the policies attached to them.
+//!
+//! Devices are assigned to named groups, such as `kids` or `testers`, and each group
+//! can block endpoints, limit how often its devices make requests, and change how
+//! verbosely their requests are logged. The policy is looked up from the device ID
+//! on every request, so a device picks up its group's policy on its next request.
+
+pub use implementation::{DeviceGroups, GroupLogging};
+
+mod implementation {
+    use std::collections::HashMap;
+
+    use anyhow::{Context as _, Result, bail};
+
+    use crate::api::rate_limit::RateLimiter;
+
+    /// How verbosely requests from a group's devices are logged, when request or
+    /// response logging is enabled.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum GroupLogging {
+        /// Requests and responses are not logged.
+        Off,
+        /// Requests and responses are logged without their bodies.
+        Headers,
+        /// Requests and responses are logged with their bodies.
+        #[default]
+        Full,
+    }
+
+    impl std::str::FromStr for GroupLogging {
+        type Err = anyhow::Error;
+
+        fn from_str(value: &str) -> Result<Self> {
+            match value {
+                "off" => Ok(Self::Off),
+                "headers" => Ok(Self::Headers),
+                "full" => Ok(Self::Full),
+                _ => bail!("Unknown logging level '{value}', expected off, headers, or full"),
+            }
+        }
+    }
+
+    /// The policies applied to the devices of one group.
+    #[derive(Debug, Default)]
+    pub struct GroupPolicy {
+        /// The group name.
+        pub name: String,
+        /// Path prefixes answered locally instead of being forwarded.
+        blocked_paths: Vec<String>,
+        /// Per-device request budget.
+        rate_limiter: RateLimiter,
+        /// How verbosely requests are logged.
+        pub logging: GroupLogging,
+    }
+
+    impl GroupPolicy {
+        /// Checks if a request path is blocked for the group.
+        pub fn blocks(&self, path: &str) -> bool {
+            self.blocked_paths
+                .iter()
+                .any(|prefix| path.starts_with(prefix.as_str()))
+        }
+
+        /// Takes a request from the budget of a device in the group.
+        ///
+        /// # Errors
+        ///
+        /// Returns how long to wait before retrying when the budget is spent.
+        pub fn check_rate_limit(&self, device_id: &str) -> Result<(), std::time::Duration> {
+            self.rate_limiter.check(device_id)
+        }
+
+        /// Applies a policy in `block:PATH`, `rate-limit:N`, or `logging:LEVEL` form.
+        fn apply(&mut self, policy: &str) -> Result<()> {
+            let Some((kind, value)) = policy.split_once(':') else {
+                bail!("Group policy '{policy}' must be in KIND:VALUE form");
+            };
+            match kind {
+                "block" => {
+                    if !value.starts_with('/') {
+                        bail!("Blocked path '{value}' must start with '/'");
+                    }
+                    self.blocked_paths.push(value.to_owned());
+                }
+                "rate-limit" => {
+                    let requests_per_minute = value
+                        .parse()
+                        .with_context(|| format!("Invalid rate limit '{value}'"))?;
+                    self.rate_limiter = RateLimiter::new(Some(requests_per_minute));
+                }
+                "logging" => self.logging = value.parse()?,
+                _ => bail!("Unknown group policy '{kind}', expected block, rate-limit, or logging"),
+            }
+            Ok(())
+        }
+    }
+
+    /// The configured device groups.
+    #[derive(Debug, Default)]
+    pub struct DeviceGroups {
+        /// The group of each device.
+        members: HashMap<String, String>,
+        /// The policies of each group.
+        policies: HashMap<String, GroupPolicy>,
+    }
+
+    impl DeviceGroups {
+        /// Creates device groups from memberships in `DEVICE=GROUP` form and policies
+        /// in `GROUP=POLICY` form, where POLICY is `block:PATH`, `rate-limit:N`
+        /// (requests per minute per device), or `logging:LEVEL` (`off`, `headers`, or
+        /// `full`). A group may have several `block` policies.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if an entry is malformed or a device is in two groups.
+        pub fn new<S: AsRef<str>>(memberships: &[S], policies: &[S]) -> Result<Self> {
+            let mut members = HashMap::new();
+            for membership in memberships {
+                let membership = membership.as_ref();
+                let Some((device_id, group)) = membership
+                    .split_once('=')
+                    .filter(|(device_id, group)| !device_id.is_empty() && !group.is_empty())
+                else {
+                    bail!("Device group '{membership}' must be in DEVICE=GROUP form");
+                };
+                if let Some(previous) = members.insert(device_id.to_owned(), group.to_owned())
+                    && previous != group
+                {
+                    bail!("Device '{device_id}' is in both the '{previous}' and '{group}' groups");
+                }
+            }
+
+            let mut group_policies = HashMap::<String, GroupPolicy>::new();
+            for policy in policies {
+                let policy = policy.as_ref();
+                let Some((group, rule)) = policy
+                    .split_once('=')
+                    .filter(|(group, _)| !group.is_empty())
+                else {
+                    bail!("Group policy '{policy}' must be in GROUP=POLICY form");
+                };
+                group_policies
+                    .entry(group.to_owned())
+                    .or_insert_with(|| GroupPolicy {
+                        name: group.to_owned(),
+                        ..GroupPolicy::default()
+                    })
+                    .apply(rule)
+                    .with_context(|| format!("Invalid group policy '{policy}'"))?;
+            }
+
+            Ok(Self {
+                members,
+                policies: group_policies,
+            })
+        }
+
+        /// Checks if any group policies are configured.
+        pub fn is_empty(&self) -> bool {
+            self.policies.is_empty()
+        }
+
+        /// Returns the policy of the group a device belongs to, if it has one.
+        pub fn policy_for(&self, device_id: &str) -> Option<&GroupPolicy> {
+            self.policies.get(self.members.get(device_id)?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_policy_from_device_id() {
+        let groups = DeviceGroups::new(
+            &["kids-kobo=kids", "test-kobo=testers"],
+            &["kids=block:/v1/products", "testers=logging:off"],
+        )
+        .unwrap();
+
+        let kids = groups.policy_for("kids-kobo").unwrap();
+        assert_eq!(kids.name, "kids");
+        assert!(kids.blocks("/v1/products/123"));
+        assert!(!kids.blocks("/v1/library/sync"));
+        assert_eq!(kids.logging, GroupLogging::Full);
+        assert_eq!(
+            groups.policy_for("test-kobo").unwrap().logging,
+            GroupLogging::Off
+        );
+        assert!(groups.policy_for("other-kobo").is_none());
+    }
+
+    #[test]
+    fn group_without_policies_has_no_policy() {
+        let groups = DeviceGroups::new(&["adult-kobo=adults"], &["kids=logging:off"]).unwrap();
+
+        assert!(groups.policy_for("adult-kobo").is_none());
+    }
+
+    #[test]
+    fn rate_limit_is_per_device() {
+        let groups = DeviceGroups::new(&["a=kids", "b=kids"], &["kids=rate-limit:1"]).unwrap();
+        let policy = groups.policy_for("a").unwrap();
+
+        assert!(policy.check_rate_limit("a").is_ok());
+        assert!(policy.check_rate_limit("a").is_err());
+        assert!(policy.check_rate_limit("b").is_ok());
+    }
+
+    #[test]
+    fn rejects_malformed_entries() {
+        let no_policies: &[&str] = &[];
+        assert!(DeviceGroups::new(&["kids-kobo"], no_policies).is_err());
+        assert!(DeviceGroups::new(&["kids-kobo=kids", "kids-kobo=adults"], no_policies).is_err());
+        for policy in [
+            "kids",
+            "kids=block",
+            "kids=block:v1/products",
+            "kids=rate-limit:many",
+            "kids=logging:loud",
+            "kids=mute:true",
+        ] {
+            assert!(DeviceGroups::new(&[], &[policy]).is_err(), "{policy}");
+        }
+    }
+}
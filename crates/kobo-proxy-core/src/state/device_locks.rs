@@ -0,0 +1,125 @@
+//! Per-device locks used to serialize requests from the same device.
+
+pub use implementation::DeviceLocks;
+
+mod implementation {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex, MutexGuard, PoisonError},
+    };
+
+    /// A queue per device. Work for the same device runs one at a time in arrival
+    /// order, while work for different devices runs concurrently.
+    #[derive(Debug, Default)]
+    pub struct DeviceLocks {
+        locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    }
+
+    impl DeviceLocks {
+        fn get_locks_lock(&self) -> MutexGuard<'_, HashMap<String, Arc<tokio::sync::Mutex<()>>>> {
+            self.locks.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Runs `future` once all earlier work for `device_id` has completed.
+        pub async fn run_serialized<F: Future>(&self, device_id: &str, future: F) -> F::Output {
+            let lock = self
+                .get_locks_lock()
+                .entry(device_id.to_owned())
+                .or_default()
+                .clone();
+
+            let output = {
+                let _guard = lock.lock().await;
+                future.await
+            };
+
+            drop(lock);
+            self.remove_idle(device_id);
+            output
+        }
+
+        /// Removes the lock for a device if nothing is holding or waiting on it.
+        fn remove_idle(&self, device_id: &str) {
+            let mut locks = self.get_locks_lock();
+            if locks
+                .get(device_id)
+                .is_some_and(|lock| Arc::strong_count(lock) == 1)
+            {
+                locks.remove(device_id);
+            }
+        }
+
+        /// Returns the number of devices with queued or running work.
+        #[cfg(test)]
+        pub fn active_devices(&self) -> usize {
+            self.get_locks_lock().len()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+
+    /// Runs work that records the maximum number of concurrent runs.
+    async fn tracked_work(running: &AtomicUsize, max_running: &AtomicUsize) {
+        let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max_running.fetch_max(now_running, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        running.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    #[tokio::test]
+    async fn run_serialized_returns_future_output() {
+        let locks = DeviceLocks::default();
+
+        let output = locks.run_serialized("device-1", async { 42 }).await;
+
+        assert_eq!(output, 42);
+    }
+
+    #[tokio::test]
+    async fn run_serialized_runs_same_device_one_at_a_time() {
+        let locks = DeviceLocks::default();
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+
+        tokio::join!(
+            locks.run_serialized("device-1", tracked_work(&running, &max_running)),
+            locks.run_serialized("device-1", tracked_work(&running, &max_running)),
+        );
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn run_serialized_runs_different_devices_concurrently() {
+        let locks = DeviceLocks::default();
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+
+        tokio::join!(
+            locks.run_serialized("device-1", tracked_work(&running, &max_running)),
+            locks.run_serialized("device-2", tracked_work(&running, &max_running)),
+        );
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn run_serialized_removes_idle_locks() {
+        let locks = DeviceLocks::default();
+
+        tokio::join!(
+            locks.run_serialized("device-1", async {}),
+            locks.run_serialized("device-1", async {}),
+        );
+
+        assert_eq!(locks.active_devices(), 0);
+    }
+}
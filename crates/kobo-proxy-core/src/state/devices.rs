@@ -0,0 +1,257 @@
+//! Registry of the devices that have made requests through the server.
+
+pub use implementation::{DeviceRecord, DeviceRegistry, identify_device};
+
+mod implementation {
+    use std::{
+        collections::HashMap,
+        sync::{Mutex, MutexGuard, PoisonError},
+    };
+
+    use axum::extract::{ConnectInfo, Request};
+    use chrono::{DateTime, Utc};
+    use serde::Serialize;
+
+    use crate::{listener::ClientAddress, utils::device_info::DeviceInfo};
+
+    /// Header some Kobo clients use to identify themselves.
+    const DEVICE_ID_HEADER: &str = "x-kobo-deviceid";
+
+    /// Number of devices tracked before the least recently seen is forgotten. Device
+    /// IDs are chosen by clients, so without a limit the registry could grow forever.
+    pub(crate) const MAX_TRACKED_DEVICES: usize = 1024;
+
+    /// Identifies the device making a request. Uses the `X-Kobo-DeviceId` header when
+    /// present, falling back to the client IP address.
+    pub fn identify_device(request: &Request) -> Option<String> {
+        let header_id = request
+            .headers()
+            .get(DEVICE_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty());
+        if let Some(device_id) = header_id {
+            return Some(device_id.to_owned());
+        }
+
+        request
+            .extensions()
+            .get::<ConnectInfo<ClientAddress>>()
+            .map(|ConnectInfo(ClientAddress(address))| address.ip().to_string())
+    }
+
+    /// Information about a single device.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+    pub struct DeviceRecord {
+        /// The device identifier
+        pub id: String,
+        /// When the device was first seen
+        pub first_seen: DateTime<Utc>,
+        /// When the device was last seen
+        pub last_seen: DateTime<Utc>,
+        /// The number of requests made by the device
+        pub request_count: u64,
+        /// The most recent `User-Agent` reported by the device
+        pub user_agent: Option<String>,
+        /// The model and firmware parsed from the `User-Agent`
+        pub device_info: Option<DeviceInfo>,
+        /// The most recent difference between the device clock and the reference clock,
+        /// in seconds. Positive values mean the device clock is ahead.
+        pub clock_skew_seconds: Option<i64>,
+    }
+
+    impl DeviceRecord {
+        fn new(id: &str, now: DateTime<Utc>) -> Self {
+            Self {
+                id: id.to_owned(),
+                first_seen: now,
+                last_seen: now,
+                request_count: 0,
+                user_agent: None,
+                device_info: None,
+                clock_skew_seconds: None,
+            }
+        }
+    }
+
+    /// Thread-safe registry of device information keyed by device ID, holding at most
+    /// [`MAX_TRACKED_DEVICES`] devices.
+    #[derive(Debug, Default)]
+    pub struct DeviceRegistry {
+        devices: Mutex<HashMap<String, DeviceRecord>>,
+    }
+
+    impl DeviceRegistry {
+        fn get_devices_lock(&self) -> MutexGuard<'_, HashMap<String, DeviceRecord>> {
+            self.devices.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Returns the record of a device, creating it if needed and forgetting the
+        /// least recently seen device to make room.
+        fn get_or_insert<'a>(
+            devices: &'a mut HashMap<String, DeviceRecord>,
+            device_id: &str,
+            now: DateTime<Utc>,
+        ) -> &'a mut DeviceRecord {
+            if devices.len() >= MAX_TRACKED_DEVICES
+                && !devices.contains_key(device_id)
+                && let Some(oldest) = devices
+                    .values()
+                    .min_by_key(|record| record.last_seen)
+                    .map(|record| record.id.clone())
+            {
+                devices.remove(&oldest);
+            }
+            devices
+                .entry(device_id.to_owned())
+                .or_insert_with(|| DeviceRecord::new(device_id, now))
+        }
+
+        /// Records a request made by a device.
+        pub fn record_request(&self, device_id: &str, user_agent: Option<&str>) {
+            let now = Utc::now();
+            let mut devices = self.get_devices_lock();
+            let record = Self::get_or_insert(&mut devices, device_id, now);
+
+            record.last_seen = now;
+            record.request_count += 1;
+            if let Some(user_agent) = user_agent {
+                record.user_agent = Some(user_agent.to_owned());
+                record.device_info = DeviceInfo::parse(user_agent);
+            }
+        }
+
+        /// Records the most recently measured clock skew for a device.
+        pub fn record_clock_skew(&self, device_id: &str, skew_seconds: i64) {
+            let now = Utc::now();
+            Self::get_or_insert(&mut self.get_devices_lock(), device_id, now).clock_skew_seconds =
+                Some(skew_seconds);
+        }
+
+        /// Returns all known devices, ordered by ID.
+        pub fn devices(&self) -> Vec<DeviceRecord> {
+            let mut devices: Vec<_> = self.get_devices_lock().values().cloned().collect();
+            devices.sort_by(|a, b| a.id.cmp(&b.id));
+            devices
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{
+        body::Body,
+        extract::{ConnectInfo, Request},
+    };
+
+    use super::{implementation::MAX_TRACKED_DEVICES, *};
+    use crate::listener::ClientAddress;
+
+    #[test]
+    fn identify_device_uses_device_id_header() {
+        let request = Request::builder()
+            .header("X-Kobo-DeviceId", "device-1")
+            .body(Body::empty())
+            .unwrap();
+
+        assert_eq!(identify_device(&request), Some("device-1".to_owned()));
+    }
+
+    #[test]
+    fn identify_device_falls_back_to_client_ip() {
+        let mut request = Request::builder().body(Body::empty()).unwrap();
+        request.extensions_mut().insert(ConnectInfo(ClientAddress(
+            "192.168.1.20:51234".parse::<SocketAddr>().unwrap(),
+        )));
+
+        assert_eq!(identify_device(&request), Some("192.168.1.20".to_owned()));
+    }
+
+    #[test]
+    fn identify_device_ignores_empty_header() {
+        let request = Request::builder()
+            .header("X-Kobo-DeviceId", " ")
+            .body(Body::empty())
+            .unwrap();
+
+        assert_eq!(identify_device(&request), None);
+    }
+
+    #[test]
+    fn registry_is_empty_initially() {
+        let registry = DeviceRegistry::default();
+
+        assert!(registry.devices().is_empty());
+    }
+
+    #[test]
+    fn record_request_counts_requests() {
+        let registry = DeviceRegistry::default();
+
+        registry.record_request("device-1", None);
+        registry.record_request("device-1", None);
+
+        assert_eq!(registry.devices()[0].request_count, 2);
+    }
+
+    #[test]
+    fn record_request_keeps_latest_user_agent() {
+        let registry = DeviceRegistry::default();
+
+        registry.record_request("device-1", Some("old"));
+        registry.record_request("device-1", Some("new"));
+
+        assert_eq!(registry.devices()[0].user_agent.as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn record_clock_skew_is_stored() {
+        let registry = DeviceRegistry::default();
+
+        registry.record_request("device-1", None);
+        registry.record_clock_skew("device-1", -600);
+
+        assert_eq!(registry.devices()[0].clock_skew_seconds, Some(-600));
+    }
+
+    #[test]
+    fn devices_are_sorted_by_id() {
+        let registry = DeviceRegistry::default();
+
+        registry.record_request("b", None);
+        registry.record_request("a", None);
+
+        let ids: Vec<_> = registry.devices().into_iter().map(|d| d.id).collect();
+        assert_eq!(ids, ["a", "b"]);
+    }
+
+    #[test]
+    fn least_recently_seen_device_is_forgotten_when_full() {
+        let registry = DeviceRegistry::default();
+
+        for index in 0..MAX_TRACKED_DEVICES {
+            registry.record_request(&format!("device-{index}"), None);
+        }
+        registry.record_request("device-0", None);
+        registry.record_request("new-device", None);
+
+        let ids: Vec<_> = registry.devices().into_iter().map(|d| d.id).collect();
+        assert_eq!(ids.len(), MAX_TRACKED_DEVICES);
+        assert!(ids.iter().any(|id| id == "device-0"));
+        assert!(ids.iter().any(|id| id == "new-device"));
+    }
+
+    #[test]
+    fn record_request_parses_device_info() {
+        let registry = DeviceRegistry::default();
+
+        registry.record_request("device-1", Some("Mozilla/5.0 (Kobo Touch 0387/4.38.21908)"));
+
+        let devices = registry.devices();
+        let info = devices[0].device_info.as_ref().unwrap();
+        assert_eq!(info.model, Some("Kobo Elipsa"));
+        assert_eq!(info.firmware.to_string(), "4.38.21908");
+    }
+}
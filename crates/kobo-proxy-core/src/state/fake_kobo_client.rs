@@ -12,7 +12,7 @@ mod implementation {
     use http_body_util::BodyExt as _;
     use hyper::Response;
 
-    use crate::server::state::client::KoboClient;
+    use crate::state::client::KoboClient;
 
     /// Representation of a request captured by the [`StubKoboClient`].
     #[derive(Clone, Debug)]
@@ -88,8 +88,8 @@ mod tests {
     use axum::http::{HeaderValue, Method, StatusCode, Uri};
     use hyper::Response;
 
-    use crate::server::state::client::KoboClient as _;
-    use crate::server::state::fake_kobo_client::FakeKoboClient;
+    use crate::state::client::KoboClient as _;
+    use crate::state::fake_kobo_client::FakeKoboClient;
 
     #[test]
     fn new_creates_empty_stub_client() {
@@ -0,0 +1,20 @@
+//! Shared state definitions for the Kobo server.
+
+pub mod audit_log;
+pub mod auth_backoff;
+pub mod background_tasks;
+pub mod bandwidth;
+pub mod capture_log;
+pub mod client;
+pub mod cookie_jar;
+pub mod device_groups;
+pub mod device_locks;
+pub mod devices;
+pub mod resource_usage;
+pub mod server_state;
+pub mod snapshots;
+pub mod upstream_fallbacks;
+pub mod upstream_timing;
+
+#[cfg(test)]
+pub mod fake_kobo_client;
@@ -0,0 +1,189 @@
+//! Resource usage of the server, sampled periodically by the resource watchdog.
+//!
+//! Long-running deployments on small hosts such as a Raspberry Pi can run out of file
+//! descriptors or memory slowly. Each sample records process usage and the sizes of
+//! the in-memory caches, along with warnings when a limit is getting close.
+
+pub use implementation::{CacheSizes, ResourceMonitor, ResourceUsage};
+
+mod implementation {
+    use std::sync::{Mutex, MutexGuard, PoisonError};
+
+    use chrono::{DateTime, Utc};
+    use serde::Serialize;
+
+    use crate::utils::process_resources::ProcessResources;
+
+    /// Percentage of the file descriptor limit at which a warning is given.
+    const FILE_DESCRIPTOR_WARNING_PERCENT: u64 = 80;
+
+    const BYTES_PER_MEGABYTE: u64 = 1024 * 1024;
+
+    /// Number of entries in each in-memory cache.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+    pub struct CacheSizes {
+        /// Devices in the device registry.
+        pub devices: usize,
+        /// Entries in the audit log.
+        pub audit_entries: usize,
+        /// Stored snapshot versions.
+        pub snapshot_versions: usize,
+        /// Cached responses replayed when the Kobo API fails.
+        pub fallback_responses: usize,
+    }
+
+    /// A single sample of the server's resource usage.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+    pub struct ResourceUsage {
+        /// When the sample was taken.
+        pub sampled_at: DateTime<Utc>,
+        /// File descriptor and memory usage of the process.
+        #[serde(flatten)]
+        pub process: ProcessResources,
+        /// Sizes of the in-memory caches.
+        pub caches: CacheSizes,
+        /// Limits that are close to being reached, with hints on how to fix them.
+        pub warnings: Vec<String>,
+    }
+
+    impl ResourceUsage {
+        /// Creates a sample taken now, warning when file descriptor usage nears its
+        /// limit or resident memory exceeds `memory_warning_bytes`.
+        pub fn new(
+            process: ProcessResources,
+            caches: CacheSizes,
+            memory_warning_bytes: Option<u64>,
+        ) -> Self {
+            let mut warnings = Vec::new();
+            if let (Some(open), Some(limit)) =
+                (process.open_file_descriptors, process.file_descriptor_limit)
+                && open.saturating_mul(100) >= limit.saturating_mul(FILE_DESCRIPTOR_WARNING_PERCENT)
+            {
+                warnings.push(format!(
+                    "{open} of {limit} file descriptors are open. Raise the open file limit \
+                     (e.g. `ulimit -n` or LimitNOFILE) or lower --upstream-idle-timeout-seconds \
+                     so idle upstream connections are closed sooner"
+                ));
+            }
+            if let (Some(resident), Some(threshold)) =
+                (process.resident_memory_bytes, memory_warning_bytes)
+                && resident >= threshold
+            {
+                warnings.push(format!(
+                    "Resident memory is {} MB, above the {} MB warning threshold. Snapshot \
+                     histories ({} versions) and cached fallback responses ({}) are kept in \
+                     memory; snapshot fewer routes or restart the server to release them",
+                    resident / BYTES_PER_MEGABYTE,
+                    threshold / BYTES_PER_MEGABYTE,
+                    caches.snapshot_versions,
+                    caches.fallback_responses,
+                ));
+            }
+
+            Self {
+                sampled_at: Utc::now(),
+                process,
+                caches,
+                warnings,
+            }
+        }
+    }
+
+    /// Keeps the most recent resource usage sample.
+    #[derive(Debug, Default)]
+    pub struct ResourceMonitor {
+        memory_warning_bytes: Option<u64>,
+        latest: Mutex<Option<ResourceUsage>>,
+    }
+
+    impl ResourceMonitor {
+        /// Creates a monitor that warns when resident memory exceeds
+        /// `memory_warning_bytes`.
+        pub fn new(memory_warning_bytes: Option<u64>) -> Self {
+            Self {
+                memory_warning_bytes,
+                latest: Mutex::default(),
+            }
+        }
+
+        fn get_latest_lock(&self) -> MutexGuard<'_, Option<ResourceUsage>> {
+            self.latest.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Returns the resident memory, in bytes, at which a warning is given.
+        pub fn memory_warning_bytes(&self) -> Option<u64> {
+            self.memory_warning_bytes
+        }
+
+        /// Stores a sample, replacing the previous one.
+        pub fn record(&self, usage: ResourceUsage) {
+            *self.get_latest_lock() = Some(usage);
+        }
+
+        /// Returns the most recent sample, if one has been taken.
+        pub fn latest(&self) -> Option<ResourceUsage> {
+            self.get_latest_lock().clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::process_resources::ProcessResources;
+
+    const MEGABYTE: u64 = 1024 * 1024;
+
+    fn process(open: u64, limit: u64, resident_megabytes: u64) -> ProcessResources {
+        ProcessResources {
+            open_file_descriptors: Some(open),
+            file_descriptor_limit: Some(limit),
+            resident_memory_bytes: Some(resident_megabytes * MEGABYTE),
+        }
+    }
+
+    #[test]
+    fn new_warns_near_file_descriptor_limit() {
+        let usage = ResourceUsage::new(process(820, 1024, 50), CacheSizes::default(), None);
+
+        assert_eq!(usage.warnings.len(), 1);
+        assert!(usage.warnings[0].contains("820 of 1024"));
+    }
+
+    #[test]
+    fn new_warns_above_memory_threshold() {
+        let usage = ResourceUsage::new(
+            process(10, 1024, 300),
+            CacheSizes::default(),
+            Some(256 * MEGABYTE),
+        );
+
+        assert_eq!(usage.warnings.len(), 1);
+        assert!(usage.warnings[0].contains("300 MB"));
+    }
+
+    #[test]
+    fn new_has_no_warnings_within_limits() {
+        let usage = ResourceUsage::new(
+            process(10, 1024, 50),
+            CacheSizes::default(),
+            Some(256 * MEGABYTE),
+        );
+
+        assert!(usage.warnings.is_empty());
+    }
+
+    #[test]
+    fn monitor_keeps_latest_sample() {
+        let monitor = ResourceMonitor::new(None);
+        assert!(monitor.latest().is_none());
+
+        monitor.record(ResourceUsage::new(
+            ProcessResources::default(),
+            CacheSizes::default(),
+            None,
+        ));
+
+        assert!(monitor.latest().is_some());
+    }
+}
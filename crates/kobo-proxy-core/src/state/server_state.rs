@@ -0,0 +1,697 @@
+//! Shared application state for the Kobo server.
+
+pub use implementation::ServerState;
+
+mod implementation {
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, atomic::AtomicU64},
+        time::Duration,
+    };
+
+    use axum::body::Body;
+    use chrono::{DateTime, Utc};
+    use flate2::Compression;
+    use hyper_util::{
+        client::legacy::{Client, connect::HttpConnector},
+        rt::TokioExecutor,
+    };
+
+    use crate::{
+        api::rate_limit::RateLimiter,
+        listener::AcceptStats,
+        state::{
+            audit_log::AuditLog,
+            auth_backoff::AuthBackoff,
+            background_tasks::BackgroundTasks,
+            bandwidth::BandwidthUsage,
+            capture_log::{CaptureLog, CapturingClient},
+            client::{HookedClient, HttpsConnector, KoboClient, RequestHook},
+            cookie_jar::CookieJar,
+            device_groups::DeviceGroups,
+            device_locks::DeviceLocks,
+            devices::DeviceRegistry,
+            resource_usage::ResourceMonitor,
+            snapshots::SnapshotStore,
+            upstream_fallbacks::UpstreamFallbacks,
+            upstream_timing::TimedConnector,
+        },
+        utils::{
+            access_schedule::AccessSchedule, address_family::FamilyResolver, chaos::ChaosRules,
+            cookie_policy::CookiePolicy, device_frontend_urls::DeviceFrontendUrls,
+            firmware_range::FirmwareRange, header_injection::HeaderInjection,
+            profile_rewrite::ProfileRewrite, region_override::RegionOverride,
+            response_patches::ResponsePatches, route_template::RouteTemplates,
+            route_timeouts::RouteTimeouts, schema_validation::SchemaValidation,
+            tcp_tuning::TcpTuning, trusted_proxies::TrustedProxies,
+        },
+    };
+
+    /// Shared application state
+    #[derive(Clone)]
+    #[expect(
+        clippy::struct_excessive_bools,
+        reason = "each bool is an independent feature toggle"
+    )]
+    pub struct ServerState {
+        /// HTTP client to forward requests to Kobo API
+        pub client: Arc<dyn KoboClient>,
+        /// HTTP/1.1-only client to forward upgrade requests with, since HTTP/2 has no
+        /// `Upgrade` mechanism
+        pub upgrade_client: Arc<dyn KoboClient>,
+        /// The Frontend URL that devices should point to (scheme + authority)
+        pub frontend_url: String,
+        /// The configuration the server was started with, with secrets redacted
+        pub config: Arc<serde_json::Value>,
+        /// Frontend URLs that replace `frontend_url` for specific devices
+        pub device_frontend_urls: Arc<DeviceFrontendUrls>,
+        /// Maximum number of body bytes included in request and response logs
+        pub log_body_max_bytes: Option<usize>,
+        /// Templates used to group request paths in logs
+        pub route_templates: Arc<RouteTemplates>,
+        /// Devices that have made requests through the server
+        pub devices: Arc<DeviceRegistry>,
+        /// Bytes exchanged with each device, by month and route template
+        pub bandwidth: Arc<BandwidthUsage>,
+        /// Device clock skew, in seconds, at which a warning is logged (0 disables it)
+        pub clock_skew_warning_seconds: u64,
+        /// Region overrides applied to requests forwarded to the Kobo API
+        pub region_override: Arc<RegionOverride>,
+        /// Headers added to requests forwarded to the Kobo API
+        pub header_injection: Arc<HeaderInjection>,
+        /// Firmware versions for which `transfer-encoding` is removed from responses
+        pub strip_transfer_encoding: Arc<FirmwareRange>,
+        /// Time-based rules that block requests from specific devices
+        pub access_schedule: Arc<AccessSchedule>,
+        /// Consecutive authentication failures of each device, for backing them off
+        pub auth_backoff: Arc<AuthBackoff>,
+        /// Device groups and the policies applied to their requests
+        pub device_groups: Arc<DeviceGroups>,
+        /// Rewrites applied to the user profile
+        pub profile_rewrite: ProfileRewrite,
+        /// Faults injected into requests for chaos testing
+        pub chaos_rules: Arc<ChaosRules>,
+        /// JSON Patch documents applied to responses
+        pub response_patches: Arc<ResponsePatches>,
+        /// Request timeouts keyed by route template
+        pub route_timeouts: Arc<RouteTimeouts>,
+        /// Reverse proxies whose forwarding headers identify the client address
+        pub trusted_proxies: Arc<TrustedProxies>,
+        /// Whether device authentication is answered locally with synthetic tokens
+        pub synthetic_device_auth: bool,
+        /// Whether `/` and `/setup` serve the local status and setup pages
+        pub landing_page: bool,
+        /// Whether HTTP/1 header name casing is preserved between devices and the Kobo
+        /// API
+        pub preserve_header_case: bool,
+        /// Whether the Kobo API host is resolved with a caching DNS resolver
+        pub upstream_dns_cache: bool,
+        /// Whether library requests from the same device are forwarded one at a time
+        pub serialize_device_requests: bool,
+        /// Per-device queues used when requests are serialized
+        pub device_locks: Arc<DeviceLocks>,
+        /// Responses served instead of a bare 502 when the Kobo API fails
+        pub upstream_fallbacks: Arc<UpstreamFallbacks>,
+        /// How `Set-Cookie` headers from the Kobo store API are handled
+        pub cookie_policy: CookiePolicy,
+        /// Cookies kept for each device when the cookie policy is `store`
+        pub cookie_jar: Arc<CookieJar>,
+        /// Whether Kobo store API responses are checked against the typed payloads
+        pub schema_validation: SchemaValidation,
+        /// Modifications made to responses on their way to devices
+        pub audit_log: Arc<AuditLog>,
+        /// File device exchanges are recorded to for later replay, if enabled
+        pub capture_log: Option<Arc<CaptureLog>>,
+        /// Compression level used when re-encoding gzip response bodies
+        pub gzip_compression: Compression,
+        /// Whether requests to snapshot routes are remembered for periodic snapshots
+        pub snapshots_enabled: bool,
+        /// Versioned snapshots of upstream responses for key endpoints
+        pub snapshots: Arc<SnapshotStore>,
+        /// Counters describing the server listener's accept loop
+        pub accept_stats: Arc<AcceptStats>,
+        /// Number of DRM-protected responses passed through untouched
+        pub protected_passthroughs: Arc<AtomicU64>,
+        /// The latest resource usage sample from the resource watchdog
+        pub resource_monitor: Arc<ResourceMonitor>,
+        /// Health of the supervised background tasks
+        pub background_tasks: Arc<BackgroundTasks>,
+        /// Request budgets of local API callers
+        pub api_rate_limiter: Arc<RateLimiter>,
+        /// When the server started
+        pub started_at: DateTime<Utc>,
+        /// The address devices connect to, once the listener is bound
+        pub server_address: Option<SocketAddr>,
+        /// The address of the admin listener, if enabled
+        pub admin_address: Option<SocketAddr>,
+        /// Whether the local API is served alongside the device routes, rather than
+        /// only on the admin listener
+        pub serve_admin_api: bool,
+    }
+
+    impl ServerState {
+        /// Start building a `ServerState` with a required frontend URL (scheme + host\[:port\]).
+        pub fn builder<T: Into<String>>(frontend_url: T) -> ServerStateBuilder {
+            ServerStateBuilder {
+                frontend_url: frontend_url.into(),
+                client: None,
+                upgrade_client: None,
+                request_hooks: Vec::new(),
+                config: serde_json::Value::Null,
+                device_frontend_urls: DeviceFrontendUrls::default(),
+                log_body_max_bytes: None,
+                route_templates: None,
+                clock_skew_warning_seconds: 0,
+                region_override: RegionOverride::default(),
+                header_injection: HeaderInjection::default(),
+                strip_transfer_encoding: FirmwareRange::default(),
+                access_schedule: AccessSchedule::default(),
+                auth_failure_backoff_after: None,
+                device_groups: DeviceGroups::default(),
+                profile_rewrite: ProfileRewrite::default(),
+                chaos_rules: ChaosRules::default(),
+                response_patches: ResponsePatches::default(),
+                route_timeouts: RouteTimeouts::default(),
+                trusted_proxies: TrustedProxies::default(),
+                synthetic_device_auth: false,
+                landing_page: false,
+                preserve_header_case: false,
+                serialize_device_requests: false,
+                upstream_fallbacks: UpstreamFallbacks::default(),
+                cookie_policy: CookiePolicy::default(),
+                schema_validation: SchemaValidation::default(),
+                capture_log: None,
+                tcp_tuning: TcpTuning::default(),
+                upstream_idle_timeout: None,
+                gzip_compression: Compression::default(),
+                resolver: FamilyResolver::default(),
+                happy_eyeballs_timeout: Some(Duration::from_millis(300)),
+                snapshots_enabled: false,
+                snapshot_routes: Vec::new(),
+                accept_stats: Arc::default(),
+                memory_warning_bytes: None,
+                api_rate_limit: None,
+                server_address: None,
+                admin_address: None,
+                serve_admin_api: true,
+            }
+        }
+
+        /// The frontend URL a device should point to: its override if one is
+        /// configured, otherwise the default.
+        pub fn frontend_url_for(&self, device_id: Option<&str>) -> &str {
+            self.device_frontend_urls
+                .get(device_id)
+                .unwrap_or(&self.frontend_url)
+        }
+    }
+
+    /// Builder for `ServerState`.
+    #[expect(
+        clippy::struct_excessive_bools,
+        reason = "each bool is an independent feature toggle"
+    )]
+    pub struct ServerStateBuilder {
+        frontend_url: String,
+        client: Option<Arc<dyn KoboClient>>,
+        upgrade_client: Option<Arc<dyn KoboClient>>,
+        request_hooks: Vec<Arc<dyn RequestHook>>,
+        config: serde_json::Value,
+        device_frontend_urls: DeviceFrontendUrls,
+        log_body_max_bytes: Option<usize>,
+        route_templates: Option<RouteTemplates>,
+        clock_skew_warning_seconds: u64,
+        region_override: RegionOverride,
+        header_injection: HeaderInjection,
+        strip_transfer_encoding: FirmwareRange,
+        access_schedule: AccessSchedule,
+        auth_failure_backoff_after: Option<u32>,
+        device_groups: DeviceGroups,
+        profile_rewrite: ProfileRewrite,
+        chaos_rules: ChaosRules,
+        response_patches: ResponsePatches,
+        route_timeouts: RouteTimeouts,
+        trusted_proxies: TrustedProxies,
+        synthetic_device_auth: bool,
+        landing_page: bool,
+        preserve_header_case: bool,
+        serialize_device_requests: bool,
+        upstream_fallbacks: UpstreamFallbacks,
+        cookie_policy: CookiePolicy,
+        schema_validation: SchemaValidation,
+        capture_log: Option<CaptureLog>,
+        tcp_tuning: TcpTuning,
+        upstream_idle_timeout: Option<Duration>,
+        gzip_compression: Compression,
+        resolver: FamilyResolver,
+        happy_eyeballs_timeout: Option<Duration>,
+        snapshots_enabled: bool,
+        snapshot_routes: Vec<String>,
+        accept_stats: Arc<AcceptStats>,
+        memory_warning_bytes: Option<u64>,
+        api_rate_limit: Option<u32>,
+        server_address: Option<SocketAddr>,
+        admin_address: Option<SocketAddr>,
+        serve_admin_api: bool,
+    }
+
+    impl ServerStateBuilder {
+        /// Provide a custom HTTP client (e.g. a test stub or the replay client).
+        pub fn client(mut self, client: Arc<dyn KoboClient>) -> Self {
+            self.client = Some(client);
+            self
+        }
+
+        /// Provide a custom HTTP client for upgrade requests. Defaults to the custom
+        /// client, if any.
+        #[cfg(test)]
+        pub fn upgrade_client(mut self, client: Arc<dyn KoboClient>) -> Self {
+            self.upgrade_client = Some(client);
+            self
+        }
+
+        /// Provide the redacted configuration reported by the state export.
+        pub fn config(mut self, config: serde_json::Value) -> Self {
+            self.config = config;
+            self
+        }
+
+        /// Provide frontend URLs that replace the default for specific devices.
+        pub fn device_frontend_urls(mut self, device_frontend_urls: DeviceFrontendUrls) -> Self {
+            self.device_frontend_urls = device_frontend_urls;
+            self
+        }
+
+        /// Limit the number of body bytes included in request and response logs.
+        pub fn log_body_max_bytes(mut self, max_bytes: Option<usize>) -> Self {
+            self.log_body_max_bytes = max_bytes;
+            self
+        }
+
+        /// Provide the templates used to group request paths in logs. Defaults to the
+        /// built-in Kobo templates.
+        pub fn route_templates(mut self, route_templates: RouteTemplates) -> Self {
+            self.route_templates = Some(route_templates);
+            self
+        }
+
+        /// Log a warning when a device clock is skewed by at least this many seconds.
+        pub fn clock_skew_warning_seconds(mut self, seconds: u64) -> Self {
+            self.clock_skew_warning_seconds = seconds;
+            self
+        }
+
+        /// Provide the region overrides applied to requests forwarded to the Kobo API.
+        pub fn region_override(mut self, region_override: RegionOverride) -> Self {
+            self.region_override = region_override;
+            self
+        }
+
+        /// Provide the headers added to requests forwarded to the Kobo API.
+        pub fn header_injection(mut self, header_injection: HeaderInjection) -> Self {
+            self.header_injection = header_injection;
+            self
+        }
+
+        /// Provide the firmware versions for which `transfer-encoding` is removed from
+        /// Kobo API responses.
+        pub fn strip_transfer_encoding(mut self, firmware: FirmwareRange) -> Self {
+            self.strip_transfer_encoding = firmware;
+            self
+        }
+
+        /// Provide the time-based rules that block requests from specific devices.
+        pub fn access_schedule(mut self, access_schedule: AccessSchedule) -> Self {
+            self.access_schedule = access_schedule;
+            self
+        }
+
+        /// Set how many consecutive `401 Unauthorized` responses a device may get
+        /// before its requests are answered locally with `429 Too Many Requests`.
+        /// `None` disables the backoff.
+        pub fn auth_failure_backoff_after(mut self, failures: Option<u32>) -> Self {
+            self.auth_failure_backoff_after = failures;
+            self
+        }
+
+        /// Provide the device groups and the policies applied to their requests.
+        pub fn device_groups(mut self, device_groups: DeviceGroups) -> Self {
+            self.device_groups = device_groups;
+            self
+        }
+
+        /// Provide the rewrites applied to the user profile. Defaults to none.
+        pub fn profile_rewrite(mut self, profile_rewrite: ProfileRewrite) -> Self {
+            self.profile_rewrite = profile_rewrite;
+            self
+        }
+
+        /// Provide the faults injected into requests for chaos testing. Defaults to none.
+        pub fn chaos_rules(mut self, chaos_rules: ChaosRules) -> Self {
+            self.chaos_rules = chaos_rules;
+            self
+        }
+
+        /// Provide the JSON Patch documents applied to responses.
+        pub fn response_patches(mut self, response_patches: ResponsePatches) -> Self {
+            self.response_patches = response_patches;
+            self
+        }
+
+        /// Provide the request timeouts keyed by route template.
+        pub fn route_timeouts(mut self, route_timeouts: RouteTimeouts) -> Self {
+            self.route_timeouts = route_timeouts;
+            self
+        }
+
+        /// Provide the reverse proxies whose forwarding headers are honored.
+        pub fn trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+            self.trusted_proxies = trusted_proxies;
+            self
+        }
+
+        /// Answer device authentication locally with synthetic tokens.
+        pub fn synthetic_device_auth(mut self, enable: bool) -> Self {
+            self.synthetic_device_auth = enable;
+            self
+        }
+
+        /// Serve the local status and setup page at `/`.
+        pub fn landing_page(mut self, enable: bool) -> Self {
+            self.landing_page = enable;
+            self
+        }
+
+        /// Keep the header name casing of HTTP/1 responses from the Kobo API.
+        pub fn preserve_header_case(mut self, enable: bool) -> Self {
+            self.preserve_header_case = enable;
+            self
+        }
+
+        /// Forward library requests from the same device one at a time.
+        pub fn serialize_device_requests(mut self, enable: bool) -> Self {
+            self.serialize_device_requests = enable;
+            self
+        }
+
+        /// Provide the responses served when the Kobo API fails. Defaults to none.
+        pub fn upstream_fallbacks(mut self, upstream_fallbacks: UpstreamFallbacks) -> Self {
+            self.upstream_fallbacks = upstream_fallbacks;
+            self
+        }
+
+        /// Provide how `Set-Cookie` headers from the Kobo store API are handled.
+        pub fn cookie_policy(mut self, cookie_policy: CookiePolicy) -> Self {
+            self.cookie_policy = cookie_policy;
+            self
+        }
+
+        /// Provide whether Kobo store API responses are checked against the typed
+        /// payloads. Defaults to off.
+        pub fn schema_validation(mut self, schema_validation: SchemaValidation) -> Self {
+            self.schema_validation = schema_validation;
+            self
+        }
+
+        /// Provide the file device exchanges are recorded to. Defaults to none.
+        pub fn capture_log(mut self, capture_log: Option<CaptureLog>) -> Self {
+            self.capture_log = capture_log;
+            self
+        }
+
+        /// Provide the socket options used for connections to the Kobo API.
+        pub fn tcp_tuning(mut self, tcp_tuning: TcpTuning) -> Self {
+            self.tcp_tuning = tcp_tuning;
+            self
+        }
+
+        /// Keep idle connections to the Kobo API open for this long. Defaults to the
+        /// client default.
+        pub fn upstream_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+            self.upstream_idle_timeout = timeout;
+            self
+        }
+
+        /// Set the gzip level (0-9) used when re-encoding response bodies. Defaults to 6.
+        pub fn gzip_level(mut self, level: u32) -> Self {
+            self.gzip_compression = Compression::new(level);
+            self
+        }
+
+        /// Set how the Kobo API host is resolved and which address family connections
+        /// to it prefer. Defaults to `getaddrinfo` in resolver order.
+        pub fn resolver(mut self, resolver: FamilyResolver) -> Self {
+            self.resolver = resolver;
+            self
+        }
+
+        /// Set how long a connection attempt to the preferred address family may take
+        /// before the other family is tried in parallel. `None` disables the race.
+        pub fn happy_eyeballs_timeout(mut self, timeout: Option<Duration>) -> Self {
+            self.happy_eyeballs_timeout = timeout;
+            self
+        }
+
+        /// Remember requests to snapshot routes so they can be replayed periodically.
+        pub fn snapshots_enabled(mut self, enable: bool) -> Self {
+            self.snapshots_enabled = enable;
+            self
+        }
+
+        /// Provide the route templates that are snapshotted. Defaults to
+        /// `/v1/initialization`.
+        pub fn snapshot_routes(mut self, routes: Vec<String>) -> Self {
+            self.snapshot_routes = routes;
+            self
+        }
+
+        /// Provide the counters updated by the server listener's accept loop.
+        pub fn accept_stats(mut self, accept_stats: Arc<AcceptStats>) -> Self {
+            self.accept_stats = accept_stats;
+            self
+        }
+
+        /// Provide the resident memory, in bytes, at which the resource watchdog warns.
+        pub fn memory_warning_bytes(mut self, bytes: Option<u64>) -> Self {
+            self.memory_warning_bytes = bytes;
+            self
+        }
+
+        /// Provide the number of local API requests each caller may make per minute, or
+        /// `None` for no limit.
+        pub fn api_rate_limit(mut self, requests_per_minute: Option<u32>) -> Self {
+            self.api_rate_limit = requests_per_minute;
+            self
+        }
+
+        /// Provide the addresses the server and admin listeners are bound to.
+        pub fn listener_addresses(
+            mut self,
+            server_address: Option<SocketAddr>,
+            admin_address: Option<SocketAddr>,
+        ) -> Self {
+            self.server_address = server_address;
+            self.admin_address = admin_address;
+            self
+        }
+
+        /// Serve the local API alongside the device routes. Disabled when it is only
+        /// served on the admin listener. Defaults to enabled.
+        pub fn serve_admin_api(mut self, enable: bool) -> Self {
+            self.serve_admin_api = enable;
+            self
+        }
+
+        /// Add hooks that modify requests before they are forwarded to the Kobo API.
+        /// Hooks run in the order they are added.
+        pub fn request_hooks(mut self, hooks: Vec<Arc<dyn RequestHook>>) -> Self {
+            self.request_hooks.extend(hooks);
+            self
+        }
+
+        /// Creates a client for the Kobo API, offering HTTP/2 during the TLS handshake
+        /// if `enable_http2` is set.
+        fn upstream_client(&self, enable_http2: bool) -> Arc<dyn KoboClient> {
+            let mut http_connector = HttpConnector::new_with_resolver(self.resolver.clone());
+            http_connector.enforce_http(false);
+            http_connector.set_happy_eyeballs_timeout(self.happy_eyeballs_timeout);
+            self.tcp_tuning.apply_to_connector(&mut http_connector);
+            let tcp_connector = TimedConnector::tcp(http_connector);
+            let tls_builder = hyper_rustls::HttpsConnectorBuilder::new()
+                .with_webpki_roots()
+                .https_only()
+                .enable_http1();
+            let connector = TimedConnector::tls(if enable_http2 {
+                tls_builder.enable_http2().wrap_connector(tcp_connector)
+            } else {
+                tls_builder.wrap_connector(tcp_connector)
+            });
+            let mut client_builder = Client::builder(TokioExecutor::new());
+            if let Some(timeout) = self.upstream_idle_timeout {
+                client_builder.pool_idle_timeout(timeout);
+            }
+            client_builder.http1_preserve_header_case(self.preserve_header_case);
+            let client: Client<HttpsConnector, Body> = client_builder.build(connector);
+            Arc::new(client)
+        }
+
+        /// Wraps `client` so it runs the request hooks, if any.
+        fn hooked(&self, client: Arc<dyn KoboClient>) -> Arc<dyn KoboClient> {
+            if self.request_hooks.is_empty() {
+                client
+            } else {
+                Arc::new(HookedClient::new(client, self.request_hooks.clone()))
+            }
+        }
+
+        /// Build the `ServerState`.
+        pub fn build(self) -> ServerState {
+            let upstream_dns_cache = self.resolver.is_cached();
+
+            let client = self
+                .client
+                .clone()
+                .unwrap_or_else(|| self.upstream_client(true));
+            let client: Arc<dyn KoboClient> = if self.capture_log.is_some() {
+                Arc::new(CapturingClient::new(client))
+            } else {
+                client
+            };
+            let client = self.hooked(client);
+            // Upgraded connections are tunnelled rather than captured.
+            let upgrade_client = self
+                .upgrade_client
+                .clone()
+                .or_else(|| self.client.clone())
+                .unwrap_or_else(|| self.upstream_client(false));
+            let upgrade_client = self.hooked(upgrade_client);
+            let frontend_url = self.frontend_url;
+
+            ServerState {
+                client,
+                upgrade_client,
+                frontend_url,
+                config: Arc::new(self.config),
+                device_frontend_urls: Arc::new(self.device_frontend_urls),
+                log_body_max_bytes: self.log_body_max_bytes,
+                route_templates: Arc::new(self.route_templates.unwrap_or_default()),
+                devices: Arc::default(),
+                bandwidth: Arc::default(),
+                clock_skew_warning_seconds: self.clock_skew_warning_seconds,
+                region_override: Arc::new(self.region_override),
+                header_injection: Arc::new(self.header_injection),
+                strip_transfer_encoding: Arc::new(self.strip_transfer_encoding),
+                access_schedule: Arc::new(self.access_schedule),
+                auth_backoff: Arc::new(AuthBackoff::new(self.auth_failure_backoff_after)),
+                device_groups: Arc::new(self.device_groups),
+                profile_rewrite: self.profile_rewrite,
+                chaos_rules: Arc::new(self.chaos_rules),
+                response_patches: Arc::new(self.response_patches),
+                route_timeouts: Arc::new(self.route_timeouts),
+                trusted_proxies: Arc::new(self.trusted_proxies),
+                synthetic_device_auth: self.synthetic_device_auth,
+                landing_page: self.landing_page,
+                preserve_header_case: self.preserve_header_case,
+                upstream_dns_cache,
+                serialize_device_requests: self.serialize_device_requests,
+                device_locks: Arc::default(),
+                upstream_fallbacks: Arc::new(self.upstream_fallbacks),
+                cookie_policy: self.cookie_policy,
+                cookie_jar: Arc::default(),
+                schema_validation: self.schema_validation,
+                capture_log: self.capture_log.map(Arc::new),
+                audit_log: Arc::default(),
+                gzip_compression: self.gzip_compression,
+                snapshots_enabled: self.snapshots_enabled,
+                snapshots: Arc::new(SnapshotStore::new(self.snapshot_routes)),
+                accept_stats: self.accept_stats,
+                protected_passthroughs: Arc::default(),
+                resource_monitor: Arc::new(ResourceMonitor::new(self.memory_warning_bytes)),
+                background_tasks: Arc::default(),
+                api_rate_limiter: Arc::new(RateLimiter::new(self.api_rate_limit)),
+                started_at: Utc::now(),
+                server_address: self.server_address,
+                admin_address: self.admin_address,
+                serve_admin_api: self.serve_admin_api,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{HeaderValue, Response},
+    };
+
+    use super::*;
+    use crate::state::{client::RequestHook, fake_kobo_client::FakeKoboClient};
+
+    struct GatewayToken;
+
+    #[async_trait::async_trait]
+    impl RequestHook for GatewayToken {
+        async fn modify_request(&self, request: &mut Request) -> anyhow::Result<()> {
+            request
+                .headers_mut()
+                .insert("x-gateway-token", HeaderValue::from_static("secret"));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn builder_sets_frontend_url() {
+        let state = ServerState::builder("https://example.test").build();
+
+        assert_eq!(state.frontend_url, "https://example.test");
+    }
+
+    #[test]
+    fn builder_defaults_frontend_url() {
+        // No implicit default anymore; test explicit usage
+        let state = ServerState::builder("http://localhost:1234").build();
+        assert_eq!(state.frontend_url, "http://localhost:1234");
+    }
+
+    #[test]
+    fn builder_defaults_log_body_max_bytes_to_none() {
+        let state = ServerState::builder("https://example.test").build();
+
+        assert_eq!(state.log_body_max_bytes, None);
+    }
+
+    #[test]
+    fn builder_sets_log_body_max_bytes() {
+        let state = ServerState::builder("https://example.test")
+            .log_body_max_bytes(Some(1024))
+            .build();
+
+        assert_eq!(state.log_body_max_bytes, Some(1024));
+    }
+
+    #[tokio::test]
+    async fn builder_runs_request_hooks_on_forwarded_requests() {
+        let stub = Arc::new(FakeKoboClient::new());
+        stub.enqueue_response(Response::new(Body::empty()));
+        let state = ServerState::builder("https://example.test")
+            .client(stub.clone())
+            .request_hooks(vec![Arc::new(GatewayToken)])
+            .build();
+
+        state
+            .client
+            .request(Request::new(Body::empty()))
+            .await
+            .expect("request should be forwarded");
+
+        let recorded = stub.recorded_requests();
+        assert_eq!(
+            recorded[0].headers.get("x-gateway-token").unwrap(),
+            "secret"
+        );
+    }
+}
@@ -0,0 +1,310 @@
+//! Versioned snapshots of upstream responses for key endpoints.
+//!
+//! Devices' most recent requests to snapshot routes are remembered, and a periodic
+//! task replays them upstream. A new version is stored whenever the response changes,
+//! together with a summary of what changed, to track when Kobo changes its API.
+
+pub use implementation::{SnapshotHistory, SnapshotRequest, SnapshotStore};
+
+mod implementation {
+    use std::{
+        collections::{BTreeMap, VecDeque},
+        sync::{Mutex, MutexGuard, PoisonError},
+    };
+
+    use chrono::{DateTime, Utc};
+    use hyper::{HeaderMap, header};
+    use serde::Serialize;
+
+    use crate::utils::json_diff::diff_json;
+
+    /// Number of versions kept per device and route before the oldest are discarded.
+    const MAX_VERSIONS: usize = 20;
+
+    /// Routes snapshotted when none are configured.
+    const DEFAULT_ROUTES: &[&str] = &["/v1/initialization"];
+
+    /// A device request that is replayed to take snapshots. Headers, including
+    /// credentials, are only kept in memory.
+    #[derive(Clone, Debug)]
+    pub struct SnapshotRequest {
+        /// The device that made the request.
+        pub device_id: String,
+        /// The route template of the request.
+        pub route: String,
+        /// The path and query of the request.
+        pub path_and_query: String,
+        /// The request headers.
+        pub headers: HeaderMap,
+    }
+
+    /// A single version of an upstream response.
+    #[derive(Clone, Debug, Serialize)]
+    pub struct Snapshot {
+        /// Incrementing version number, starting at 1.
+        pub version: u64,
+        /// When this version was first seen.
+        pub captured_at: DateTime<Utc>,
+        /// The upstream status code.
+        pub status: u16,
+        /// The decoded body size, in bytes.
+        pub size: usize,
+        /// Paths that changed since the previous version.
+        pub changes: Vec<String>,
+        /// The decoded response body.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub body: Option<String>,
+    }
+
+    /// The stored versions for a device and route, oldest first.
+    #[derive(Clone, Debug, Serialize)]
+    pub struct SnapshotHistory {
+        /// The device the snapshots were taken for.
+        pub device_id: String,
+        /// The route template that was snapshotted.
+        pub route: String,
+        /// The stored versions, oldest first.
+        pub versions: Vec<Snapshot>,
+    }
+
+    type Key = (String, String);
+
+    /// Thread-safe store of snapshot requests and versions.
+    #[derive(Debug)]
+    pub struct SnapshotStore {
+        routes: Vec<String>,
+        requests: Mutex<BTreeMap<Key, SnapshotRequest>>,
+        versions: Mutex<BTreeMap<Key, VecDeque<Snapshot>>>,
+    }
+
+    impl Default for SnapshotStore {
+        fn default() -> Self {
+            Self::new(Vec::new())
+        }
+    }
+
+    impl SnapshotStore {
+        /// Creates a store for the given route templates, defaulting to
+        /// `/v1/initialization` when empty.
+        pub fn new(routes: Vec<String>) -> Self {
+            let routes = if routes.is_empty() {
+                DEFAULT_ROUTES
+                    .iter()
+                    .map(|&route| route.to_owned())
+                    .collect()
+            } else {
+                routes
+            };
+            Self {
+                routes,
+                requests: Mutex::default(),
+                versions: Mutex::default(),
+            }
+        }
+
+        fn get_requests_lock(&self) -> MutexGuard<'_, BTreeMap<Key, SnapshotRequest>> {
+            self.requests.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        fn get_versions_lock(&self) -> MutexGuard<'_, BTreeMap<Key, VecDeque<Snapshot>>> {
+            self.versions.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Checks if a route template is snapshotted.
+        pub fn is_snapshot_route(&self, route: &str) -> bool {
+            self.routes
+                .iter()
+                .any(|snapshot_route| snapshot_route == route)
+        }
+
+        /// Remembers the most recent request a device made to a snapshot route.
+        pub fn remember_request(&self, mut request: SnapshotRequest) {
+            request.headers.remove(header::CONTENT_LENGTH);
+            self.get_requests_lock()
+                .insert((request.device_id.clone(), request.route.clone()), request);
+        }
+
+        /// Forgets the request a device made to a route, so it is no longer replayed.
+        pub fn forget_request(&self, device_id: &str, route: &str) {
+            self.get_requests_lock()
+                .remove(&(device_id.to_owned(), route.to_owned()));
+        }
+
+        /// Returns the number of stored versions across every history.
+        pub fn version_count(&self) -> usize {
+            self.get_versions_lock().values().map(VecDeque::len).sum()
+        }
+
+        /// Returns the remembered requests.
+        pub fn requests(&self) -> Vec<SnapshotRequest> {
+            self.get_requests_lock().values().cloned().collect()
+        }
+
+        /// Records a response, storing a new version if it differs from the latest
+        /// one. Returns the changes if a new version was stored.
+        pub fn record(
+            &self,
+            device_id: &str,
+            route: &str,
+            status: u16,
+            body: String,
+        ) -> Option<Vec<String>> {
+            let mut versions = self.get_versions_lock();
+            let history = versions
+                .entry((device_id.to_owned(), route.to_owned()))
+                .or_default();
+
+            let (version, changes) = match history.back() {
+                Some(latest)
+                    if latest.status == status && latest.body.as_deref() == Some(&body) =>
+                {
+                    return None;
+                }
+                Some(latest) => {
+                    let mut changes = diff_json(latest.body.as_deref().unwrap_or_default(), &body);
+                    if latest.status != status {
+                        changes.insert(0, format!("~ (status {} -> {status})", latest.status));
+                    }
+                    (latest.version + 1, changes)
+                }
+                None => (1, Vec::new()),
+            };
+
+            if history.len() >= MAX_VERSIONS {
+                history.pop_front();
+            }
+            history.push_back(Snapshot {
+                version,
+                captured_at: Utc::now(),
+                status,
+                size: body.len(),
+                changes: changes.clone(),
+                body: Some(body),
+            });
+            Some(changes)
+        }
+
+        /// Returns the stored histories, optionally filtered by device and route.
+        /// Bodies are only included if `include_bodies` is set.
+        pub fn histories(
+            &self,
+            device_id: Option<&str>,
+            route: Option<&str>,
+            include_bodies: bool,
+        ) -> Vec<SnapshotHistory> {
+            self.get_versions_lock()
+                .iter()
+                .filter(|((history_device, history_route), _)| {
+                    device_id.is_none_or(|device_id| device_id == history_device)
+                        && route.is_none_or(|route| route == history_route)
+                })
+                .map(|((device_id, route), versions)| SnapshotHistory {
+                    device_id: device_id.clone(),
+                    route: route.clone(),
+                    versions: versions
+                        .iter()
+                        .cloned()
+                        .map(|mut snapshot| {
+                            if !include_bodies {
+                                snapshot.body = None;
+                            }
+                            snapshot
+                        })
+                        .collect(),
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::HeaderMap;
+
+    use super::*;
+
+    #[test]
+    fn new_defaults_to_initialization_route() {
+        let store = SnapshotStore::default();
+
+        assert!(store.is_snapshot_route("/v1/initialization"));
+        assert!(!store.is_snapshot_route("/v1/library/sync"));
+    }
+
+    #[test]
+    fn remember_request_keeps_latest_per_device_and_route() {
+        let store = SnapshotStore::default();
+        for path_and_query in ["/v1/initialization?a", "/v1/initialization?b"] {
+            store.remember_request(SnapshotRequest {
+                device_id: "device-1".to_owned(),
+                route: "/v1/initialization".to_owned(),
+                path_and_query: path_and_query.to_owned(),
+                headers: HeaderMap::new(),
+            });
+        }
+
+        let requests = store.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path_and_query, "/v1/initialization?b");
+    }
+
+    #[test]
+    fn record_stores_versions_only_when_changed() {
+        let store = SnapshotStore::default();
+
+        assert_eq!(
+            store.record(
+                "device-1",
+                "/v1/initialization",
+                200,
+                r#"{"a":1}"#.to_owned()
+            ),
+            Some(Vec::new())
+        );
+        assert_eq!(
+            store.record(
+                "device-1",
+                "/v1/initialization",
+                200,
+                r#"{"a":1}"#.to_owned()
+            ),
+            None
+        );
+        assert_eq!(
+            store.record(
+                "device-1",
+                "/v1/initialization",
+                200,
+                r#"{"a":2}"#.to_owned()
+            ),
+            Some(vec!["~ /a".to_owned()])
+        );
+
+        let histories = store.histories(None, None, false);
+        assert_eq!(histories[0].versions.len(), 2);
+        assert_eq!(histories[0].versions[1].version, 2);
+        assert!(histories[0].versions[1].body.is_none());
+    }
+
+    #[test]
+    fn record_reports_status_changes() {
+        let store = SnapshotStore::default();
+
+        store.record("device-1", "/v1/initialization", 200, "{}".to_owned());
+        let changes = store.record("device-1", "/v1/initialization", 401, "{}".to_owned());
+
+        assert_eq!(changes, Some(vec!["~ (status 200 -> 401)".to_owned()]));
+    }
+
+    #[test]
+    fn histories_filter_by_device_and_include_bodies() {
+        let store = SnapshotStore::default();
+        store.record("device-1", "/v1/initialization", 200, "{}".to_owned());
+        store.record("device-2", "/v1/initialization", 200, "[]".to_owned());
+
+        let histories = store.histories(Some("device-2"), None, true);
+
+        assert_eq!(histories.len(), 1);
+        assert_eq!(histories[0].versions[0].body.as_deref(), Some("[]"));
+    }
+}
@@ -0,0 +1,241 @@
+//! Responses served instead of a bare `502 Bad Gateway` when the Kobo API fails.
+//!
+//! Devices show cryptic errors when a sync fails, so key endpoints can degrade
+//! gracefully: the most recent successful response is replayed when one was cached,
+//! otherwise a configured or built-in body (e.g. an empty sync payload) is returned.
+//! Fallbacks are keyed by route template.
+
+pub use implementation::{DEGRADED_HEADER, UpstreamFallbacks};
+
+mod implementation {
+    use std::{
+        collections::HashMap,
+        sync::{Mutex, MutexGuard, PoisonError},
+    };
+
+    use anyhow::{Context as _, Result, bail};
+    use axum::{
+        body::{Body, Bytes},
+        response::Response,
+    };
+    use hyper::{HeaderMap, StatusCode, header, header::HeaderValue};
+
+    /// Header added to every fallback response so degraded responses can be identified.
+    pub const DEGRADED_HEADER: &str = "x-kobo-proxy-degraded";
+
+    /// Built-in fallback bodies for endpoints with a safe empty response.
+    const BUILT_IN_FALLBACKS: &[(&str, &str)] = &[("/v1/library/sync", "[]")];
+
+    /// A successful response kept to be replayed when the upstream fails.
+    #[derive(Clone, Debug)]
+    struct CachedResponse {
+        headers: HeaderMap,
+        body: Bytes,
+    }
+
+    /// Fallback responses keyed by route template.
+    #[derive(Debug, Default)]
+    pub struct UpstreamFallbacks {
+        enabled: bool,
+        custom: HashMap<String, Bytes>,
+        cached: Mutex<HashMap<String, CachedResponse>>,
+    }
+
+    impl UpstreamFallbacks {
+        /// Creates fallbacks. `enabled` turns on the built-in fallbacks and response
+        /// caching, while `custom` maps route templates to JSON bodies and is always used.
+        pub fn new(enabled: bool, custom: HashMap<String, Bytes>) -> Self {
+            Self {
+                enabled,
+                custom,
+                cached: Mutex::default(),
+            }
+        }
+
+        /// Reads custom fallback bodies from `ROUTE=FILE` specifications.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if a specification is malformed or a file cannot be read.
+        pub async fn read_custom<S: AsRef<str>>(
+            specifications: &[S],
+        ) -> Result<HashMap<String, Bytes>> {
+            let mut custom = HashMap::new();
+            for specification in specifications {
+                let specification = specification.as_ref();
+                let Some((route, path)) = specification.split_once('=') else {
+                    bail!("Upstream failure response '{specification}' must be in ROUTE=FILE form");
+                };
+                let body = tokio::fs::read(path).await.with_context(|| {
+                    format!("Failed to read upstream failure response '{path}'")
+                })?;
+                custom.insert(route.to_owned(), Bytes::from(body));
+            }
+
+            Ok(custom)
+        }
+
+        fn get_cached_lock(&self) -> MutexGuard<'_, HashMap<String, CachedResponse>> {
+            self.cached.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Stores a successful response for `route` so it can be replayed later.
+        pub fn cache(&self, route: &str, headers: &HeaderMap, body: &Bytes) {
+            if !self.enabled {
+                return;
+            }
+
+            let mut cached_headers = HeaderMap::new();
+            for name in [header::CONTENT_TYPE, header::CONTENT_ENCODING] {
+                if let Some(value) = headers.get(&name) {
+                    cached_headers.insert(name, value.clone());
+                }
+            }
+            self.get_cached_lock().insert(
+                route.to_owned(),
+                CachedResponse {
+                    headers: cached_headers,
+                    body: body.clone(),
+                },
+            );
+        }
+
+        /// Returns the number of cached successful responses.
+        pub fn cached_count(&self) -> usize {
+            self.get_cached_lock().len()
+        }
+
+        /// Returns the fallback response for `route`, if one is available.
+        pub fn response_for(&self, route: &str) -> Option<Response> {
+            let cached = self.get_cached_lock().get(route).cloned();
+            if let Some(cached) = cached {
+                return Some(build_response(cached.headers, cached.body));
+            }
+
+            let body = self.custom.get(route).cloned().or_else(|| {
+                self.enabled
+                    .then(|| {
+                        BUILT_IN_FALLBACKS
+                            .iter()
+                            .find(|(built_in_route, _)| *built_in_route == route)
+                            .map(|(_, body)| Bytes::from_static(body.as_bytes()))
+                    })
+                    .flatten()
+            })?;
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json; charset=utf-8"),
+            );
+
+            Some(build_response(headers, body))
+        }
+    }
+
+    /// Builds a `200 OK` fallback response marked as degraded.
+    fn build_response(mut headers: HeaderMap, body: Bytes) -> Response {
+        headers.insert(DEGRADED_HEADER, HeaderValue::from_static("true"));
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = StatusCode::OK;
+        *response.headers_mut() = headers;
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::body::Bytes;
+    use http_body_util::BodyExt as _;
+    use hyper::{HeaderMap, header};
+
+    use super::*;
+
+    async fn body_text(fallbacks: &UpstreamFallbacks, route: &str) -> String {
+        let response = fallbacks.response_for(route).expect("expected a fallback");
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn disabled_fallbacks_have_no_built_in_responses() {
+        let fallbacks = UpstreamFallbacks::default();
+
+        assert!(fallbacks.response_for("/v1/library/sync").is_none());
+    }
+
+    #[tokio::test]
+    async fn enabled_fallbacks_return_empty_sync_payload() {
+        let fallbacks = UpstreamFallbacks::new(true, HashMap::new());
+
+        assert_eq!(body_text(&fallbacks, "/v1/library/sync").await, "[]");
+    }
+
+    #[test]
+    fn fallback_responses_are_marked_as_degraded() {
+        let fallbacks = UpstreamFallbacks::new(true, HashMap::new());
+
+        let response = fallbacks.response_for("/v1/library/sync").unwrap();
+
+        assert_eq!(
+            response.headers().get("x-kobo-proxy-degraded").unwrap(),
+            "true"
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_fallbacks_are_used_when_disabled() {
+        let custom = HashMap::from([("/v1/user/profile".to_owned(), Bytes::from("{}"))]);
+        let fallbacks = UpstreamFallbacks::new(false, custom);
+
+        assert_eq!(body_text(&fallbacks, "/v1/user/profile").await, "{}");
+    }
+
+    #[tokio::test]
+    async fn custom_fallbacks_take_precedence_over_built_in() {
+        let custom = HashMap::from([("/v1/library/sync".to_owned(), Bytes::from("[{}]"))]);
+        let fallbacks = UpstreamFallbacks::new(true, custom);
+
+        assert_eq!(body_text(&fallbacks, "/v1/library/sync").await, "[{}]");
+    }
+
+    #[tokio::test]
+    async fn cached_responses_take_precedence() {
+        let fallbacks = UpstreamFallbacks::new(true, HashMap::new());
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_ENCODING, "identity".parse().unwrap());
+
+        fallbacks.cache("/v1/initialization", &headers, &Bytes::from("cached"));
+        let response = fallbacks.response_for("/v1/initialization").unwrap();
+
+        assert_eq!(
+            response.headers().get(header::CONTENT_ENCODING).unwrap(),
+            "identity"
+        );
+        assert_eq!(body_text(&fallbacks, "/v1/initialization").await, "cached");
+    }
+
+    #[test]
+    fn cache_is_ignored_when_disabled() {
+        let fallbacks = UpstreamFallbacks::default();
+
+        fallbacks.cache("/v1/initialization", &HeaderMap::new(), &Bytes::from("x"));
+
+        assert!(fallbacks.response_for("/v1/initialization").is_none());
+    }
+
+    #[tokio::test]
+    async fn read_custom_rejects_malformed_specification() {
+        let result = UpstreamFallbacks::read_custom(&["/v1/library/sync"]).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn read_custom_reports_missing_files() {
+        let result = UpstreamFallbacks::read_custom(&["/v1/library/sync=/nonexistent.json"]).await;
+
+        assert!(result.is_err());
+    }
+}
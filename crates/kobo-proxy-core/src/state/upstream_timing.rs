@@ -0,0 +1,372 @@
+//! Timing of requests forwarded to the Kobo store API.
+//!
+//! The client marks when each phase of an upstream request finishes: DNS
+//! resolution, the TCP connection, the TLS handshake, the response headers, and the
+//! response body. The phase durations are recorded on an `upstream` span and logged
+//! at debug level once the body completes, so slow syncs can be attributed to a
+//! phase without a packet capture. Requests sent on a pooled connection have no
+//! DNS, connect, or TLS timings.
+
+pub use implementation::{TimedBody, TimedConnector, UpstreamTimings};
+
+mod implementation {
+    use std::{
+        pin::Pin,
+        sync::{Arc, Mutex, PoisonError},
+        task::{Context, Poll},
+        time::{Duration, Instant},
+    };
+
+    use axum::body::Bytes;
+    use hyper::{
+        Uri,
+        body::{Body, Frame, Incoming, SizeHint},
+    };
+    use tower::Service;
+    use tracing::{Span, field::Empty};
+
+    tokio::task_local! {
+        /// Timings of the upstream request being sent by the current task.
+        static CURRENT: Arc<UpstreamTimings>;
+    }
+
+    /// When each phase of an upstream request finished, relative to its start.
+    #[derive(Clone, Copy, Debug, Default)]
+    struct Marks {
+        connect_started: Option<Duration>,
+        resolved: Option<Duration>,
+        connected: Option<Duration>,
+        handshaken: Option<Duration>,
+        first_byte: Option<Duration>,
+        complete: Option<Duration>,
+    }
+
+    /// Phase timings of one upstream request.
+    #[derive(Debug)]
+    pub struct UpstreamTimings {
+        started: Instant,
+        marks: Mutex<Marks>,
+        span: Span,
+    }
+
+    impl UpstreamTimings {
+        /// Starts timing an upstream request, with a new `upstream` span.
+        pub fn start() -> Arc<Self> {
+            Arc::new(Self {
+                started: Instant::now(),
+                marks: Mutex::default(),
+                span: tracing::debug_span!(
+                    "upstream",
+                    dns_ms = Empty,
+                    connect_ms = Empty,
+                    tls_ms = Empty,
+                    ttfb_ms = Empty,
+                    body_ms = Empty,
+                    reused_connection = Empty,
+                ),
+            })
+        }
+
+        /// Returns the span the phase timings are recorded on.
+        pub fn span(&self) -> &Span {
+            &self.span
+        }
+
+        /// Runs `future` with these timings as the current task's, so the connector
+        /// and resolver can mark the connection phases.
+        pub async fn scope<F: Future>(self: &Arc<Self>, future: F) -> F::Output {
+            CURRENT.scope(self.clone(), future).await
+        }
+
+        /// Returns the timings of the upstream request sent by the current task.
+        pub fn current() -> Option<Arc<Self>> {
+            CURRENT.try_with(Arc::clone).ok()
+        }
+
+        fn mark(&self, mark: impl FnOnce(&mut Marks, Duration)) {
+            let elapsed = self.started.elapsed();
+            mark(
+                &mut self.marks.lock().unwrap_or_else(PoisonError::into_inner),
+                elapsed,
+            );
+        }
+
+        /// Marks that a new connection is being opened.
+        pub fn mark_connect_started(&self) {
+            self.mark(|marks, elapsed| marks.connect_started = Some(elapsed));
+        }
+
+        /// Marks that the upstream host name was resolved.
+        pub fn mark_resolved(&self) {
+            self.mark(|marks, elapsed| marks.resolved = Some(elapsed));
+        }
+
+        /// Marks that the TCP connection was established.
+        pub fn mark_connected(&self) {
+            self.mark(|marks, elapsed| marks.connected = Some(elapsed));
+        }
+
+        /// Marks that the TLS handshake finished.
+        pub fn mark_handshaken(&self) {
+            self.mark(|marks, elapsed| marks.handshaken = Some(elapsed));
+        }
+
+        /// Marks that the response headers were received.
+        pub fn mark_first_byte(&self) {
+            self.mark(|marks, elapsed| marks.first_byte = Some(elapsed));
+        }
+
+        /// Marks that the response body was received, and records the phase
+        /// timings on the span.
+        pub fn finish(&self) {
+            self.mark(|marks, elapsed| marks.complete = Some(elapsed));
+            let marks = *self.marks.lock().unwrap_or_else(PoisonError::into_inner);
+            let phase = |from: Option<Duration>, to: Option<Duration>| {
+                Some(u64::try_from(to?.saturating_sub(from?).as_millis()).unwrap_or(u64::MAX))
+            };
+            let span = &self.span;
+            span.record("reused_connection", marks.connect_started.is_none());
+            if let Some(ms) = phase(marks.connect_started, marks.resolved) {
+                span.record("dns_ms", ms);
+            }
+            if let Some(ms) = phase(marks.resolved.or(marks.connect_started), marks.connected) {
+                span.record("connect_ms", ms);
+            }
+            if let Some(ms) = phase(marks.connected, marks.handshaken) {
+                span.record("tls_ms", ms);
+            }
+            if let Some(ms) = phase(Some(Duration::ZERO), marks.first_byte) {
+                span.record("ttfb_ms", ms);
+            }
+            if let Some(ms) = phase(marks.first_byte, marks.complete) {
+                span.record("body_ms", ms);
+            }
+        }
+    }
+
+    /// The connection phase a [`TimedConnector`] marks when it finishes.
+    #[derive(Clone, Copy, Debug)]
+    enum ConnectPhase {
+        Tcp,
+        Tls,
+    }
+
+    /// A connector that marks when the connection it wraps is established.
+    #[derive(Clone, Debug)]
+    pub struct TimedConnector<C> {
+        inner: C,
+        phase: ConnectPhase,
+    }
+
+    impl<C> TimedConnector<C> {
+        /// Wraps a TCP connector, marking when connections start and connect.
+        pub fn tcp(inner: C) -> Self {
+            Self {
+                inner,
+                phase: ConnectPhase::Tcp,
+            }
+        }
+
+        /// Wraps a TLS connector, marking when handshakes finish.
+        pub fn tls(inner: C) -> Self {
+            Self {
+                inner,
+                phase: ConnectPhase::Tls,
+            }
+        }
+    }
+
+    impl<C> Service<Uri> for TimedConnector<C>
+    where
+        C: Service<Uri>,
+        C::Future: Send + 'static,
+    {
+        type Response = C::Response;
+        type Error = C::Error;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, uri: Uri) -> Self::Future {
+            let timings = UpstreamTimings::current();
+            let phase = self.phase;
+            if let (Some(timings), ConnectPhase::Tcp) = (&timings, phase) {
+                timings.mark_connect_started();
+            }
+            let connecting = self.inner.call(uri);
+            Box::pin(async move {
+                let connection = connecting.await?;
+                match (timings, phase) {
+                    (Some(timings), ConnectPhase::Tcp) => timings.mark_connected(),
+                    (Some(timings), ConnectPhase::Tls) => timings.mark_handshaken(),
+                    (None, _) => {}
+                }
+                Ok(connection)
+            })
+        }
+    }
+
+    /// An upstream response body that finishes its request's timings when it ends.
+    pub struct TimedBody {
+        inner: Incoming,
+        timings: Option<Arc<UpstreamTimings>>,
+    }
+
+    impl TimedBody {
+        /// Wraps a response body, finishing `timings` once it has been read.
+        pub fn new(inner: Incoming, timings: Arc<UpstreamTimings>) -> Self {
+            Self {
+                inner,
+                timings: Some(timings),
+            }
+        }
+
+        fn finish(&mut self, outcome: &str) {
+            if let Some(timings) = self.timings.take() {
+                timings.finish();
+                tracing::debug!(parent: timings.span(), "Upstream response {outcome}");
+            }
+        }
+    }
+
+    impl Body for TimedBody {
+        type Data = Bytes;
+        type Error = hyper::Error;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+            let frame = Pin::new(&mut self.inner).poll_frame(cx);
+            match &frame {
+                Poll::Ready(None) => self.finish("complete"),
+                Poll::Ready(Some(Err(_))) => self.finish("failed"),
+                Poll::Ready(Some(Ok(_))) | Poll::Pending => {}
+            }
+            frame
+        }
+
+        fn is_end_stream(&self) -> bool {
+            self.inner.is_end_stream()
+        }
+
+        fn size_hint(&self) -> SizeHint {
+            self.inner.size_hint()
+        }
+    }
+
+    impl Drop for TimedBody {
+        fn drop(&mut self) {
+            self.finish("abandoned");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::{Arc, Mutex},
+    };
+
+    use hyper::Uri;
+    use tower::{ServiceExt as _, service_fn};
+    use tracing::{
+        Subscriber,
+        field::{Field, Visit},
+        span::{Id, Record},
+    };
+    use tracing_subscriber::{Layer, layer::Context, prelude::*};
+
+    use super::*;
+
+    /// Collects the fields recorded on spans.
+    #[derive(Clone, Default)]
+    struct RecordedFields(Arc<Mutex<Vec<String>>>);
+
+    impl Visit for RecordedFields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{}={value:?}", field.name()));
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for RecordedFields {
+        fn on_record(&self, _: &Id, values: &Record<'_>, _: Context<'_, S>) {
+            values.record(&mut self.clone());
+        }
+    }
+
+    fn recorded_names(fields: &RecordedFields) -> Vec<String> {
+        fields
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|field| field.split('=').next().unwrap().to_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn new_connection_records_every_phase() {
+        let fields = RecordedFields::default();
+        let _guard = tracing_subscriber::registry()
+            .with(fields.clone())
+            .set_default();
+        let tcp = TimedConnector::tcp(service_fn(|_: Uri| async {
+            if let Some(timings) = UpstreamTimings::current() {
+                timings.mark_resolved();
+            }
+            Ok::<_, Infallible>(())
+        }));
+        let tls = TimedConnector::tls(tcp);
+        let timings = UpstreamTimings::start();
+
+        timings
+            .scope(tls.oneshot(Uri::from_static("https://storeapi.kobo.com")))
+            .await
+            .unwrap();
+        timings.mark_first_byte();
+        timings.finish();
+
+        assert_eq!(
+            recorded_names(&fields),
+            vec![
+                "reused_connection",
+                "dns_ms",
+                "connect_ms",
+                "tls_ms",
+                "ttfb_ms",
+                "body_ms"
+            ]
+        );
+        assert!(
+            fields
+                .0
+                .lock()
+                .unwrap()
+                .contains(&"reused_connection=false".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn pooled_connection_records_only_response_phases() {
+        let fields = RecordedFields::default();
+        let _guard = tracing_subscriber::registry()
+            .with(fields.clone())
+            .set_default();
+        let timings = UpstreamTimings::start();
+
+        timings.mark_first_byte();
+        timings.finish();
+
+        assert_eq!(
+            recorded_names(&fields),
+            vec!["reused_connection", "ttfb_ms", "body_ms"]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Supervision of background tasks: restarts tasks that panic and records their
+//! health for `/readyz`.
+
+pub use implementation::{RestartPolicy, supervise};
+
+mod implementation {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::task::JoinHandle;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::state::background_tasks::{BackgroundTasks, TaskState};
+
+    /// Longest pause between restarts of a task that keeps panicking.
+    const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(300);
+
+    /// How a supervised task is restarted after it panics.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct RestartPolicy {
+        /// How many times the task is restarted before it is left dead.
+        pub max_restarts: u32,
+        /// The pause before the first restart, doubled after each further restart.
+        pub backoff: Duration,
+    }
+
+    /// Registers `name` in `tasks` and runs the task returned by `start`, restarting
+    /// it according to `policy` whenever it panics, until `cancellation_token` is
+    /// cancelled.
+    pub fn supervise<F, Fut>(
+        tasks: Arc<BackgroundTasks>,
+        name: &'static str,
+        critical: bool,
+        heartbeat_interval: Duration,
+        policy: RestartPolicy,
+        cancellation_token: CancellationToken,
+        start: F,
+    ) -> JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        tasks.register(name, critical, heartbeat_interval);
+        tokio::spawn(async move {
+            let mut backoff = policy.backoff;
+            let mut restarts = 0;
+            loop {
+                let reason = match tokio::spawn(start()).await {
+                    Ok(()) => {
+                        tasks.set_state(name, TaskState::Stopped);
+                        return;
+                    }
+                    Err(error) => error.to_string(),
+                };
+                if restarts >= policy.max_restarts {
+                    tracing::error!(
+                        task = name,
+                        critical,
+                        restarts,
+                        "Background task died and will not be restarted: {reason}"
+                    );
+                    tasks.set_state(name, TaskState::Dead(reason));
+                    return;
+                }
+                tracing::error!(
+                    task = name,
+                    critical,
+                    restarts,
+                    "Background task died, restarting in {backoff:?}: {reason}"
+                );
+                tasks.set_state(name, TaskState::Restarting(reason));
+                if cancellation_token
+                    .run_until_cancelled(tokio::time::sleep(backoff))
+                    .await
+                    .is_none()
+                {
+                    tasks.set_state(name, TaskState::Stopped);
+                    return;
+                }
+                restarts += 1;
+                backoff = (backoff * 2).min(MAX_RESTART_BACKOFF);
+                tasks.record_restart(name);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicU32, Ordering},
+        },
+        time::Duration,
+    };
+
+    use tokio_util::sync::CancellationToken;
+
+    use super::*;
+    use crate::state::background_tasks::{BackgroundTasks, TaskState};
+
+    const POLICY: RestartPolicy = RestartPolicy {
+        max_restarts: 2,
+        backoff: Duration::ZERO,
+    };
+
+    #[tokio::test]
+    #[expect(clippy::panic, reason = "the task panics to be restarted")]
+    async fn panicking_task_is_restarted_until_the_policy_gives_up() {
+        let tasks = Arc::new(BackgroundTasks::default());
+        let starts = Arc::new(AtomicU32::new(0));
+        let counter = starts.clone();
+
+        supervise(
+            tasks.clone(),
+            "flaky",
+            true,
+            Duration::from_secs(60),
+            POLICY,
+            CancellationToken::new(),
+            move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+                async { panic!("flaky task failed") }
+            },
+        )
+        .await
+        .unwrap();
+
+        let health = tasks.health();
+        assert_eq!(starts.load(Ordering::Relaxed), 3);
+        assert_eq!(health[0].restarts, 2);
+        assert!(matches!(health[0].state, TaskState::Dead(_)));
+        assert!(!tasks.is_ready());
+    }
+
+    #[tokio::test]
+    async fn finished_task_is_stopped() {
+        let tasks = Arc::new(BackgroundTasks::default());
+
+        supervise(
+            tasks.clone(),
+            "once",
+            false,
+            Duration::from_secs(60),
+            POLICY,
+            CancellationToken::new(),
+            || async {},
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(tasks.health()[0].state, TaskState::Stopped);
+    }
+}
@@ -0,0 +1,222 @@
+//! Time-based access rules, such as a bedtime for a child's device.
+//!
+//! Each rule blocks either store browsing or all requests for a device during a daily
+//! time window. Windows use the server's local time and may cross midnight; a window
+//! that starts and ends at the same time lasts all day.
+
+pub use implementation::AccessSchedule;
+
+mod implementation {
+    use std::cmp::Ordering;
+
+    use anyhow::{Context as _, Result, bail};
+    use chrono::NaiveTime;
+
+    /// Path prefixes of the store browsing endpoints.
+    const STORE_PATH_PREFIXES: &[&str] = &[
+        "/v1/products",
+        "/v1/categories",
+        "/v1/deals",
+        "/v1/user/recommendations",
+        "/v1/user/wishlist",
+    ];
+
+    /// Which requests a rule blocks.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum AccessScope {
+        /// Block store browsing, while library syncs keep working.
+        Store,
+        /// Block every request, including library syncs.
+        All,
+    }
+
+    impl AccessScope {
+        fn matches(self, path: &str) -> bool {
+            match self {
+                Self::Store => STORE_PATH_PREFIXES
+                    .iter()
+                    .any(|prefix| path.starts_with(prefix)),
+                Self::All => true,
+            }
+        }
+    }
+
+    /// A daily window during which a device's requests are blocked.
+    #[derive(Debug, PartialEq, Eq)]
+    struct AccessRule {
+        /// The device the rule applies to, or `None` for every device.
+        device_id: Option<String>,
+        start: NaiveTime,
+        end: NaiveTime,
+        scope: AccessScope,
+    }
+
+    impl AccessRule {
+        /// Parses a rule in `DEVICE=HH:MM-HH:MM[/SCOPE]` form.
+        fn parse(rule: &str) -> Result<Self> {
+            let Some((device_id, window)) = rule.split_once('=') else {
+                bail!("Access rule '{rule}' must be in DEVICE=HH:MM-HH:MM[/SCOPE] form");
+            };
+            let (window, scope) = match window.split_once('/') {
+                Some((window, "store")) => (window, AccessScope::Store),
+                Some((window, "all")) => (window, AccessScope::All),
+                None => (window, AccessScope::All),
+                Some((_, scope)) => {
+                    bail!("Unknown scope '{scope}' in access rule '{rule}', expected store or all")
+                }
+            };
+            let Some((start, end)) = window.split_once('-') else {
+                bail!("Access rule '{rule}' must have a HH:MM-HH:MM window");
+            };
+            let parse_time = |time: &str| {
+                NaiveTime::parse_from_str(time, "%H:%M")
+                    .with_context(|| format!("Invalid time '{time}' in access rule '{rule}'"))
+            };
+
+            Ok(Self {
+                device_id: match device_id {
+                    "" => bail!("Access rule '{rule}' has an empty device"),
+                    "*" => None,
+                    device_id => Some(device_id.to_owned()),
+                },
+                start: parse_time(start)?,
+                end: parse_time(end)?,
+                scope,
+            })
+        }
+
+        fn is_active(&self, now: NaiveTime) -> bool {
+            match self.start.cmp(&self.end) {
+                Ordering::Equal => true,
+                Ordering::Less => self.start <= now && now < self.end,
+                Ordering::Greater => now >= self.start || now < self.end,
+            }
+        }
+    }
+
+    /// The configured access rules.
+    #[derive(Debug, Default)]
+    pub struct AccessSchedule {
+        rules: Vec<AccessRule>,
+    }
+
+    impl AccessSchedule {
+        /// Creates a schedule from rules in `DEVICE=HH:MM-HH:MM[/SCOPE]` form, where
+        /// `DEVICE` is a device ID or `*` for every device, and `SCOPE` is `store` or
+        /// `all` (the default).
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if a rule is malformed.
+        pub fn new<S: AsRef<str>>(rules: &[S]) -> Result<Self> {
+            let rules = rules
+                .iter()
+                .map(|rule| AccessRule::parse(rule.as_ref()))
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(Self { rules })
+        }
+
+        /// Checks if any rules are configured.
+        pub fn is_empty(&self) -> bool {
+            self.rules.is_empty()
+        }
+
+        /// Returns when the block on a request ends, if an active rule blocks it.
+        pub fn blocked_until(
+            &self,
+            device_id: Option<&str>,
+            path: &str,
+            now: NaiveTime,
+        ) -> Option<NaiveTime> {
+            self.rules
+                .iter()
+                .filter(|rule| {
+                    rule.device_id
+                        .as_deref()
+                        .is_none_or(|rule_device_id| Some(rule_device_id) == device_id)
+                })
+                .find(|rule| rule.scope.matches(path) && rule.is_active(now))
+                .map(|rule| rule.end)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::*;
+
+    fn time(value: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(value, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn rules_block_inside_window_only() {
+        let schedule = AccessSchedule::new(&["kids-kobo=20:00-21:30"]).unwrap();
+
+        let blocked =
+            |now| schedule.blocked_until(Some("kids-kobo"), "/v1/library/sync", time(now));
+
+        assert_eq!(blocked("20:00"), Some(time("21:30")));
+        assert_eq!(blocked("21:29"), Some(time("21:30")));
+        assert_eq!(blocked("21:30"), None);
+        assert_eq!(blocked("19:59"), None);
+    }
+
+    #[test]
+    fn windows_may_cross_midnight() {
+        let schedule = AccessSchedule::new(&["*=21:00-07:00"]).unwrap();
+
+        assert!(
+            schedule
+                .blocked_until(Some("any"), "/v1/library/sync", time("23:00"))
+                .is_some()
+        );
+        assert!(
+            schedule
+                .blocked_until(None, "/v1/library/sync", time("06:59"))
+                .is_some()
+        );
+        assert!(
+            schedule
+                .blocked_until(None, "/v1/library/sync", time("12:00"))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn rules_only_apply_to_their_device() {
+        let schedule = AccessSchedule::new(&["kids-kobo=00:00-00:00"]).unwrap();
+
+        assert!(
+            schedule
+                .blocked_until(Some("parent-kobo"), "/v1/library/sync", time("12:00"))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn store_scope_only_blocks_store_browsing() {
+        let schedule = AccessSchedule::new(&["kids-kobo=00:00-00:00/store"]).unwrap();
+
+        let blocked = |path| schedule.blocked_until(Some("kids-kobo"), path, time("12:00"));
+
+        assert!(blocked("/v1/products/featured").is_some());
+        assert!(blocked("/v1/library/sync").is_none());
+    }
+
+    #[test]
+    fn new_rejects_malformed_rules() {
+        for rule in [
+            "kids-kobo",
+            "=20:00-21:00",
+            "kids-kobo=20:00",
+            "kids-kobo=25:00-21:00",
+            "kids-kobo=20:00-21:00/sync",
+        ] {
+            assert!(AccessSchedule::new(&[rule]).is_err(), "{rule}");
+        }
+    }
+}
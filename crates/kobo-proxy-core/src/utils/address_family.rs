@@ -0,0 +1,194 @@
+//! Address family preference for connections to the Kobo store API.
+//!
+//! The upstream connector races IPv6 and IPv4 connection attempts (RFC 8305 happy
+//! eyeballs), starting with the family of the first resolved address. Reordering or
+//! filtering resolved addresses lets users prefer, or restrict to, one family when an
+//! ISP's IPv6 or IPv4 path to the store is unreliable.
+
+pub use implementation::{AddressFamily, FamilyResolver};
+
+mod implementation {
+    use std::{
+        fmt, io,
+        net::SocketAddr,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use hyper_util::client::legacy::connect::dns::Name;
+    use tower::Service;
+
+    use crate::{state::upstream_timing::UpstreamTimings, utils::dns_resolver::UpstreamResolver};
+
+    /// Which address family upstream connections prefer.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum AddressFamily {
+        /// Use addresses in the order the system resolver returns them.
+        #[default]
+        Auto,
+        /// Try IPv4 addresses first, falling back to IPv6.
+        PreferIpv4,
+        /// Try IPv6 addresses first, falling back to IPv4.
+        PreferIpv6,
+        /// Only connect over IPv4.
+        Ipv4Only,
+        /// Only connect over IPv6.
+        Ipv6Only,
+    }
+
+    impl fmt::Display for AddressFamily {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::Auto => write!(f, "auto"),
+                Self::PreferIpv4 => write!(f, "prefer-ipv4"),
+                Self::PreferIpv6 => write!(f, "prefer-ipv6"),
+                Self::Ipv4Only => write!(f, "ipv4-only"),
+                Self::Ipv6Only => write!(f, "ipv6-only"),
+            }
+        }
+    }
+
+    impl AddressFamily {
+        /// Orders or filters resolved addresses according to the preference.
+        pub fn apply<I: IntoIterator<Item = SocketAddr>>(self, addresses: I) -> Vec<SocketAddr> {
+            let mut addresses: Vec<_> = addresses.into_iter().collect();
+            match self {
+                Self::Auto => {}
+                Self::PreferIpv4 => addresses.sort_by_key(SocketAddr::is_ipv6),
+                Self::PreferIpv6 => addresses.sort_by_key(SocketAddr::is_ipv4),
+                Self::Ipv4Only => addresses.retain(SocketAddr::is_ipv4),
+                Self::Ipv6Only => addresses.retain(SocketAddr::is_ipv6),
+            }
+            addresses
+        }
+    }
+
+    /// A DNS resolver that applies an [`AddressFamily`] preference to the addresses
+    /// returned by an [`UpstreamResolver`].
+    #[derive(Clone, Debug, Default)]
+    pub struct FamilyResolver {
+        inner: UpstreamResolver,
+        address_family: AddressFamily,
+    }
+
+    impl FamilyResolver {
+        /// Creates a resolver applying `address_family` to addresses resolved by `inner`.
+        pub fn new(inner: UpstreamResolver, address_family: AddressFamily) -> Self {
+            Self {
+                inner,
+                address_family,
+            }
+        }
+
+        /// Whether answers are cached by the underlying resolver.
+        pub fn is_cached(&self) -> bool {
+            self.inner.is_cached()
+        }
+    }
+
+    impl Service<Name> for FamilyResolver {
+        type Response = std::vec::IntoIter<SocketAddr>;
+        type Error = io::Error;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, name: Name) -> Self::Future {
+            let address_family = self.address_family;
+            let timings = UpstreamTimings::current();
+            let inner = self.inner.clone();
+            Box::pin(async move {
+                let resolved = inner.resolve(name.clone()).await?;
+                if let Some(timings) = timings {
+                    timings.mark_resolved();
+                }
+                let addresses = address_family.apply(resolved);
+                if addresses.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrNotAvailable,
+                        format!("No {address_family} address found for {name}"),
+                    ));
+                }
+                Ok(addresses.into_iter())
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+        str::FromStr as _,
+    };
+
+    use hyper_util::client::legacy::connect::dns::Name;
+    use tower::ServiceExt as _;
+
+    use super::*;
+    use crate::utils::dns_resolver::UpstreamResolver;
+
+    fn addresses() -> Vec<SocketAddr> {
+        vec![
+            SocketAddr::from((Ipv6Addr::LOCALHOST, 443)),
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 443)),
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 443)),
+        ]
+    }
+
+    #[test]
+    fn auto_keeps_resolver_order() {
+        assert_eq!(AddressFamily::Auto.apply(addresses()), addresses());
+    }
+
+    #[test]
+    fn prefer_ipv4_moves_ipv4_first_and_keeps_ipv6() {
+        let ordered = AddressFamily::PreferIpv4.apply(addresses());
+
+        assert_eq!(ordered[0], SocketAddr::from((Ipv4Addr::LOCALHOST, 443)));
+        assert_eq!(ordered.len(), 3);
+    }
+
+    #[test]
+    fn prefer_ipv6_keeps_ipv6_first() {
+        let ordered = AddressFamily::PreferIpv6.apply(addresses());
+
+        assert!(ordered[0].is_ipv6());
+        assert!(ordered[2].is_ipv4());
+    }
+
+    #[test]
+    fn only_variants_filter_addresses() {
+        assert!(
+            AddressFamily::Ipv4Only
+                .apply(addresses())
+                .iter()
+                .all(SocketAddr::is_ipv4)
+        );
+        assert_eq!(AddressFamily::Ipv6Only.apply(addresses()).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn resolver_fails_when_no_address_matches() {
+        let resolver = FamilyResolver::new(UpstreamResolver::default(), AddressFamily::Ipv6Only);
+
+        let result = resolver.oneshot(Name::from_str("127.0.0.1").unwrap()).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn resolver_returns_matching_addresses() {
+        let resolver = FamilyResolver::new(UpstreamResolver::default(), AddressFamily::Ipv4Only);
+
+        let addresses: Vec<_> = resolver
+            .oneshot(Name::from_str("127.0.0.1").unwrap())
+            .await
+            .unwrap()
+            .collect();
+
+        assert_eq!(addresses, [SocketAddr::from((Ipv4Addr::LOCALHOST, 0))]);
+    }
+}
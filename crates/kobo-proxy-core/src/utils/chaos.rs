@@ -0,0 +1,191 @@
+//! Fault injection rules for chaos testing.
+//!
+//! Developers can make the proxy misbehave on purpose to see how devices cope with
+//! a slow or failing proxy or upstream. Each rule injects a fault into requests for a
+//! route with a given probability.
+
+pub use implementation::{ChaosFault, ChaosRules};
+
+mod implementation {
+    use std::time::Duration;
+
+    use anyhow::{Context as _, Result, bail};
+    use hyper::StatusCode;
+    use rand::Rng;
+
+    /// A fault injected into a request.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ChaosFault {
+        /// Delay the request before handling it.
+        Delay(Duration),
+        /// Abort the connection without a complete response.
+        Drop,
+        /// Respond with a server error instead of handling the request.
+        Status(StatusCode),
+    }
+
+    impl ChaosFault {
+        /// Parses a fault in `delay=MS`, `drop`, or `status=CODE` form.
+        fn parse(fault: &str) -> Result<Self> {
+            match fault.split_once('=') {
+                None if fault == "drop" => Ok(Self::Drop),
+                Some(("delay", milliseconds)) => {
+                    let milliseconds = milliseconds
+                        .parse()
+                        .with_context(|| format!("Invalid delay in chaos fault '{fault}'"))?;
+                    Ok(Self::Delay(Duration::from_millis(milliseconds)))
+                }
+                Some(("status", code)) => {
+                    let status = code
+                        .parse::<u16>()
+                        .ok()
+                        .and_then(|code| StatusCode::from_u16(code).ok())
+                        .filter(StatusCode::is_server_error)
+                        .with_context(|| {
+                            format!("Chaos fault '{fault}' must use a 5xx status code")
+                        })?;
+                    Ok(Self::Status(status))
+                }
+                _ => {
+                    bail!("Unknown chaos fault '{fault}', expected delay=MS, drop, or status=CODE")
+                }
+            }
+        }
+    }
+
+    /// A fault injected into requests for a route with some probability.
+    #[derive(Debug, PartialEq)]
+    struct ChaosRule {
+        /// The route template the rule applies to, or `None` for every route.
+        route: Option<String>,
+        probability: f64,
+        fault: ChaosFault,
+    }
+
+    impl ChaosRule {
+        /// Parses a rule in `ROUTE=PROBABILITY:FAULT` form.
+        fn parse(rule: &str) -> Result<Self> {
+            let Some((route, rest)) = rule.split_once('=') else {
+                bail!("Chaos rule '{rule}' must be in ROUTE=PROBABILITY:FAULT form");
+            };
+            let Some((probability, fault)) = rest.split_once(':') else {
+                bail!("Chaos rule '{rule}' must be in ROUTE=PROBABILITY:FAULT form");
+            };
+            let probability = probability
+                .parse::<f64>()
+                .ok()
+                .filter(|probability| (0.0..=1.0).contains(probability))
+                .with_context(|| {
+                    format!("Chaos rule '{rule}' must have a probability between 0 and 1")
+                })?;
+
+            Ok(Self {
+                route: match route {
+                    "" => bail!("Chaos rule '{rule}' has an empty route"),
+                    "*" => None,
+                    route => Some(route.to_owned()),
+                },
+                probability,
+                fault: ChaosFault::parse(fault)?,
+            })
+        }
+    }
+
+    /// The configured chaos rules.
+    #[derive(Debug, Default)]
+    pub struct ChaosRules {
+        rules: Vec<ChaosRule>,
+    }
+
+    impl ChaosRules {
+        /// Creates rules in `ROUTE=PROBABILITY:FAULT` form, where `ROUTE` is a route
+        /// template or `*` for every route, `PROBABILITY` is between 0 and 1, and
+        /// `FAULT` is `delay=MS`, `drop`, or `status=CODE` with a 5xx code.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if a rule is malformed.
+        pub fn new<S: AsRef<str>>(rules: &[S]) -> Result<Self> {
+            let rules = rules
+                .iter()
+                .map(|rule| ChaosRule::parse(rule.as_ref()))
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(Self { rules })
+        }
+
+        /// Checks if any rules are configured.
+        pub fn is_empty(&self) -> bool {
+            self.rules.is_empty()
+        }
+
+        /// Rolls each rule for `route` and returns the faults to inject, in rule order.
+        pub fn faults_for<R: Rng + ?Sized>(&self, route: &str, rng: &mut R) -> Vec<ChaosFault> {
+            self.rules
+                .iter()
+                .filter(|rule| {
+                    rule.route
+                        .as_deref()
+                        .is_none_or(|rule_route| rule_route == route)
+                })
+                .filter(|rule| rng.random_bool(rule.probability))
+                .map(|rule| rule.fault)
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hyper::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn faults_for_applies_matching_rules_in_order() {
+        let rules = ChaosRules::new(&[
+            "/v1/library/sync=1:delay=250",
+            "*=1:status=503",
+            "/v1/initialization=1:drop",
+        ])
+        .unwrap();
+
+        let faults = rules.faults_for("/v1/library/sync", &mut rand::rng());
+
+        assert_eq!(
+            faults,
+            [
+                ChaosFault::Delay(Duration::from_millis(250)),
+                ChaosFault::Status(StatusCode::SERVICE_UNAVAILABLE),
+            ]
+        );
+    }
+
+    #[test]
+    fn faults_for_skips_rules_with_zero_probability() {
+        let rules = ChaosRules::new(&["*=0:drop"]).unwrap();
+
+        assert!(
+            rules
+                .faults_for("/v1/library/sync", &mut rand::rng())
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn new_rejects_malformed_rules() {
+        for rule in [
+            "/v1/library/sync",
+            "/v1/library/sync=0.5",
+            "=0.5:drop",
+            "*=1.5:drop",
+            "*=0.5:status=404",
+            "*=0.5:delay=soon",
+            "*=0.5:explode",
+        ] {
+            assert!(ChaosRules::new(&[rule]).is_err(), "{rule}");
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! How `Set-Cookie` headers from the Kobo store API are handled.
+//!
+//! Kobo devices keep cookies set by the store, and some endpoints behave differently
+//! when a session cookie is sent back. The proxy can forward cookies untouched, drop
+//! them, or keep them itself and replay them on the device's later requests.
+
+pub use implementation::CookiePolicy;
+
+mod implementation {
+    use std::fmt;
+
+    /// How `Set-Cookie` headers in upstream responses are handled.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum CookiePolicy {
+        /// Forward `Set-Cookie` headers to the device unchanged.
+        #[default]
+        Pass,
+        /// Remove `Set-Cookie` headers from responses.
+        Strip,
+        /// Remove `Set-Cookie` headers from responses, storing the cookies per session
+        /// token and sending them upstream with later requests using the same token.
+        Store,
+    }
+
+    impl fmt::Display for CookiePolicy {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::Pass => write!(f, "pass"),
+                Self::Strip => write!(f, "strip"),
+                Self::Store => write!(f, "store"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_policies_in_lowercase() {
+        assert_eq!(CookiePolicy::Pass.to_string(), "pass");
+        assert_eq!(CookiePolicy::Strip.to_string(), "strip");
+        assert_eq!(CookiePolicy::Store.to_string(), "store");
+    }
+}
@@ -0,0 +1,95 @@
+//! Per-device frontend URL overrides.
+//!
+//! Devices are pointed at the proxy by rewriting Kobo API URLs to the frontend URL.
+//! Households with several networks may need a different URL per device, e.g. a LAN
+//! address for one device and a Tailscale hostname for another that roams.
+
+pub use implementation::DeviceFrontendUrls;
+
+mod implementation {
+    use std::collections::HashMap;
+
+    use anyhow::{Context as _, Result, bail};
+    use hyper::Uri;
+
+    /// Frontend URLs that replace the default for specific devices.
+    #[derive(Debug, Default)]
+    pub struct DeviceFrontendUrls {
+        urls: HashMap<String, String>,
+    }
+
+    impl DeviceFrontendUrls {
+        /// Creates overrides from mappings in `DEVICE=URL` form, where `DEVICE` is a
+        /// device ID as listed by `/api/devices`.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if a mapping is not in `DEVICE=URL` form, or the URL has no
+        /// scheme or host.
+        pub fn new<S: AsRef<str>>(mappings: &[S]) -> Result<Self> {
+            let urls = mappings
+                .iter()
+                .map(|mapping| {
+                    let mapping = mapping.as_ref();
+                    let Some((device_id, url)) = mapping.split_once('=') else {
+                        bail!("Device frontend URL '{mapping}' must be in DEVICE=URL form");
+                    };
+                    if device_id.is_empty() {
+                        bail!("Device frontend URL '{mapping}' has an empty device");
+                    }
+                    let uri: Uri = url
+                        .parse()
+                        .with_context(|| format!("Invalid frontend URL in '{mapping}'"))?;
+                    if uri.scheme().is_none() || uri.authority().is_none() {
+                        bail!("Frontend URL in '{mapping}' must include a scheme and host");
+                    }
+                    Ok((device_id.to_owned(), url.trim_end_matches('/').to_owned()))
+                })
+                .collect::<Result<HashMap<_, _>>>()?;
+
+            Ok(Self { urls })
+        }
+
+        /// Returns the frontend URL configured for a device, if any.
+        pub fn get(&self, device_id: Option<&str>) -> Option<&str> {
+            self.urls.get(device_id?).map(String::as_str)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_returns_configured_url() {
+        let urls = DeviceFrontendUrls::new(&[
+            "kobo-lan=http://192.168.1.10:8080",
+            "kobo-travel=https://kobo.tailnet.ts.net/",
+        ])
+        .unwrap();
+
+        assert_eq!(urls.get(Some("kobo-lan")), Some("http://192.168.1.10:8080"));
+        assert_eq!(
+            urls.get(Some("kobo-travel")),
+            Some("https://kobo.tailnet.ts.net")
+        );
+        assert_eq!(urls.get(Some("other")), None);
+        assert_eq!(urls.get(None), None);
+    }
+
+    #[test]
+    fn new_rejects_mapping_without_url() {
+        assert!(DeviceFrontendUrls::new(&["kobo-lan"]).is_err());
+    }
+
+    #[test]
+    fn new_rejects_mapping_without_device() {
+        assert!(DeviceFrontendUrls::new(&["=http://192.168.1.10"]).is_err());
+    }
+
+    #[test]
+    fn new_rejects_url_without_scheme() {
+        assert!(DeviceFrontendUrls::new(&["kobo-lan=192.168.1.10:8080"]).is_err());
+    }
+}
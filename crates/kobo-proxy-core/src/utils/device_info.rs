@@ -0,0 +1,154 @@
+//! Parsing of the device model and firmware version from Kobo `User-Agent` headers.
+//!
+//! Kobo e-readers end their `User-Agent` with `(Kobo Touch PRODUCT/FIRMWARE)`, e.g.
+//! `(Kobo Touch 0388/4.38.21908)` for a Libra 2 running firmware 4.38.21908.
+
+pub use implementation::{DeviceInfo, FirmwareVersion};
+
+mod implementation {
+    use std::{fmt, str::FromStr};
+
+    use anyhow::{Context as _, Result, bail};
+    use serde::{Serialize, Serializer};
+
+    /// Marker preceding the product ID and firmware version in the `User-Agent`.
+    const USER_AGENT_MARKER: &str = "(Kobo Touch ";
+
+    /// Model names by product ID, without leading zeros.
+    const MODELS: &[(&str, &str)] = &[
+        ("310", "Kobo Touch"),
+        ("320", "Kobo Touch"),
+        ("330", "Kobo Glo"),
+        ("340", "Kobo Mini"),
+        ("350", "Kobo Aura HD"),
+        ("360", "Kobo Aura"),
+        ("370", "Kobo Aura H2O"),
+        ("371", "Kobo Glo HD"),
+        ("372", "Kobo Touch 2.0"),
+        ("373", "Kobo Aura ONE"),
+        ("374", "Kobo Aura H2O Edition 2"),
+        ("375", "Kobo Aura Edition 2"),
+        ("376", "Kobo Clara HD"),
+        ("377", "Kobo Forma"),
+        ("380", "Kobo Forma"),
+        ("381", "Kobo Aura ONE Limited Edition"),
+        ("382", "Kobo Nia"),
+        ("383", "Kobo Sage"),
+        ("384", "Kobo Libra H2O"),
+        ("386", "Kobo Clara 2E"),
+        ("387", "Kobo Elipsa"),
+        ("388", "Kobo Libra 2"),
+        ("389", "Kobo Elipsa 2E"),
+        ("390", "Kobo Libra Colour"),
+        ("391", "Kobo Clara BW"),
+        ("393", "Kobo Clara Colour"),
+    ];
+
+    /// A dotted firmware version such as `4.38.21908`, ordered component by component.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    pub struct FirmwareVersion(Vec<u32>);
+
+    impl FromStr for FirmwareVersion {
+        type Err = anyhow::Error;
+
+        fn from_str(version: &str) -> Result<Self> {
+            if version.is_empty() {
+                bail!("Firmware version is empty");
+            }
+            let components = version
+                .split('.')
+                .map(|component| {
+                    component
+                        .parse()
+                        .with_context(|| format!("Invalid firmware version '{version}'"))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Self(components))
+        }
+    }
+
+    impl fmt::Display for FirmwareVersion {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let components = self.0.iter().map(u32::to_string).collect::<Vec<_>>();
+            f.write_str(&components.join("."))
+        }
+    }
+
+    impl Serialize for FirmwareVersion {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(self)
+        }
+    }
+
+    /// The model and firmware of a Kobo e-reader.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+    pub struct DeviceInfo {
+        /// The product ID, e.g. `0388`
+        pub product_id: String,
+        /// The model name, if the product ID is known
+        pub model: Option<&'static str>,
+        /// The firmware version
+        pub firmware: FirmwareVersion,
+    }
+
+    impl DeviceInfo {
+        /// Parses the device information from a Kobo `User-Agent`. Returns `None` if
+        /// the `User-Agent` is not from a Kobo e-reader.
+        pub fn parse(user_agent: &str) -> Option<Self> {
+            let start = user_agent.find(USER_AGENT_MARKER)? + USER_AGENT_MARKER.len();
+            let rest = &user_agent[start..];
+            let (product_id, firmware) = rest[..rest.find(')')?].trim().split_once('/')?;
+            let model = MODELS
+                .iter()
+                .find(|(id, _)| *id == product_id.trim_start_matches('0'))
+                .map(|(_, model)| *model);
+
+            Some(Self {
+                product_id: product_id.to_owned(),
+                model,
+                firmware: firmware.parse().ok()?,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRA_2_USER_AGENT: &str = "Mozilla/5.0 (Linux; U; Android 2.0; en-us;) \
+        AppleWebKit/538.1 (KHTML, like Gecko) Version/4.0 Mobile Safari/538.1 \
+        (Kobo Touch 0388/4.38.21908)";
+
+    #[test]
+    fn parse_reads_model_and_firmware() {
+        let info = DeviceInfo::parse(LIBRA_2_USER_AGENT).unwrap();
+
+        assert_eq!(info.product_id, "0388");
+        assert_eq!(info.model, Some("Kobo Libra 2"));
+        assert_eq!(info.firmware.to_string(), "4.38.21908");
+    }
+
+    #[test]
+    fn parse_keeps_unknown_product_ids() {
+        let info = DeviceInfo::parse("Mozilla/5.0 (Kobo Touch 0999/5.1.1)").unwrap();
+
+        assert_eq!(info.product_id, "0999");
+        assert_eq!(info.model, None);
+    }
+
+    #[test]
+    fn parse_ignores_other_user_agents() {
+        assert_eq!(DeviceInfo::parse("curl/8.5.0"), None);
+        assert_eq!(DeviceInfo::parse("Mozilla/5.0 (Kobo Touch 0388)"), None);
+    }
+
+    #[test]
+    fn firmware_versions_compare_numerically() {
+        let parse = |version: &str| version.parse::<FirmwareVersion>().unwrap();
+
+        assert!(parse("4.9.1") < parse("4.38.21908"));
+        assert!(parse("4.38") < parse("4.38.1"));
+        assert!("4.x".parse::<FirmwareVersion>().is_err());
+    }
+}
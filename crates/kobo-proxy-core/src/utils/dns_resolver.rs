@@ -0,0 +1,251 @@
+//! DNS resolution for connections to the Kobo store API.
+//!
+//! By default the store API host is resolved with the system's `getaddrinfo` on every
+//! new connection, which adds noticeable latency behind some home routers. Configuring
+//! upstream DNS switches to an asynchronous resolver that caches answers for as long
+//! as their TTL allows, and can query specific DNS servers over plain DNS,
+//! DNS-over-TLS, or DNS-over-HTTPS. The cached resolver needs the `upstream-dns`
+//! feature.
+
+pub use implementation::UpstreamResolver;
+
+mod implementation {
+    use std::{io, net::SocketAddr};
+    #[cfg(feature = "upstream-dns")]
+    use std::{net::IpAddr, sync::Arc};
+
+    #[cfg(feature = "upstream-dns")]
+    use anyhow::Context as _;
+    use anyhow::{Result, bail};
+    #[cfg(feature = "upstream-dns")]
+    use hickory_resolver::{
+        TokioResolver,
+        config::{LookupIpStrategy, NameServerConfigGroup, ResolverConfig},
+        name_server::TokioConnectionProvider,
+    };
+    use hyper_util::client::legacy::connect::dns::{GaiResolver, Name};
+    use tower::ServiceExt as _;
+
+    /// Entry selecting the system's DNS configuration with caching.
+    #[cfg(feature = "upstream-dns")]
+    const SYSTEM_ENTRY: &str = "system";
+
+    /// Default port for plain DNS servers.
+    #[cfg(feature = "upstream-dns")]
+    const DNS_PORT: u16 = 53;
+
+    /// Default port for DNS-over-TLS servers.
+    #[cfg(feature = "upstream-dns")]
+    const DNS_OVER_TLS_PORT: u16 = 853;
+
+    /// Default port for DNS-over-HTTPS servers.
+    #[cfg(feature = "upstream-dns")]
+    const DNS_OVER_HTTPS_PORT: u16 = 443;
+
+    /// How the Kobo store API host is resolved.
+    #[derive(Clone, Debug, Default)]
+    pub enum UpstreamResolver {
+        /// Resolve with the system's `getaddrinfo` on every connection.
+        #[default]
+        Getaddrinfo,
+        /// Resolve with a caching asynchronous resolver.
+        #[cfg(feature = "upstream-dns")]
+        Cached(Arc<TokioResolver>),
+    }
+
+    impl UpstreamResolver {
+        /// Creates a resolver from upstream DNS entries. No entries keeps `getaddrinfo`,
+        /// `system` caches answers from the system's configured DNS servers, and any
+        /// other entry is a DNS server in `[SCHEME://]IP[:PORT][#NAME]` form. SCHEME is
+        /// `udp` (default), `tls`, or `https`; encrypted servers need the NAME their
+        /// certificate is issued for, e.g. `tls://1.1.1.1#cloudflare-dns.com`.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if an entry is invalid, if `system` is mixed with DNS
+        /// servers, if the system's DNS configuration cannot be read, or if the
+        /// `upstream-dns` feature is disabled.
+        pub fn new<S: AsRef<str>>(entries: &[S]) -> Result<Self> {
+            let entries: Vec<&str> = entries.iter().map(|entry| entry.as_ref().trim()).collect();
+            if entries.is_empty() {
+                return Ok(Self::Getaddrinfo);
+            }
+            Self::cached(&entries)
+        }
+
+        /// Creates a caching resolver from non-empty upstream DNS entries.
+        #[cfg(feature = "upstream-dns")]
+        fn cached(entries: &[&str]) -> Result<Self> {
+            let mut builder = if entries.contains(&SYSTEM_ENTRY) {
+                if entries.len() > 1 {
+                    bail!("Upstream DNS '{SYSTEM_ENTRY}' cannot be combined with DNS servers");
+                }
+                TokioResolver::builder_tokio()
+                    .context("Failed to read the system DNS configuration")?
+            } else {
+                let mut name_servers = NameServerConfigGroup::new();
+                for entry in entries {
+                    name_servers.merge(parse_name_server(entry)?);
+                }
+                TokioResolver::builder_with_config(
+                    ResolverConfig::from_parts(None, vec![], name_servers),
+                    TokioConnectionProvider::default(),
+                )
+            };
+            // Both families are looked up so the address family preference still applies.
+            builder.options_mut().ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+
+            Ok(Self::Cached(Arc::new(builder.build())))
+        }
+
+        /// Rejects upstream DNS entries, since caching needs the `upstream-dns` feature.
+        #[cfg(not(feature = "upstream-dns"))]
+        fn cached(_entries: &[&str]) -> Result<Self> {
+            bail!("Upstream DNS needs the `upstream-dns` feature")
+        }
+
+        /// Whether answers are cached by this resolver.
+        pub fn is_cached(&self) -> bool {
+            !matches!(self, Self::Getaddrinfo)
+        }
+
+        /// Resolves `name` to addresses with port 0; the connector fills in the port.
+        pub async fn resolve(&self, name: Name) -> io::Result<Vec<SocketAddr>> {
+            match self {
+                Self::Getaddrinfo => Ok(GaiResolver::new().oneshot(name).await?.collect()),
+                #[cfg(feature = "upstream-dns")]
+                Self::Cached(resolver) => {
+                    let lookup = resolver
+                        .lookup_ip(name.as_str())
+                        .await
+                        .map_err(io::Error::other)?;
+                    Ok(lookup.iter().map(|ip| SocketAddr::new(ip, 0)).collect())
+                }
+            }
+        }
+    }
+
+    /// Parses a DNS server entry in `[SCHEME://]IP[:PORT][#NAME]` form.
+    #[cfg(feature = "upstream-dns")]
+    pub fn parse_name_server(entry: &str) -> Result<NameServerConfigGroup> {
+        let (scheme, server) = entry.split_once("://").unwrap_or(("udp", entry));
+        let (address, tls_name) = match server.split_once('#') {
+            Some((address, tls_name)) => (address, Some(tls_name.to_owned())),
+            None => (server, None),
+        };
+        let default_port = match scheme {
+            "udp" => DNS_PORT,
+            "tls" => DNS_OVER_TLS_PORT,
+            "https" => DNS_OVER_HTTPS_PORT,
+            _ => bail!("Unknown upstream DNS scheme '{scheme}', expected udp, tls, or https"),
+        };
+        let address = address
+            .parse::<SocketAddr>()
+            .or_else(|_| {
+                address
+                    .parse::<IpAddr>()
+                    .map(|ip| SocketAddr::new(ip, default_port))
+            })
+            .with_context(|| format!("Invalid upstream DNS server '{entry}'"))?;
+
+        let ips = [address.ip()];
+        Ok(match (scheme, tls_name) {
+            ("udp", None) => NameServerConfigGroup::from_ips_clear(&ips, address.port(), true),
+            ("udp", Some(_)) => bail!("Upstream DNS server '{entry}' does not use TLS"),
+            ("tls", Some(name)) => {
+                NameServerConfigGroup::from_ips_tls(&ips, address.port(), name, true)
+            }
+            (_, Some(name)) => {
+                NameServerConfigGroup::from_ips_https(&ips, address.port(), name, true)
+            }
+            (_, None) => bail!(
+                "Upstream DNS server '{entry}' needs the name its certificate is issued \
+                 for, e.g. '{entry}#dns.example.com'"
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "upstream-dns")]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    use hickory_resolver::proto::xfer::Protocol;
+
+    use super::{implementation::parse_name_server, *};
+
+    #[test]
+    fn plain_server_defaults_to_port_53_over_udp_and_tcp() {
+        let group = parse_name_server("192.168.1.1").unwrap();
+
+        assert_eq!(group.len(), 2);
+        assert!(
+            group.iter().all(|server| server.socket_addr
+                == SocketAddr::from((Ipv4Addr::new(192, 168, 1, 1), 53)))
+        );
+    }
+
+    #[test]
+    fn server_port_can_be_overridden() {
+        let group = parse_name_server("udp://[::1]:5353").unwrap();
+
+        assert_eq!(
+            group[0].socket_addr,
+            SocketAddr::from((Ipv6Addr::LOCALHOST, 5353))
+        );
+    }
+
+    #[test]
+    fn tls_server_uses_port_853_and_certificate_name() {
+        let group = parse_name_server("tls://1.1.1.1#cloudflare-dns.com").unwrap();
+
+        assert_eq!(group.len(), 1);
+        assert_eq!(group[0].protocol, Protocol::Tls);
+        assert_eq!(group[0].socket_addr.port(), 853);
+        assert_eq!(group[0].tls_dns_name.as_deref(), Some("cloudflare-dns.com"));
+    }
+
+    #[test]
+    fn https_server_uses_port_443() {
+        let group = parse_name_server("https://9.9.9.9#dns.quad9.net").unwrap();
+
+        assert_eq!(group[0].protocol, Protocol::Https);
+        assert_eq!(group[0].socket_addr.port(), 443);
+    }
+
+    #[test]
+    fn invalid_servers_are_rejected() {
+        for entry in [
+            "dns.example.com",
+            "tls://1.1.1.1",
+            "udp://1.1.1.1#one.one.one.one",
+            "quic://1.1.1.1#one.one.one.one",
+        ] {
+            assert!(parse_name_server(entry).is_err(), "{entry}");
+        }
+    }
+
+    #[test]
+    fn system_cannot_be_combined_with_servers() {
+        assert!(UpstreamResolver::new(&["system", "1.1.1.1"]).is_err());
+    }
+
+    #[test]
+    fn no_entries_keep_getaddrinfo() {
+        assert!(!UpstreamResolver::new::<&str>(&[]).unwrap().is_cached());
+    }
+
+    #[tokio::test]
+    async fn cached_resolver_resolves_ip_literals() {
+        let resolver = UpstreamResolver::new(&["127.0.0.1"]).unwrap();
+
+        let addresses = resolver
+            .resolve("127.0.0.1".parse().unwrap())
+            .await
+            .unwrap();
+
+        assert!(resolver.is_cached());
+        assert_eq!(addresses, [SocketAddr::from((Ipv4Addr::LOCALHOST, 0))]);
+    }
+}
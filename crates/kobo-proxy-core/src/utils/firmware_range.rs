@@ -0,0 +1,130 @@
+//! Firmware version ranges that enable device workarounds.
+//!
+//! Workarounds for bugs in old firmware can degrade newer devices, so each one can
+//! be limited to a range of firmware versions, e.g. `<4.38` or `>=4.20 <4.38`.
+
+pub use implementation::FirmwareRange;
+
+mod implementation {
+    use std::{cmp::Ordering, str::FromStr};
+
+    use anyhow::{Result, bail};
+
+    use crate::utils::device_info::FirmwareVersion;
+
+    /// A single comparison against a firmware version.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Comparator {
+        /// The orderings of the device firmware relative to `version` that match.
+        accepted: &'static [Ordering],
+        version: FirmwareVersion,
+    }
+
+    impl Comparator {
+        fn parse(comparator: &str) -> Result<Self> {
+            let (accepted, version): (&'static [Ordering], _) =
+                if let Some(version) = comparator.strip_prefix(">=") {
+                    (&[Ordering::Greater, Ordering::Equal], version)
+                } else if let Some(version) = comparator.strip_prefix("<=") {
+                    (&[Ordering::Less, Ordering::Equal], version)
+                } else if let Some(version) = comparator.strip_prefix('>') {
+                    (&[Ordering::Greater], version)
+                } else if let Some(version) = comparator.strip_prefix('<') {
+                    (&[Ordering::Less], version)
+                } else if let Some(version) = comparator.strip_prefix('=') {
+                    (&[Ordering::Equal], version)
+                } else {
+                    bail!("Firmware comparator '{comparator}' must start with <, <=, >, >=, or =");
+                };
+
+            Ok(Self {
+                accepted,
+                version: version.parse()?,
+            })
+        }
+
+        fn matches(&self, firmware: &FirmwareVersion) -> bool {
+            self.accepted.contains(&firmware.cmp(&self.version))
+        }
+    }
+
+    /// A range of firmware versions, matching versions that satisfy every comparator.
+    /// The default range matches every version.
+    #[derive(Clone, Debug, Default, PartialEq, Eq)]
+    pub struct FirmwareRange {
+        comparators: Vec<Comparator>,
+    }
+
+    impl FromStr for FirmwareRange {
+        type Err = anyhow::Error;
+
+        /// Parses space-separated comparators such as `>=4.20 <4.38`, or `*` for every
+        /// version.
+        fn from_str(range: &str) -> Result<Self> {
+            if range.trim() == "*" {
+                return Ok(Self::default());
+            }
+            let comparators = range
+                .split_whitespace()
+                .map(Comparator::parse)
+                .collect::<Result<Vec<_>>>()?;
+            if comparators.is_empty() {
+                bail!("Firmware range is empty, use * for every version");
+            }
+            Ok(Self { comparators })
+        }
+    }
+
+    impl FirmwareRange {
+        /// Checks if a device's firmware is in the range. Devices with unknown firmware
+        /// are always in the range, so workarounds stay enabled for them.
+        pub fn matches(&self, firmware: Option<&FirmwareVersion>) -> bool {
+            firmware.is_none_or(|firmware| {
+                self.comparators
+                    .iter()
+                    .all(|comparator| comparator.matches(firmware))
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::device_info::FirmwareVersion;
+
+    fn firmware(version: &str) -> FirmwareVersion {
+        version.parse().unwrap()
+    }
+
+    #[test]
+    fn range_matches_versions_satisfying_every_comparator() {
+        let range: FirmwareRange = ">=4.20 <4.38".parse().unwrap();
+
+        assert!(range.matches(Some(&firmware("4.20"))));
+        assert!(range.matches(Some(&firmware("4.37.21586"))));
+        assert!(!range.matches(Some(&firmware("4.38.21908"))));
+        assert!(!range.matches(Some(&firmware("4.19.14123"))));
+    }
+
+    #[test]
+    fn range_matches_unknown_firmware() {
+        let range: FirmwareRange = "<4.38".parse().unwrap();
+
+        assert!(range.matches(None));
+    }
+
+    #[test]
+    fn wildcard_matches_every_version() {
+        let range: FirmwareRange = "*".parse().unwrap();
+
+        assert!(range.matches(Some(&firmware("5.0"))));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_ranges() {
+        for range in ["", "4.38", "<4.x", "~4.38"] {
+            assert!(range.parse::<FirmwareRange>().is_err(), "{range}");
+        }
+    }
+}
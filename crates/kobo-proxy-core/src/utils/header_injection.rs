@@ -0,0 +1,183 @@
+//! Header injection for requests forwarded to the Kobo store API.
+//!
+//! Some setups need extra headers on upstream requests, e.g. an affiliate header or a
+//! tracing header required by an intermediate gateway. Values are either static or
+//! templated from information about the requesting device.
+
+pub use implementation::HeaderInjection;
+
+mod implementation {
+    use anyhow::{Context as _, Result, bail};
+    use hyper::{
+        HeaderMap, header,
+        header::{HeaderName, HeaderValue},
+    };
+
+    /// A piece of a header value template.
+    #[derive(Debug, PartialEq, Eq)]
+    enum Segment {
+        /// Text copied as-is.
+        Literal(String),
+        /// The `{device_id}` placeholder.
+        DeviceId,
+        /// The `{user_agent}` placeholder, the device's `User-Agent` header.
+        UserAgent,
+    }
+
+    /// Parses a header value template into segments.
+    fn parse_template(template: &str) -> Result<Vec<Segment>> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}') else {
+                bail!("Unclosed placeholder in header template '{template}'");
+            };
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_owned()));
+            }
+            segments.push(match &rest[start + 1..start + end] {
+                "device_id" => Segment::DeviceId,
+                "user_agent" => Segment::UserAgent,
+                placeholder => bail!(
+                    "Unknown placeholder '{{{placeholder}}}' in header template '{template}', \
+                     expected {{device_id}} or {{user_agent}}"
+                ),
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_owned()));
+        }
+        Ok(segments)
+    }
+
+    /// Headers added to, or replaced on, every request forwarded upstream.
+    #[derive(Debug, Default)]
+    pub struct HeaderInjection {
+        headers: Vec<(HeaderName, Vec<Segment>)>,
+    }
+
+    impl HeaderInjection {
+        /// Creates an injection from headers in `NAME=VALUE` form. Values may contain
+        /// the `{device_id}` and `{user_agent}` placeholders.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if a header is not in `NAME=VALUE` form, the name is not a
+        /// valid header name, or the value has an unknown or unclosed placeholder.
+        pub fn new<S: AsRef<str>>(headers: &[S]) -> Result<Self> {
+            let headers = headers
+                .iter()
+                .map(|header| {
+                    let header = header.as_ref();
+                    let Some((name, template)) = header.split_once('=') else {
+                        bail!("Upstream header '{header}' must be in NAME=VALUE form");
+                    };
+                    let name = HeaderName::from_bytes(name.as_bytes())
+                        .with_context(|| format!("Invalid upstream header name in '{header}'"))?;
+                    Ok((name, parse_template(template)?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(Self { headers })
+        }
+
+        /// Sets the configured headers, replacing any sent by the device. A templated
+        /// header is skipped if a placeholder has no value for this request, or the
+        /// rendered value is not a valid header value.
+        pub fn apply_to_headers(&self, headers: &mut HeaderMap, device_id: Option<&str>) {
+            let user_agent = headers
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned);
+
+            for (name, segments) in &self.headers {
+                let value = segments
+                    .iter()
+                    .map(|segment| match segment {
+                        Segment::Literal(text) => Some(text.as_str()),
+                        Segment::DeviceId => device_id,
+                        Segment::UserAgent => user_agent.as_deref(),
+                    })
+                    .collect::<Option<String>>();
+                let Some(value) = value else {
+                    tracing::debug!("Skipping upstream header {name}, a placeholder has no value");
+                    continue;
+                };
+                match HeaderValue::from_str(&value) {
+                    Ok(value) => {
+                        headers.insert(name.clone(), value);
+                    }
+                    Err(e) => tracing::warn!("Skipping invalid upstream header {name}: {e}"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{HeaderMap, header};
+
+    use super::*;
+
+    #[test]
+    fn default_leaves_headers_unchanged() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-affiliate", "device".parse().unwrap());
+
+        HeaderInjection::default().apply_to_headers(&mut headers, Some("device-1"));
+
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers.get("x-affiliate").unwrap(), "device");
+    }
+
+    #[test]
+    fn static_headers_are_added_and_replaced() {
+        let injection = HeaderInjection::new(&["X-Affiliate=proxy", "X-Gateway=token"]).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-affiliate", "device".parse().unwrap());
+
+        injection.apply_to_headers(&mut headers, None);
+
+        assert_eq!(headers.get("x-affiliate").unwrap(), "proxy");
+        assert_eq!(headers.get("x-gateway").unwrap(), "token");
+    }
+
+    #[test]
+    fn templated_headers_use_device_info() {
+        let injection = HeaderInjection::new(&["X-Trace=kobo-{device_id}/{user_agent}"]).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, "Kobo Touch".parse().unwrap());
+
+        injection.apply_to_headers(&mut headers, Some("device-1"));
+
+        assert_eq!(headers.get("x-trace").unwrap(), "kobo-device-1/Kobo Touch");
+    }
+
+    #[test]
+    fn templated_headers_without_value_are_skipped() {
+        let injection = HeaderInjection::new(&["X-Trace={user_agent}"]).unwrap();
+        let mut headers = HeaderMap::new();
+
+        injection.apply_to_headers(&mut headers, Some("device-1"));
+
+        assert!(headers.get("x-trace").is_none());
+    }
+
+    #[test]
+    fn new_rejects_header_without_value() {
+        assert!(HeaderInjection::new(&["X-Affiliate"]).is_err());
+    }
+
+    #[test]
+    fn new_rejects_invalid_header_name() {
+        assert!(HeaderInjection::new(&["X Affiliate=proxy"]).is_err());
+    }
+
+    #[test]
+    fn new_rejects_unknown_and_unclosed_placeholders() {
+        assert!(HeaderInjection::new(&["X-Trace={serial}"]).is_err());
+        assert!(HeaderInjection::new(&["X-Trace={device_id"]).is_err());
+    }
+}
@@ -3,11 +3,12 @@
 //! This module provides shared functionality for working with HTTP bodies,
 //! including gzip compression/decompression and encoding detection.
 
+#[cfg(test)]
+pub use implementation::decompress_gzip;
 pub use implementation::{
-    buffer_body, decode_response_body, encode_response_body, is_gzip_encoded, read_response_body,
+    buffer_body, compress_gzip, decode_response_body, encode_response_body, is_gzip_encoded,
+    is_json_response, read_response_body,
 };
-#[cfg(test)]
-pub use implementation::{compress_gzip, decompress_gzip};
 
 mod implementation {
     use std::{
@@ -20,10 +21,14 @@ mod implementation {
         body::{Body, Bytes, HttpBody},
         response::Response,
     };
-    use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+    use flate2::{Compression, read::MultiGzDecoder, write::GzEncoder};
     use http_body_util::BodyExt as _;
     use hyper::StatusCode;
 
+    /// Bodies at least this large are (de)compressed on the blocking thread pool, so
+    /// multi-megabyte bodies do not stall the async worker threads.
+    const BLOCKING_THRESHOLD_BYTES: usize = 256 * 1024;
+
     /// Buffers the entire HTTP body into memory for inspection.
     ///
     /// This helper function collects all bytes from an HTTP body stream,
@@ -48,26 +53,27 @@ mod implementation {
         Ok(collected_bytes.to_bytes())
     }
 
-    /// Decompresses gzip-encoded bytes to a string.
+    /// Decompresses gzip-encoded bytes to a string. Every member of a multi-member
+    /// stream, as emitted by some CDNs, is decoded rather than only the first.
     ///
     /// # Errors
     ///
     /// Returns an error if the gzip content cannot be decompressed or
     /// if the decompressed content is not valid UTF-8.
     pub fn decompress_gzip(bytes: &Bytes) -> Result<String> {
-        let mut gz = GzDecoder::new(&bytes[..]);
+        let mut gz = MultiGzDecoder::new(&bytes[..]);
         let mut text = String::new();
         gz.read_to_string(&mut text)?;
         Ok(text)
     }
 
-    /// Compresses a string using gzip encoding.
+    /// Compresses a string using gzip encoding at the given compression level.
     ///
     /// # Errors
     ///
     /// Returns an error if the compression fails.
-    pub fn compress_gzip(text: &str) -> Result<Vec<u8>> {
-        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    pub fn compress_gzip(text: &str, level: Compression) -> Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), level);
         encoder.write_all(text.as_bytes())?;
         Ok(encoder.finish()?)
     }
@@ -91,17 +97,35 @@ mod implementation {
         headers.get("content-encoding").is_some_and(|v| v == "gzip")
     }
 
-    /// Decodes a response body based on its encoding type.
+    /// Checks if a response carries a JSON body.
+    pub fn is_json_response(response: &Response) -> bool {
+        response
+            .headers()
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("json"))
+    }
+
+    /// Decodes a response body based on its encoding type. Large gzip bodies are
+    /// decompressed on the blocking thread pool.
     ///
     /// # Errors
     ///
     /// Returns a `StatusCode` error if decoding fails.
-    pub fn decode_response_body(
+    pub async fn decode_response_body(
         bytes: &Bytes,
         is_gzipped: bool,
     ) -> Result<Cow<'_, str>, StatusCode> {
         if is_gzipped {
-            let decompressed = decompress_gzip(bytes).map_err(|err| {
+            let decompressed = if bytes.len() < BLOCKING_THRESHOLD_BYTES {
+                decompress_gzip(bytes)
+            } else {
+                let bytes = bytes.clone();
+                tokio::task::spawn_blocking(move || decompress_gzip(&bytes))
+                    .await
+                    .unwrap_or_else(|err| Err(err.into()))
+            };
+            let decompressed = decompressed.map_err(|err| {
                 tracing::error!("Failed to decompress gzip response: {err}");
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
@@ -111,14 +135,26 @@ mod implementation {
         }
     }
 
-    /// Encodes a string into a response body, optionally compressing it.
+    /// Encodes a string into a response body, compressing it at the given level if
+    /// one is provided. Large bodies are compressed on the blocking thread pool.
     ///
     /// # Errors
     ///
     /// Returns a `StatusCode` error if encoding fails.
-    pub fn encode_response_body(text: &str, should_compress: bool) -> Result<Body, StatusCode> {
-        if should_compress {
-            let compressed = compress_gzip(text).map_err(|err| {
+    pub async fn encode_response_body(
+        text: &str,
+        compression: Option<Compression>,
+    ) -> Result<Body, StatusCode> {
+        if let Some(level) = compression {
+            let compressed = if text.len() < BLOCKING_THRESHOLD_BYTES {
+                compress_gzip(text, level)
+            } else {
+                let text = text.to_owned();
+                tokio::task::spawn_blocking(move || compress_gzip(&text, level))
+                    .await
+                    .unwrap_or_else(|err| Err(err.into()))
+            };
+            let compressed = compressed.map_err(|err| {
                 tracing::error!("Failed to compress response: {err}");
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
@@ -140,7 +176,7 @@ mod tests {
     use flate2::{Compression, write::GzEncoder};
     use hyper::{HeaderMap, StatusCode};
 
-    use crate::server::utils::http_body::{
+    use crate::utils::http_body::{
         buffer_body, compress_gzip, decode_response_body, decompress_gzip, encode_response_body,
         is_gzip_encoded, read_response_body,
     };
@@ -194,6 +230,17 @@ mod tests {
         assert_eq!(text, EMPTY_TEXT);
     }
 
+    #[test]
+    fn test_decompress_gzip_multiple_members() {
+        let mut compressed = create_gzipped_bytes("first member, ");
+        compressed.extend(create_gzipped_bytes("second member"));
+        let bytes = Bytes::from(compressed);
+
+        let text = decompress_gzip(&bytes).unwrap();
+
+        assert_eq!(text, "first member, second member");
+    }
+
     #[test]
     fn test_decompress_gzip_invalid_data() {
         let invalid_data = b"not gzipped data";
@@ -206,7 +253,7 @@ mod tests {
 
     #[test]
     fn test_compress_gzip_success() {
-        let compressed = compress_gzip(TEST_TEXT).unwrap();
+        let compressed = compress_gzip(TEST_TEXT, Compression::default()).unwrap();
         let bytes = Bytes::from(compressed);
         let decompressed = decompress_gzip(&bytes).unwrap();
 
@@ -215,7 +262,7 @@ mod tests {
 
     #[test]
     fn test_compress_gzip_empty() {
-        let compressed = compress_gzip(EMPTY_TEXT).unwrap();
+        let compressed = compress_gzip(EMPTY_TEXT, Compression::default()).unwrap();
         let bytes = Bytes::from(compressed);
         let decompressed = decompress_gzip(&bytes).unwrap();
 
@@ -301,92 +348,92 @@ mod tests {
         assert!(!result);
     }
 
-    #[test]
-    fn test_decode_response_body_plain_text() {
+    #[tokio::test]
+    async fn test_decode_response_body_plain_text() {
         let bytes = Bytes::from(TEST_TEXT);
 
-        let result = decode_response_body(&bytes, false);
+        let result = decode_response_body(&bytes, false).await;
 
         assert!(result.is_ok());
     }
 
-    #[test]
-    fn test_decode_response_body_plain_text_content() {
+    #[tokio::test]
+    async fn test_decode_response_body_plain_text_content() {
         let bytes = Bytes::from(TEST_TEXT);
 
-        let decoded = decode_response_body(&bytes, false).unwrap();
+        let decoded = decode_response_body(&bytes, false).await.unwrap();
 
         assert_eq!(decoded, TEST_TEXT);
     }
 
-    #[test]
-    fn test_decode_response_body_gzipped() {
+    #[tokio::test]
+    async fn test_decode_response_body_gzipped() {
         let compressed = create_gzipped_bytes(TEST_TEXT);
         let bytes = Bytes::from(compressed);
 
-        let result = decode_response_body(&bytes, true);
+        let result = decode_response_body(&bytes, true).await;
 
         assert!(result.is_ok());
     }
 
-    #[test]
-    fn test_decode_response_body_gzipped_content() {
+    #[tokio::test]
+    async fn test_decode_response_body_gzipped_content() {
         let compressed = create_gzipped_bytes(TEST_TEXT);
         let bytes = Bytes::from(compressed);
 
-        let decoded = decode_response_body(&bytes, true).unwrap();
+        let decoded = decode_response_body(&bytes, true).await.unwrap();
 
         assert_eq!(decoded, TEST_TEXT);
     }
 
-    #[test]
-    fn test_decode_response_body_invalid_utf8() {
+    #[tokio::test]
+    async fn test_decode_response_body_invalid_utf8() {
         let invalid_utf8 = vec![0xFF, 0xFE, 0xFD];
         let bytes = Bytes::from(invalid_utf8);
 
-        let result = decode_response_body(&bytes, false);
+        let result = decode_response_body(&bytes, false).await;
 
         assert!(result.is_ok());
     }
 
-    #[test]
-    fn test_decode_response_body_invalid_utf8_uses_replacement_chars() {
+    #[tokio::test]
+    async fn test_decode_response_body_invalid_utf8_uses_replacement_chars() {
         let invalid_utf8 = vec![0xFF, 0xFE, 0xFD];
         let bytes = Bytes::from(invalid_utf8);
 
-        let decoded = decode_response_body(&bytes, false).unwrap();
+        let decoded = decode_response_body(&bytes, false).await.unwrap();
 
         assert!(decoded.contains('�')); // Replacement character
     }
 
-    #[test]
-    fn test_decode_response_body_gzip_error() {
+    #[tokio::test]
+    async fn test_decode_response_body_gzip_error() {
         let invalid_gzip = Bytes::from("not gzipped");
 
-        let result = decode_response_body(&invalid_gzip, true);
+        let result = decode_response_body(&invalid_gzip, true).await;
 
         assert!(result.is_err());
     }
 
-    #[test]
-    fn test_decode_response_body_gzip_error_status() {
+    #[tokio::test]
+    async fn test_decode_response_body_gzip_error_status() {
         let invalid_gzip = Bytes::from("not gzipped");
 
-        let error = decode_response_body(&invalid_gzip, true).unwrap_err();
+        let error = decode_response_body(&invalid_gzip, true).await.unwrap_err();
 
         assert_eq!(error, StatusCode::INTERNAL_SERVER_ERROR);
     }
 
     #[tokio::test]
     async fn test_encode_response_body_plain() {
-        let result = encode_response_body(TEST_TEXT, false);
+        let result = encode_response_body(TEST_TEXT, None).await;
 
         assert!(result.is_ok());
     }
 
     #[tokio::test]
     async fn test_encode_response_body_plain_content() {
-        let body = encode_response_body(TEST_TEXT, false).unwrap();
+        let body = encode_response_body(TEST_TEXT, None).await.unwrap();
         let bytes = buffer_body(body).await.unwrap();
 
         assert_eq!(bytes, TEST_TEXT.as_bytes());
@@ -394,14 +441,16 @@ mod tests {
 
     #[tokio::test]
     async fn test_encode_response_body_compressed() {
-        let result = encode_response_body(TEST_TEXT, true);
+        let result = encode_response_body(TEST_TEXT, Some(Compression::default())).await;
 
         assert!(result.is_ok());
     }
 
     #[tokio::test]
     async fn test_encode_response_body_compressed_content() {
-        let body = encode_response_body(TEST_TEXT, true).unwrap();
+        let body = encode_response_body(TEST_TEXT, Some(Compression::default()))
+            .await
+            .unwrap();
         let bytes = buffer_body(body).await.unwrap();
         let decompressed = decompress_gzip(&bytes).unwrap();
 
@@ -410,14 +459,16 @@ mod tests {
 
     #[tokio::test]
     async fn test_encode_response_body_empty_compressed() {
-        let result = encode_response_body(EMPTY_TEXT, true);
+        let result = encode_response_body(EMPTY_TEXT, Some(Compression::default())).await;
 
         assert!(result.is_ok());
     }
 
     #[tokio::test]
     async fn test_encode_response_body_empty_compressed_content() {
-        let body = encode_response_body(EMPTY_TEXT, true).unwrap();
+        let body = encode_response_body(EMPTY_TEXT, Some(Compression::default()))
+            .await
+            .unwrap();
         let bytes = buffer_body(body).await.unwrap();
         let decompressed = decompress_gzip(&bytes).unwrap();
 
@@ -426,7 +477,7 @@ mod tests {
 
     #[test]
     fn test_round_trip_compression_test_text() {
-        let compressed = compress_gzip(TEST_TEXT).unwrap();
+        let compressed = compress_gzip(TEST_TEXT, Compression::default()).unwrap();
         let bytes = Bytes::from(compressed);
         let decompressed = decompress_gzip(&bytes).unwrap();
 
@@ -435,7 +486,7 @@ mod tests {
 
     #[test]
     fn test_round_trip_compression_empty_text() {
-        let compressed = compress_gzip(EMPTY_TEXT).unwrap();
+        let compressed = compress_gzip(EMPTY_TEXT, Compression::default()).unwrap();
         let bytes = Bytes::from(compressed);
         let decompressed = decompress_gzip(&bytes).unwrap();
 
@@ -446,7 +497,7 @@ mod tests {
     fn test_round_trip_compression_single_char() {
         let original_text = "a";
 
-        let compressed = compress_gzip(original_text).unwrap();
+        let compressed = compress_gzip(original_text, Compression::default()).unwrap();
         let bytes = Bytes::from(compressed);
         let decompressed = decompress_gzip(&bytes).unwrap();
 
@@ -457,10 +508,35 @@ mod tests {
     fn test_round_trip_compression_unicode() {
         let original_text = "🚀";
 
-        let compressed = compress_gzip(original_text).unwrap();
+        let compressed = compress_gzip(original_text, Compression::default()).unwrap();
         let bytes = Bytes::from(compressed);
         let decompressed = decompress_gzip(&bytes).unwrap();
 
         assert_eq!(decompressed, original_text);
     }
+
+    #[test]
+    fn test_compress_gzip_respects_level() {
+        let text = TEST_TEXT.repeat(100);
+
+        let stored = compress_gzip(&text, Compression::none()).unwrap();
+        let best = compress_gzip(&text, Compression::best()).unwrap();
+
+        assert!(best.len() < stored.len());
+        assert_eq!(decompress_gzip(&Bytes::from(stored)).unwrap(), text);
+        assert_eq!(decompress_gzip(&Bytes::from(best)).unwrap(), text);
+    }
+
+    #[tokio::test]
+    async fn test_large_body_round_trips_through_blocking_pool() {
+        let text = TEST_TEXT.repeat(64 * 1024);
+
+        let body = encode_response_body(&text, Some(Compression::fast()))
+            .await
+            .unwrap();
+        let bytes = buffer_body(body).await.unwrap();
+        let decoded = decode_response_body(&bytes, true).await.unwrap();
+
+        assert_eq!(decoded, text);
+    }
 }
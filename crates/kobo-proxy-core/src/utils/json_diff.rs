@@ -0,0 +1,124 @@
+//! Structural diffs between JSON documents.
+//!
+//! Used to summarize how an upstream response changed between snapshots, e.g. when
+//! Kobo adds or removes a resource URL from `/v1/initialization`.
+
+pub use implementation::diff_json;
+
+mod implementation {
+    use std::collections::BTreeMap;
+
+    use serde_json::Value;
+
+    /// Maximum number of changes reported, so large rewrites stay readable.
+    const MAX_CHANGES: usize = 50;
+
+    /// Flattens a JSON value into a map of JSON pointer paths to leaf values.
+    fn flatten<'a>(value: &'a Value, path: String, leaves: &mut BTreeMap<String, &'a Value>) {
+        match value {
+            Value::Object(object) if !object.is_empty() => {
+                for (key, value) in object {
+                    flatten(value, format!("{path}/{key}"), leaves);
+                }
+            }
+            Value::Array(array) if !array.is_empty() => {
+                for (index, value) in array.iter().enumerate() {
+                    flatten(value, format!("{path}/{index}"), leaves);
+                }
+            }
+            Value::Null
+            | Value::Bool(_)
+            | Value::Number(_)
+            | Value::String(_)
+            | Value::Array(_)
+            | Value::Object(_) => {
+                leaves.insert(path, value);
+            }
+        }
+    }
+
+    /// Lists the paths that differ between two JSON documents, prefixed with `+`
+    /// (added), `-` (removed), or `~` (changed). Bodies that are not valid JSON are
+    /// compared as a whole and reported as a single `~` change.
+    pub fn diff_json(old: &str, new: &str) -> Vec<String> {
+        let (Ok(old_value), Ok(new_value)) = (
+            serde_json::from_str::<Value>(old),
+            serde_json::from_str::<Value>(new),
+        ) else {
+            return if old == new {
+                Vec::new()
+            } else {
+                vec!["~ (body)".to_owned()]
+            };
+        };
+
+        let mut old_leaves = BTreeMap::new();
+        flatten(&old_value, String::new(), &mut old_leaves);
+        let mut new_leaves = BTreeMap::new();
+        flatten(&new_value, String::new(), &mut new_leaves);
+
+        let removed = old_leaves
+            .keys()
+            .filter(|path| !new_leaves.contains_key(*path))
+            .map(|path| format!("- {path}"));
+        let added_or_changed =
+            new_leaves
+                .iter()
+                .filter_map(|(path, value)| match old_leaves.get(path) {
+                    None => Some(format!("+ {path}")),
+                    Some(old_value) if old_value != value => Some(format!("~ {path}")),
+                    Some(_) => None,
+                });
+
+        let mut changes: Vec<_> = removed.chain(added_or_changed).collect();
+        changes.sort_by(|a, b| a[2..].cmp(&b[2..]));
+        if changes.len() > MAX_CHANGES {
+            let omitted = changes.len() - MAX_CHANGES;
+            changes.truncate(MAX_CHANGES);
+            changes.push(format!("... {omitted} more"));
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_documents_have_no_changes() {
+        assert!(diff_json(r#"{"a":1}"#, r#"{"a":1}"#).is_empty());
+    }
+
+    #[test]
+    fn reports_added_removed_and_changed_paths() {
+        let old = r#"{"Resources":{"a":"1","b":"2"}}"#;
+        let new = r#"{"Resources":{"b":"3","c":"4"}}"#;
+
+        assert_eq!(
+            diff_json(old, new),
+            ["- /Resources/a", "~ /Resources/b", "+ /Resources/c"]
+        );
+    }
+
+    #[test]
+    fn reports_array_elements_by_index() {
+        assert_eq!(diff_json("[1,2]", "[1,3,4]"), ["~ /1", "+ /2"]);
+    }
+
+    #[test]
+    fn non_json_bodies_are_compared_whole() {
+        assert_eq!(diff_json("old", "new"), ["~ (body)"]);
+        assert!(diff_json("same", "same").is_empty());
+    }
+
+    #[test]
+    fn changes_are_limited() {
+        let new = serde_json::to_string(&(0..60).collect::<Vec<_>>()).unwrap();
+
+        let changes = diff_json("[0]", &new);
+
+        assert_eq!(changes.len(), 51);
+        assert_eq!(changes[50], "... 9 more");
+    }
+}
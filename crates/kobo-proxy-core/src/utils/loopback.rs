@@ -0,0 +1,49 @@
+//! Detection of frontend URLs that devices cannot reach.
+//!
+//! A frontend URL on a loopback host only routes back to the proxy from the machine
+//! it runs on, so e-readers on the network would fail to sync through it.
+
+pub use implementation::is_loopback_url;
+
+mod implementation {
+    use hyper::Uri;
+
+    /// Checks if a URL points at a loopback host.
+    #[must_use]
+    pub fn is_loopback_url(url: &str) -> bool {
+        let Some(host) = url
+            .parse::<Uri>()
+            .ok()
+            .and_then(|uri| uri.host().map(str::to_owned))
+        else {
+            return false;
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        host.eq_ignore_ascii_case("localhost")
+            || host
+                .parse::<std::net::IpAddr>()
+                .is_ok_and(|address| address.is_loopback())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_loopback_url_detects_localhost() {
+        assert!(is_loopback_url("http://localhost:8089"));
+    }
+
+    #[test]
+    fn is_loopback_url_detects_loopback_addresses() {
+        assert!(is_loopback_url("http://127.0.0.1:8089"));
+        assert!(is_loopback_url("http://[::1]:8089"));
+    }
+
+    #[test]
+    fn is_loopback_url_false_for_lan_addresses() {
+        assert!(!is_loopback_url("http://192.168.1.10:8089"));
+    }
+}
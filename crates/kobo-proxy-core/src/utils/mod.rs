@@ -0,0 +1,29 @@
+//! Utility modules for common server functionality.
+
+pub mod access_schedule;
+pub mod address_family;
+pub mod chaos;
+pub mod cookie_policy;
+pub mod device_frontend_urls;
+pub mod device_info;
+pub mod dns_resolver;
+pub mod firmware_range;
+pub mod header_injection;
+pub mod http_body;
+pub mod json_diff;
+pub mod loopback;
+#[cfg(feature = "admin-tls")]
+pub mod mutual_tls;
+pub mod port_binding;
+pub mod privileges;
+pub mod process_resources;
+pub mod profile_rewrite;
+pub mod protected_content;
+pub mod region_override;
+pub mod response_patches;
+pub mod route_template;
+pub mod route_timeouts;
+pub mod schema_validation;
+pub mod tcp_tuning;
+pub mod trusted_proxies;
+pub mod upgrade;
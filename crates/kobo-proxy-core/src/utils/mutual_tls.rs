@@ -0,0 +1,96 @@
+//! TLS configuration that requires client certificates (mutual TLS).
+//!
+//! The admin API can be served on a dedicated listener that only accepts clients
+//! presenting a certificate signed by a configured CA, which is safer to expose than
+//! a static API key.
+
+pub use implementation::MutualTls;
+
+mod implementation {
+    use std::{path::Path, sync::Arc};
+
+    use anyhow::{Context as _, Result, bail};
+    use chrono::{DateTime, Utc};
+    use rustls::{
+        RootCertStore, ServerConfig,
+        crypto::aws_lc_rs,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject as _},
+        server::WebPkiClientVerifier,
+    };
+    use tokio_rustls::TlsAcceptor;
+    use x509_parser::parse_x509_certificate;
+
+    /// A server certificate and the CA client certificates are validated against.
+    #[derive(Clone)]
+    pub struct MutualTls {
+        config: Arc<ServerConfig>,
+        certificate_expiry: Option<DateTime<Utc>>,
+    }
+
+    impl MutualTls {
+        /// Loads the server certificate chain, its private key, and the client CA
+        /// certificates from PEM files.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if a file cannot be read or parsed, the CA file holds no
+        /// certificates, or the key does not match the certificate.
+        pub fn new(certificate: &Path, key: &Path, client_ca: &Path) -> Result<Self> {
+            let certificates = read_certificates(certificate)?;
+            let certificate_expiry =
+                parse_x509_certificate(&certificates[0])
+                    .ok()
+                    .and_then(|(_, leaf)| {
+                        DateTime::from_timestamp(leaf.validity().not_after.timestamp(), 0)
+                    });
+            let key = PrivateKeyDer::from_pem_file(key)
+                .with_context(|| format!("Failed to read private key '{}'", key.display()))?;
+            let mut roots = RootCertStore::empty();
+            for ca in read_certificates(client_ca)? {
+                roots.add(ca).with_context(|| {
+                    format!("Invalid CA certificate in '{}'", client_ca.display())
+                })?;
+            }
+
+            let provider = Arc::new(aws_lc_rs::default_provider());
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                    .build()
+                    .context("Failed to create the client certificate verifier")?;
+            let config = ServerConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .context("Failed to select TLS protocol versions")?
+                .with_client_cert_verifier(verifier)
+                .with_single_cert(certificates, key)
+                .context("The private key does not match the certificate")?;
+
+            Ok(Self {
+                config: Arc::new(config),
+                certificate_expiry,
+            })
+        }
+
+        /// Returns when the server certificate expires, if it could be parsed.
+        #[must_use]
+        pub fn certificate_expiry(&self) -> Option<DateTime<Utc>> {
+            self.certificate_expiry
+        }
+
+        /// Returns an acceptor that performs the TLS handshake on accepted connections.
+        #[must_use]
+        pub fn acceptor(&self) -> TlsAcceptor {
+            TlsAcceptor::from(self.config.clone())
+        }
+    }
+
+    /// Reads every certificate in a PEM file, failing if there are none.
+    fn read_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+        let certificates = CertificateDer::pem_file_iter(path)
+            .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+            .with_context(|| format!("Failed to read certificates from '{}'", path.display()))?;
+        if certificates.is_empty() {
+            bail!("No certificates found in '{}'", path.display());
+        }
+        Ok(certificates)
+    }
+}
@@ -0,0 +1,77 @@
+//! Binding of listening sockets, optionally shared with another process.
+//!
+//! With `SO_REUSEPORT` a newly started proxy can bind the same port while the
+//! previous process is still serving. The kernel spreads new connections across
+//! both, so the old process can drain its in-flight syncs and exit without a gap
+//! in which devices are refused.
+
+pub use implementation::bind_listener;
+
+mod implementation {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use socket2::{Domain, Protocol, Socket, Type};
+    use tokio::net::TcpListener;
+
+    /// Number of pending connections the kernel queues before refusing new ones.
+    const BACKLOG: i32 = 1024;
+
+    /// Binds a TCP listener on all interfaces at `port`.
+    ///
+    /// # Arguments
+    /// * `port` - The port to bind, or 0 for an ephemeral port
+    /// * `reuse_port` - Whether to set `SO_REUSEPORT`, letting other processes that also set it
+    ///   bind the same port
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket cannot be created, configured, or bound.
+    pub fn bind_listener(port: u16, reuse_port: bool) -> std::io::Result<TcpListener> {
+        let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_reuse_address(true)?;
+        if reuse_port {
+            set_reuse_port(&socket)?;
+        }
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+        socket.listen(BACKLOG)?;
+
+        TcpListener::from_std(socket.into())
+    }
+
+    #[cfg(unix)]
+    fn set_reuse_port(socket: &Socket) -> std::io::Result<()> {
+        socket.set_reuse_port(true)
+    }
+
+    #[cfg(not(unix))]
+    fn set_reuse_port(_socket: &Socket) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "SO_REUSEPORT is not supported on this platform",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reuse_port_lets_two_listeners_share_a_port() {
+        let first = bind_listener(0, true).unwrap();
+        let port = first.local_addr().unwrap().port();
+
+        let second = bind_listener(port, true).unwrap();
+
+        assert_eq!(second.local_addr().unwrap().port(), port);
+    }
+
+    #[tokio::test]
+    async fn port_is_exclusive_without_reuse_port() {
+        let first = bind_listener(0, false).unwrap();
+        let port = first.local_addr().unwrap().port();
+
+        assert!(bind_listener(port, false).is_err());
+    }
+}
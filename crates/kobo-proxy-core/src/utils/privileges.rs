@@ -0,0 +1,142 @@
+//! Dropping root privileges after binding the listening port.
+//!
+//! Binding ports below 1024 (e.g. 80 or 443) requires root on most systems. Instead of
+//! running the whole proxy as root, the server can be started as root, bind its port,
+//! and then switch to an unprivileged user and group before serving any requests.
+
+pub use implementation::PrivilegeDrop;
+
+mod implementation {
+    #[cfg(unix)]
+    use anyhow::Context as _;
+    #[cfg(all(unix, not(target_vendor = "apple")))]
+    use nix::unistd::{Gid, setgroups};
+
+    /// The user and group the process switches to after binding.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct PrivilegeDrop {
+        uid: Option<u32>,
+        gid: Option<u32>,
+    }
+
+    #[cfg(unix)]
+    impl PrivilegeDrop {
+        /// Resolves the user and group names. The group defaults to the user's primary
+        /// group. Returns `None` if neither is configured.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the user or group does not exist.
+        pub fn new(user: Option<&str>, group: Option<&str>) -> anyhow::Result<Option<Self>> {
+            let user = user
+                .map(|name| {
+                    uzers::get_user_by_name(name).with_context(|| format!("Unknown user '{name}'"))
+                })
+                .transpose()?;
+            let gid = match group {
+                Some(name) => Some(
+                    uzers::get_group_by_name(name)
+                        .with_context(|| format!("Unknown group '{name}'"))?
+                        .gid(),
+                ),
+                None => user.as_ref().map(uzers::User::primary_group_id),
+            };
+            let uid = user.as_ref().map(uzers::User::uid);
+
+            Ok((uid.is_some() || gid.is_some()).then_some(Self { uid, gid }))
+        }
+
+        /// Switches the process to the configured group, then user. The supplementary
+        /// groups, such as root's, are replaced by the configured group, and the groups
+        /// are changed first, since changing them requires the privileges that
+        /// changing the user gives up.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the groups or user could not be changed, e.g. because
+        /// the process is not running as root.
+        pub fn apply(&self) -> anyhow::Result<()> {
+            if let Some(gid) = self.gid {
+                set_supplementary_group(gid)
+                    .with_context(|| format!("Failed to set supplementary groups to {gid}"))?;
+                uzers::switch::set_current_gid(gid)
+                    .with_context(|| format!("Failed to switch to group {gid}"))?;
+            }
+            if let Some(uid) = self.uid {
+                uzers::switch::set_current_uid(uid)
+                    .with_context(|| format!("Failed to switch to user {uid}"))?;
+            }
+
+            tracing::info!(
+                uid = uzers::get_current_uid(),
+                gid = uzers::get_current_gid(),
+                "Dropped privileges"
+            );
+            Ok(())
+        }
+    }
+
+    /// Replaces the supplementary groups of the process with just `gid`.
+    #[cfg(all(unix, not(target_vendor = "apple")))]
+    fn set_supplementary_group(gid: u32) -> anyhow::Result<()> {
+        Ok(setgroups(&[Gid::from_raw(gid)])?)
+    }
+
+    /// macOS limits `setgroups` to its legacy group list, so nix does not expose it.
+    /// Refuses to switch groups rather than keeping root's supplementary groups.
+    #[cfg(target_vendor = "apple")]
+    fn set_supplementary_group(_gid: u32) -> anyhow::Result<()> {
+        anyhow::bail!("Setting supplementary groups is not supported on macOS")
+    }
+
+    #[cfg(not(unix))]
+    impl PrivilegeDrop {
+        /// Privileges can only be dropped on Unix. Returns `None` if neither a user nor a
+        /// group is configured.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if a user or group is configured.
+        pub fn new(user: Option<&str>, group: Option<&str>) -> anyhow::Result<Option<Self>> {
+            if user.is_some() || group.is_some() {
+                anyhow::bail!("Dropping privileges is only supported on Unix");
+            }
+            Ok(None)
+        }
+
+        /// Does nothing, since no `PrivilegeDrop` can be created.
+        ///
+        /// # Errors
+        ///
+        /// Never returns an error.
+        pub fn apply(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_returns_none_without_user_or_group() {
+        assert_eq!(PrivilegeDrop::new(None, None).unwrap(), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn new_resolves_user_and_primary_group() {
+        assert!(PrivilegeDrop::new(Some("root"), None).unwrap().is_some());
+    }
+
+    #[test]
+    fn new_rejects_unknown_user() {
+        assert!(PrivilegeDrop::new(Some("kobo-server-no-such-user"), None).is_err());
+    }
+
+    #[test]
+    fn new_rejects_unknown_group() {
+        assert!(PrivilegeDrop::new(None, Some("kobo-server-no-such-group")).is_err());
+    }
+}
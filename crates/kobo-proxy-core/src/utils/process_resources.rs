@@ -0,0 +1,105 @@
+//! Process resource usage read from `/proc`.
+//!
+//! Only available on Linux. On other platforms every value is unknown.
+
+pub use implementation::ProcessResources;
+
+mod implementation {
+    use serde::Serialize;
+
+    /// File descriptor and memory usage of the server process.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+    pub struct ProcessResources {
+        /// Number of open file descriptors.
+        pub open_file_descriptors: Option<u64>,
+        /// Soft limit on open file descriptors, or `None` if unlimited or unknown.
+        pub file_descriptor_limit: Option<u64>,
+        /// Resident set size, in bytes.
+        pub resident_memory_bytes: Option<u64>,
+    }
+
+    /// Parses the soft `Max open files` limit from `/proc/self/limits`.
+    pub(super) fn parse_file_descriptor_limit(limits: &str) -> Option<u64> {
+        limits
+            .lines()
+            .find_map(|line| line.strip_prefix("Max open files"))
+            .and_then(|values| values.split_whitespace().next())
+            .and_then(|soft_limit| soft_limit.parse().ok())
+    }
+
+    /// Parses `VmRSS` from `/proc/self/status`, in bytes.
+    pub(super) fn parse_resident_memory(status: &str) -> Option<u64> {
+        let kilobytes: u64 = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))?
+            .trim()
+            .strip_suffix("kB")?
+            .trim()
+            .parse()
+            .ok()?;
+        kilobytes.checked_mul(1024)
+    }
+
+    impl ProcessResources {
+        /// Reads the current usage of the server process.
+        pub fn sample() -> Self {
+            // The directory handle used to list the descriptors is itself one of them.
+            let open_file_descriptors = std::fs::read_dir("/proc/self/fd")
+                .ok()
+                .map(|entries| entries.count().saturating_sub(1))
+                .and_then(|count| u64::try_from(count).ok());
+            Self {
+                open_file_descriptors,
+                file_descriptor_limit: std::fs::read_to_string("/proc/self/limits")
+                    .ok()
+                    .and_then(|limits| parse_file_descriptor_limit(&limits)),
+                resident_memory_bytes: std::fs::read_to_string("/proc/self/status")
+                    .ok()
+                    .and_then(|status| parse_resident_memory(&status)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::implementation::{parse_file_descriptor_limit, parse_resident_memory};
+
+    #[test]
+    fn parse_file_descriptor_limit_reads_soft_limit() {
+        let limits = "Limit                     Soft Limit           Hard Limit           Units\n\
+                      Max processes             7823                 7823                 processes\n\
+                      Max open files            1024                 524288               files\n";
+
+        assert_eq!(parse_file_descriptor_limit(limits), Some(1024));
+        assert_eq!(
+            parse_file_descriptor_limit("Max open files  unlimited  unlimited  files"),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_resident_memory_reads_vm_rss() {
+        let status = "Name:\tkobo-server\nVmPeak:\t  20000 kB\nVmRSS:\t   12345 kB\n";
+
+        assert_eq!(parse_resident_memory(status), Some(12345 * 1024));
+        assert_eq!(parse_resident_memory("Name:\tkobo-server\n"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sample_reads_current_process() {
+        let resources = super::ProcessResources::sample();
+
+        assert!(
+            resources
+                .open_file_descriptors
+                .is_some_and(|count| count > 0)
+        );
+        assert!(
+            resources
+                .resident_memory_bytes
+                .is_some_and(|bytes| bytes > 0)
+        );
+    }
+}
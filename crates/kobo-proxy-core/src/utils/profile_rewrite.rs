@@ -0,0 +1,137 @@
+//! Rewrites applied to the `/v1/user/profile` response.
+//!
+//! The profile holds account identifiers that users may not want to reach the
+//! device or appear in logs, and Kobo API URLs (e.g. for the avatar) that bypass the
+//! proxy unless rewritten.
+
+pub use implementation::ProfileRewrite;
+
+mod implementation {
+    use serde_json::Value;
+
+    use crate::routes::initialization::rewrite_urls;
+
+    /// Profile fields holding account identifiers, compared case-insensitively.
+    const IDENTIFIER_FIELDS: &[&str] = &[
+        "AccountId",
+        "Email",
+        "EmailAddress",
+        "PartnerUserId",
+        "UserId",
+        "UserKey",
+        "UserName",
+    ];
+
+    /// Number of trailing characters left visible in masked identifiers.
+    const VISIBLE_SUFFIX_CHARS: usize = 4;
+
+    /// Which rewrites are applied to the profile.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct ProfileRewrite {
+        /// Mask account identifiers, leaving only their last characters visible.
+        pub mask_identifiers: bool,
+        /// Rewrite Kobo API URLs, such as the avatar URL, to the frontend URL.
+        pub rewrite_urls: bool,
+    }
+
+    impl ProfileRewrite {
+        /// Checks if any rewrite is enabled.
+        pub fn is_enabled(self) -> bool {
+            self.mask_identifiers || self.rewrite_urls
+        }
+
+        /// Masks the account identifiers in a profile body. Bodies that are not JSON
+        /// are returned unchanged.
+        pub fn mask_identifiers(body: &str) -> String {
+            let Ok(mut profile) = serde_json::from_str::<Value>(body) else {
+                return body.to_owned();
+            };
+            if !mask_value(&mut profile) {
+                return body.to_owned();
+            }
+            serde_json::to_string(&profile).unwrap_or_else(|_| body.to_owned())
+        }
+
+        /// Rewrites Kobo API URLs in a profile body to `frontend_url`.
+        pub fn rewrite_urls(body: &str, frontend_url: &str) -> String {
+            rewrite_urls(body, frontend_url)
+        }
+    }
+
+    /// Masks identifier fields anywhere in `value`, returning whether any changed.
+    fn mask_value(value: &mut Value) -> bool {
+        match value {
+            Value::Object(object) => {
+                let mut changed = false;
+                for (key, value) in object.iter_mut() {
+                    let is_identifier = IDENTIFIER_FIELDS
+                        .iter()
+                        .any(|field| field.eq_ignore_ascii_case(key));
+                    changed |= if is_identifier && let Value::String(text) = value {
+                        mask_text(text)
+                    } else {
+                        mask_value(value)
+                    };
+                }
+                changed
+            }
+            Value::Array(values) => values
+                .iter_mut()
+                .fold(false, |changed, value| mask_value(value) | changed),
+            Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => false,
+        }
+    }
+
+    /// Replaces all but the last few characters of `text` with `*`.
+    fn mask_text(text: &mut String) -> bool {
+        let length = text.chars().count();
+        let visible = if length > VISIBLE_SUFFIX_CHARS * 2 {
+            VISIBLE_SUFFIX_CHARS
+        } else {
+            0
+        };
+        let masked =
+            "*".repeat(length - visible) + &text.chars().skip(length - visible).collect::<String>();
+        if masked == *text {
+            return false;
+        }
+        *text = masked;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_identifiers_masks_nested_identifier_fields() {
+        let body = r#"{"UserId":"0123456789abcdef","Profile":{"email":"reader@example.com","DisplayName":"Reader"}}"#;
+
+        let masked = ProfileRewrite::mask_identifiers(body);
+
+        assert_eq!(
+            masked,
+            r#"{"Profile":{"DisplayName":"Reader","email":"**************.com"},"UserId":"************cdef"}"#
+        );
+    }
+
+    #[test]
+    fn mask_identifiers_fully_masks_short_identifiers() {
+        let masked = ProfileRewrite::mask_identifiers(r#"{"UserKey":"abc123"}"#);
+
+        assert_eq!(masked, r#"{"UserKey":"******"}"#);
+    }
+
+    #[test]
+    fn mask_identifiers_leaves_non_json_unchanged() {
+        assert_eq!(ProfileRewrite::mask_identifiers("not json"), "not json");
+    }
+
+    #[test]
+    fn mask_identifiers_leaves_profiles_without_identifiers_unchanged() {
+        let body = r#"{ "DisplayName": "Reader" }"#;
+
+        assert_eq!(ProfileRewrite::mask_identifiers(body), body);
+    }
+}
@@ -0,0 +1,60 @@
+//! Detection of DRM-protected content passing through the proxy.
+//!
+//! Adobe ADEPT and Kobo kdrm files are encrypted for the device that requested them,
+//! so altering a single byte makes them unreadable. Responses with these content
+//! types are streamed to the device untouched: they are never decoded, logged,
+//! patched, or recorded. Devices download book files directly from Kobo's CDN, so
+//! the protected content seen here is store API responses such as Adobe fulfilment
+//! tokens, not the books themselves.
+
+pub use implementation::is_protected_content;
+
+mod implementation {
+    use hyper::{HeaderMap, header};
+
+    /// Markers of DRM-protected content types, e.g. `application/vnd.adobe.adept+xml`
+    /// for Adobe fulfilment tokens and the `kdrm` types of Kobo-encrypted books.
+    const PROTECTED_CONTENT_TYPE_MARKERS: [&str; 2] = ["adept", "kdrm"];
+
+    /// Checks if headers describe DRM-protected content.
+    pub fn is_protected_content(headers: &HeaderMap) -> bool {
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .is_some_and(|essence| {
+                let essence = essence.trim().to_ascii_lowercase();
+                PROTECTED_CONTENT_TYPE_MARKERS
+                    .iter()
+                    .any(|marker| essence.contains(marker))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{HeaderMap, header};
+
+    use super::*;
+
+    fn headers(content_type: &'static str) -> HeaderMap {
+        HeaderMap::from_iter([(header::CONTENT_TYPE, content_type.parse().unwrap())])
+    }
+
+    #[test]
+    fn detects_adobe_and_kobo_drm_content_types() {
+        assert!(is_protected_content(&headers(
+            "application/vnd.adobe.adept+xml"
+        )));
+        assert!(is_protected_content(&headers(
+            "Application/X-Kobo-KDRM; charset=binary"
+        )));
+    }
+
+    #[test]
+    fn ignores_unprotected_content_types() {
+        assert!(!is_protected_content(&headers("application/epub+zip")));
+        assert!(!is_protected_content(&headers("application/json")));
+        assert!(!is_protected_content(&HeaderMap::new()));
+    }
+}
@@ -0,0 +1,206 @@
+//! Region overrides for requests forwarded to the Kobo store API.
+//!
+//! The store decides which catalog, prices, and currency to show from locale related
+//! query parameters and the `Accept-Language` header sent by the device. Overriding
+//! them lets a device bought in one region consistently browse another region's
+//! catalog through the proxy.
+
+pub use implementation::RegionOverride;
+
+mod implementation {
+    use std::borrow::Cow;
+
+    use anyhow::{Context as _, Result, bail};
+    use hyper::{HeaderMap, header, header::HeaderValue};
+
+    /// Overrides applied to every request forwarded upstream.
+    #[derive(Debug, Default)]
+    pub struct RegionOverride {
+        accept_language: Option<HeaderValue>,
+        query_overrides: Vec<(String, String)>,
+    }
+
+    impl RegionOverride {
+        /// Creates an override from an optional `Accept-Language` value and query
+        /// parameter overrides in `NAME=VALUE` form.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the `Accept-Language` value is not a valid header value or
+        /// a query override is not in `NAME=VALUE` form.
+        pub fn new<S: AsRef<str>>(
+            accept_language: Option<&str>,
+            query_overrides: &[S],
+        ) -> Result<Self> {
+            let accept_language = accept_language
+                .map(|value| {
+                    HeaderValue::from_str(value)
+                        .with_context(|| format!("Invalid Accept-Language override '{value}'"))
+                })
+                .transpose()?;
+
+            let query_overrides = query_overrides
+                .iter()
+                .map(|query_override| {
+                    let query_override = query_override.as_ref();
+                    let Some((name, value)) = query_override.split_once('=') else {
+                        bail!("Query override '{query_override}' must be in NAME=VALUE form");
+                    };
+                    if name.is_empty() {
+                        bail!("Query override '{query_override}' has an empty name");
+                    }
+                    Ok((name.to_owned(), percent_encode(value)))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(Self {
+                accept_language,
+                query_overrides,
+            })
+        }
+
+        /// Replaces the `Accept-Language` header if an override is configured.
+        pub fn apply_to_headers(&self, headers: &mut HeaderMap) {
+            if let Some(accept_language) = &self.accept_language {
+                headers.insert(header::ACCEPT_LANGUAGE, accept_language.clone());
+            }
+        }
+
+        /// Replaces the values of overridden query parameters. Parameter names are
+        /// matched case-insensitively, and parameters the device did not send are not
+        /// added, since not every endpoint accepts them.
+        pub fn apply_to_path_and_query<'a>(&self, path_and_query: &'a str) -> Cow<'a, str> {
+            let Some((path, query)) = path_and_query.split_once('?') else {
+                return Cow::Borrowed(path_and_query);
+            };
+            if self.query_overrides.is_empty() {
+                return Cow::Borrowed(path_and_query);
+            }
+
+            let query = query
+                .split('&')
+                .map(|pair| {
+                    let name = pair.split_once('=').map_or(pair, |(name, _)| name);
+                    self.query_overrides
+                        .iter()
+                        .find(|(override_name, _)| override_name.eq_ignore_ascii_case(name))
+                        .map_or(Cow::Borrowed(pair), |(_, value)| {
+                            Cow::Owned(format!("{name}={value}"))
+                        })
+                })
+                .collect::<Vec<_>>()
+                .join("&");
+
+            Cow::Owned(format!("{path}?{query}"))
+        }
+    }
+
+    /// Percent-encodes every byte outside the RFC 3986 unreserved set.
+    fn percent_encode(value: &str) -> String {
+        value
+            .bytes()
+            .map(|byte| {
+                if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                    char::from(byte).to_string()
+                } else {
+                    format!("%{byte:02X}")
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{HeaderMap, header};
+
+    use super::*;
+
+    #[test]
+    fn default_leaves_path_and_query_unchanged() {
+        let region_override = RegionOverride::default();
+
+        let path = region_override.apply_to_path_and_query("/v1/products?Locale=en-US");
+
+        assert_eq!(path, "/v1/products?Locale=en-US");
+    }
+
+    #[test]
+    fn apply_to_path_and_query_replaces_matching_parameters() {
+        let region_override = RegionOverride::new(None, &["locale=fr-CA"]).unwrap();
+
+        let path = region_override.apply_to_path_and_query("/v1/products?Locale=en-US&page=2");
+
+        assert_eq!(path, "/v1/products?Locale=fr-CA&page=2");
+    }
+
+    #[test]
+    fn apply_to_path_and_query_does_not_add_missing_parameters() {
+        let region_override = RegionOverride::new(None, &["CurrencyCode=CAD"]).unwrap();
+
+        let path = region_override.apply_to_path_and_query("/v1/products?page=2");
+
+        assert_eq!(path, "/v1/products?page=2");
+    }
+
+    #[test]
+    fn apply_to_path_and_query_ignores_paths_without_query() {
+        let region_override = RegionOverride::new(None, &["Locale=fr-CA"]).unwrap();
+
+        let path = region_override.apply_to_path_and_query("/v1/products");
+
+        assert_eq!(path, "/v1/products");
+    }
+
+    #[test]
+    fn query_override_values_are_percent_encoded() {
+        let region_override = RegionOverride::new(None, &["Locale=fr CA"]).unwrap();
+
+        let path = region_override.apply_to_path_and_query("/v1/products?Locale=en");
+
+        assert_eq!(path, "/v1/products?Locale=fr%20CA");
+    }
+
+    #[test]
+    fn apply_to_headers_overrides_accept_language() {
+        let region_override = RegionOverride::new::<&str>(Some("fr-CA"), &[]).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_LANGUAGE, "en-US".parse().unwrap());
+
+        region_override.apply_to_headers(&mut headers);
+
+        assert_eq!(headers.get(header::ACCEPT_LANGUAGE).unwrap(), "fr-CA");
+    }
+
+    #[test]
+    fn apply_to_headers_without_override_keeps_header() {
+        let region_override = RegionOverride::default();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_LANGUAGE, "en-US".parse().unwrap());
+
+        region_override.apply_to_headers(&mut headers);
+
+        assert_eq!(headers.get(header::ACCEPT_LANGUAGE).unwrap(), "en-US");
+    }
+
+    #[test]
+    fn new_rejects_query_override_without_value() {
+        let result = RegionOverride::new(None, &["Locale"]);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn new_rejects_query_override_without_name() {
+        let result = RegionOverride::new(None, &["=fr-CA"]);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn new_rejects_invalid_accept_language() {
+        let result = RegionOverride::new::<&str>(Some("fr\nCA"), &[]);
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! RFC 6902 JSON Patch documents applied to Kobo API responses.
+//!
+//! Patches are keyed by route template and give precise structured edits to a
+//! response, such as replacing a single field, where string replacement is too blunt.
+
+pub use implementation::ResponsePatches;
+
+mod implementation {
+    use std::collections::HashMap;
+
+    use anyhow::{Context as _, Result, bail};
+    use json_patch::Patch;
+
+    /// JSON Patch documents keyed by route template.
+    #[derive(Debug, Default)]
+    pub struct ResponsePatches {
+        patches: HashMap<String, Patch>,
+    }
+
+    impl ResponsePatches {
+        /// Creates response patches from JSON Patch documents keyed by route template.
+        pub fn new(patches: HashMap<String, Patch>) -> Self {
+            Self { patches }
+        }
+
+        /// Reads JSON Patch documents from `ROUTE=FILE` specifications.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if a specification is malformed, or a file cannot be read
+        /// or is not a JSON Patch document.
+        pub async fn read<S: AsRef<str>>(specifications: &[S]) -> Result<Self> {
+            let mut patches = HashMap::new();
+            for specification in specifications {
+                let specification = specification.as_ref();
+                let Some((route, path)) = specification.split_once('=') else {
+                    bail!("Response patch '{specification}' must be in ROUTE=FILE form");
+                };
+                let document = tokio::fs::read(path)
+                    .await
+                    .with_context(|| format!("Failed to read response patch '{path}'"))?;
+                let document: Patch = serde_json::from_slice(&document)
+                    .with_context(|| format!("Response patch '{path}' is not a JSON Patch"))?;
+                patches.insert(route.to_owned(), document);
+            }
+
+            Ok(Self::new(patches))
+        }
+
+        /// Checks if no patches are configured.
+        pub fn is_empty(&self) -> bool {
+            self.patches.is_empty()
+        }
+
+        /// Checks if a patch is configured for `route`.
+        pub fn applies_to(&self, route: &str) -> bool {
+            self.patches.contains_key(route)
+        }
+
+        /// Applies the patch for `route` to a JSON body. Returns `None` if no patch is
+        /// configured for the route. Patches are applied atomically, so a failed
+        /// operation leaves the body unchanged.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the body is not JSON or an operation fails, e.g. a
+        /// `test` operation does not match.
+        pub fn apply(&self, route: &str, body: &str) -> Result<Option<String>> {
+            let Some(patch) = self.patches.get(route) else {
+                return Ok(None);
+            };
+            let mut document: serde_json::Value = serde_json::from_str(body)?;
+            json_patch::patch(&mut document, patch)?;
+            Ok(Some(serde_json::to_string(&document)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn patches_for(route: &str, patch: serde_json::Value) -> ResponsePatches {
+        ResponsePatches::new(HashMap::from([(
+            route.to_owned(),
+            serde_json::from_value(patch).unwrap(),
+        )]))
+    }
+
+    #[test]
+    fn apply_patches_configured_route() {
+        let patches = patches_for(
+            "/v1/initialization",
+            json!([
+                {"op": "replace", "path": "/Resources/library_sync", "value": "local"},
+                {"op": "remove", "path": "/Resources/ads"}
+            ]),
+        );
+
+        let body = patches
+            .apply(
+                "/v1/initialization",
+                r#"{"Resources": {"library_sync": "kobo", "ads": "on"}}"#,
+            )
+            .unwrap();
+
+        assert_eq!(
+            body.as_deref(),
+            Some(r#"{"Resources":{"library_sync":"local"}}"#)
+        );
+        assert!(patches.apply("/v1/user/profile", "{}").unwrap().is_none());
+    }
+
+    #[test]
+    fn apply_fails_when_test_operation_does_not_match() {
+        let patches = patches_for(
+            "/v1/initialization",
+            json!([{"op": "test", "path": "/Version", "value": 2}]),
+        );
+
+        assert!(
+            patches
+                .apply("/v1/initialization", r#"{"Version": 1}"#)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn read_rejects_malformed_specifications() {
+        assert!(
+            ResponsePatches::read(&["/v1/initialization"])
+                .await
+                .is_err()
+        );
+        assert!(
+            ResponsePatches::read(&["/v1/initialization=/nonexistent/patch.json"])
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,294 @@
+//! Path templating for log fields and metric labels.
+//!
+//! Raw request paths contain book, tag, and product IDs, which makes them unsuitable as
+//! labels. Paths are matched against a list of templates such as
+//! `/v1/library/{id}/state`, with user-supplied templates taking precedence over the
+//! built-in Kobo ones. Paths that match no template have ID-like segments replaced with
+//! `{id}` so the set of distinct values stays bounded.
+
+pub use implementation::RouteTemplates;
+
+mod implementation {
+    use std::borrow::Cow;
+
+    use anyhow::{Result, bail};
+
+    /// Templates for the Kobo store API endpoints known to carry IDs in their paths.
+    const DEFAULT_TEMPLATES: &[&str] = &[
+        "/v1/library/sync",
+        "/v1/library/tags/{id}/items/delete",
+        "/v1/library/tags/{id}/items",
+        "/v1/library/tags/{id}",
+        "/v1/library/{id}/metadata",
+        "/v1/library/{id}/state",
+        "/v1/library/{id}/preview",
+        "/v1/library/{id}",
+        "/v1/products/books/series/{id}",
+        "/v1/products/books/{id}",
+        "/v1/products/{id}/nextread",
+        "/v1/products/{id}/prices",
+        "/v1/products/{id}/recommendations",
+        "/v1/products/{id}/reviews",
+        "/{id}/{width}/{height}/{quality}/{greyscale}/image.jpg",
+        "/{id}/{width}/{height}/{greyscale}/image.jpg",
+    ];
+
+    /// The placeholder used for ID-like segments that match no template.
+    const ID_PLACEHOLDER: &str = "{id}";
+
+    /// A single segment of a route template.
+    #[derive(Debug)]
+    enum Segment {
+        /// A segment that must match exactly.
+        Literal(String),
+        /// A `{name}` segment that matches any single path segment.
+        Placeholder,
+    }
+
+    /// A parsed route template such as `/v1/library/{id}/state`.
+    #[derive(Debug)]
+    struct RouteTemplate {
+        template: String,
+        segments: Vec<Segment>,
+    }
+
+    impl RouteTemplate {
+        /// Parses a template. Templates must start with `/` and placeholders must span a
+        /// whole segment.
+        fn parse(template: &str) -> Result<Self> {
+            let Some(path) = template.strip_prefix('/') else {
+                bail!("Route template '{template}' must start with '/'");
+            };
+
+            let segments = path
+                .split('/')
+                .map(|segment| {
+                    if segment.starts_with('{') && segment.ends_with('}') && segment.len() > 2 {
+                        Ok(Segment::Placeholder)
+                    } else if segment.contains(['{', '}']) {
+                        bail!("Route template '{template}' has a malformed placeholder")
+                    } else {
+                        Ok(Segment::Literal(segment.to_owned()))
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(Self {
+                template: template.to_owned(),
+                segments,
+            })
+        }
+
+        /// Checks if the path matches this template segment by segment.
+        fn matches(&self, path: &str) -> bool {
+            let Some(path) = path.strip_prefix('/') else {
+                return false;
+            };
+
+            let mut path_segments = path.split('/');
+            let all_segments_match = self.segments.iter().all(|segment| {
+                path_segments
+                    .next()
+                    .is_some_and(|path_segment| match segment {
+                        Segment::Literal(literal) => literal == path_segment,
+                        Segment::Placeholder => !path_segment.is_empty(),
+                    })
+            });
+
+            all_segments_match && path_segments.next().is_none()
+        }
+    }
+
+    /// An ordered set of route templates used to normalize request paths.
+    #[derive(Debug)]
+    pub struct RouteTemplates {
+        templates: Vec<RouteTemplate>,
+    }
+
+    impl Default for RouteTemplates {
+        /// Creates a set containing only the built-in Kobo templates.
+        fn default() -> Self {
+            Self {
+                templates: DEFAULT_TEMPLATES
+                    .iter()
+                    .filter_map(|template| RouteTemplate::parse(template).ok())
+                    .collect(),
+            }
+        }
+    }
+
+    impl RouteTemplates {
+        /// Creates a set of templates from user-supplied templates followed by the
+        /// built-in Kobo templates. Earlier templates take precedence.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if any template is malformed.
+        pub fn new<I, S>(user_templates: I) -> Result<Self>
+        where
+            I: IntoIterator<Item = S>,
+            S: AsRef<str>,
+        {
+            let mut templates = user_templates
+                .into_iter()
+                .map(|template| RouteTemplate::parse(template.as_ref()))
+                .collect::<Result<Vec<_>>>()?;
+            templates.extend(Self::default().templates);
+
+            Ok(Self { templates })
+        }
+
+        /// Returns the number of templates in the set.
+        #[cfg(test)]
+        pub fn len(&self) -> usize {
+            self.templates.len()
+        }
+
+        /// Returns the template matching `path`, or the path with ID-like segments
+        /// replaced by `{id}` if no template matches.
+        pub fn normalize<'a>(&'a self, path: &'a str) -> Cow<'a, str> {
+            if let Some(template) = self.templates.iter().find(|t| t.matches(path)) {
+                return Cow::Borrowed(template.template.as_str());
+            }
+
+            if !path.split('/').any(is_id_like) {
+                return Cow::Borrowed(path);
+            }
+
+            Cow::Owned(
+                path.split('/')
+                    .map(|segment| {
+                        if is_id_like(segment) {
+                            ID_PLACEHOLDER
+                        } else {
+                            segment
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            )
+        }
+    }
+
+    /// Checks if a path segment looks like an identifier: purely numeric, or a long
+    /// run of hex digits and dashes such as a UUID.
+    fn is_id_like(segment: &str) -> bool {
+        if segment.is_empty() {
+            return false;
+        }
+
+        let is_numeric = segment.bytes().all(|b| b.is_ascii_digit());
+        let is_hex_id =
+            segment.len() >= 16 && segment.bytes().all(|b| b.is_ascii_hexdigit() || b == b'-');
+
+        is_numeric || is_hex_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOK_ID: &str = "3f2a9c4e-8b1d-4e6f-9a7c-2d5b8e1f0a3c";
+
+    #[test]
+    fn default_parses_all_builtin_templates() {
+        let templates = RouteTemplates::default();
+
+        assert_eq!(templates.len(), 16);
+    }
+
+    #[test]
+    fn new_appends_builtin_templates() {
+        let templates = RouteTemplates::new(["/v1/custom/{id}"]).unwrap();
+
+        assert_eq!(templates.len(), 17);
+    }
+
+    #[test]
+    fn normalize_matches_builtin_template() {
+        let templates = RouteTemplates::default();
+
+        let path = format!("/v1/library/{BOOK_ID}/state");
+
+        let route = templates.normalize(&path);
+
+        assert_eq!(route, "/v1/library/{id}/state");
+    }
+
+    #[test]
+    fn normalize_prefers_literal_sync_route() {
+        let templates = RouteTemplates::default();
+
+        let route = templates.normalize("/v1/library/sync");
+
+        assert_eq!(route, "/v1/library/sync");
+    }
+
+    #[test]
+    fn normalize_matches_image_template() {
+        let templates = RouteTemplates::default();
+        let path = format!("/{BOOK_ID}/355/530/85/false/image.jpg");
+
+        let route = templates.normalize(&path);
+
+        assert_eq!(
+            route,
+            "/{id}/{width}/{height}/{quality}/{greyscale}/image.jpg"
+        );
+    }
+
+    #[test]
+    fn normalize_user_template_takes_precedence() {
+        let templates = RouteTemplates::new(["/v1/library/{book}/state"]).unwrap();
+
+        let path = format!("/v1/library/{BOOK_ID}/state");
+
+        let route = templates.normalize(&path);
+
+        assert_eq!(route, "/v1/library/{book}/state");
+    }
+
+    #[test]
+    fn normalize_replaces_id_like_segments_without_template() {
+        let templates = RouteTemplates::default();
+
+        let path = format!("/v2/unknown/{BOOK_ID}/items/12345");
+
+        let route = templates.normalize(&path);
+
+        assert_eq!(route, "/v2/unknown/{id}/items/{id}");
+    }
+
+    #[test]
+    fn normalize_keeps_paths_without_ids() {
+        let templates = RouteTemplates::default();
+
+        let route = templates.normalize("/v1/initialization");
+
+        assert_eq!(route, "/v1/initialization");
+    }
+
+    #[test]
+    fn normalize_does_not_match_longer_paths() {
+        let templates = RouteTemplates::new(["/v1/custom/{id}"]).unwrap();
+
+        let route = templates.normalize("/v1/custom/abc/extra");
+
+        assert_eq!(route, "/v1/custom/abc/extra");
+    }
+
+    #[test]
+    fn new_rejects_template_without_leading_slash() {
+        let result = RouteTemplates::new(["v1/library/{id}"]);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn new_rejects_malformed_placeholder() {
+        let result = RouteTemplates::new(["/v1/library/{id"]);
+
+        assert!(result.is_err());
+    }
+}
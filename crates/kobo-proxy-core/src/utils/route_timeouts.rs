@@ -0,0 +1,130 @@
+//! Per-route request timeouts and the deadlines derived from them.
+//!
+//! A request's deadline is fixed when it arrives, so the time left for the upstream
+//! request and for buffering its response shrinks as the request is handled. The
+//! remaining budget is passed upstream so the Kobo API can see how long it has.
+
+pub use implementation::{BUDGET_HEADER, Deadline, RouteTimeouts};
+
+mod implementation {
+    use std::{collections::HashMap, time::Duration};
+
+    use anyhow::{Context as _, Result, bail};
+    use tokio::time::Instant;
+
+    /// Header carrying the milliseconds left before a request's deadline.
+    pub const BUDGET_HEADER: &str = "x-kobo-proxy-budget-ms";
+
+    /// The instant by which a request must be answered. Stored as a request extension.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Deadline(pub Instant);
+
+    impl Deadline {
+        /// Returns the time left before the deadline, or zero if it has passed.
+        pub fn remaining(self) -> Duration {
+            self.0.saturating_duration_since(Instant::now())
+        }
+    }
+
+    /// Request timeouts keyed by route template, with an optional default for every
+    /// other route.
+    #[derive(Debug, Default)]
+    pub struct RouteTimeouts {
+        routes: HashMap<String, Duration>,
+        default: Option<Duration>,
+    }
+
+    impl RouteTimeouts {
+        /// Creates timeouts in `ROUTE=MS` form, where `ROUTE` is a route template or
+        /// `*` for every route without its own timeout.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if a timeout is malformed or zero.
+        pub fn new<S: AsRef<str>>(timeouts: &[S]) -> Result<Self> {
+            let mut route_timeouts = Self::default();
+            for timeout in timeouts {
+                let timeout = timeout.as_ref();
+                let Some((route, milliseconds)) = timeout.split_once('=') else {
+                    bail!("Route timeout '{timeout}' must be in ROUTE=MS form");
+                };
+                let duration = milliseconds
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|milliseconds| *milliseconds > 0)
+                    .map(Duration::from_millis)
+                    .with_context(|| {
+                        format!(
+                            "Route timeout '{timeout}' must be a positive number of milliseconds"
+                        )
+                    })?;
+                match route {
+                    "" => bail!("Route timeout '{timeout}' has an empty route"),
+                    "*" => route_timeouts.default = Some(duration),
+                    route => {
+                        route_timeouts.routes.insert(route.to_owned(), duration);
+                    }
+                }
+            }
+
+            Ok(route_timeouts)
+        }
+
+        /// Checks if no timeouts are configured.
+        pub fn is_empty(&self) -> bool {
+            self.routes.is_empty() && self.default.is_none()
+        }
+
+        /// Returns the timeout for `route`, if one is configured.
+        pub fn timeout_for(&self, route: &str) -> Option<Duration> {
+            self.routes.get(route).copied().or(self.default)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn timeout_for_prefers_route_over_default() {
+        let timeouts = RouteTimeouts::new(&["*=5000", "/v1/library/sync=30000"]).unwrap();
+
+        assert_eq!(
+            timeouts.timeout_for("/v1/library/sync"),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            timeouts.timeout_for("/v1/initialization"),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn timeout_for_returns_none_without_default() {
+        let timeouts = RouteTimeouts::new(&["/v1/library/sync=30000"]).unwrap();
+
+        assert_eq!(timeouts.timeout_for("/v1/initialization"), None);
+        assert!(RouteTimeouts::default().is_empty());
+    }
+
+    #[test]
+    fn new_rejects_malformed_timeouts() {
+        for timeout in ["/v1/library/sync", "/v1/library/sync=0", "=100", "*=soon"] {
+            assert!(RouteTimeouts::new(&[timeout]).is_err(), "{timeout}");
+        }
+    }
+
+    #[test]
+    fn deadline_remaining_saturates_at_zero() {
+        let now = tokio::time::Instant::now();
+
+        assert!(Deadline(now + Duration::from_secs(60)).remaining() > Duration::from_secs(59));
+        assert_eq!(
+            Deadline(now.checked_sub(Duration::from_secs(1)).unwrap()).remaining(),
+            Duration::ZERO
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Validation of Kobo store API responses against the typed payloads in
+//! [`kobo_protocol`](crate::kobo_protocol).
+//!
+//! Devices misbehave in confusing ways when Kobo renames a field or changes its type.
+//! Checking responses for the endpoints with typed payloads reports such changes as
+//! soon as the proxy sees them. Fields and sync item kinds the types do not model
+//! are ignored, so only changes to what devices rely on are reported.
+
+pub use implementation::{SchemaValidation, has_schema, validate_response};
+
+mod implementation {
+    use anyhow::{Context as _, Result};
+    use serde_json::Value;
+
+    use crate::kobo_protocol::{ReadingState, SyncItem};
+
+    /// Route of the library sync endpoint, whose items are [`SyncItem`]s.
+    const LIBRARY_SYNC_ROUTE: &str = "/v1/library/sync";
+
+    /// Route of the reading state endpoint, which returns [`ReadingState`]s.
+    const READING_STATE_ROUTE: &str = "/v1/library/{id}/state";
+
+    /// Kinds of library sync items modelled by [`SyncItem`].
+    const TYPED_SYNC_ITEMS: [&str; 3] = [
+        "NewEntitlement",
+        "ChangedEntitlement",
+        "ChangedReadingState",
+    ];
+
+    /// Whether upstream responses are checked against the typed payloads.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum SchemaValidation {
+        /// Forward responses without checking them.
+        #[default]
+        Off,
+        /// Log a warning for each response that does not match, forwarding it anyway.
+        Warn,
+        /// Log an error and answer with `502 Bad Gateway` instead of forwarding a
+        /// response that does not match, e.g. to fail tests against the live API.
+        Strict,
+    }
+
+    impl SchemaValidation {
+        /// Checks if responses are validated at all.
+        #[must_use]
+        pub fn is_enabled(self) -> bool {
+            self != Self::Off
+        }
+    }
+
+    /// Checks the items of a library sync response whose kind is modelled.
+    fn validate_sync_items(body: &str) -> Result<()> {
+        let items: Vec<Value> = serde_json::from_str(body)?;
+        for (index, item) in items.into_iter().enumerate() {
+            let is_typed = item.as_object().is_some_and(|object| {
+                object.len() == 1
+                    && object
+                        .keys()
+                        .all(|kind| TYPED_SYNC_ITEMS.contains(&&**kind))
+            });
+            if is_typed {
+                serde_json::from_value::<SyncItem>(item)
+                    .with_context(|| format!("sync item {index}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks if `route`, formatted as a route template, has typed payloads.
+    #[must_use]
+    pub fn has_schema(route: &str) -> bool {
+        matches!(route, LIBRARY_SYNC_ROUTE | READING_STATE_ROUTE)
+    }
+
+    /// Validates the body of a successful `GET` response for `route`, formatted as a
+    /// route template. Returns `None` for routes without typed payloads.
+    #[must_use]
+    pub fn validate_response(route: &str, body: &str) -> Option<Result<()>> {
+        match route {
+            LIBRARY_SYNC_ROUTE => Some(validate_sync_items(body)),
+            READING_STATE_ROUTE => Some(
+                serde_json::from_str::<Vec<ReadingState>>(body)
+                    .map(drop)
+                    .map_err(Into::into),
+            ),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::Fixture;
+
+    #[test]
+    fn only_off_disables_validation() {
+        assert!(!SchemaValidation::Off.is_enabled());
+        assert!(SchemaValidation::Warn.is_enabled());
+        assert!(SchemaValidation::Strict.is_enabled());
+    }
+
+    #[test]
+    fn recorded_responses_match_the_typed_payloads() {
+        assert!(
+            validate_response("/v1/library/sync", Fixture::LibrarySync.body())
+                .unwrap()
+                .is_ok()
+        );
+        assert!(
+            validate_response("/v1/library/{id}/state", Fixture::ReadingState.body())
+                .unwrap()
+                .is_ok()
+        );
+        assert!(validate_response("/v1/user/profile", Fixture::UserProfile.body()).is_none());
+    }
+
+    #[test]
+    fn reports_changed_fields() {
+        let body = Fixture::LibrarySync
+            .body()
+            .replace("\"EntitlementId\"", "\"EntitlementID\"");
+
+        let error = validate_response("/v1/library/sync", &body)
+            .unwrap()
+            .unwrap_err();
+
+        assert!(format!("{error:#}").contains("sync item 0"), "{error:#}");
+    }
+
+    #[test]
+    fn ignores_untyped_sync_items() {
+        let body = r#"[{"NewTag": {"Tag": {"Name": "Favourites"}}}]"#;
+
+        assert!(validate_response("/v1/library/sync", body).unwrap().is_ok());
+    }
+}
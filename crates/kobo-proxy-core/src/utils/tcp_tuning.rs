@@ -0,0 +1,123 @@
+//! TCP socket tuning shared by the inbound listener and the upstream client.
+//!
+//! E-readers on power-saving Wi-Fi drop idle connections aggressively. Tuned TCP
+//! keepalives keep long-lived connections open, and disabling Nagle's algorithm
+//! avoids delaying the many small requests a sync makes.
+
+pub use implementation::TcpTuning;
+
+mod implementation {
+    use std::time::Duration;
+
+    use hyper_util::client::legacy::connect::HttpConnector;
+    use socket2::{SockRef, TcpKeepalive};
+    use tokio::net::TcpStream;
+
+    /// Socket options applied to TCP connections.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct TcpTuning {
+        /// Whether `TCP_NODELAY` is set, disabling Nagle's algorithm.
+        pub nodelay: bool,
+        /// Idle time before the first keepalive probe. `None` leaves `SO_KEEPALIVE` off.
+        pub keepalive_time: Option<Duration>,
+        /// Time between keepalive probes. Only used when `keepalive_time` is set.
+        pub keepalive_interval: Option<Duration>,
+    }
+
+    impl TcpTuning {
+        /// Returns the keepalive parameters, if keepalives are enabled.
+        fn keepalive(&self) -> Option<TcpKeepalive> {
+            let keepalive = TcpKeepalive::new().with_time(self.keepalive_time?);
+            Some(match self.keepalive_interval {
+                Some(interval) => keepalive.with_interval(interval),
+                None => keepalive,
+            })
+        }
+
+        /// Applies the socket options to an accepted stream.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if a socket option cannot be set.
+        pub fn apply_to_stream(&self, stream: &TcpStream) -> std::io::Result<()> {
+            if self.nodelay {
+                stream.set_nodelay(true)?;
+            }
+            if let Some(keepalive) = self.keepalive() {
+                SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+            }
+
+            Ok(())
+        }
+
+        /// Applies the socket options to connections opened by an HTTP connector.
+        pub fn apply_to_connector<R>(&self, connector: &mut HttpConnector<R>) {
+            connector.set_nodelay(self.nodelay);
+            connector.set_keepalive(self.keepalive_time);
+            connector.set_keepalive_interval(self.keepalive_time.and(self.keepalive_interval));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use socket2::SockRef;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+
+    async fn connected_stream() -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (client, _server) = tokio::join!(TcpStream::connect(address), listener.accept());
+        client.unwrap()
+    }
+
+    #[tokio::test]
+    async fn default_leaves_stream_unchanged() {
+        let stream = connected_stream().await;
+
+        TcpTuning::default().apply_to_stream(&stream).unwrap();
+
+        assert!(!stream.nodelay().unwrap());
+        assert!(!SockRef::from(&stream).keepalive().unwrap());
+    }
+
+    #[tokio::test]
+    async fn apply_to_stream_sets_nodelay() {
+        let stream = connected_stream().await;
+        let tuning = TcpTuning {
+            nodelay: true,
+            ..TcpTuning::default()
+        };
+
+        tuning.apply_to_stream(&stream).unwrap();
+
+        assert!(stream.nodelay().unwrap());
+    }
+
+    #[tokio::test]
+    async fn apply_to_stream_enables_keepalive() {
+        let stream = connected_stream().await;
+        let tuning = TcpTuning {
+            keepalive_time: Some(Duration::from_secs(30)),
+            keepalive_interval: Some(Duration::from_secs(10)),
+            ..TcpTuning::default()
+        };
+
+        tuning.apply_to_stream(&stream).unwrap();
+
+        let socket = SockRef::from(&stream);
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(
+            socket.tcp_keepalive_time().unwrap(),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            socket.tcp_keepalive_interval().unwrap(),
+            Duration::from_secs(10)
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Client IP resolution behind trusted reverse proxies.
+//!
+//! When the server runs behind a reverse proxy, every connection comes from the
+//! proxy, so the client IP is taken from the `X-Forwarded-For` or `X-Real-IP` header
+//! instead. Those headers are only honored on connections from a configured trusted
+//! proxy; otherwise anyone could spoof their address by sending them.
+
+pub use implementation::TrustedProxies;
+
+mod implementation {
+    use std::net::IpAddr;
+
+    use anyhow::{Context as _, Result};
+    use hyper::HeaderMap;
+    use ipnet::IpNet;
+
+    /// Header listing the addresses a request was forwarded for, client first.
+    const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+    /// Header some proxies use to pass the client address.
+    const REAL_IP_HEADER: &str = "x-real-ip";
+
+    /// The networks of the reverse proxies whose forwarding headers are honored.
+    #[derive(Clone, Debug, Default)]
+    pub struct TrustedProxies {
+        networks: Vec<IpNet>,
+    }
+
+    impl TrustedProxies {
+        /// Parses trusted proxies from CIDRs such as `10.0.0.0/8` or bare addresses.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if a network is not a valid CIDR or address.
+        pub fn new<S: AsRef<str>>(networks: &[S]) -> Result<Self> {
+            let networks = networks
+                .iter()
+                .map(|network| {
+                    let network = network.as_ref().trim();
+                    network
+                        .parse::<IpNet>()
+                        .or_else(|_| network.parse::<IpAddr>().map(IpNet::from))
+                        .with_context(|| format!("Invalid trusted proxy '{network}'"))
+                })
+                .collect::<Result<_>>()?;
+
+            Ok(Self { networks })
+        }
+
+        /// Whether no proxies are trusted.
+        pub fn is_empty(&self) -> bool {
+            self.networks.is_empty()
+        }
+
+        fn is_trusted(&self, address: IpAddr) -> bool {
+            let address = address.to_canonical();
+            self.networks
+                .iter()
+                .any(|network| network.contains(&address))
+        }
+
+        /// Resolves the client IP of a request received from `peer`. `X-Forwarded-For`
+        /// is walked from the nearest hop back while the hops are trusted proxies,
+        /// stopping at the first untrusted or unparsable hop, so addresses prepended by
+        /// the client itself are ignored. `X-Real-IP` is only used without it.
+        pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+            if !self.is_trusted(peer) {
+                return peer;
+            }
+
+            let mut forwarded = headers.get_all(FORWARDED_FOR_HEADER).iter().peekable();
+            if forwarded.peek().is_some() {
+                let hops: Vec<&str> = forwarded
+                    .flat_map(|value| value.to_str().unwrap_or_default().split(','))
+                    .collect();
+                let mut client = peer;
+                for hop in hops.into_iter().rev() {
+                    if !self.is_trusted(client) {
+                        break;
+                    }
+                    let Ok(address) = hop.trim().parse() else {
+                        break;
+                    };
+                    client = address;
+                }
+                return client;
+            }
+
+            headers
+                .get(REAL_IP_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(peer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use hyper::{HeaderMap, header::HeaderValue};
+
+    use super::*;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    fn headers(name: &'static str, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn ignores_headers_from_untrusted_peers() {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8"]).unwrap();
+
+        let client = proxies.client_ip(
+            ip("203.0.113.5"),
+            &headers("x-forwarded-for", "198.51.100.1"),
+        );
+
+        assert_eq!(client, ip("203.0.113.5"));
+    }
+
+    #[test]
+    fn skips_trusted_hops_in_forwarded_for() {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8", "192.168.1.1"]).unwrap();
+
+        let client = proxies.client_ip(
+            ip("10.0.0.2"),
+            &headers("x-forwarded-for", "1.2.3.4, 198.51.100.1, 192.168.1.1"),
+        );
+
+        assert_eq!(client, ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn ignores_entries_prepended_by_the_client() {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8"]).unwrap();
+
+        let client = proxies.client_ip(
+            ip("10.0.0.2"),
+            &headers("x-forwarded-for", "9.9.9.9, junk, 198.51.100.1"),
+        );
+
+        assert_eq!(client, ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn stops_at_unparsable_hops() {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8"]).unwrap();
+        let mut forwarded = headers("x-forwarded-for", "198.51.100.1, junk");
+        forwarded.insert("x-real-ip", HeaderValue::from_static("9.9.9.9"));
+
+        let client = proxies.client_ip(ip("10.0.0.2"), &forwarded);
+
+        assert_eq!(client, ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn falls_back_to_real_ip() {
+        let proxies = TrustedProxies::new(&["::1"]).unwrap();
+
+        let client = proxies.client_ip(ip("::1"), &headers("x-real-ip", "198.51.100.1"));
+
+        assert_eq!(client, ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn rejects_invalid_networks() {
+        assert!(TrustedProxies::new(&["10.0.0.0/33"]).is_err());
+        assert!(TrustedProxies::new(&["proxy.local"]).is_err());
+    }
+}
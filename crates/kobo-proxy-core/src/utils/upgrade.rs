@@ -0,0 +1,108 @@
+//! Helpers for passing HTTP upgrades (e.g. WebSocket) through the proxy.
+//!
+//! Upgraded connections cannot be buffered like regular requests, so once both the
+//! device and the upstream agree to switch protocols the raw connections are joined
+//! and bytes are copied in both directions until either side closes.
+
+pub use implementation::{is_upgrade_request, tunnel_upgrade};
+
+mod implementation {
+    use hyper::{HeaderMap, header};
+    use hyper_util::rt::TokioIo;
+
+    /// Checks if the headers request a protocol upgrade (`Connection: upgrade` together
+    /// with an `Upgrade` header).
+    pub fn is_upgrade_request(headers: &HeaderMap) -> bool {
+        let connection_upgrade = headers
+            .get_all(header::CONNECTION)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+
+        connection_upgrade && headers.contains_key(header::UPGRADE)
+    }
+
+    /// Spawns a task that waits for both sides of an upgrade to complete and then
+    /// tunnels bytes between them until either connection closes.
+    pub fn tunnel_upgrade(
+        downstream: hyper::upgrade::OnUpgrade,
+        upstream: hyper::upgrade::OnUpgrade,
+    ) {
+        tokio::spawn(async move {
+            let (downstream, upstream) = match tokio::try_join!(downstream, upstream) {
+                Ok(connections) => connections,
+                Err(e) => {
+                    tracing::warn!("Failed to complete upgraded connection: {e}");
+                    return;
+                }
+            };
+
+            let mut downstream = TokioIo::new(downstream);
+            let mut upstream = TokioIo::new(upstream);
+            match tokio::io::copy_bidirectional(&mut downstream, &mut upstream).await {
+                Ok((sent, received)) => tracing::debug!(
+                    sent_bytes = sent,
+                    received_bytes = received,
+                    "Upgraded connection closed"
+                ),
+                Err(e) => tracing::warn!("Upgraded connection closed with error: {e}"),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{HeaderMap, header};
+
+    use super::*;
+
+    fn headers(pairs: &[(header::HeaderName, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), value.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn is_upgrade_request_true_for_websocket_handshake() {
+        let headers = headers(&[
+            (header::CONNECTION, "Upgrade"),
+            (header::UPGRADE, "websocket"),
+        ]);
+
+        assert!(is_upgrade_request(&headers));
+    }
+
+    #[test]
+    fn is_upgrade_request_true_with_multiple_connection_tokens() {
+        let headers = headers(&[
+            (header::CONNECTION, "keep-alive, Upgrade"),
+            (header::UPGRADE, "websocket"),
+        ]);
+
+        assert!(is_upgrade_request(&headers));
+    }
+
+    #[test]
+    fn is_upgrade_request_false_without_upgrade_header() {
+        let headers = headers(&[(header::CONNECTION, "upgrade")]);
+
+        assert!(!is_upgrade_request(&headers));
+    }
+
+    #[test]
+    fn is_upgrade_request_false_without_connection_header() {
+        let headers = headers(&[(header::UPGRADE, "websocket")]);
+
+        assert!(!is_upgrade_request(&headers));
+    }
+
+    #[test]
+    fn is_upgrade_request_false_for_keep_alive() {
+        let headers = headers(&[(header::CONNECTION, "keep-alive")]);
+
+        assert!(!is_upgrade_request(&headers));
+    }
+}
@@ -5,20 +5,22 @@ pub use implementation::App;
 mod implementation {
     use std::{
         net::SocketAddr,
-        sync::{Mutex, PoisonError},
+        sync::{Arc, Mutex, PoisonError},
+        time::Duration,
     };
 
     use anyhow::Result;
     use axum::serve::Listener;
+    use kobo_proxy_core::{
+        RequestHook, Server, ServerBuilder,
+        listener::{AcceptPolicy, IntoListener, TokioTcpListener},
+    };
     use tokio_util::sync::CancellationToken;
 
-    use crate::{
-        command_line_arguments::CommandLineArguments,
-        server::{
-            Server, ServerBuilder,
-            listener::{IntoListener, TokioTcpListener},
-        },
-    };
+    use crate::{command_line_arguments::CommandLineArguments, crash_report::CrashReporter};
+
+    /// Default delay before the other address family is tried for upstream connections.
+    const DEFAULT_HAPPY_EYEBALLS_TIMEOUT: Duration = Duration::from_millis(300);
 
     /// The main application struct that orchestrates the entire application lifecycle.
     pub struct App<L> {
@@ -30,6 +32,8 @@ mod implementation {
         server_started: CancellationToken,
         // The server builder
         server_builder: Mutex<Option<ServerBuilder<L>>>,
+        // Optional crash reporter, installed when the application runs
+        crash_reporter: Option<Arc<CrashReporter>>,
     }
 
     impl<L> App<L>
@@ -46,15 +50,35 @@ mod implementation {
                 server: Mutex::new(None),
                 server_started: CancellationToken::new(),
                 server_builder: Mutex::new(Some(server_builder)),
+                crash_reporter: None,
             }
         }
 
+        /// Adds a hook that modifies requests before they are forwarded to the Kobo
+        /// store API, e.g. to add credentials for an authenticated egress gateway. Has
+        /// no effect once the server is started.
+        #[must_use]
+        pub fn with_request_hook(mut self, hook: Arc<dyn RequestHook>) -> Self {
+            let server_builder = self
+                .server_builder
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner);
+            *server_builder = server_builder
+                .take()
+                .map(|server_builder| server_builder.request_hook(hook));
+            self
+        }
+
         /// Initialize and run the application
         ///
         /// # Errors
         ///
         /// If the server fails to start.
         pub async fn run(&self) -> Result<()> {
+            if let Some(crash_reporter) = &self.crash_reporter {
+                crash_reporter.install();
+                tokio::spawn(crash_reporter.clone().upload_pending());
+            }
             self.start_server().await?;
             self.wait_for_shutdown_signal().await;
             self.shutdown().await?;
@@ -86,7 +110,7 @@ mod implementation {
             Ok(())
         }
 
-        /// Waits for a shutdown signal (Ctrl+C or cancellation token)
+        /// Waits for a shutdown signal (Ctrl+C, `SIGTERM`, or cancellation token)
         async fn wait_for_shutdown_signal(&self) {
             tokio::select! {
                 result = tokio::signal::ctrl_c() => {
@@ -95,6 +119,7 @@ mod implementation {
                         return;
                     }
                 }
+                () = terminate_signal() => (),
                 () = self.cancellation_token.cancelled() => ()
             }
 
@@ -141,30 +166,171 @@ mod implementation {
         #[must_use]
         pub fn new(command_line_arguments: CommandLineArguments) -> Self {
             let cancellation_token = CancellationToken::new();
+            let config = command_line_arguments.to_redacted_json();
+            let crash_reporter = command_line_arguments
+                .crash_report_dir
+                .clone()
+                .map(|directory| {
+                    Arc::new(CrashReporter::new(
+                        directory,
+                        command_line_arguments.crash_report_url.clone(),
+                        &config,
+                    ))
+                });
+            let mut server_builder = with_connection_options(
+                ServerBuilder::new(cancellation_token.clone()),
+                &command_line_arguments,
+            );
+            if let Some(crash_reporter) = &crash_reporter {
+                server_builder = server_builder.request_hook(crash_reporter.recent_requests());
+            }
             let server_builder =
-                ServerBuilder::new(cancellation_token.clone())
+                server_builder
+                    .config(config)
                     .port(command_line_arguments.port)
                     .frontend_url(command_line_arguments.frontend_url.unwrap_or_else(|| {
                         format!("http://localhost:{}", command_line_arguments.port)
                     }))
+                    .device_frontend_urls(command_line_arguments.device_frontend_urls)
                     .enable_request_logging(command_line_arguments.enable_request_logging)
-                    .enable_response_logging(command_line_arguments.enable_response_logging);
+                    .enable_response_logging(command_line_arguments.enable_response_logging)
+                    .log_body_max_bytes(command_line_arguments.log_body_max_bytes)
+                    .route_templates(command_line_arguments.route_templates)
+                    .clock_skew_warning_seconds(command_line_arguments.clock_skew_warning_seconds)
+                    .auth_failure_backoff_after(command_line_arguments.auth_failure_backoff_after)
+                    .upstream_accept_language(command_line_arguments.upstream_accept_language)
+                    .upstream_query_overrides(command_line_arguments.upstream_query_overrides)
+                    .upstream_headers(command_line_arguments.upstream_headers)
+                    .access_rules(command_line_arguments.access_rules)
+                    .device_groups(command_line_arguments.device_groups)
+                    .group_policies(command_line_arguments.group_policies)
+                    .mask_profile_identifiers(command_line_arguments.mask_profile_identifiers)
+                    .rewrite_profile_urls(command_line_arguments.rewrite_profile_urls)
+                    .chaos_mode(command_line_arguments.enable_chaos_mode)
+                    .chaos_rules(command_line_arguments.chaos_rules)
+                    .synthetic_device_auth(command_line_arguments.synthetic_device_auth)
+                    .landing_page(command_line_arguments.landing_page)
+                    .preserve_header_case(command_line_arguments.preserve_header_case)
+                    .strip_transfer_encoding_firmware(
+                        command_line_arguments.strip_transfer_encoding_firmware,
+                    )
+                    .serialize_device_requests(command_line_arguments.serialize_device_requests)
+                    .upstream_failure_fallbacks(command_line_arguments.upstream_failure_fallbacks)
+                    .upstream_failure_responses(command_line_arguments.upstream_failure_responses)
+                    .response_patches(command_line_arguments.response_patches)
+                    .route_timeouts(command_line_arguments.route_timeouts)
+                    .trusted_proxies(command_line_arguments.trusted_proxies)
+                    .gzip_level(command_line_arguments.gzip_level)
+                    .cookie_policy(command_line_arguments.cookie_policy.into())
+                    .schema_validation(command_line_arguments.schema_validation.into())
+                    .snapshot_interval(
+                        command_line_arguments
+                            .snapshot_interval_seconds
+                            .filter(|&seconds| seconds > 0)
+                            .map(Duration::from_secs),
+                    )
+                    .snapshot_routes(command_line_arguments.snapshot_routes)
+                    .resource_watchdog_interval(
+                        Some(command_line_arguments.resource_watchdog_interval_seconds)
+                            .filter(|&seconds| seconds > 0)
+                            .map(Duration::from_secs),
+                    )
+                    .api_rate_limit(Some(command_line_arguments.api_rate_limit_per_minute))
+                    .memory_warning_bytes(
+                        command_line_arguments
+                            .memory_warning_mb
+                            .map(|megabytes| megabytes.saturating_mul(1024 * 1024)),
+                    )
+                    .run_as_user(command_line_arguments.run_as_user)
+                    .run_as_group(command_line_arguments.run_as_group)
+                    .admin_port(command_line_arguments.admin_port)
+                    .admin_tls_certificate(command_line_arguments.admin_tls_cert)
+                    .admin_tls_key(command_line_arguments.admin_tls_key)
+                    .admin_tls_client_ca(command_line_arguments.admin_tls_client_ca)
+                    .capture_file(command_line_arguments.capture_file);
 
-            Self::with_server_builder(server_builder)
+            Self {
+                crash_reporter,
+                ..Self::with_server_builder(server_builder)
+            }
         }
     }
+
+    /// Applies the listener and upstream connection options to `server_builder`.
+    fn with_connection_options(
+        server_builder: ServerBuilder<TokioTcpListener>,
+        command_line_arguments: &CommandLineArguments,
+    ) -> ServerBuilder<TokioTcpListener> {
+        server_builder
+            .reuse_port(command_line_arguments.reuse_port)
+            .tcp_nodelay(command_line_arguments.tcp_nodelay)
+            .tcp_keepalive(
+                command_line_arguments
+                    .tcp_keepalive_seconds
+                    .map(Duration::from_secs),
+            )
+            .tcp_keepalive_interval(
+                command_line_arguments
+                    .tcp_keepalive_interval_seconds
+                    .map(Duration::from_secs),
+            )
+            .upstream_idle_timeout(
+                command_line_arguments
+                    .upstream_idle_timeout_seconds
+                    .map(Duration::from_secs),
+            )
+            .upstream_address_family(command_line_arguments.upstream_address_family.into())
+            .upstream_dns(command_line_arguments.upstream_dns.clone())
+            .upstream_happy_eyeballs_timeout(
+                match command_line_arguments.upstream_happy_eyeballs_ms {
+                    Some(0) => None,
+                    Some(milliseconds) => Some(Duration::from_millis(milliseconds)),
+                    None => Some(DEFAULT_HAPPY_EYEBALLS_TIMEOUT),
+                },
+            )
+            .accept_policy(AcceptPolicy {
+                pause: Duration::from_millis(command_line_arguments.accept_error_pause_ms),
+                max_pause: Duration::from_millis(command_line_arguments.accept_error_max_pause_ms),
+            })
+    }
+
+    /// Completes when the process receives `SIGTERM`, as sent by service managers
+    /// when stopping or replacing the proxy.
+    #[cfg(unix)]
+    async fn terminate_signal() {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Never completes; `SIGTERM` is only delivered on Unix.
+    #[cfg(not(unix))]
+    async fn terminate_signal() {
+        std::future::pending::<()>().await;
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
+    use kobo_proxy_core::{ServerBuilder, listener::FakeListenerBuilder};
     use tokio_util::sync::CancellationToken;
 
     use super::*;
     use crate::{
-        command_line_arguments::CommandLineArguments,
-        server::{ServerBuilder, listener::FakeListenerBuilder},
+        command_line_arguments::{
+            AddressFamily, CommandLineArguments, CookiePolicy, SchemaValidation,
+        },
+        log_file::{LogFileFormat, LogRotation},
     };
 
     impl App<FakeListenerBuilder> {
@@ -182,11 +348,70 @@ mod tests {
     #[test]
     fn new_creates_app_with_tcp_listener() {
         let args = CommandLineArguments {
+            command: None,
             port: 8080,
+            reuse_port: false,
             frontend_url: Some("http://localhost:8080".to_owned()),
+            device_frontend_urls: Vec::new(),
             enable_request_logging: false,
             enable_response_logging: false,
+            log_body_max_bytes: None,
+            route_templates: Vec::new(),
+            clock_skew_warning_seconds: 300,
+            auth_failure_backoff_after: None,
+            upstream_accept_language: None,
+            upstream_query_overrides: Vec::new(),
+            upstream_headers: Vec::new(),
+            access_rules: Vec::new(),
+            device_groups: Vec::new(),
+            group_policies: Vec::new(),
+            mask_profile_identifiers: false,
+            rewrite_profile_urls: false,
+            enable_chaos_mode: false,
+            chaos_rules: Vec::new(),
+            synthetic_device_auth: false,
+            landing_page: false,
+            preserve_header_case: false,
+            strip_transfer_encoding_firmware: None,
+            serialize_device_requests: false,
+            upstream_failure_fallbacks: false,
+            upstream_failure_responses: Vec::new(),
+            response_patches: Vec::new(),
+            route_timeouts: Vec::new(),
+            trusted_proxies: Vec::new(),
+            tcp_nodelay: false,
+            tcp_keepalive_seconds: None,
+            tcp_keepalive_interval_seconds: None,
+            upstream_idle_timeout_seconds: None,
+            gzip_level: 6,
+            upstream_address_family: AddressFamily::Auto,
+            upstream_dns: Vec::new(),
+            cookie_policy: CookiePolicy::Pass,
+            schema_validation: SchemaValidation::Off,
+            upstream_happy_eyeballs_ms: None,
+            accept_error_pause_ms: 1000,
+            accept_error_max_pause_ms: 30_000,
+            snapshot_interval_seconds: None,
+            snapshot_routes: Vec::new(),
+            resource_watchdog_interval_seconds: 60,
+            memory_warning_mb: None,
+            api_rate_limit_per_minute: 300,
+            run_as_user: None,
+            run_as_group: None,
+            admin_port: None,
+            admin_tls_cert: None,
+            admin_tls_key: None,
+            admin_tls_client_ca: None,
+            capture_file: None,
+            crash_report_dir: None,
+            crash_report_url: None,
             log_level: "info".to_owned(),
+            log_file: None,
+            log_file_level: None,
+            log_file_format: LogFileFormat::Full,
+            log_file_rotation: LogRotation::Daily,
+            log_file_max_mb: None,
+            log_file_retention: 7,
         };
 
         let app = App::new(args);
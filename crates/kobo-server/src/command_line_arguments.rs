@@ -1,30 +1,478 @@
 //! Contains the command line arguments for the kobo-server application.
 
-pub use implementation::CommandLineArguments;
+pub use implementation::{
+    AddressFamily, Command, CommandLineArguments, CookiePolicy, SchemaValidation, Shell,
+};
 
 mod implementation {
-    use clap::Parser;
+    use std::{
+        io::{self, Write},
+        path::PathBuf,
+    };
+
+    use clap::{CommandFactory as _, Parser, Subcommand, ValueEnum};
+    use clap_complete::Generator as _;
+    use serde::Serialize;
+
+    use crate::log_file::{LogFileFormat, LogRotation};
+
+    /// Placeholder for values removed from the redacted configuration.
+    const REDACTED: &str = "<redacted>";
+
+    /// Subcommands for the kobo-server application. Without a subcommand the server
+    /// is started.
+    #[derive(Clone, Debug, Subcommand)]
+    pub enum Command {
+        /// Run pre-flight checks of the configuration and environment, then exit.
+        Doctor,
+        /// Benchmark the gzip decode, rewrite and encode pipeline on representative
+        /// payloads, then exit.
+        Bench {
+            /// Number of times each payload is processed.
+            #[arg(long, default_value_t = 200)]
+            iterations: u32,
+        },
+        /// Replay a file recorded with `--capture-file` through the current code,
+        /// answering with the recorded Kobo store API responses, and report responses
+        /// that differ from the recording. Exits with an error if any differ.
+        ReplaySync {
+            /// The capture file to replay.
+            capture_file: PathBuf,
+        },
+        /// Print a shell completion script to stdout.
+        Completions {
+            /// The shell to generate completions for.
+            #[arg(value_enum)]
+            shell: Shell,
+        },
+        /// Print a man page in roff format to stdout.
+        Manpage,
+    }
+
+    /// Shells that completion scripts can be generated for.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+    pub enum Shell {
+        /// Bourne Again `SHell` (bash)
+        Bash,
+        /// Elvish shell
+        Elvish,
+        /// Friendly Interactive `SHell` (fish)
+        Fish,
+        /// `PowerShell`
+        #[value(name = "powershell")]
+        PowerShell,
+        /// Z `SHell` (zsh)
+        Zsh,
+    }
+
+    impl From<Shell> for clap_complete::Shell {
+        fn from(shell: Shell) -> Self {
+            match shell {
+                Shell::Bash => Self::Bash,
+                Shell::Elvish => Self::Elvish,
+                Shell::Fish => Self::Fish,
+                Shell::PowerShell => Self::PowerShell,
+                Shell::Zsh => Self::Zsh,
+            }
+        }
+    }
+
+    /// Address families connections to the Kobo store API can prefer.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, ValueEnum)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum AddressFamily {
+        /// Use addresses in the order they are resolved.
+        #[default]
+        Auto,
+        /// Try IPv4 addresses first, falling back to IPv6.
+        PreferIpv4,
+        /// Try IPv6 addresses first, falling back to IPv4.
+        PreferIpv6,
+        /// Only connect over IPv4.
+        Ipv4Only,
+        /// Only connect over IPv6.
+        Ipv6Only,
+    }
+
+    impl From<AddressFamily> for kobo_proxy_core::AddressFamily {
+        fn from(address_family: AddressFamily) -> Self {
+            match address_family {
+                AddressFamily::Auto => Self::Auto,
+                AddressFamily::PreferIpv4 => Self::PreferIpv4,
+                AddressFamily::PreferIpv6 => Self::PreferIpv6,
+                AddressFamily::Ipv4Only => Self::Ipv4Only,
+                AddressFamily::Ipv6Only => Self::Ipv6Only,
+            }
+        }
+    }
+
+    /// Ways `Set-Cookie` headers from the Kobo store API can be handled.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, ValueEnum)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum CookiePolicy {
+        /// Forward them to the device.
+        #[default]
+        Pass,
+        /// Remove them.
+        Strip,
+        /// Remove them, but keep the cookies per session token and replay them on later
+        /// requests with the same token.
+        Store,
+    }
+
+    impl From<CookiePolicy> for kobo_proxy_core::CookiePolicy {
+        fn from(cookie_policy: CookiePolicy) -> Self {
+            match cookie_policy {
+                CookiePolicy::Pass => Self::Pass,
+                CookiePolicy::Strip => Self::Strip,
+                CookiePolicy::Store => Self::Store,
+            }
+        }
+    }
+
+    /// Ways Kobo store API responses can be checked against their typed payloads.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, ValueEnum)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum SchemaValidation {
+        /// Forward responses without checking them.
+        #[default]
+        Off,
+        /// Log responses that do not match.
+        Warn,
+        /// Log responses that do not match and answer devices with `502 Bad Gateway`,
+        /// e.g. to fail tests as soon as Kobo changes a field.
+        Strict,
+    }
+
+    impl From<SchemaValidation> for kobo_proxy_core::SchemaValidation {
+        fn from(schema_validation: SchemaValidation) -> Self {
+            match schema_validation {
+                SchemaValidation::Off => Self::Off,
+                SchemaValidation::Warn => Self::Warn,
+                SchemaValidation::Strict => Self::Strict,
+            }
+        }
+    }
 
     /// Command line arguments for the kobo-server application.
-    #[derive(Clone, Debug, Default, Parser)]
+    #[derive(Clone, Debug, Default, Parser, Serialize)]
     #[command(author, version, about, long_about = None)]
+    #[expect(
+        clippy::struct_excessive_bools,
+        reason = "each bool is an independent command line flag"
+    )]
     pub struct CommandLineArguments {
+        /// The subcommand to run instead of starting the server.
+        #[command(subcommand)]
+        #[serde(skip)]
+        pub command: Option<Command>,
         /// The log level for the application.
         #[arg(short, long, default_value = "info", env)]
         pub log_level: String,
+        /// Also write logs to this file, without colours. The file is rotated by
+        /// `--log-file-rotation` and `--log-file-max-mb`.
+        #[arg(long, env)]
+        pub log_file: Option<PathBuf>,
+        /// The log level for `--log-file`. Defaults to `--log-level`.
+        #[arg(long, env)]
+        pub log_file_level: Option<String>,
+        /// How events are formatted in `--log-file`.
+        #[arg(long, value_enum, default_value_t, env)]
+        pub log_file_format: LogFileFormat,
+        /// When `--log-file` is rotated, by the UTC time.
+        #[arg(long, value_enum, default_value_t, env)]
+        pub log_file_rotation: LogRotation,
+        /// Also rotate `--log-file` when it would grow past this many megabytes.
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..), env)]
+        pub log_file_max_mb: Option<u64>,
+        /// Number of rotated log files to keep. Older files are deleted.
+        #[arg(long, default_value_t = 7, env)]
+        pub log_file_retention: usize,
         /// The port to listen on.
         #[arg(short, long, default_value_t = 8089, env)]
         pub port: u16,
+        /// Set `SO_REUSEPORT` on the listening sockets so a new version of the proxy
+        /// can be started on the same ports before this one is stopped. On
+        /// `SIGTERM` the old process stops accepting and drains in-flight syncs.
+        #[arg(long, default_value_t = false, env)]
+        pub reuse_port: bool,
         /// The front end URL the application will be accessed from. This is used
         /// to generate URLs in responses to Kobo devices.
         #[arg(short, long, env)]
         pub frontend_url: Option<String>,
+        /// Frontend URLs for specific devices, in `DEVICE=URL` form where DEVICE is a
+        /// device ID as listed by `/api/devices`, e.g. for a device that roams via a
+        /// Tailscale hostname while others use a LAN address.
+        #[arg(
+            long = "device-frontend-url",
+            env = "DEVICE_FRONTEND_URLS",
+            value_delimiter = ','
+        )]
+        pub device_frontend_urls: Vec<String>,
         /// Enable request logging middleware.
         #[arg(short = 'q', long, default_value_t = false, env)]
         pub enable_request_logging: bool,
         /// Enable response logging middleware.
         #[arg(short = 'r', long, default_value_t = false, env)]
         pub enable_response_logging: bool,
+        /// The maximum number of body bytes to include when logging requests and
+        /// responses. Larger bodies are logged as a head and tail with a marker
+        /// describing how much was omitted.
+        #[arg(long, env)]
+        pub log_body_max_bytes: Option<usize>,
+        /// Additional route templates (e.g. `/v1/custom/{id}/state`) used to group
+        /// request paths in logs. These take precedence over the built-in templates.
+        #[arg(
+            long = "route-template",
+            env = "ROUTE_TEMPLATES",
+            value_delimiter = ','
+        )]
+        pub route_templates: Vec<String>,
+        /// Log a warning when a device clock differs from the server clock by at least
+        /// this many seconds. Set to 0 to disable the warning.
+        #[arg(long, default_value_t = 300, env)]
+        pub clock_skew_warning_seconds: u64,
+        /// Back off a device after this many consecutive `401 Unauthorized` responses
+        /// from the Kobo store API. Its requests are answered with `429 Too Many
+        /// Requests` for 30 seconds, doubling with each further failure up to an hour,
+        /// until a request succeeds. Only requests with the same `Authorization` header
+        /// are backed off, and sign-in requests are always forwarded. Unset never backs
+        /// devices off.
+        #[arg(long, env, value_parser = clap::value_parser!(u32).range(1..))]
+        pub auth_failure_backoff_after: Option<u32>,
+        /// Override the `Accept-Language` header sent to the Kobo store API, e.g.
+        /// `fr-CA`, to browse another region's catalog.
+        #[arg(long, env)]
+        pub upstream_accept_language: Option<String>,
+        /// Override query parameters sent to the Kobo store API, in `NAME=VALUE` form
+        /// (e.g. a locale or currency parameter). Only parameters sent by the device are
+        /// replaced.
+        #[arg(
+            long = "upstream-query-override",
+            env = "UPSTREAM_QUERY_OVERRIDES",
+            value_delimiter = ','
+        )]
+        pub upstream_query_overrides: Vec<String>,
+        /// Add or replace headers on requests forwarded to the Kobo store API, in
+        /// `NAME=VALUE` form. Values may contain the `{device_id}` and `{user_agent}`
+        /// placeholders, e.g. `X-Trace=kobo-{device_id}`.
+        #[arg(
+            long = "upstream-header",
+            env = "UPSTREAM_HEADERS",
+            value_delimiter = ','
+        )]
+        pub upstream_headers: Vec<String>,
+        /// Block requests from a device during a daily window (server local time), in
+        /// `DEVICE=HH:MM-HH:MM[/SCOPE]` form, e.g. `kids-kobo=20:00-07:00/store`.
+        /// DEVICE is a device ID or `*` for every device. SCOPE is `store` to block
+        /// store browsing or `all` (default) to also block syncs.
+        #[arg(long = "access-rule", env = "ACCESS_RULES", value_delimiter = ',')]
+        pub access_rules: Vec<String>,
+        /// Assign a device to a group in `DEVICE=GROUP` form, e.g. `kids-kobo=kids`.
+        /// Groups get the policies set with `--group-policy`.
+        #[arg(long = "device-group", env = "DEVICE_GROUPS", value_delimiter = ',')]
+        pub device_groups: Vec<String>,
+        /// Policies applied to a device group's requests, in `GROUP=POLICY` form.
+        /// POLICY is `block:PATH` to answer requests under a path prefix locally,
+        /// `rate-limit:N` to allow each device N requests per minute, or
+        /// `logging:LEVEL` with LEVEL `off`, `headers`, or `full` (default).
+        #[arg(long = "group-policy", env = "GROUP_POLICIES", value_delimiter = ',')]
+        pub group_policies: Vec<String>,
+        /// Mask account identifiers, such as the user ID and email address, in the user
+        /// profile sent to devices.
+        #[arg(long, default_value_t = false, env)]
+        pub mask_profile_identifiers: bool,
+        /// Rewrite Kobo API URLs, such as the avatar URL, in the user profile to the
+        /// frontend URL so they are fetched through the proxy.
+        #[arg(long, default_value_t = false, env)]
+        pub rewrite_profile_urls: bool,
+        /// Enable chaos mode, a developer tool that applies the `--chaos-rule` faults.
+        /// Never enable this for real use.
+        #[arg(long, default_value_t = false, env)]
+        pub enable_chaos_mode: bool,
+        /// Faults injected into requests in chaos mode, in `ROUTE=PROBABILITY:FAULT`
+        /// form, where ROUTE is a route template or `*`, PROBABILITY is between 0 and
+        /// 1, and FAULT is `delay=MS`, `drop`, or `status=CODE` with a 5xx code.
+        #[arg(long = "chaos-rule", env = "CHAOS_RULES", value_delimiter = ',')]
+        pub chaos_rules: Vec<String>,
+        /// Answer device authentication locally with synthetic tokens, a developer mode
+        /// for devices not linked to a Kobo account. Requests the Kobo store API
+        /// receives with these tokens are rejected.
+        #[arg(long, default_value_t = false, env)]
+        pub synthetic_device_auth: bool,
+        /// Serve a status and setup page at `/`, showing the `api_endpoint` line to
+        /// put in the device's `Kobo eReader.conf`, and the line itself at `/setup`,
+        /// instead of forwarding these paths to the Kobo store API.
+        #[arg(long, default_value_t = false, env)]
+        pub landing_page: bool,
+        /// Preserve the original casing of HTTP/1 header names when forwarding requests
+        /// to the Kobo store API and returning responses to the device, for embedded
+        /// HTTP stacks that match header names case-sensitively.
+        #[arg(long, default_value_t = false, env)]
+        pub preserve_header_case: bool,
+        /// Firmware versions for which the `transfer-encoding` header is removed from
+        /// Kobo API responses, as space-separated comparators such as `<4.38` or
+        /// `>=4.20 <4.38`. Defaults to every version. Devices with unknown firmware
+        /// always get the workaround.
+        #[arg(long, env)]
+        pub strip_transfer_encoding_firmware: Option<String>,
+        /// Forward library requests (sync, reading state, tags) from the same device one
+        /// at a time, so overlapping syncs cannot race on upstream state.
+        #[arg(long, default_value_t = false, env)]
+        pub serialize_device_requests: bool,
+        /// When the Kobo store API fails, answer key endpoints with device-friendly
+        /// responses (an empty library sync, the last successful initialization) instead
+        /// of a bare 502.
+        #[arg(long, default_value_t = false, env)]
+        pub upstream_failure_fallbacks: bool,
+        /// Custom JSON responses served when the Kobo store API fails, in
+        /// `ROUTE=FILE` form where ROUTE is a route template such as
+        /// `/v1/user/profile`.
+        #[arg(
+            long = "upstream-failure-response",
+            env = "UPSTREAM_FAILURE_RESPONSES",
+            value_delimiter = ','
+        )]
+        pub upstream_failure_responses: Vec<String>,
+        /// RFC 6902 JSON Patch documents applied to successful JSON responses, in
+        /// `ROUTE=FILE` form where ROUTE is a route template such as
+        /// `/v1/initialization`. Responses are left unchanged if a patch fails.
+        #[arg(
+            long = "response-patch",
+            env = "RESPONSE_PATCHES",
+            value_delimiter = ','
+        )]
+        pub response_patches: Vec<String>,
+        /// Request timeouts in `ROUTE=MS` form, where ROUTE is a route template or `*`
+        /// for every other route. The remaining budget is sent upstream in the
+        /// `X-Kobo-Proxy-Budget-Ms` header, and requests that run out of time get a
+        /// `504 Gateway Timeout`.
+        #[arg(long = "route-timeout", env = "ROUTE_TIMEOUTS", value_delimiter = ',')]
+        pub route_timeouts: Vec<String>,
+        /// CIDRs or addresses of reverse proxies in front of the server. Only requests
+        /// from these proxies have their `X-Forwarded-For` or `X-Real-IP` header used
+        /// as the client address for device identification, rate limits and logs.
+        #[arg(
+            long = "trusted-proxies",
+            env = "TRUSTED_PROXIES",
+            value_delimiter = ','
+        )]
+        pub trusted_proxies: Vec<String>,
+        /// Set `TCP_NODELAY` on device and upstream connections, sending small
+        /// responses without delay.
+        #[arg(long, default_value_t = false, env)]
+        pub tcp_nodelay: bool,
+        /// Enable TCP keepalives on device and upstream connections, sending the first
+        /// probe after this many idle seconds. Helps e-readers on power-saving Wi-Fi
+        /// keep connections open.
+        #[arg(long, env)]
+        pub tcp_keepalive_seconds: Option<u64>,
+        /// Seconds between TCP keepalive probes. Only used with `--tcp-keepalive-seconds`.
+        #[arg(long, env)]
+        pub tcp_keepalive_interval_seconds: Option<u64>,
+        /// Seconds an idle upstream connection is kept alive for reuse. Defaults to the
+        /// HTTP client default.
+        #[arg(long, env)]
+        pub upstream_idle_timeout_seconds: Option<u64>,
+        /// Gzip level (0-9) used when re-encoding rewritten responses. Lower levels
+        /// trade larger responses for less CPU time on slow hardware.
+        #[arg(long, default_value_t = 6, env, value_parser = clap::value_parser!(u32).range(0..=9))]
+        pub gzip_level: u32,
+        /// Address family preferred for connections to the Kobo store API.
+        #[arg(long, value_enum, default_value_t, env)]
+        pub upstream_address_family: AddressFamily,
+        /// DNS servers used to resolve the Kobo store API instead of the system's
+        /// uncached `getaddrinfo`. Answers are cached for as long as their TTL allows.
+        /// Use `system` to cache answers from the system's configured DNS servers, or
+        /// list servers as `[SCHEME://]IP[:PORT][#NAME]`, where SCHEME is `udp`
+        /// (default), `tls` (DNS-over-TLS), or `https` (DNS-over-HTTPS), and NAME is the
+        /// name on an encrypted server's certificate, e.g.
+        /// `tls://1.1.1.1#cloudflare-dns.com`.
+        #[arg(long = "upstream-dns", env = "UPSTREAM_DNS", value_delimiter = ',')]
+        pub upstream_dns: Vec<String>,
+        /// How `Set-Cookie` headers from the Kobo store API are handled.
+        #[arg(long, value_enum, default_value_t, env)]
+        pub cookie_policy: CookiePolicy,
+        /// Check Kobo store API responses for endpoints with typed payloads, such as
+        /// library sync, against them.
+        #[arg(long, value_enum, default_value_t, env)]
+        pub schema_validation: SchemaValidation,
+        /// Milliseconds to wait on the preferred address family before also trying the
+        /// other one (RFC 8305 happy eyeballs). Defaults to 300; set to 0 to try
+        /// addresses one at a time.
+        #[arg(long, env)]
+        pub upstream_happy_eyeballs_ms: Option<u64>,
+        /// Milliseconds the listener pauses after an accept error, such as running out
+        /// of file descriptors, before retrying. The pause doubles with each consecutive
+        /// error up to `--accept-error-max-pause-ms`.
+        #[arg(long, default_value_t = 1000, env)]
+        pub accept_error_pause_ms: u64,
+        /// Longest pause, in milliseconds, between retries after consecutive accept
+        /// errors.
+        #[arg(long, default_value_t = 30_000, env)]
+        pub accept_error_max_pause_ms: u64,
+        /// Replay each device's last request to the snapshot routes every this many
+        /// seconds, keeping versioned upstream responses with a summary of changes at
+        /// `/api/snapshots`. Disabled by default.
+        #[arg(long, env)]
+        pub snapshot_interval_seconds: Option<u64>,
+        /// Route templates that are snapshotted. Defaults to `/v1/initialization`.
+        #[arg(
+            long = "snapshot-route",
+            env = "SNAPSHOT_ROUTES",
+            value_delimiter = ','
+        )]
+        pub snapshot_routes: Vec<String>,
+        /// Sample open file descriptors, resident memory, and cache sizes every this
+        /// many seconds, logging a warning when a limit is close to being reached. The
+        /// latest sample is served at `/api/resources`. Set to 0 to disable.
+        #[arg(long, default_value_t = 60, env)]
+        pub resource_watchdog_interval_seconds: u64,
+        /// Warn when the resident memory of the proxy reaches this many megabytes.
+        #[arg(long, env)]
+        pub memory_warning_mb: Option<u64>,
+        /// Local API requests each caller may make per minute. Callers are identified
+        /// by their client IP address. Set to 0 to disable the limit.
+        #[arg(long, default_value_t = 300, env)]
+        pub api_rate_limit_per_minute: u32,
+        /// Switch to this user after binding the port, so privileged ports such as 80
+        /// can be used without running the proxy as root. Requires starting as root.
+        #[arg(long, env)]
+        pub run_as_user: Option<String>,
+        /// Switch to this group after binding the port. Defaults to the primary group of
+        /// `--run-as-user`.
+        #[arg(long, env)]
+        pub run_as_group: Option<String>,
+        /// Serve the local `/api` routes only on this port, over TLS with required
+        /// client certificates, instead of on the device port. Requires
+        /// `--admin-tls-cert`, `--admin-tls-key`, and `--admin-tls-client-ca`.
+        #[arg(long, env)]
+        pub admin_port: Option<u16>,
+        /// PEM file with the certificate chain presented by the admin listener.
+        #[arg(long, env)]
+        pub admin_tls_cert: Option<PathBuf>,
+        /// PEM file with the private key of the admin listener certificate.
+        #[arg(long, env)]
+        pub admin_tls_key: Option<PathBuf>,
+        /// PEM file with the CA certificates that admin client certificates must be
+        /// signed by.
+        #[arg(long, env)]
+        pub admin_tls_client_ca: Option<PathBuf>,
+        /// Append every device request, the Kobo store API response, and the response
+        /// sent to the device to this file, for replay with the `replay-sync`
+        /// subcommand. Responses are buffered while capturing. Captures contain
+        /// account tokens, so keep them private.
+        #[arg(long, env)]
+        pub capture_file: Option<PathBuf>,
+        /// Write a crash report to this directory when the server panics, with the
+        /// version, a configuration hash, a backtrace, and the paths of the most
+        /// recent forwarded requests.
+        #[arg(long, env)]
+        pub crash_report_dir: Option<PathBuf>,
+        /// Post crash reports to this URL as JSON. Reports that could not be posted
+        /// are retried at the next start. Only used with `--crash-report-dir`.
+        #[arg(long, env)]
+        pub crash_report_url: Option<String>,
     }
 
     impl CommandLineArguments {
@@ -34,6 +482,40 @@ mod implementation {
         pub fn parse_arguments() -> Self {
             <Self as Parser>::parse()
         }
+
+        /// The arguments as JSON for diagnostics, with upstream header values, which
+        /// may hold credentials, redacted.
+        pub(crate) fn to_redacted_json(&self) -> serde_json::Value {
+            let mut redacted = self.clone();
+            for header in &mut redacted.upstream_headers {
+                if let Some((name, _)) = header.split_once('=') {
+                    *header = format!("{name}={REDACTED}");
+                }
+            }
+            serde_json::to_value(redacted).unwrap_or_default()
+        }
+
+        /// Write a completion script for `shell` to `writer`.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if writing to `writer` fails.
+        pub fn write_completions(shell: Shell, writer: &mut dyn Write) -> io::Result<()> {
+            let mut command = Self::command();
+            let bin_name = command.get_name().to_owned();
+            command.set_bin_name(bin_name);
+            command.build();
+            clap_complete::Shell::from(shell).try_generate(&command, writer)
+        }
+
+        /// Write the man page, in roff format, to `writer`.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if writing to `writer` fails.
+        pub fn write_manpage(writer: &mut dyn Write) -> io::Result<()> {
+            clap_mangen::Man::new(Self::command()).render(writer)
+        }
     }
 }
 
@@ -43,11 +525,128 @@ mod tests {
 
     use clap::Parser as _;
 
-    use super::CommandLineArguments;
+    use super::{
+        AddressFamily, Command, CommandLineArguments, CookiePolicy, SchemaValidation, Shell,
+    };
 
     #[test]
     fn test_default_log_level_is_valid() {
         let args = CommandLineArguments::parse_from(["kobo-server"]);
         assert!(tracing::Level::from_str(&args.log_level).is_ok());
     }
+
+    #[test]
+    fn test_no_subcommand_by_default() {
+        let args = CommandLineArguments::parse_from(["kobo-server"]);
+        assert!(args.command.is_none());
+    }
+
+    #[test]
+    fn test_doctor_subcommand_is_parsed() {
+        let args = CommandLineArguments::parse_from(["kobo-server", "doctor"]);
+        assert!(matches!(args.command, Some(Command::Doctor)));
+    }
+
+    #[test]
+    fn test_bench_subcommand_is_parsed() {
+        let args = CommandLineArguments::parse_from(["kobo-server", "bench", "--iterations", "5"]);
+        assert!(matches!(
+            args.command,
+            Some(Command::Bench { iterations: 5 })
+        ));
+    }
+
+    #[test]
+    fn test_gzip_level_out_of_range_is_rejected() {
+        let result = CommandLineArguments::try_parse_from(["kobo-server", "--gzip-level", "10"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_upstream_address_family_is_parsed() {
+        let args = CommandLineArguments::parse_from([
+            "kobo-server",
+            "--upstream-address-family",
+            "prefer-ipv4",
+        ]);
+        assert_eq!(args.upstream_address_family, AddressFamily::PreferIpv4);
+
+        let result = CommandLineArguments::try_parse_from([
+            "kobo-server",
+            "--upstream-address-family",
+            "ipv5",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_cookie_policy_is_parsed() {
+        let args = CommandLineArguments::parse_from(["kobo-server", "--cookie-policy", "store"]);
+        assert_eq!(args.cookie_policy, CookiePolicy::Store);
+
+        let result =
+            CommandLineArguments::try_parse_from(["kobo-server", "--cookie-policy", "keep"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_schema_validation_is_parsed() {
+        let args =
+            CommandLineArguments::parse_from(["kobo-server", "--schema-validation", "strict"]);
+        assert_eq!(args.schema_validation, SchemaValidation::Strict);
+
+        let result =
+            CommandLineArguments::try_parse_from(["kobo-server", "--schema-validation", "loud"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_completions_subcommand_is_parsed() {
+        let args = CommandLineArguments::parse_from(["kobo-server", "completions", "zsh"]);
+        assert!(matches!(
+            args.command,
+            Some(Command::Completions { shell: Shell::Zsh })
+        ));
+    }
+
+    #[test]
+    fn test_completions_include_subcommands_and_flags() {
+        let mut output = Vec::new();
+        CommandLineArguments::write_completions(Shell::Bash, &mut output)
+            .expect("failed to write completions");
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("kobo-server"));
+        assert!(output.contains("doctor"));
+        assert!(output.contains("--frontend-url"));
+    }
+
+    #[test]
+    fn test_manpage_documents_flags() {
+        let mut output = Vec::new();
+        CommandLineArguments::write_manpage(&mut output).expect("failed to write man page");
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with(".ie"));
+        assert!(output.contains("kobo\\-server"));
+        assert!(output.contains("frontend\\-url"));
+    }
+
+    #[test]
+    fn test_redacted_json_hides_upstream_header_values() {
+        let args = CommandLineArguments::parse_from([
+            "kobo-server",
+            "--upstream-header",
+            "Authorization=Bearer secret",
+        ]);
+
+        let config = args.to_redacted_json();
+
+        assert_eq!(config["port"], 8089);
+        assert_eq!(
+            config["upstream_headers"],
+            serde_json::json!(["Authorization=<redacted>"])
+        );
+        assert!(!config.to_string().contains("secret"));
+    }
 }
@@ -0,0 +1,402 @@
+//! Crash reports written when the server panics.
+//!
+//! A panic hook writes a JSON report to the crash report directory with the
+//! version, a hash of the configuration, the panic message and location, a
+//! backtrace, and the most recent requests forwarded to the Kobo store API. Only the
+//! method and path of each request are kept, so reports hold no device tokens.
+//! Reports can also be posted to an endpoint. Reports that could not be posted,
+//! e.g. because the panic ended the process, are posted at the next start.
+
+pub use implementation::CrashReporter;
+
+mod implementation {
+    use std::{
+        backtrace::Backtrace,
+        collections::VecDeque,
+        fs,
+        io::{self, Write as _},
+        panic::PanicHookInfo,
+        path::{Path, PathBuf},
+        sync::{
+            Arc, Mutex, PoisonError, TryLockError,
+            atomic::{AtomicU64, Ordering},
+        },
+        time::Duration,
+    };
+
+    use anyhow::{Result, bail};
+    use axum::{body::Body, extract::Request};
+    use chrono::{DateTime, Utc};
+    use hyper::{Method, Uri, header};
+    use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+    use kobo_proxy_core::RequestHook;
+    use serde::{Deserialize, Serialize};
+
+    /// Number of forwarded requests included in each report.
+    const RECENT_REQUEST_LIMIT: usize = 20;
+
+    /// How long posting a report may take before it is left for the next attempt.
+    const UPLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Extension added to reports once they have been posted.
+    const UPLOADED_EXTENSION: &str = "json.uploaded";
+
+    /// A request forwarded to the Kobo store API shortly before a crash.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct RecentRequest {
+        /// When the request was forwarded.
+        pub at: DateTime<Utc>,
+        /// The request method.
+        pub method: String,
+        /// The request path, without the query string.
+        pub path: String,
+    }
+
+    /// The most recent requests forwarded to the Kobo store API.
+    #[derive(Debug, Default)]
+    pub struct RecentRequests {
+        requests: Mutex<VecDeque<RecentRequest>>,
+    }
+
+    impl RecentRequests {
+        /// Remembers a forwarded request, forgetting the oldest once the limit is
+        /// reached.
+        pub fn push(&self, method: &Method, path: &str) {
+            let mut requests = self.requests.lock().unwrap_or_else(PoisonError::into_inner);
+            if requests.len() == RECENT_REQUEST_LIMIT {
+                requests.pop_front();
+            }
+            requests.push_back(RecentRequest {
+                at: Utc::now(),
+                method: method.to_string(),
+                path: path.to_owned(),
+            });
+        }
+
+        /// Returns the remembered requests, oldest first.
+        pub fn snapshot(&self) -> Vec<RecentRequest> {
+            // The panicking thread may hold the lock, so never wait for it.
+            match self.requests.try_lock() {
+                Ok(requests) => requests.iter().cloned().collect(),
+                Err(TryLockError::Poisoned(requests)) => {
+                    requests.into_inner().iter().cloned().collect()
+                }
+                Err(TryLockError::WouldBlock) => Vec::new(),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl RequestHook for RecentRequests {
+        async fn modify_request(&self, request: &mut Request) -> Result<()> {
+            self.push(request.method(), request.uri().path());
+            Ok(())
+        }
+    }
+
+    /// A structured report of a panic.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CrashReport {
+        /// The version of the server that crashed.
+        pub version: String,
+        /// Hash of the redacted configuration, to group reports by configuration.
+        pub config_hash: String,
+        /// When the panic happened.
+        pub timestamp: DateTime<Utc>,
+        /// The name of the thread that panicked, if it has one.
+        pub thread: Option<String>,
+        /// The panic message.
+        pub message: String,
+        /// The source location of the panic.
+        pub location: Option<String>,
+        /// The backtrace of the panicking thread.
+        pub backtrace: String,
+        /// The requests forwarded shortly before the panic, oldest first.
+        pub recent_requests: Vec<RecentRequest>,
+    }
+
+    /// Writes crash reports when the server panics, and posts them to an endpoint.
+    #[derive(Debug)]
+    pub struct CrashReporter {
+        directory: PathBuf,
+        endpoint: Option<String>,
+        config_hash: String,
+        recent_requests: Arc<RecentRequests>,
+        sequence: AtomicU64,
+        uploading: tokio::sync::Mutex<()>,
+    }
+
+    impl CrashReporter {
+        /// Creates a reporter writing reports to `directory`.
+        ///
+        /// # Arguments
+        /// * `directory` - The directory reports are written to
+        /// * `endpoint` - The URL reports are posted to, if any
+        /// * `config` - The redacted configuration, hashed into each report
+        pub fn new(
+            directory: PathBuf,
+            endpoint: Option<String>,
+            config: &serde_json::Value,
+        ) -> Self {
+            Self {
+                directory,
+                endpoint,
+                config_hash: config_hash(config),
+                recent_requests: Arc::default(),
+                sequence: AtomicU64::new(0),
+                uploading: tokio::sync::Mutex::new(()),
+            }
+        }
+
+        /// Returns the request hook that remembers forwarded requests for reports.
+        pub fn recent_requests(&self) -> Arc<RecentRequests> {
+            self.recent_requests.clone()
+        }
+
+        /// Installs a panic hook that writes a report before running the previous
+        /// hook, and posts the report if an endpoint is configured.
+        pub fn install(self: &Arc<Self>) {
+            let reporter = self.clone();
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                reporter.handle_panic(info);
+                previous(info);
+            }));
+        }
+
+        fn handle_panic(self: &Arc<Self>, info: &PanicHookInfo<'_>) {
+            let report = self.report(
+                info.payload_as_str().unwrap_or("Box<dyn Any>").to_owned(),
+                info.location().map(ToString::to_string),
+            );
+            match self.write(&report) {
+                Ok(path) => tracing::error!("Wrote crash report to {}", path.display()),
+                Err(e) => tracing::error!("Failed to write crash report: {e}"),
+            }
+            if self.endpoint.is_some()
+                && let Ok(runtime) = tokio::runtime::Handle::try_current()
+            {
+                runtime.spawn(self.clone().upload_pending());
+            }
+        }
+
+        /// Builds a report of a panic on the current thread.
+        pub(crate) fn report(&self, message: String, location: Option<String>) -> CrashReport {
+            CrashReport {
+                version: env!("CARGO_PKG_VERSION").to_owned(),
+                config_hash: self.config_hash.clone(),
+                timestamp: Utc::now(),
+                thread: std::thread::current().name().map(ToOwned::to_owned),
+                message,
+                location,
+                backtrace: Backtrace::force_capture().to_string(),
+                recent_requests: self.recent_requests.snapshot(),
+            }
+        }
+
+        /// Writes `report` to a new file in the crash report directory.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the directory or file cannot be written.
+        pub(crate) fn write(&self, report: &CrashReport) -> io::Result<PathBuf> {
+            fs::create_dir_all(&self.directory)?;
+            let path = self.directory.join(format!(
+                "crash-{}-{}.json",
+                report.timestamp.format("%Y%m%dT%H%M%S%.3fZ"),
+                self.sequence.fetch_add(1, Ordering::Relaxed)
+            ));
+            let mut file = fs::File::create_new(&path)?;
+            serde_json::to_writer_pretty(&mut file, report)?;
+            file.write_all(b"\n")?;
+            Ok(path)
+        }
+
+        /// Posts the reports that have not been posted yet, oldest first, stopping at
+        /// the first failure so the rest are retried at the next attempt.
+        pub async fn upload_pending(self: Arc<Self>) {
+            let Some(endpoint) = &self.endpoint else {
+                return;
+            };
+            let _uploading = self.uploading.lock().await;
+            let paths = match pending_reports(&self.directory).await {
+                Ok(paths) => paths,
+                Err(e) => {
+                    tracing::warn!("Failed to list crash reports: {e}");
+                    return;
+                }
+            };
+            for path in paths {
+                let result = tokio::time::timeout(UPLOAD_TIMEOUT, upload(endpoint, &path)).await;
+                match result {
+                    Ok(Ok(())) => tracing::info!("Posted crash report {}", path.display()),
+                    Ok(Err(e)) => {
+                        tracing::warn!("Failed to post crash report {}: {e}", path.display());
+                        return;
+                    }
+                    Err(_) => {
+                        tracing::warn!(
+                            "Posting crash report {} timed out after {UPLOAD_TIMEOUT:?}",
+                            path.display()
+                        );
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lists the reports in `directory` that have not been posted, oldest first.
+    async fn pending_reports(directory: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut entries = match tokio::fs::read_dir(directory).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut paths = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with("crash-") && name.ends_with(".json") {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Posts the report at `path` to `endpoint`, then marks it as posted.
+    async fn upload(endpoint: &str, path: &Path) -> Result<()> {
+        let body = tokio::fs::read(path).await?;
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        let client = Client::builder(TokioExecutor::new()).build::<_, Body>(connector);
+        let response = client
+            .request(
+                hyper::Request::post(endpoint.parse::<Uri>()?)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body))?,
+            )
+            .await?;
+        if !response.status().is_success() {
+            bail!("endpoint responded with {}", response.status());
+        }
+        tokio::fs::rename(path, path.with_extension(UPLOADED_EXTENSION)).await?;
+        Ok(())
+    }
+
+    /// Hashes the configuration with 64-bit FNV-1a, which is stable across builds.
+    pub(crate) fn config_hash(config: &serde_json::Value) -> String {
+        let hash = config
+            .to_string()
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            });
+        format!("{hash:016x}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{Router, routing::post};
+    use hyper::Method;
+    use serde_json::json;
+
+    use super::{
+        implementation::{CrashReport, RecentRequests, config_hash},
+        *,
+    };
+
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("kobo-crash-reports-{}-{name}", std::process::id()));
+        let _ignored = std::fs::remove_dir_all(&directory);
+        directory
+    }
+
+    #[test]
+    fn recent_requests_keep_only_the_latest() {
+        let recent = RecentRequests::default();
+        for index in 0..25 {
+            recent.push(&Method::GET, &format!("/v1/library/{index}"));
+        }
+
+        let snapshot = recent.snapshot();
+
+        assert_eq!(snapshot.len(), 20);
+        assert_eq!(snapshot[0].path, "/v1/library/5");
+        assert_eq!(snapshot[19].path, "/v1/library/24");
+    }
+
+    #[test]
+    fn config_hash_is_stable_and_distinguishes_configs() {
+        let config = json!({ "port": 8089 });
+
+        assert_eq!(config_hash(&config), config_hash(&json!({ "port": 8089 })));
+        assert_ne!(config_hash(&config), config_hash(&json!({ "port": 8090 })));
+        assert_eq!(config_hash(&config).len(), 16);
+    }
+
+    #[test]
+    fn writes_report_with_recent_requests() {
+        let directory = temp_directory("write");
+        let reporter = CrashReporter::new(directory.clone(), None, &json!({ "port": 8089 }));
+        reporter
+            .recent_requests()
+            .push(&Method::GET, "/v1/library/sync");
+
+        let report = reporter.report("boom".to_owned(), Some("src/main.rs:1:1".to_owned()));
+        let path = reporter.write(&report).unwrap();
+        let written: CrashReport = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(written.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(written.config_hash, config_hash(&json!({ "port": 8089 })));
+        assert_eq!(written.message, "boom");
+        assert_eq!(written.recent_requests.len(), 1);
+        assert_eq!(written.recent_requests[0].path, "/v1/library/sync");
+    }
+
+    #[tokio::test]
+    async fn uploads_pending_reports_once() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let router = Router::new().route(
+            "/crashes",
+            post({
+                let received = received.clone();
+                move |body: String| async move {
+                    received.lock().unwrap().push(body);
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/crashes", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        let directory = temp_directory("upload");
+        let reporter = Arc::new(CrashReporter::new(
+            directory.clone(),
+            Some(endpoint),
+            &json!({}),
+        ));
+        let report = reporter.report("boom".to_owned(), None);
+        let path = reporter.write(&report).unwrap();
+
+        reporter.clone().upload_pending().await;
+        reporter.clone().upload_pending().await;
+        let uploaded = path.with_extension("json.uploaded").exists();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(received.lock().unwrap().len(), 1);
+        assert!(received.lock().unwrap()[0].contains("\"message\": \"boom\""));
+        assert!(uploaded);
+    }
+}
@@ -0,0 +1,480 @@
+//! Pre-flight checks run by the `doctor` subcommand.
+//!
+//! The checks verify the environment before a device is pointed at the server: the
+//! listening port can be bound, the Kobo store API is reachable over TLS, the
+//! frontend URL routes back to this host, and the admin listener's TLS files load,
+//! if it is configured.
+
+pub use implementation::{CheckResult, CheckStatus, Doctor};
+
+mod implementation {
+    use std::{fmt, path::PathBuf, time::Duration};
+
+    use axum::{Router, body::Body};
+    use http_body_util::BodyExt as _;
+    use hyper::{Request, Uri};
+    use hyper_util::{
+        client::legacy::{Client, connect::HttpConnector},
+        rt::TokioExecutor,
+    };
+    use kobo_proxy_core::{KOBO_API_URL, is_loopback_url};
+    use tokio::net::TcpListener;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::command_line_arguments::CommandLineArguments;
+
+    /// How long each network check may take before it is reported as failed.
+    const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Path requested through the frontend URL during the round trip check.
+    const ROUND_TRIP_PATH: &str = "/kobo-server-doctor";
+
+    type HttpClient = Client<hyper_rustls::HttpsConnector<HttpConnector>, Body>;
+
+    /// The outcome of a single check.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum CheckStatus {
+        /// The check succeeded.
+        Pass,
+        /// The check succeeded, but the configuration is likely to cause problems.
+        Warn,
+        /// The check failed.
+        Fail,
+    }
+
+    impl fmt::Display for CheckStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::Pass => write!(f, "PASS"),
+                Self::Warn => write!(f, "WARN"),
+                Self::Fail => write!(f, "FAIL"),
+            }
+        }
+    }
+
+    /// The result of a single check, with an actionable message.
+    #[derive(Clone, Debug)]
+    pub struct CheckResult {
+        /// A short description of what was checked.
+        pub name: String,
+        /// The outcome of the check.
+        pub status: CheckStatus,
+        /// Details about the outcome, including how to fix failures.
+        pub message: String,
+    }
+
+    impl CheckResult {
+        fn new(name: impl Into<String>, status: CheckStatus, message: impl Into<String>) -> Self {
+            Self {
+                name: name.into(),
+                status,
+                message: message.into(),
+            }
+        }
+    }
+
+    impl fmt::Display for CheckResult {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "[{}] {}: {}", self.status, self.name, self.message)
+        }
+    }
+
+    /// The admin listener port and TLS files, as configured.
+    type AdminTlsFiles = (
+        Option<u16>,
+        Option<PathBuf>,
+        Option<PathBuf>,
+        Option<PathBuf>,
+    );
+
+    /// Runs the pre-flight checks for a configuration.
+    pub struct Doctor {
+        port: u16,
+        frontend_url: String,
+        upstream_url: String,
+        admin_tls: AdminTlsFiles,
+        client: HttpClient,
+    }
+
+    impl Doctor {
+        /// Creates a `Doctor` for the given command line arguments.
+        #[must_use]
+        pub fn new(command_line_arguments: &CommandLineArguments) -> Self {
+            let port = command_line_arguments.port;
+            let frontend_url = command_line_arguments
+                .frontend_url
+                .clone()
+                .unwrap_or_else(|| format!("http://localhost:{port}"));
+            let connector = hyper_rustls::HttpsConnectorBuilder::new()
+                .with_webpki_roots()
+                .https_or_http()
+                .enable_http1()
+                .build();
+
+            Self {
+                port,
+                frontend_url,
+                upstream_url: KOBO_API_URL.to_owned(),
+                admin_tls: (
+                    command_line_arguments.admin_port,
+                    command_line_arguments.admin_tls_cert.clone(),
+                    command_line_arguments.admin_tls_key.clone(),
+                    command_line_arguments.admin_tls_client_ca.clone(),
+                ),
+                client: Client::builder(TokioExecutor::new()).build(connector),
+            }
+        }
+
+        /// Overrides the upstream URL checked for reachability.
+        #[cfg(test)]
+        #[must_use]
+        pub fn upstream_url<T: Into<String>>(mut self, upstream_url: T) -> Self {
+            self.upstream_url = upstream_url.into();
+            self
+        }
+
+        /// Runs every check and returns the results in order.
+        pub async fn run(&self) -> Vec<CheckResult> {
+            let (port_result, listener) = self.check_port().await;
+            let frontend_result = match listener {
+                Some(listener) => self.check_frontend_url(listener).await,
+                None => CheckResult::new(
+                    "Frontend URL",
+                    CheckStatus::Fail,
+                    format!("Skipped because port {} could not be bound", self.port),
+                ),
+            };
+            let upstream_result = self.check_upstream().await;
+
+            let mut results = vec![port_result, upstream_result, frontend_result];
+            results.extend(self.check_admin_tls());
+            results
+        }
+
+        /// Checks that the admin listener's certificate, key, and client CA load and
+        /// match, if the admin listener is configured.
+        fn check_admin_tls(&self) -> Option<CheckResult> {
+            let name = "Admin TLS";
+            let (certificate, key, client_ca) = match &self.admin_tls {
+                (None, None, None, None) => return None,
+                (Some(_), Some(certificate), Some(key), Some(client_ca)) => {
+                    (certificate, key, client_ca)
+                }
+                _ => {
+                    return Some(CheckResult::new(
+                        name,
+                        CheckStatus::Fail,
+                        "The admin listener needs --admin-port, --admin-tls-cert, \
+                         --admin-tls-key, and --admin-tls-client-ca together",
+                    ));
+                }
+            };
+            Some(Self::load_admin_tls(name, certificate, key, client_ca))
+        }
+
+        #[cfg(feature = "admin-tls")]
+        fn load_admin_tls(
+            name: &str,
+            certificate: &std::path::Path,
+            key: &std::path::Path,
+            client_ca: &std::path::Path,
+        ) -> CheckResult {
+            match kobo_proxy_core::MutualTls::new(certificate, key, client_ca) {
+                Ok(mutual_tls) => match mutual_tls.certificate_expiry() {
+                    Some(expiry) if expiry <= chrono::Utc::now() => CheckResult::new(
+                        name,
+                        CheckStatus::Fail,
+                        format!(
+                            "The certificate expired on {}. Renew it",
+                            expiry.date_naive()
+                        ),
+                    ),
+                    Some(expiry) => CheckResult::new(
+                        name,
+                        CheckStatus::Pass,
+                        format!(
+                            "Certificate, key, and client CA load; the certificate expires on {}",
+                            expiry.date_naive()
+                        ),
+                    ),
+                    None => CheckResult::new(
+                        name,
+                        CheckStatus::Pass,
+                        "Certificate, key, and client CA load",
+                    ),
+                },
+                Err(e) => CheckResult::new(
+                    name,
+                    CheckStatus::Fail,
+                    format!(
+                        "Failed to load: {e:#}. Check that the files are PEM encoded and that \
+                         the key belongs to the certificate"
+                    ),
+                ),
+            }
+        }
+
+        #[cfg(not(feature = "admin-tls"))]
+        fn load_admin_tls(
+            name: &str,
+            _certificate: &std::path::Path,
+            _key: &std::path::Path,
+            _client_ca: &std::path::Path,
+        ) -> CheckResult {
+            CheckResult::new(
+                name,
+                CheckStatus::Fail,
+                "This build has no TLS support for the admin listener. Rebuild with the \
+                 `admin-tls` feature",
+            )
+        }
+
+        /// Checks that the configured port can be bound, returning the listener so it
+        /// can be reused by the frontend URL check.
+        async fn check_port(&self) -> (CheckResult, Option<TcpListener>) {
+            let name = format!("Port {}", self.port);
+            match TcpListener::bind(("0.0.0.0", self.port)).await {
+                Ok(listener) => (
+                    CheckResult::new(name, CheckStatus::Pass, "Port can be bound"),
+                    Some(listener),
+                ),
+                Err(e) => (
+                    CheckResult::new(
+                        name,
+                        CheckStatus::Fail,
+                        format!(
+                            "Failed to bind: {e}. Stop the process using the port or choose \
+                             another with --port"
+                        ),
+                    ),
+                    None,
+                ),
+            }
+        }
+
+        /// Checks that the upstream Kobo API answers over TLS. Any HTTP response counts
+        /// as reachable, since unauthenticated requests are expected to be rejected.
+        async fn check_upstream(&self) -> CheckResult {
+            let name = format!("Upstream {}", self.upstream_url);
+            match self
+                .get(&format!("{}/v1/initialization", self.upstream_url))
+                .await
+            {
+                Ok((status, _)) => CheckResult::new(
+                    name,
+                    CheckStatus::Pass,
+                    format!("Reachable (responded with {status})"),
+                ),
+                Err(e) => CheckResult::new(
+                    name,
+                    CheckStatus::Fail,
+                    format!(
+                        "Not reachable: {e}. Check DNS resolution, firewall rules, and that \
+                         outbound HTTPS is allowed"
+                    ),
+                ),
+            }
+        }
+
+        /// Serves a unique token on the bound port and requests it through the frontend
+        /// URL, proving that the frontend URL routes back to this host.
+        async fn check_frontend_url(&self, listener: TcpListener) -> CheckResult {
+            let name = format!("Frontend URL {}", self.frontend_url);
+            let token = format!(
+                "kobo-server-doctor-{}-{}",
+                std::process::id(),
+                chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+            );
+
+            let cancellation_token = CancellationToken::new();
+            let shutdown = cancellation_token.clone();
+            let response_token = token.clone();
+            let router = Router::new().fallback(move || async move { response_token });
+            let server = tokio::spawn(async move {
+                axum::serve(listener, router)
+                    .with_graceful_shutdown(async move { shutdown.cancelled().await })
+                    .await
+            });
+
+            let url = format!(
+                "{}{ROUND_TRIP_PATH}",
+                self.frontend_url.trim_end_matches('/')
+            );
+            let result = self.get(&url).await;
+            cancellation_token.cancel();
+            if let Err(e) = server.await {
+                tracing::warn!("Doctor round trip server did not shut down cleanly: {e}");
+            }
+
+            match result {
+                Ok((_, body)) if body == token => {
+                    if is_loopback_url(&self.frontend_url) {
+                        CheckResult::new(
+                            name,
+                            CheckStatus::Warn,
+                            "Routes back to this host, but devices cannot reach a loopback \
+                             address. Set --frontend-url to an address reachable from the \
+                             device",
+                        )
+                    } else {
+                        CheckResult::new(name, CheckStatus::Pass, "Routes back to this host")
+                    }
+                }
+                Ok((status, _)) => CheckResult::new(
+                    name,
+                    CheckStatus::Fail,
+                    format!(
+                        "Reached a different server (responded with {status}). Check that the \
+                         frontend URL or reverse proxy forwards to port {}",
+                        self.port
+                    ),
+                ),
+                Err(e) => CheckResult::new(
+                    name,
+                    CheckStatus::Fail,
+                    format!(
+                        "Not reachable: {e}. Check that the host name resolves to this machine \
+                         and that the port is open"
+                    ),
+                ),
+            }
+        }
+
+        /// Sends a GET request and returns the status and body text.
+        async fn get(&self, url: &str) -> anyhow::Result<(hyper::StatusCode, String)> {
+            let uri: Uri = url.parse()?;
+            let request = Request::get(uri).body(Body::empty())?;
+            let response = tokio::time::timeout(CHECK_TIMEOUT, async {
+                let response = self.client.request(request).await?;
+                let status = response.status();
+                let body = response.into_body().collect().await?.to_bytes();
+                anyhow::Ok((status, String::from_utf8_lossy(&body).into_owned()))
+            })
+            .await
+            .map_err(|_| anyhow::anyhow!("timed out after {CHECK_TIMEOUT:?}"))??;
+
+            Ok(response)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CommandLineArguments;
+
+    fn unused_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[test]
+    fn check_result_display_includes_status() {
+        let result = CheckResult {
+            name: "Port 80".to_owned(),
+            status: CheckStatus::Fail,
+            message: "Failed to bind".to_owned(),
+        };
+
+        assert_eq!(result.to_string(), "[FAIL] Port 80: Failed to bind");
+    }
+
+    #[tokio::test]
+    async fn run_fails_port_check_when_port_is_in_use() {
+        let listener = std::net::TcpListener::bind("0.0.0.0:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let doctor = Doctor::new(&CommandLineArguments {
+            port,
+            ..Default::default()
+        })
+        .upstream_url(format!("http://127.0.0.1:{}", unused_port()));
+
+        let results = doctor.run().await;
+
+        assert_eq!(results[0].status, CheckStatus::Fail);
+        assert_eq!(results[2].status, CheckStatus::Fail);
+    }
+
+    #[tokio::test]
+    async fn run_fails_upstream_check_when_unreachable() {
+        let doctor = Doctor::new(&CommandLineArguments {
+            port: unused_port(),
+            ..Default::default()
+        })
+        .upstream_url(format!("http://127.0.0.1:{}", unused_port()));
+
+        let results = doctor.run().await;
+
+        assert_eq!(results[1].status, CheckStatus::Fail);
+    }
+
+    #[tokio::test]
+    async fn run_warns_when_frontend_url_is_loopback() {
+        let port = unused_port();
+        let doctor = Doctor::new(&CommandLineArguments {
+            port,
+            frontend_url: Some(format!("http://127.0.0.1:{port}")),
+            ..Default::default()
+        })
+        .upstream_url(format!("http://127.0.0.1:{}", unused_port()));
+
+        let results = doctor.run().await;
+
+        assert_eq!(results[0].status, CheckStatus::Pass);
+        assert_eq!(results[2].status, CheckStatus::Warn);
+    }
+
+    #[tokio::test]
+    async fn run_skips_admin_tls_check_when_not_configured() {
+        let doctor = Doctor::new(&CommandLineArguments {
+            port: unused_port(),
+            ..Default::default()
+        })
+        .upstream_url(format!("http://127.0.0.1:{}", unused_port()));
+
+        let results = doctor.run().await;
+
+        assert_eq!(results.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn run_fails_admin_tls_check_for_incomplete_or_unreadable_files() {
+        let missing = std::env::temp_dir().join("kobo-server-doctor-missing.pem");
+        for (admin_port, admin_tls_key) in [
+            (None, Some(missing.clone())),
+            (Some(0), Some(missing.clone())),
+        ] {
+            let doctor = Doctor::new(&CommandLineArguments {
+                port: unused_port(),
+                admin_port,
+                admin_tls_cert: Some(missing.clone()),
+                admin_tls_key,
+                admin_tls_client_ca: Some(missing.clone()),
+                ..Default::default()
+            })
+            .upstream_url(format!("http://127.0.0.1:{}", unused_port()));
+
+            let results = doctor.run().await;
+
+            assert_eq!(results[3].name, "Admin TLS");
+            assert_eq!(results[3].status, CheckStatus::Fail);
+        }
+    }
+
+    #[tokio::test]
+    async fn run_fails_when_frontend_url_reaches_another_server() {
+        let doctor = Doctor::new(&CommandLineArguments {
+            port: unused_port(),
+            frontend_url: Some(format!("http://127.0.0.1:{}", unused_port())),
+            ..Default::default()
+        })
+        .upstream_url(format!("http://127.0.0.1:{}", unused_port()));
+
+        let results = doctor.run().await;
+
+        assert_eq!(results[2].status, CheckStatus::Fail);
+    }
+}
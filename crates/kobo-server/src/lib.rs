@@ -2,7 +2,16 @@
 
 mod app;
 mod command_line_arguments;
-mod server;
+mod crash_report;
+mod doctor;
+mod log_file;
 
 pub use app::App;
-pub use command_line_arguments::CommandLineArguments;
+pub use command_line_arguments::{
+    AddressFamily, Command, CommandLineArguments, CookiePolicy, SchemaValidation, Shell,
+};
+pub use doctor::{CheckResult, CheckStatus, Doctor};
+pub use kobo_proxy_core::{
+    Bench, BenchResult, Replay, ReplayDifference, ReplayReport, RequestHook, kobo_protocol,
+};
+pub use log_file::{LogFileFormat, LogRotation, RotatingFile};
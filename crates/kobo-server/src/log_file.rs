@@ -0,0 +1,262 @@
+//! Rotating log file written alongside the console output.
+//!
+//! Long-running installs can keep a searchable log history without an external log
+//! shipper. The log file is rotated when a new hour or day starts (UTC) and, when a
+//! size limit is set, when it would grow past the limit. Rotated files are renamed
+//! with the time of rotation and only the most recent ones are kept.
+
+pub use implementation::{LogFileFormat, LogRotation, RotatingFile};
+
+mod implementation {
+    use std::{
+        ffi::OsString,
+        fs::{self, File, OpenOptions},
+        io::{self, Write},
+        path::{Path, PathBuf},
+    };
+
+    use chrono::{DateTime, Utc};
+    use clap::ValueEnum;
+    use serde::Serialize;
+
+    const SECONDS_PER_HOUR: i64 = 60 * 60;
+
+    const SECONDS_PER_DAY: i64 = 24 * SECONDS_PER_HOUR;
+
+    /// When the log file is rotated, in addition to any size limit.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, ValueEnum)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum LogRotation {
+        /// Only rotate when the size limit is reached.
+        Never,
+        /// Rotate when a new hour starts.
+        Hourly,
+        /// Rotate when a new day starts.
+        #[default]
+        Daily,
+    }
+
+    impl LogRotation {
+        /// Returns the rotation period `time` falls in, or `None` if the file is not
+        /// rotated by time.
+        fn period(self, time: DateTime<Utc>) -> Option<i64> {
+            match self {
+                Self::Never => None,
+                Self::Hourly => Some(time.timestamp().div_euclid(SECONDS_PER_HOUR)),
+                Self::Daily => Some(time.timestamp().div_euclid(SECONDS_PER_DAY)),
+            }
+        }
+    }
+
+    /// How events are formatted in the log file.
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, ValueEnum)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum LogFileFormat {
+        /// One line per event with its timestamp, level, target, and fields.
+        #[default]
+        Full,
+        /// One shorter line per event.
+        Compact,
+    }
+
+    /// A log file that rotates by time and size and prunes old rotated files.
+    #[derive(Debug)]
+    pub struct RotatingFile {
+        path: PathBuf,
+        rotation: LogRotation,
+        max_bytes: Option<u64>,
+        retention: usize,
+        file: File,
+        size: u64,
+        period: Option<i64>,
+    }
+
+    impl RotatingFile {
+        /// Opens `path` for appending, creating its directory if needed.
+        ///
+        /// # Arguments
+        /// * `rotation` - When the file is rotated by time
+        /// * `max_bytes` - The size at which the file is rotated, if any
+        /// * `retention` - How many rotated files are kept
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the directory or file cannot be created.
+        pub fn open(
+            path: PathBuf,
+            rotation: LogRotation,
+            max_bytes: Option<u64>,
+            retention: usize,
+        ) -> io::Result<Self> {
+            if let Some(directory) = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+            {
+                fs::create_dir_all(directory)?;
+            }
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let metadata = file.metadata()?;
+            // A file left by an earlier run belongs to the period it was last written in,
+            // so it is rotated on the first write if that period has passed.
+            let last_written = metadata
+                .modified()
+                .map_or_else(|_| Utc::now(), DateTime::from);
+
+            Ok(Self {
+                period: rotation.period(last_written),
+                path,
+                rotation,
+                max_bytes,
+                retention,
+                file,
+                size: metadata.len(),
+            })
+        }
+
+        /// Whether writing `length` more bytes now should start a new file.
+        fn needs_rotation(&self, length: usize) -> bool {
+            if self.size == 0 {
+                return false;
+            }
+            let period_ended = self.rotation.period(Utc::now()) != self.period;
+            let too_large = self
+                .max_bytes
+                .is_some_and(|max_bytes| self.size.saturating_add(length as u64) > max_bytes);
+            period_ended || too_large
+        }
+
+        /// The name prefix shared by the rotated files.
+        fn rotated_prefix(&self) -> OsString {
+            let mut prefix = self.path.file_name().unwrap_or_default().to_owned();
+            prefix.push(".");
+            prefix
+        }
+
+        /// Renames the current file with the time of rotation, starts a new one, and
+        /// removes the oldest rotated files beyond the retention.
+        fn rotate(&mut self) -> io::Result<()> {
+            self.file.flush()?;
+            let mut rotated_name = self.rotated_prefix();
+            rotated_name.push(Utc::now().format("%Y%m%dT%H%M%S%.3fZ").to_string());
+            fs::rename(&self.path, self.path.with_file_name(rotated_name))?;
+
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            self.size = 0;
+            self.period = self.rotation.period(Utc::now());
+            self.prune()
+        }
+
+        /// Removes the oldest rotated files so at most `retention` remain.
+        fn prune(&self) -> io::Result<()> {
+            let directory = match self.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            let prefix = self.rotated_prefix();
+            let prefix = prefix.to_string_lossy();
+            let mut rotated: Vec<PathBuf> = fs::read_dir(directory)?
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with(&*prefix))
+                .map(|entry| entry.path())
+                .collect();
+            // Rotation timestamps sort chronologically, so the oldest files come first.
+            rotated.sort();
+            let excess = rotated.len().saturating_sub(self.retention);
+            for path in &rotated[..excess] {
+                fs::remove_file(path)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl Write for RotatingFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.needs_rotation(buf.len()) {
+                self.rotate()?;
+            }
+            self.file.write_all(buf)?;
+            self.size = self.size.saturating_add(buf.len() as u64);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.file.flush()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write as _,
+        path::{Path, PathBuf},
+    };
+
+    use super::*;
+
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("kobo-log-files-{}-{name}", std::process::id()));
+        let _ignored = std::fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn file_names(directory: &Path) -> Vec<String> {
+        let mut names: Vec<_> = std::fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn file_is_created_and_appended() {
+        let directory = temp_directory("append");
+        let path = directory.join("server.log");
+        let mut file = RotatingFile::open(path.clone(), LogRotation::Never, None, 3).unwrap();
+
+        file.write_all(b"first\n").unwrap();
+        file.write_all(b"second\n").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+        assert_eq!(file_names(&directory), ["server.log"]);
+    }
+
+    #[test]
+    fn file_is_rotated_at_the_size_limit() {
+        let directory = temp_directory("size");
+        let path = directory.join("server.log");
+        let mut file = RotatingFile::open(path.clone(), LogRotation::Never, Some(10), 3).unwrap();
+
+        file.write_all(b"12345678\n").unwrap();
+        file.write_all(b"next\n").unwrap();
+
+        let names = file_names(&directory);
+        assert_eq!(names.len(), 2);
+        assert!(names[1].starts_with("server.log."));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "next\n");
+    }
+
+    #[test]
+    fn only_the_latest_rotated_files_are_kept() {
+        let directory = temp_directory("retention");
+        let path = directory.join("server.log");
+        let mut file = RotatingFile::open(path, LogRotation::Never, Some(1), 2).unwrap();
+
+        for line in 0..5 {
+            file.write_all(format!("{line}\n").as_bytes()).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        let names = file_names(&directory);
+        assert_eq!(names.len(), 3);
+        assert_eq!(
+            std::fs::read_to_string(directory.join(&names[2])).unwrap(),
+            "3\n"
+        );
+    }
+}
@@ -1,32 +1,147 @@
 //! A simple web server using Axum framework
 
-use kobo_server::{App, CommandLineArguments};
-use tracing_subscriber::{layer::SubscriberExt as _, util::SubscriberInitExt as _};
+use std::{io, path::Path, sync::Mutex};
+
+use kobo_server::{
+    App, Bench, CheckStatus, Command, CommandLineArguments, Doctor, LogFileFormat, Replay,
+    RotatingFile,
+};
+use tracing_subscriber::{
+    EnvFilter, Layer as _, layer::SubscriberExt as _, util::SubscriberInitExt as _,
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let command_line_arguments = CommandLineArguments::parse_arguments();
-    initialize_logging(&command_line_arguments.log_level);
-    let app = App::new(command_line_arguments);
-    app.run().await
+    initialize_logging(&command_line_arguments);
+    match command_line_arguments.command {
+        Some(Command::Doctor) => run_doctor(&command_line_arguments).await,
+        Some(Command::Bench { iterations }) => {
+            run_bench(iterations, command_line_arguments.gzip_level).await
+        }
+        Some(Command::ReplaySync { ref capture_file }) => {
+            run_replay_sync(capture_file, &command_line_arguments).await
+        }
+        Some(Command::Completions { shell }) => {
+            CommandLineArguments::write_completions(shell, &mut io::stdout().lock())
+                .map_err(Into::into)
+        }
+        Some(Command::Manpage) => {
+            CommandLineArguments::write_manpage(&mut io::stdout().lock()).map_err(Into::into)
+        }
+        None => {
+            let app = App::new(command_line_arguments);
+            app.run().await
+        }
+    }
 }
 
-/// Initialize the logging subsystem with the specified log level.
-fn initialize_logging(log_level: &str) {
-    let mut parse_error: Option<String> = None;
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::builder()
-                .parse(log_level)
-                .inspect_err(|e| {
-                    parse_error = Some(format!("Failed to parse log level '{log_level}': {e}"));
-                })
-                .unwrap_or_default(),
+/// Run the pre-flight checks and print their results.
+#[expect(
+    clippy::print_stdout,
+    reason = "The doctor report is the output of the subcommand."
+)]
+async fn run_doctor(command_line_arguments: &CommandLineArguments) -> anyhow::Result<()> {
+    let results = Doctor::new(command_line_arguments).run().await;
+    for result in &results {
+        println!("{result}");
+    }
+
+    let failures = results
+        .iter()
+        .filter(|result| result.status == CheckStatus::Fail)
+        .count();
+    if failures > 0 {
+        anyhow::bail!("{failures} check(s) failed");
+    }
+
+    Ok(())
+}
+
+/// Run the rewrite pipeline benchmark and print its results.
+#[expect(
+    clippy::print_stdout,
+    reason = "The benchmark report is the output of the subcommand."
+)]
+async fn run_bench(iterations: u32, gzip_level: u32) -> anyhow::Result<()> {
+    for result in Bench::new(iterations, gzip_level).run().await? {
+        println!("{result}");
+    }
+
+    Ok(())
+}
+
+/// Replay a capture file through the current code and print the differences.
+#[expect(
+    clippy::print_stdout,
+    reason = "The replay report is the output of the subcommand."
+)]
+async fn run_replay_sync(
+    capture_file: &Path,
+    command_line_arguments: &CommandLineArguments,
+) -> anyhow::Result<()> {
+    let frontend_url = command_line_arguments
+        .frontend_url
+        .clone()
+        .unwrap_or_else(|| format!("http://localhost:{}", command_line_arguments.port));
+    let report = Replay::from_file(capture_file, frontend_url)?.run().await?;
+    println!("{report}");
+    if !report.differences.is_empty() {
+        anyhow::bail!("The replayed responses differ from the recording");
+    }
+
+    Ok(())
+}
+
+/// Initialize the logging subsystem with console output and, if configured, a
+/// rotating log file with its own level and format.
+fn initialize_logging(command_line_arguments: &CommandLineArguments) {
+    let mut errors = Vec::new();
+    let log_level = &command_line_arguments.log_level;
+    let console_filter = parse_log_level(log_level, &mut errors);
+
+    let file_layer = command_line_arguments.log_file.as_ref().and_then(|path| {
+        let file = RotatingFile::open(
+            path.clone(),
+            command_line_arguments.log_file_rotation,
+            command_line_arguments
+                .log_file_max_mb
+                .map(|megabytes| megabytes.saturating_mul(1024 * 1024)),
+            command_line_arguments.log_file_retention,
         )
-        .with(tracing_subscriber::fmt::layer())
+        .inspect_err(|e| {
+            errors.push(format!("Failed to open log file '{}': {e}", path.display()));
+        })
+        .ok()?;
+        let file_level = command_line_arguments
+            .log_file_level
+            .as_ref()
+            .unwrap_or(log_level);
+        let layer = tracing_subscriber::fmt::layer()
+            .with_writer(Mutex::new(file))
+            .with_ansi(false);
+        let layer = match command_line_arguments.log_file_format {
+            LogFileFormat::Full => layer.boxed(),
+            LogFileFormat::Compact => layer.compact().boxed(),
+        };
+        Some(layer.with_filter(parse_log_level(file_level, &mut errors)))
+    });
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(console_filter))
+        .with(file_layer)
         .init();
 
-    if let Some(message) = parse_error {
+    for message in errors {
         tracing::error!("{message}");
     }
 }
+
+/// Parse a log level filter, recording an error and falling back to the default if
+/// it is invalid.
+fn parse_log_level(log_level: &str, errors: &mut Vec<String>) -> EnvFilter {
+    EnvFilter::builder()
+        .parse(log_level)
+        .inspect_err(|e| errors.push(format!("Failed to parse log level '{log_level}': {e}")))
+        .unwrap_or_default()
+}
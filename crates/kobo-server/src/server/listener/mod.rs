@@ -1,9 +0,0 @@
-#[cfg(test)]
-mod fake_listener;
-#[cfg(test)]
-mod fake_listener_builder;
-mod into_listener;
-
-#[cfg(test)]
-pub use fake_listener_builder::FakeListenerBuilder;
-pub use into_listener::{IntoListener, TokioTcpListener};
@@ -1,3 +0,0 @@
-//! Middleware components used by the Kobo server.
-
-pub mod request_logging;
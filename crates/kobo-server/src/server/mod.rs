@@ -1,11 +0,0 @@
-//! Server components for the Kobo proxy application.
-
-pub mod listener;
-mod middleware;
-mod router;
-mod routes;
-mod server_implementation;
-mod state;
-mod utils;
-
-pub use server_implementation::{Server, ServerBuilder};
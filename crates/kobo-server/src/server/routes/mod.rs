@@ -1,5 +0,0 @@
-//! Route handlers for the Kobo server.
-
-pub mod constants;
-pub mod initialization;
-pub mod kobo_store_request;
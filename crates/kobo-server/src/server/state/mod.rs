@@ -1,7 +0,0 @@
-//! Shared state definitions for the Kobo server.
-
-pub mod client;
-pub mod server_state;
-
-#[cfg(test)]
-pub mod fake_kobo_client;
@@ -1,3 +0,0 @@
-//! Utility modules for common server functionality.
-
-pub mod http_body;